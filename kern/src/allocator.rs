@@ -22,6 +22,24 @@ pub trait LocalAlloc {
     unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout);
 }
 
+/// ヒープの使用状況.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MemStats {
+    /// ヒープ全体のバイト数.
+    pub total: usize,
+    /// 割り当て中のバイト数. サイズクラスへの切り上げ分を含む。
+    pub allocated: usize,
+    /// まだ一度も割り当てに使われていない領域のバイト数.
+    pub untouched: usize,
+}
+
+impl MemStats {
+    /// 割り当てに使用できるバイト数を返す.
+    pub fn free(&self) -> usize {
+        self.total - self.allocated
+    }
+}
+
 /// 特定のメモリアロケータをラップするスレッドセーフな（ロッキング）
 /// ラッパー.
 pub struct Allocator(Mutex<Option<AllocatorImpl>>);
@@ -47,6 +65,11 @@ impl Allocator {
         //info!("heap beg: {:x}, end: {:x}", start, end);
         *self.0.lock() = Some(AllocatorImpl::new(start, end));
     }
+
+    /// ヒープの使用状況を返す. 初期化前は `None` を返す。
+    pub fn stats(&self) -> Option<MemStats> {
+        self.0.lock().as_ref().map(|alloc| alloc.stats())
+    }
 }
 
 unsafe impl GlobalAlloc for Allocator {
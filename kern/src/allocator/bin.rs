@@ -4,7 +4,7 @@ use core::fmt::{Debug, Formatter, Result};
 
 use crate::allocator::linked_list::LinkedList;
 use crate::allocator::util::*;
-use crate::allocator::LocalAlloc;
+use crate::allocator::{LocalAlloc, MemStats};
 
 //use crate::console::kprintln;
 
@@ -20,9 +20,13 @@ const NUM_BINS: usize = 30;
 ///
 
 pub struct Allocator {
+    /// ヒープの先頭アドレス
+    heap_start: usize,
     start: usize,
     end: usize,
-    bins: [LinkedList; NUM_BINS]
+    bins: [LinkedList; NUM_BINS],
+    /// 割り当て中のbinエントリの合計バイト数
+    allocated: usize,
 }
 
 /// `ptr`は`align`にアラインされているか.
@@ -34,10 +38,22 @@ impl Allocator {
     /// アドレス `start` から始まりアドレス `end` で終わる領域から
     /// メモリを割り当てる新しい bin アロケータを作成する.
     pub fn new(start: usize, end: usize) -> Allocator {
+        let start = align_up(start, 8);         // 少なくとも8バイトアライン
         Allocator {
-            start: align_up(start, 8),          // 少なくとも8バイトアライン
+            heap_start: start,
+            start,
             end,
-            bins: [LinkedList::new(); NUM_BINS]
+            bins: [LinkedList::new(); NUM_BINS],
+            allocated: 0,
+        }
+    }
+
+    /// ヒープの使用状況を返す.
+    pub fn stats(&self) -> MemStats {
+        MemStats {
+            total: self.end - self.heap_start,
+            allocated: self.allocated,
+            untouched: self.end - self.start,
         }
     }
 
@@ -182,21 +198,18 @@ impl Allocator {
         // 1. 使用するbinを決定する
         let bin = self.layout_to_bin(layout);
 
-        // 2. binにエントリがあればそれを返す。
-        if let Some(p) = self.bins[bin].pop() {
-            return Some(p as *mut u8);
-        }
-
-        // 3. binのエントリをメモリ領域から作成する
-        if !self.scavenge_bin(bin) {
+        // 2. binにエントリがなければメモリ領域から作成する
+        if self.bins[bin].is_empty() && !self.scavenge_bin(bin) {
             return None;
         }
 
+        self.allocated += self.bin_size(bin);
         Some(self.bins[bin].pop().unwrap() as *mut u8)
     }
 
     fn do_dealloc(&mut self, ptr: *mut u8, layout: Layout) {
         let bin = self.layout_to_bin(layout);
+        self.allocated = self.allocated.saturating_sub(self.bin_size(bin));
         // 8バイトにアラインされたポインタしか渡さないのでキャストは安全
         unsafe { self.bins[bin].push(ptr as *mut usize) };
     }
@@ -2,12 +2,13 @@ use core::alloc::Layout;
 //use core::ptr;
 
 use crate::allocator::util::*;
-use crate::allocator::LocalAlloc;
+use crate::allocator::{LocalAlloc, MemStats};
 
 /// "バンプ"アロケータ: ポインタを動かすことでメモリを割り当てる。
 /// 開放はしない。
 #[derive(Debug)]
 pub struct Allocator {
+    start: usize,
     current: usize,
     end: usize,
 }
@@ -18,10 +19,21 @@ impl Allocator {
     #[allow(dead_code)]
     pub fn new(start: usize, end: usize) -> Allocator {
         Allocator {
+            start,
             current: start,
             end
         }
     }
+
+    /// ヒープの使用状況を返す. 解放されたメモリも割り当て中として数える。
+    #[allow(dead_code)]
+    pub fn stats(&self) -> MemStats {
+        MemStats {
+            total: self.end - self.start,
+            allocated: self.current - self.start,
+            untouched: self.end - self.current,
+        }
+    }
 }
 
 impl LocalAlloc for Allocator {
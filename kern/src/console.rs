@@ -29,6 +29,11 @@ impl Console {
         self.inner.as_mut().unwrap()
     }
 
+    /// 読み込めるバイトがある場合は `true` を返す. このメソッドはブロックしない。
+    pub fn has_byte(&mut self) -> bool {
+        self.inner().has_byte()
+    }
+
     /// UARTデバイスから1バイト読み込む。1バイト読み込めるまでブロックする.
     pub fn read_byte(&mut self) -> u8 {
         self.inner().read_byte()
@@ -1,29 +1,37 @@
+pub mod dev;
+pub mod fat;
+pub mod path;
+pub mod proc;
 pub mod sd;
+pub mod tmp;
+pub mod vfs;
 
+use alloc::boxed::Box;
 use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::fmt::{self, Debug};
 use shim::io;
-//use shim::ioerr;
+use shim::ioerr;
 use shim::path::Path;
 
 pub use fat32::traits;
 use fat32::vfat::{VFat, VFatHandle};
+use tmpfs::{TmpFsHandle, TmpFsState};
 
+use self::dev::DevFs;
+use self::fat::FatFs;
+use self::proc::ProcFs;
+use self::sd::SharedSd;
+use self::tmp::TmpFs;
+use self::vfs::{DirEntry, FileObject, FileType, InodeRef, Mount, MountTable, Stat, StatFs};
 //use self::sd::Sd;
 use crate::mutex::Mutex;
+use crate::param::TMPFS_LIMIT;
+use crate::single_core::SingleCore;
 
 #[derive(Clone)]
-pub struct PiVFatHandle(Rc<Mutex<VFat<Self>>>);
-
-// これらの実装は *不健全* である。`PiVFatHandle`の `Sync`
-// トレイトと `Send` トレイトの実装には `Rc` ではなく `Arc` を
-// 使うべきである。しかし、`Arc` はアトミックメモリアクセスを
-// 使用するため、ARMアーキテクチャではMMUを初期化する必要がある。
-// 私たちはボード上の1つのコアしか有効にしていないので、これらの
-// 不健全な実装は今のところ直ちに害を及ぼすことはない。いずれ
-// 修正する予定である。
-unsafe impl Send for PiVFatHandle {}
-unsafe impl Sync for PiVFatHandle {}
+pub struct PiVFatHandle(SingleCore<Rc<Mutex<VFat<Self>>>>);
 
 impl Debug for PiVFatHandle {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
@@ -33,7 +41,7 @@ impl Debug for PiVFatHandle {
 
 impl VFatHandle for PiVFatHandle {
     fn new(val: VFat<PiVFatHandle>) -> Self {
-        PiVFatHandle(Rc::new(Mutex::new(val)))
+        PiVFatHandle(SingleCore::new(Rc::new(Mutex::new(val))))
     }
 
     fn lock<R>(&self, f: impl FnOnce(&mut VFat<PiVFatHandle>) -> R) -> R {
@@ -41,7 +49,31 @@ impl VFatHandle for PiVFatHandle {
     }
 }
 
-pub struct FileSystem(Mutex<Option<PiVFatHandle>>);
+#[derive(Clone)]
+pub struct PiTmpFsHandle(SingleCore<Rc<Mutex<TmpFsState>>>);
+
+impl Debug for PiTmpFsHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "PiTmpFsHandle")
+    }
+}
+
+impl TmpFsHandle for PiTmpFsHandle {
+    fn new(val: TmpFsState) -> Self {
+        PiTmpFsHandle(SingleCore::new(Rc::new(Mutex::new(val))))
+    }
+
+    fn lock<R>(&self, f: impl FnOnce(&mut TmpFsState) -> R) -> R {
+        f(&mut self.0.lock())
+    }
+}
+
+/// 連続したセクタを読み込む際に先読みするセクタ数.
+const READ_AHEAD_SECTORS: usize = 16;
+
+/// マウントテーブルを介してすべてのファイルシステムにアクセスする
+/// カーネルのVFS.
+pub struct FileSystem(Mutex<MountTable>);
 
 impl FileSystem {
     /// 初期化していない `FileSystem` を返す.
@@ -50,10 +82,12 @@ impl FileSystem {
     /// ファイルシステムを初期化する必要がある。そうしないと
     /// パニックを起こすことになる。
     pub const fn uninitialized() -> Self {
-        FileSystem(Mutex::new(None))
+        FileSystem(Mutex::new(MountTable::new()))
     }
 
-    /// ファイルシステムを初期化する.
+    /// ファイルシステムを初期化する. SDカードのFATパーティションを
+    /// ルートに、tmpfsを `/tmp` に、procfsを `/proc` にマウントする.
+    /// 組み込みのデバイスを登録し、devfsを `/dev` にマウントする。
     ///
     /// callerはカーネルの初期化の際に1度だけこのメソッドを
     /// 実行するいつ用がある。
@@ -63,21 +97,141 @@ impl FileSystem {
     /// ディスクまたはファイルシステムの初期化に失敗した場合は
     /// パニックを起こす
     pub unsafe fn initialize(&self) {
-        let sd = sd::Sd::new().expect("failed to init sd card");
-        let vfat = VFat::<PiVFatHandle>::from(sd).expect("failed to init vfat");
+        let sd = SharedSd::new(sd::Sd::new().expect("failed to init sd card"));
+        let vfat = VFat::<PiVFatHandle>::from(sd.clone()).expect("failed to init vfat");
+        vfat.lock(|fs| fs.set_read_ahead(READ_AHEAD_SECTORS));
+
+        self.mount("/", Rc::new(FatFs::new(vfat))).expect("failed to mount root");
+        self.mount("/tmp", Rc::new(TmpFs::new(TMPFS_LIMIT))).expect("failed to mount /tmp");
+        self.mount("/proc", Rc::new(ProcFs)).expect("failed to mount /proc");
 
-        *self.0.lock() = Some(vfat);
+        dev::register_builtin(sd).expect("failed to register devices");
+        self.mount("/dev", Rc::new(DevFs)).expect("failed to mount /dev");
+    }
+
+    /// `fs` をパス `path` にマウントする. マウントポイントは既存の
+    /// ディレクトリである必要はなく、親ディレクトリの一覧に現れる。
+    ///
+    /// # エラー
+    ///
+    /// `path` にすでにファイルシステムがマウントされている場合は
+    /// `AlreadyExists` を返す。
+    pub fn mount<P: AsRef<Path>>(&self, path: P, fs: Rc<dyn vfs::FileSystem>) -> io::Result<()> {
+        self.0.lock().mount(path.as_ref(), fs)
+    }
+
+    /// パス `path` のファイルシステムの変更を書き出してアンマウントする.
+    pub fn unmount<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let fs = self.0.lock().unmount(path.as_ref())?;
+        fs.sync()
+    }
+
+    /// マウントされているファイルシステムの一覧を返す.
+    pub fn mounts(&self) -> Vec<Mount> {
+        self.0.lock().mounts().to_vec()
     }
-}
 
-// FIXME: Implement `fat32::traits::FileSystem` for `&FileSystem`
+    /// パス `path` のノードを返す.
+    ///
+    /// ファイルシステムの操作中はマウントテーブルをロックしない。
+    pub fn lookup<P: AsRef<Path>>(&self, path: P) -> io::Result<InodeRef> {
+        let (mount, rest) = self.0.lock().find(path.as_ref())?;
+        mount.lookup(&rest)
+    }
+
+    /// パス `path` の親ディレクトリのノード、その属するマウント、最後の
+    /// コンポーネントを返す.
+    ///
+    /// # エラー
+    ///
+    /// `path` がルートの場合は `InvalidInput` を、マウントポイントの
+    /// 場合は `PermissionDenied` を返す。
+    fn parent<P: AsRef<Path>>(&self, path: P) -> io::Result<(InodeRef, Mount, String)> {
+        let path = vfs::normalize(path.as_ref())?;
+        let name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => String::from(name),
+            None => return ioerr!(InvalidInput, "invalid path"),
+        };
+        let (mount, rest) = {
+            let mounts = self.0.lock();
+            if mounts.is_mount_point(&path) {
+                return ioerr!(PermissionDenied, "mount point is busy");
+            }
+            mounts.find(path.parent().expect("not root"))?
+        };
+        Ok((mount.lookup(&rest)?, mount, name))
+    }
+
+    /// パス `path` のノードの属性を返す.
+    pub fn stat<P: AsRef<Path>>(&self, path: P) -> io::Result<Stat> {
+        self.lookup(path)?.stat()
+    }
+
+    /// パス `path` のファイルまたはデバイスをオープンする.
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> io::Result<Box<dyn FileObject>> {
+        self.lookup(path)?.open()
+    }
+
+    /// ディレクトリ `path` のエントリの一覧を返す. 直下のマウント
+    /// ポイントも含まれる。
+    pub fn readdir<P: AsRef<Path>>(&self, path: P) -> io::Result<Vec<DirEntry>> {
+        let path = vfs::normalize(path.as_ref())?;
+        let mut entries = self.lookup(&path)?.readdir()?;
+        let children = self.0.lock().children(&path);
+        for name in children {
+            if entries.iter().any(|e| e.name == name) {
+                continue;
+            }
+            let stat = self.stat(path.join(&name))?;
+            entries.push(DirEntry { name, stat });
+        }
+        Ok(entries)
+    }
 
-impl fat32::traits::FileSystem for &FileSystem {
-    type File = fat32::vfat::File<PiVFatHandle>;
-    type Dir = fat32::vfat::Dir<PiVFatHandle>;
-    type Entry = fat32::vfat::Entry<PiVFatHandle>;
+    /// パス `path` に空の通常ファイルを作成してオープンする.
+    pub fn create_file<P: AsRef<Path>>(&self, path: P) -> io::Result<Box<dyn FileObject>> {
+        let (parent, _, name) = self.parent(path)?;
+        parent.create(&name, FileType::File)?.open()
+    }
+
+    /// パス `path` に空のディレクトリを作成する.
+    pub fn create_dir<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let (parent, _, name) = self.parent(path)?;
+        parent.create(&name, FileType::Dir).map(|_| ())
+    }
+
+    /// パス `path` のファイルまたは空のディレクトリを削除する.
+    pub fn remove<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let (parent, _, name) = self.parent(path)?;
+        parent.remove(&name)
+    }
+
+    /// `from` のエントリを `to` に移動する.
+    ///
+    /// # エラー
+    ///
+    /// `from` と `to` が異なるファイルシステムにある場合は `InvalidInput`
+    /// を返す。
+    pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> io::Result<()> {
+        let (src, src_mount, from) = self.parent(from)?;
+        let (dst, dst_mount, to) = self.parent(to)?;
+        if !Rc::ptr_eq(&src_mount.fs, &dst_mount.fs) {
+            return ioerr!(InvalidInput, "cannot rename across file systems");
+        }
+        src.rename(&from, &*dst, &to)
+    }
+
+    /// パス `path` を含むファイルシステムの使用状況を返す.
+    pub fn statfs<P: AsRef<Path>>(&self, path: P) -> io::Result<StatFs> {
+        let (mount, _) = self.0.lock().find(path.as_ref())?;
+        mount.fs.statfs()
+    }
 
-    fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry> {
-        self.0.lock().as_ref().expect("kernel::fs uninitialized").open(path)
+    /// マウントされているすべてのファイルシステムの変更を書き出す.
+    pub fn sync(&self) -> io::Result<()> {
+        for mount in self.mounts() {
+            mount.fs.sync()?;
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,374 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::cmp::min;
+
+use shim::io;
+use shim::ioerr;
+
+use fat32::gpt::{GuidPartitionTable, PROTECTIVE_PARTITION_TYPE};
+use fat32::mbr::MasterBootRecord;
+use fat32::traits::BlockDevice;
+use pi::rng::Rng;
+
+use crate::console::CONSOLE;
+use crate::mutex::Mutex;
+use crate::single_core::SingleCore;
+
+use super::sd::SharedSd;
+use super::vfs::{self, DirEntry, FileObject, FileType, InodeRef, Stat};
+
+/// デバイスのドライバ. devfsのノードはドライバを介して読み書きされる。
+pub trait Driver {
+    /// デバイスのバイトオフセット `offset` から `buf` に読み込み、読み込んだ
+    /// バイト数を返す. キャラクタデバイスは `offset` を無視する。
+    fn read(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// `buf` をデバイスのバイトオフセット `offset` に書き込み、書き込んだ
+    /// バイト数を返す. キャラクタデバイスは `offset` を無視する。
+    fn write(&self, offset: u64, buf: &[u8]) -> io::Result<usize>;
+
+    /// ブロックデバイスのバイト単位のサイズ. キャラクタデバイスや
+    /// サイズが分からないデバイスでは `None`.
+    fn size(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// 名前とドライバの対応表. devfsはこの表の内容を公開する。
+pub struct DeviceTable(Mutex<SingleCore<Vec<(String, Rc<dyn Driver>)>>>);
+
+impl DeviceTable {
+    pub const fn new() -> DeviceTable {
+        DeviceTable(Mutex::new(SingleCore::new(Vec::new())))
+    }
+
+    /// ドライバ `driver` を名前 `name` で登録する.
+    ///
+    /// # エラー
+    ///
+    /// `name` がすでに登録されている場合は `AlreadyExists` を、`name` が
+    /// 空または `/` を含む場合は `InvalidInput` を返す。
+    pub fn register(&self, name: &str, driver: Rc<dyn Driver>) -> io::Result<()> {
+        if name.is_empty() || name.contains('/') {
+            return ioerr!(InvalidInput, "invalid device name");
+        }
+
+        let mut devices = self.0.lock();
+        if devices.iter().any(|(n, _)| n == name) {
+            return ioerr!(AlreadyExists, "device already registered");
+        }
+        devices.push((String::from(name), driver));
+        Ok(())
+    }
+
+    /// 名前 `name` のドライバを返す.
+    pub fn get(&self, name: &str) -> Option<Rc<dyn Driver>> {
+        self.0.lock().iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, driver)| driver.clone())
+    }
+
+    /// 登録されているデバイスの名前とドライバの一覧を返す.
+    fn list(&self) -> Vec<(String, Rc<dyn Driver>)> {
+        self.0.lock().to_vec()
+    }
+}
+
+/// グローバルなデバイスの対応表. ドライバはカーネルの初期化時に登録する。
+pub static DEVICES: DeviceTable = DeviceTable::new();
+
+/// 組み込みのドライバを `DEVICES` に登録する. SDカード `sd` はカード全体を
+/// `sd0` として、使用中のパーティションを `sd0pN` として登録する。`N` は
+/// MBRではパーティションエントリの番号 (1から4)、GPTではパーティション
+/// エントリ配列の1から始まるインデックスである。
+///
+/// # エラー
+///
+/// いずれかの名前がすでに登録されている場合は `AlreadyExists` を返す。
+pub fn register_builtin(sd: SharedSd) -> io::Result<()> {
+    DEVICES.register("console", Rc::new(ConsoleDriver))?;
+    DEVICES.register("null", Rc::new(NullDriver))?;
+    DEVICES.register("zero", Rc::new(ZeroDriver))?;
+    DEVICES.register("random", Rc::new(RandomDriver))?;
+
+    let mut disk = sd.clone();
+    let mut partitions = Vec::new();
+    if let Ok(mbr) = MasterBootRecord::from(&mut disk) {
+        let protective = mbr.partitions.iter()
+            .any(|p| p.partition_type == PROTECTIVE_PARTITION_TYPE);
+        if !protective {
+            for (i, partition) in mbr.partitions.iter().enumerate() {
+                if partition.partition_type != 0 {
+                    partitions.push((i, partition.relative_sector as u64,
+                        partition.total_sectores as u64));
+                }
+            }
+        } else if let Ok(gpt) = GuidPartitionTable::from_mbr(&mut disk, &mbr) {
+            for (i, partition) in gpt.partitions.iter().enumerate() {
+                if partition.is_used() {
+                    partitions.push((i, partition.first_lba, partition.num_sectors()));
+                }
+            }
+        }
+    }
+    for (i, start, num_sectors) in partitions {
+        let driver = BlockDriver::new(sd.clone(), start, Some(num_sectors));
+        DEVICES.register(&format!("sd0p{}", i + 1), Rc::new(driver))?;
+    }
+    DEVICES.register("sd0", Rc::new(BlockDriver::new(sd, 0, None)))
+}
+
+/// `DEVICES` に登録されたデバイスを公開する疑似ファイルシステム.
+pub struct DevFs;
+
+impl vfs::FileSystem for DevFs {
+    fn name(&self) -> &str {
+        "devfs"
+    }
+
+    fn root(&self) -> io::Result<InodeRef> {
+        Ok(Rc::new(DevRoot))
+    }
+}
+
+/// ドライバ `driver` のデバイスノードの属性を返す.
+fn device_stat(driver: &dyn Driver) -> Stat {
+    Stat::new(FileType::Device, driver.size().unwrap_or(0))
+}
+
+/// devfsのルートディレクトリ.
+struct DevRoot;
+
+impl vfs::Inode for DevRoot {
+    fn stat(&self) -> io::Result<Stat> {
+        Ok(Stat::new(FileType::Dir, 0))
+    }
+
+    fn lookup(&self, name: &str) -> io::Result<InodeRef> {
+        match DEVICES.get(name) {
+            Some(driver) => Ok(Rc::new(DevNode(driver))),
+            None => ioerr!(NotFound, "no such device"),
+        }
+    }
+
+    fn readdir(&self) -> io::Result<Vec<DirEntry>> {
+        Ok(DEVICES.list()
+            .into_iter()
+            .map(|(name, driver)| DirEntry { name, stat: device_stat(&*driver) })
+            .collect())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// デバイスノード.
+struct DevNode(Rc<dyn Driver>);
+
+impl vfs::Inode for DevNode {
+    fn stat(&self) -> io::Result<Stat> {
+        Ok(device_stat(&*self.0))
+    }
+
+    fn open(&self) -> io::Result<Box<dyn FileObject>> {
+        Ok(Box::new(DevFile { driver: self.0.clone(), offset: 0 }))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// オープンされたデバイス. 読み書きの位置はブロックデバイスでのみ意味を持つ。
+struct DevFile {
+    driver: Rc<dyn Driver>,
+    offset: u64,
+}
+
+impl io::Read for DevFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.driver.read(self.offset, buf)?;
+        self.offset += read as u64;
+        Ok(read)
+    }
+}
+
+impl io::Write for DevFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.driver.write(self.offset, buf)?;
+        self.offset += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Seek for DevFile {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let offset = match pos {
+            io::SeekFrom::Start(offset) => offset as i128,
+            io::SeekFrom::Current(delta) => self.offset as i128 + delta as i128,
+            io::SeekFrom::End(delta) => match self.driver.size() {
+                Some(size) => size as i128 + delta as i128,
+                None => return ioerr!(InvalidInput, "device has no size"),
+            },
+        };
+        if offset < 0 {
+            return ioerr!(InvalidInput, "seek before start of device");
+        }
+
+        self.offset = offset as u64;
+        Ok(self.offset)
+    }
+}
+
+impl FileObject for DevFile {
+    fn stat(&self) -> io::Result<Stat> {
+        Ok(device_stat(&*self.driver))
+    }
+}
+
+/// コンソール (mini UART). 読み込みはブロックせず、受信したバイトがない
+/// 場合は `WouldBlock` を返す。待機は呼び出し側が行う。
+pub struct ConsoleDriver;
+
+impl Driver for ConsoleDriver {
+    fn read(&self, _offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        // 受信を待つ間に `CONSOLE` をロックしたままにすると他の出力が止まる
+        let mut console = CONSOLE.lock();
+        if !console.has_byte() {
+            return ioerr!(WouldBlock, "no input available");
+        }
+        io::Read::read(&mut *console, buf)
+    }
+
+    fn write(&self, _offset: u64, buf: &[u8]) -> io::Result<usize> {
+        io::Write::write(&mut *CONSOLE.lock(), buf)
+    }
+}
+
+/// 読み込みは常に終端に達し、書き込みはすべて捨てるデバイス.
+pub struct NullDriver;
+
+impl Driver for NullDriver {
+    fn read(&self, _offset: u64, _buf: &mut [u8]) -> io::Result<usize> {
+        Ok(0)
+    }
+
+    fn write(&self, _offset: u64, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+}
+
+/// 読み込むと0を返し、書き込みはすべて捨てるデバイス.
+pub struct ZeroDriver;
+
+impl Driver for ZeroDriver {
+    fn read(&self, _offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        for byte in buf.iter_mut() {
+            *byte = 0;
+        }
+        Ok(buf.len())
+    }
+
+    fn write(&self, _offset: u64, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+}
+
+/// ハードウェア乱数生成器. 書き込みはすべて捨てる。
+pub struct RandomDriver;
+
+impl Driver for RandomDriver {
+    fn read(&self, _offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        Rng::new().fill_bytes(buf);
+        Ok(buf.len())
+    }
+
+    fn write(&self, _offset: u64, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+}
+
+/// ブロックデバイスの連続したセクタをバイト単位で読み書きするデバイス.
+/// ディスク全体やパーティションを表す。
+pub struct BlockDriver<T> {
+    device: Mutex<T>,
+    /// 最初の物理セクタ.
+    start: u64,
+    /// セクタ数. 分からない場合は `None`.
+    num_sectors: Option<u64>,
+}
+
+impl<T: BlockDevice> BlockDriver<T> {
+    /// `device` の物理セクタ `start` から始まる `num_sectors` セクタの
+    /// 領域を表すデバイスを返す.
+    pub fn new(device: T, start: u64, num_sectors: Option<u64>) -> BlockDriver<T> {
+        BlockDriver { device: Mutex::new(device), start, num_sectors }
+    }
+
+    /// `offset` から `len` バイトのうち、領域内に収まるバイト数を返す.
+    fn clamp(&self, offset: u64, len: usize) -> usize {
+        match self.size() {
+            Some(size) if offset >= size => 0,
+            Some(size) => min(len as u64, size - offset) as usize,
+            None => len,
+        }
+    }
+}
+
+impl<T: BlockDevice> Driver for BlockDriver<T> {
+    fn read(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.clamp(offset, buf.len());
+        let mut device = self.device.lock();
+        let sector_size = device.sector_size();
+        let mut sector = vec![0u8; sector_size as usize];
+
+        let mut read = 0;
+        while read < len {
+            let pos = offset + read as u64;
+            let skip = (pos % sector_size) as usize;
+            let count = min(sector.len() - skip, len - read);
+            device.read_sector(self.start + pos / sector_size, &mut sector)?;
+            buf[read..read + count].copy_from_slice(&sector[skip..skip + count]);
+            read += count;
+        }
+        Ok(read)
+    }
+
+    fn write(&self, offset: u64, buf: &[u8]) -> io::Result<usize> {
+        let len = self.clamp(offset, buf.len());
+        let mut device = self.device.lock();
+        let sector_size = device.sector_size();
+        let mut sector = vec![0u8; sector_size as usize];
+
+        let mut written = 0;
+        while written < len {
+            let pos = offset + written as u64;
+            let n = self.start + pos / sector_size;
+            let skip = (pos % sector_size) as usize;
+            let count = min(sector.len() - skip, len - written);
+            // セクタの一部だけを書き換える場合は残りの内容を保つ
+            if count < sector.len() {
+                device.read_sector(n, &mut sector)?;
+            }
+            sector[skip..skip + count].copy_from_slice(&buf[written..written + count]);
+            device.write_sector(n, &sector)?;
+            written += count;
+        }
+        Ok(written)
+    }
+
+    fn size(&self) -> Option<u64> {
+        let sector_size = self.device.lock().sector_size();
+        self.num_sectors.map(|n| n * sector_size)
+    }
+}
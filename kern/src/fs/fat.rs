@@ -0,0 +1,53 @@
+use alloc::rc::Rc;
+
+use shim::io;
+
+use fat32::vfat::{self, VFatHandle};
+
+use super::path::{PathFs, PathInode};
+use super::vfs::{self, InodeRef, StatFs};
+use super::PiVFatHandle;
+
+/// VFSにマウントするFATファイルシステム.
+pub struct FatFs {
+    vfat: PiVFatHandle,
+}
+
+impl FatFs {
+    pub fn new(vfat: PiVFatHandle) -> FatFs {
+        FatFs { vfat }
+    }
+}
+
+impl PathFs for PiVFatHandle {
+    type File = vfat::File<PiVFatHandle>;
+    type Dir = vfat::Dir<PiVFatHandle>;
+    type Entry = vfat::Entry<PiVFatHandle>;
+
+    fn same_fs(&self, other: &PiVFatHandle) -> bool {
+        Rc::ptr_eq(&*self.0, &*other.0)
+    }
+}
+
+impl vfs::FileSystem for FatFs {
+    fn name(&self) -> &str {
+        "vfat"
+    }
+
+    fn root(&self) -> io::Result<InodeRef> {
+        Ok(Rc::new(PathInode::root(self.vfat.clone())))
+    }
+
+    fn statfs(&self) -> io::Result<StatFs> {
+        let stats = self.vfat.lock(|fs| fs.statfs())?;
+        Ok(StatFs {
+            block_size: stats.cluster_size,
+            total_blocks: stats.total_clusters as u64,
+            free_blocks: stats.free_clusters as u64,
+        })
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.vfat.lock(|fs| fs.flush())
+    }
+}
@@ -0,0 +1,183 @@
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::any::Any;
+
+use shim::io;
+use shim::ioerr;
+use shim::path::PathBuf;
+
+use fat32::traits::{self, Dir, Entry, File, FileSystem, Metadata, Timestamp};
+
+use super::vfs::{self, DateTime, DirEntry, FileObject, FileType, InodeRef, Stat};
+
+/// `fat32::traits::FileSystem` を実装し、パスでエントリを開く
+/// ファイルシステムのハンドル.
+pub trait PathFs: Clone + 'static
+where
+    for<'a> &'a Self: FileSystem<File = Self::File, Dir = Self::Dir, Entry = Self::Entry>,
+{
+    type File: traits::File + 'static;
+    type Dir: traits::Dir<Entry = Self::Entry>;
+    type Entry: traits::Entry<File = Self::File, Dir = Self::Dir>;
+
+    /// `self` と `other` が同じファイルシステムを指すか否かを返す.
+    fn same_fs(&self, other: &Self) -> bool;
+}
+
+/// メタデータ `metadata` から種類 `kind`、サイズ `size` のノードの属性を
+/// 作成する.
+fn stat_from<M: Metadata>(metadata: &M, kind: FileType, size: u64) -> Stat {
+    let modified = metadata.modified();
+    Stat {
+        kind,
+        size,
+        read_only: metadata.read_only(),
+        hidden: metadata.hidden(),
+        modified: DateTime {
+            year: modified.year() as u16,
+            month: modified.month(),
+            day: modified.day(),
+            hour: modified.hour(),
+            minute: modified.minute(),
+            second: modified.second(),
+        },
+    }
+}
+
+/// エントリ `entry` の属性を返す.
+fn stat_of<E: Entry>(entry: &E) -> Stat {
+    match entry.as_file() {
+        Some(file) => stat_from(entry.metadata(), FileType::File, file.size()),
+        None => stat_from(entry.metadata(), FileType::Dir, 0),
+    }
+}
+
+/// `PathFs` 内のノード. エントリはパスで開くため、ファイルシステム内
+/// での絶対パスを保持する。
+pub struct PathInode<H> {
+    fs: H,
+    path: PathBuf,
+}
+
+impl<H> PathInode<H> {
+    /// ファイルシステム `fs` のルートディレクトリのノードを返す.
+    pub fn root(fs: H) -> PathInode<H> {
+        PathInode { fs, path: PathBuf::from("/") }
+    }
+}
+
+impl<H: Clone> PathInode<H> {
+    fn child(&self, name: &str) -> PathInode<H> {
+        PathInode {
+            fs: self.fs.clone(),
+            path: self.path.join(name),
+        }
+    }
+}
+
+impl<H: PathFs> vfs::Inode for PathInode<H>
+where
+    for<'a> &'a H: FileSystem<File = H::File, Dir = H::Dir, Entry = H::Entry>,
+{
+    fn stat(&self) -> io::Result<Stat> {
+        if self.path.parent().is_none() {
+            return Ok(Stat::new(FileType::Dir, 0));
+        }
+        Ok(stat_of(&(&self.fs).open(&self.path)?))
+    }
+
+    fn lookup(&self, name: &str) -> io::Result<InodeRef> {
+        let child = self.child(name);
+        (&self.fs).open(&child.path)?;
+        Ok(Rc::new(child))
+    }
+
+    fn readdir(&self) -> io::Result<Vec<DirEntry>> {
+        let dir = (&self.fs).open_dir(&self.path)?;
+        Ok(dir.entries()?
+            .map(|entry| DirEntry {
+                name: entry.name().into(),
+                stat: stat_of(&entry),
+            })
+            .collect())
+    }
+
+    fn open(&self) -> io::Result<Box<dyn FileObject>> {
+        let entry = (&self.fs).open(&self.path)?;
+        let stat = stat_of(&entry);
+        match entry.into_file() {
+            Some(file) => Ok(Box::new(PathFile { file, stat })),
+            None => ioerr!(Other, "not a regular file"),
+        }
+    }
+
+    fn create(&self, name: &str, kind: FileType) -> io::Result<InodeRef> {
+        let child = self.child(name);
+        match kind {
+            FileType::File => {
+                (&self.fs).create_file(&child.path)?;
+            }
+            FileType::Dir => {
+                (&self.fs).create_dir(&child.path)?;
+            }
+            FileType::Device => return ioerr!(PermissionDenied, "device nodes not supported"),
+        }
+        Ok(Rc::new(child))
+    }
+
+    fn remove(&self, name: &str) -> io::Result<()> {
+        (&self.fs).remove(self.path.join(name))
+    }
+
+    fn rename(&self, from: &str, to_dir: &dyn vfs::Inode, to: &str) -> io::Result<()> {
+        let to_dir = match to_dir.as_any().downcast_ref::<PathInode<H>>() {
+            Some(dir) if dir.fs.same_fs(&self.fs) => dir,
+            _ => return ioerr!(InvalidInput, "cannot rename across file systems"),
+        };
+        (&self.fs).rename(self.path.join(from), to_dir.path.join(to))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// オープンされた `PathFs` のファイル.
+struct PathFile<F> {
+    file: F,
+    /// オープン時の属性. サイズは `file` から取得する。
+    stat: Stat,
+}
+
+impl<F: File> io::Read for PathFile<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl<F: File> io::Write for PathFile<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl<F: File> io::Seek for PathFile<F> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+impl<F: File> FileObject for PathFile<F> {
+    fn stat(&self) -> io::Result<Stat> {
+        Ok(Stat { size: self.file.size(), ..self.stat })
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.file.sync()
+    }
+}
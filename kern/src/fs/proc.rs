@@ -0,0 +1,269 @@
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::any::Any;
+use core::fmt::{self, Write};
+
+use shim::io::{self, Read, Seek};
+use shim::ioerr;
+
+use crate::param::NCORES;
+use crate::percore::core_info;
+use crate::process::Id;
+use crate::{ALLOCATOR, ETHERNET, SCHEDULER};
+
+use super::vfs::{self, DirEntry, FileObject, FileType, InodeRef, Stat};
+
+/// カーネルの状態を読み込み専用のファイルとして公開する疑似ファイル
+/// システム.
+///
+/// ファイルの内容はオープンするたびに生成される。
+pub struct ProcFs;
+
+impl vfs::FileSystem for ProcFs {
+    fn name(&self) -> &str {
+        "proc"
+    }
+
+    fn root(&self) -> io::Result<InodeRef> {
+        Ok(Rc::new(ProcNode::Root))
+    }
+}
+
+/// 内容を生成するファイルの種類.
+#[derive(Copy, Clone, Debug)]
+enum Generator {
+    MemInfo,
+    Uptime,
+    CpuInfo,
+    NetTcp,
+    Status(Id),
+}
+
+/// procfs内のノード.
+#[derive(Copy, Clone, Debug)]
+enum ProcNode {
+    Root,
+    Net,
+    Process(Id),
+    File(Generator),
+}
+
+/// ルートディレクトリの固定のエントリ.
+const ROOT_ENTRIES: &[(&str, ProcNode)] = &[
+    ("cpuinfo", ProcNode::File(Generator::CpuInfo)),
+    ("meminfo", ProcNode::File(Generator::MemInfo)),
+    ("net", ProcNode::Net),
+    ("uptime", ProcNode::File(Generator::Uptime)),
+];
+
+/// `/proc/net` のエントリ.
+const NET_ENTRIES: &[(&str, ProcNode)] = &[
+    ("tcp", ProcNode::File(Generator::NetTcp)),
+];
+
+/// プロセスディレクトリのエントリ.
+fn process_entries(id: Id) -> [(&'static str, ProcNode); 1] {
+    [("status", ProcNode::File(Generator::Status(id)))]
+}
+
+/// スケジューラのキューにあるプロセスのIDを返す.
+fn process_ids() -> Vec<Id> {
+    SCHEDULER.critical(|scheduler| {
+        scheduler.processes().map(|p| p.context.tpidr).collect()
+    })
+}
+
+impl Generator {
+    /// ファイルの内容を生成する.
+    ///
+    /// # エラー
+    ///
+    /// プロセスがすでに存在しない場合は `NotFound` を返す。
+    fn generate(&self) -> io::Result<String> {
+        let mut out = String::new();
+        let result = match *self {
+            Generator::MemInfo => write_meminfo(&mut out),
+            Generator::Uptime => write_uptime(&mut out),
+            Generator::CpuInfo => write_cpuinfo(&mut out),
+            Generator::NetTcp => write_net_tcp(&mut out),
+            Generator::Status(id) => {
+                let status = SCHEDULER.critical(|scheduler| {
+                    scheduler.processes()
+                        .find(|p| p.context.tpidr == id)
+                        .map(|p| (p.state.name(), p.vmap.page_count(), p.fds.socket_count()))
+                });
+                match status {
+                    Some((state, pages, sockets)) => {
+                        write_status(&mut out, id, state, pages, sockets)
+                    }
+                    None => return ioerr!(NotFound, "process exited"),
+                }
+            }
+        };
+        result.map_err(|_| io::Error::new(io::ErrorKind::Other, "format error"))?;
+        Ok(out)
+    }
+}
+
+fn write_meminfo(out: &mut String) -> fmt::Result {
+    let stats = ALLOCATOR.stats().unwrap_or_default();
+    writeln!(out, "MemTotal:     {:>10} kB", stats.total / 1024)?;
+    writeln!(out, "MemFree:      {:>10} kB", stats.free() / 1024)?;
+    writeln!(out, "MemAllocated: {:>10} kB", stats.allocated / 1024)?;
+    writeln!(out, "MemUntouched: {:>10} kB", stats.untouched / 1024)
+}
+
+fn write_uptime(out: &mut String) -> fmt::Result {
+    let uptime = pi::timer::current_time();
+    writeln!(out, "{}.{:02}", uptime.as_secs(), uptime.subsec_millis() / 10)
+}
+
+fn write_cpuinfo(out: &mut String) -> fmt::Result {
+    for cpu in 0..NCORES {
+        let info = core_info(cpu);
+        writeln!(out, "processor\t: {}", cpu)?;
+        writeln!(out, "mmu\t\t: {}", if info.mmu_ready { "ready" } else { "off" })?;
+        writeln!(out, "preemption\t: {}", info.preemption)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn write_net_tcp(out: &mut String) -> fmt::Result {
+    // 出力の書式化はソケットセットのロックの外で行う
+    let sockets: Vec<(String, String, String)> = ETHERNET.critical(|driver| {
+        driver.tcp_sockets()
+            .map(|socket| (socket.local_endpoint().to_string(),
+                socket.remote_endpoint().to_string(),
+                socket.state().to_string()))
+            .collect()
+    });
+
+    writeln!(out, "{:>4}  {:<22} {:<22} {}", "sl", "local", "remote", "state")?;
+    for (i, (local, remote, state)) in sockets.iter().enumerate() {
+        writeln!(out, "{:>4}  {:<22} {:<22} {}", i, local, remote, state)?;
+    }
+    Ok(())
+}
+
+fn write_status(out: &mut String, id: Id, state: &str, pages: usize,
+    sockets: usize) -> fmt::Result {
+    writeln!(out, "Pid:     {}", id)?;
+    writeln!(out, "State:   {}", state)?;
+    writeln!(out, "Pages:   {}", pages)?;
+    writeln!(out, "Sockets: {}", sockets)
+}
+
+impl ProcNode {
+    /// ディレクトリのエントリの名前とノードの一覧を返す.
+    fn children(&self) -> io::Result<Vec<(String, ProcNode)>> {
+        let fixed: &[(&str, ProcNode)] = match self {
+            ProcNode::Root => ROOT_ENTRIES,
+            ProcNode::Net => NET_ENTRIES,
+            ProcNode::Process(id) => {
+                return Ok(process_entries(*id).iter()
+                    .map(|&(name, node)| (String::from(name), node))
+                    .collect());
+            }
+            ProcNode::File(_) => return ioerr!(Other, "not a directory"),
+        };
+
+        let mut children: Vec<(String, ProcNode)> = fixed.iter()
+            .map(|&(name, node)| (String::from(name), node))
+            .collect();
+        if let ProcNode::Root = self {
+            for id in process_ids() {
+                children.push((id.to_string(), ProcNode::Process(id)));
+            }
+        }
+        Ok(children)
+    }
+}
+
+impl vfs::Inode for ProcNode {
+    fn stat(&self) -> io::Result<Stat> {
+        let mut stat = match self {
+            ProcNode::File(generator) => {
+                Stat::new(FileType::File, generator.generate()?.len() as u64)
+            }
+            _ => Stat::new(FileType::Dir, 0),
+        };
+        stat.read_only = true;
+        Ok(stat)
+    }
+
+    fn lookup(&self, name: &str) -> io::Result<InodeRef> {
+        if let ProcNode::Root = self {
+            if let Ok(id) = name.parse::<Id>() {
+                if process_ids().contains(&id) {
+                    return Ok(Rc::new(ProcNode::Process(id)));
+                }
+                return ioerr!(NotFound, "no such process");
+            }
+        }
+
+        match self.children()?.into_iter().find(|(n, _)| n == name) {
+            Some((_, node)) => Ok(Rc::new(node)),
+            None => ioerr!(NotFound, "entry not found"),
+        }
+    }
+
+    fn readdir(&self) -> io::Result<Vec<DirEntry>> {
+        Ok(self.children()?
+            .into_iter()
+            .filter_map(|(name, node)| {
+                // 列挙の途中で終了したプロセスは含めない
+                node.stat().ok().map(|stat| DirEntry { name, stat })
+            })
+            .collect())
+    }
+
+    fn open(&self) -> io::Result<Box<dyn FileObject>> {
+        match self {
+            ProcNode::File(generator) => {
+                let data = generator.generate()?.into_bytes();
+                Ok(Box::new(ProcFile(io::Cursor::new(data))))
+            }
+            _ => ioerr!(Other, "not a regular file"),
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// オープンされたprocfsのファイル. オープン時に生成した内容を保持する。
+struct ProcFile(io::Cursor<Vec<u8>>);
+
+impl io::Read for ProcFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl io::Write for ProcFile {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        ioerr!(PermissionDenied, "read only file system")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Seek for ProcFile {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+impl FileObject for ProcFile {
+    fn stat(&self) -> io::Result<Stat> {
+        let mut stat = Stat::new(FileType::File, self.0.get_ref().len() as u64);
+        stat.read_only = true;
+        Ok(stat)
+    }
+}
@@ -1,46 +1,19 @@
-use core::time::Duration;
+use alloc::rc::Rc;
+
 use shim::io;
 use shim::ioerr;
-use pi::timer;
+use pi::emmc::{self, Emmc};
 
 use fat32::traits::BlockDevice;
 
-extern "C" {
-    /// 最後に発生したSDコントローラのエラーを表すグローバル.
-    static sd_err: i64;
-
-    /// SDカードコントローラを初期化する.
-    ///
-    /// 初期化に成功した場合は0を返す。初期化に失敗した場合、
-    /// タイムアウトが発生した場合は-1、SDコントローラへの
-    /// コマンド送信エラーが発生した場合は-2を返す。
-    fn sd_init() -> i32;
-
-    /// SDカードからセクタ `n` (512 バイト) を読み込み、`buffer` に
-    /// 書き込む。`buffer` が少なくとも512バイトのメモリを指していない
-    /// と未定義の動作となる。また、この関数のcallerは `buffer` が
-    /// 少なくとも4バイトアラインされていることを確認する必要がある。
-    ///
-    /// 成功した場合は読み込んだバイト数（正数）を返す。
-    ///
-    /// エラーの場合は0を返す。真のエラーコードはグローバル変数
-    /// `sd_err` に格納される。`sd_err` には、タイムアウトが発生した
-    /// 場合は -1、SDコントローラへのコマンド送信エラーが発生した
-    /// 場合は -2がセットされる。その他のエラーコードも可能であるが
-    /// 0より小さい値としてのみ定義される。
-    fn sd_readsector(n: i32, buffer: *mut u8) -> i32;
-}
-
-// FIXME: Define a `#[no_mangle]` `wait_micros` function for use by `libsd`.
-// The `wait_micros` C signature is: `void wait_micros(unsigned int);`
-#[no_mangle]
-fn wait_micros(micros: u32) {
-    timer::spin_sleep(Duration::from_micros(micros as u64))
-}
+use crate::mutex::Mutex;
+use crate::single_core::SingleCore;
 
 /// SDカードコントローラへのハンドル.
 #[derive(Debug)]
-pub struct Sd();
+pub struct Sd {
+    emmc: Emmc,
+}
 
 impl Sd {
     /// SDカードコントローラを初期化し、そのハンドルを返す。
@@ -50,12 +23,9 @@ impl Sd {
     /// できるがまだメモリ管理ユニット（MMU）を書いていないので
     /// 使えない。
     pub unsafe fn new() -> Result<Sd, io::Error> {
-        match sd_init() {
-            0 => Ok(Sd{}),
-            -1 => ioerr!(TimedOut, "Timeout occured in sd_init()"),
-            -2 => ioerr!(BrokenPipe, "could not send init command"),
-            _ => ioerr!(Other, "unkown initialization error pccured"),
-        }
+        Ok(Sd {
+            emmc: Emmc::new()?,
+        })
     }
 }
 
@@ -66,37 +36,82 @@ impl BlockDevice for Sd {
     ///
     /// # エラー
     ///
-    /// `buf.len() < 512` または  `n > 2^31 - 1` (`i32` の最大値)の
+    /// `buf.len() < 512` またはセクタ番号がカードのアドレス範囲外の
     /// 場合はエラー種別 `InvalidInput` のI/Oエラーが返される。
     ///
     /// SDカードからの読み込み中にタイムアウトが発生した場合は
-    ///  `TimedOut` のエラーが返される。
-    ///
-    /// その他のエラーに対してはエラー種別 `Other` のエラーが返される。
+    ///  `TimedOut` のエラーが、コントローラがエラーを報告した場合は
+    /// `BrokenPipe` のエラーが返される。
     fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
-        if buf.len() < 512 {
+        if buf.len() < emmc::BLOCK_SIZE {
             return ioerr!(InvalidInput, "invalid buf len");
         }
+        self.emmc.read_blocks(n, &mut buf[..emmc::BLOCK_SIZE])
+    }
 
-        if n > i32::max_value() as u64 {
-            return ioerr!(InvalidInput, "invalid sector number");
+    /// `buf` の内容でSDカードのセクタ `n` を上書きする。
+    /// 成功した場合はバイト数が返される。
+    ///
+    /// # エラー
+    ///
+    /// `read_sector()` と同様.
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        if buf.len() < emmc::BLOCK_SIZE {
+            return ioerr!(InvalidInput, "invalid buf len");
         }
+        self.emmc.write_blocks(n, &buf[..emmc::BLOCK_SIZE])
+    }
 
-        let result = unsafe { sd_readsector(n as i32, buf.as_mut_ptr()) };
+    /// セクタ `n` から始まる連続したセクタを `buf` に読み込む. 最大
+    /// `emmc::MAX_BLOCKS` ブロックずつマルチブロック転送で読み込む。
+    fn read_sectors(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+        for chunk in buf.chunks_mut(emmc::MAX_BLOCKS * emmc::BLOCK_SIZE) {
+            read += self.emmc.read_blocks(n + (read / emmc::BLOCK_SIZE) as u64, chunk)?;
+        }
+        Ok(read)
+    }
 
-        if result == 0 {
-            return match unsafe { sd_err } {
-                -1 => ioerr!(TimedOut, "sd read timed out"),
-                -2 => ioerr!(BrokenPipe, "could not send command"),
-                _ => ioerr!(Other, "unknown read error")
-            }
+    /// `buf` の内容でセクタ `n` から始まる連続したセクタを上書きする. 最大
+    /// `emmc::MAX_BLOCKS` ブロックずつマルチブロック転送で書き込む。
+    fn write_sectors(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        let mut written = 0;
+        for chunk in buf.chunks(emmc::MAX_BLOCKS * emmc::BLOCK_SIZE) {
+            written += self.emmc.write_blocks(n + (written / emmc::BLOCK_SIZE) as u64, chunk)?;
         }
+        Ok(written)
+    }
+}
+
+/// 複数の所有者で共有できる `Sd` へのハンドル. FATファイルシステムと
+/// devfsのブロックデバイスが同じカードを使用するために用いる。
+///
+/// FATファイルシステムはセクタをキャッシュするため、マウント中の
+/// パーティションをブロックデバイスとして書き換えた場合の結果は
+/// 保証されない。
+#[derive(Clone, Debug)]
+pub struct SharedSd(SingleCore<Rc<Mutex<Sd>>>);
 
-        Ok(512)
+impl SharedSd {
+    pub fn new(sd: Sd) -> SharedSd {
+        SharedSd(SingleCore::new(Rc::new(Mutex::new(sd))))
+    }
+}
+
+impl BlockDevice for SharedSd {
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.lock().read_sector(n, buf)
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().write_sector(n, buf)
+    }
 
+    fn read_sectors(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.lock().read_sectors(n, buf)
     }
 
-    fn write_sector(&mut self, _n: u64, _buf: &[u8]) -> io::Result<usize> {
-        unimplemented!("SD card and file system are read only")
+    fn write_sectors(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().write_sectors(n, buf)
     }
 }
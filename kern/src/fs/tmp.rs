@@ -0,0 +1,53 @@
+use alloc::rc::Rc;
+
+use shim::io;
+
+use super::path::{PathFs, PathInode};
+use super::vfs::{self, InodeRef, StatFs};
+use super::PiTmpFsHandle;
+
+/// `statfs()` が報告するブロックのバイト数.
+const BLOCK_SIZE: u64 = 512;
+
+/// VFSにマウントするtmpfs. ファイルの内容はカーネルヒープに置かれる。
+pub struct TmpFs {
+    tmpfs: tmpfs::TmpFs<PiTmpFsHandle>,
+}
+
+impl TmpFs {
+    /// 空のtmpfsを作成する. ファイルの内容の合計は `limit` バイトまでに
+    /// 制限される。
+    pub fn new(limit: u64) -> TmpFs {
+        TmpFs { tmpfs: tmpfs::TmpFs::new(Some(limit)) }
+    }
+}
+
+impl PathFs for tmpfs::TmpFs<PiTmpFsHandle> {
+    type File = tmpfs::File<PiTmpFsHandle>;
+    type Dir = tmpfs::Dir<PiTmpFsHandle>;
+    type Entry = tmpfs::Entry<PiTmpFsHandle>;
+
+    fn same_fs(&self, other: &tmpfs::TmpFs<PiTmpFsHandle>) -> bool {
+        Rc::ptr_eq(&*self.handle().0, &*other.handle().0)
+    }
+}
+
+impl vfs::FileSystem for TmpFs {
+    fn name(&self) -> &str {
+        "tmpfs"
+    }
+
+    fn root(&self) -> io::Result<InodeRef> {
+        Ok(Rc::new(PathInode::root(self.tmpfs.clone())))
+    }
+
+    fn statfs(&self) -> io::Result<StatFs> {
+        let limit = self.tmpfs.limit().unwrap_or(0);
+        let used = self.tmpfs.used_bytes();
+        Ok(StatFs {
+            block_size: BLOCK_SIZE,
+            total_blocks: limit / BLOCK_SIZE,
+            free_blocks: limit.saturating_sub(used) / BLOCK_SIZE,
+        })
+    }
+}
@@ -0,0 +1,330 @@
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::fmt;
+
+use shim::io;
+use shim::ioerr;
+use shim::path::{Component, Path, PathBuf};
+
+use crate::single_core::SingleCore;
+
+/// ノードの種類.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FileType {
+    File,
+    Dir,
+    Device,
+}
+
+/// ノードの変更日時.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:0>4}-{:0>2}-{:0>2} {:0>2}:{:0>2}:{:0>2}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second)
+    }
+}
+
+/// ノードの属性.
+#[derive(Copy, Clone, Debug)]
+pub struct Stat {
+    pub kind: FileType,
+    /// バイト単位のサイズ. ディレクトリとキャラクタデバイスでは0.
+    pub size: u64,
+    pub read_only: bool,
+    pub hidden: bool,
+    pub modified: DateTime,
+}
+
+impl Stat {
+    /// 属性がすべてデフォルト値の、種類 `kind` のノードの属性を返す.
+    pub fn new(kind: FileType, size: u64) -> Stat {
+        Stat {
+            kind,
+            size,
+            read_only: false,
+            hidden: false,
+            modified: DateTime::default(),
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.kind == FileType::Dir
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.kind == FileType::File
+    }
+}
+
+/// `Inode::readdir()` が返すディレクトリのエントリ.
+#[derive(Clone, Debug)]
+pub struct DirEntry {
+    pub name: String,
+    pub stat: Stat,
+}
+
+/// ファイルシステムの使用状況.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct StatFs {
+    /// 割り当て単位のバイト数.
+    pub block_size: u64,
+    pub total_blocks: u64,
+    pub free_blocks: u64,
+}
+
+impl StatFs {
+    pub fn total_bytes(&self) -> u64 {
+        self.total_blocks * self.block_size
+    }
+
+    pub fn free_bytes(&self) -> u64 {
+        self.free_blocks * self.block_size
+    }
+
+    pub fn used_bytes(&self) -> u64 {
+        self.total_bytes() - self.free_bytes()
+    }
+}
+
+/// 共有されるノードへの参照.
+pub type InodeRef = Rc<dyn Inode>;
+
+/// オープンされたファイル.
+///
+/// 読み書きの位置はファイルオブジェクトごとに保持される。
+pub trait FileObject: io::Read + io::Write + io::Seek {
+    /// このファイルの属性を返す.
+    fn stat(&self) -> io::Result<Stat>;
+
+    /// バッファされているデータを書き出す.
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// ファイルシステム内のノード（ファイル、ディレクトリ、デバイス）.
+///
+/// ディレクトリ以外のノードではディレクトリ操作はエラーを返し、
+/// 読み込み専用のファイルシステムでは変更操作は `PermissionDenied`
+/// エラーを返す。`name` はパスではなく1つのコンポーネントである。
+pub trait Inode {
+    /// このノードの属性を返す.
+    fn stat(&self) -> io::Result<Stat>;
+
+    /// ディレクトリから名前 `name` のノードを探す.
+    ///
+    /// # エラー
+    ///
+    /// `name` が存在しない場合は `NotFound` を返す。
+    fn lookup(&self, _name: &str) -> io::Result<InodeRef> {
+        ioerr!(Other, "not a directory")
+    }
+
+    /// ディレクトリのエントリの一覧を返す.
+    fn readdir(&self) -> io::Result<Vec<DirEntry>> {
+        ioerr!(Other, "not a directory")
+    }
+
+    /// ファイルまたはデバイスをオープンする.
+    fn open(&self) -> io::Result<Box<dyn FileObject>> {
+        ioerr!(Other, "not a regular file")
+    }
+
+    /// ディレクトリに種類 `kind` の空のノード `name` を作成する.
+    ///
+    /// # エラー
+    ///
+    /// `name` がすでに存在する場合は `AlreadyExists` を返す。
+    fn create(&self, _name: &str, _kind: FileType) -> io::Result<InodeRef> {
+        ioerr!(PermissionDenied, "read only file system")
+    }
+
+    /// ディレクトリからファイルまたは空のディレクトリ `name` を削除する.
+    fn remove(&self, _name: &str) -> io::Result<()> {
+        ioerr!(PermissionDenied, "read only file system")
+    }
+
+    /// ディレクトリのエントリ `from` をディレクトリ `to_dir` のエントリ
+    /// `to` に移動する. `to_dir` は同じファイルシステムのノードである。
+    fn rename(&self, _from: &str, _to_dir: &dyn Inode, _to: &str) -> io::Result<()> {
+        ioerr!(PermissionDenied, "read only file system")
+    }
+
+    /// 実装の型へのダウンキャストに使用する.
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// マウントできるファイルシステム.
+pub trait FileSystem {
+    /// ファイルシステムの種類の名前 (`"vfat"` など).
+    fn name(&self) -> &str;
+
+    /// ルートディレクトリを返す.
+    fn root(&self) -> io::Result<InodeRef>;
+
+    /// ファイルシステムの使用状況を返す.
+    fn statfs(&self) -> io::Result<StatFs> {
+        Ok(StatFs::default())
+    }
+
+    /// 変更をすべて書き出す.
+    fn sync(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// 絶対パス `path` から `.` と `..` を取り除いて正規化する.
+/// ルートの `..` はルートを指す。
+///
+/// # エラー
+///
+/// `path` が絶対パスでない場合は `InvalidInput` を返す。
+pub fn normalize(path: &Path) -> io::Result<PathBuf> {
+    if !path.is_absolute() {
+        return ioerr!(InvalidInput, "path is not absolute");
+    }
+
+    let mut normalized = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            _ => {}
+        }
+    }
+    Ok(normalized)
+}
+
+/// マウントされたファイルシステム.
+#[derive(Clone)]
+pub struct Mount {
+    /// 正規化されたマウントポイント.
+    pub path: PathBuf,
+    pub fs: Rc<dyn FileSystem>,
+}
+
+/// マウントポイントのパスとファイルシステムの対応表.
+pub struct MountTable {
+    mounts: SingleCore<Vec<Mount>>,
+}
+
+impl MountTable {
+    pub const fn new() -> MountTable {
+        MountTable { mounts: SingleCore::new(Vec::new()) }
+    }
+
+    /// `fs` をパス `path` にマウントする.
+    ///
+    /// # エラー
+    ///
+    /// `path` にすでにファイルシステムがマウントされている場合は
+    /// `AlreadyExists` を返す。
+    pub fn mount(&mut self, path: &Path, fs: Rc<dyn FileSystem>) -> io::Result<()> {
+        let path = normalize(path)?;
+        if self.mounts.iter().any(|m| m.path == path) {
+            return ioerr!(AlreadyExists, "file system already mounted");
+        }
+
+        self.mounts.push(Mount { path, fs });
+        Ok(())
+    }
+
+    /// パス `path` にマウントされているファイルシステムをアンマウントして
+    /// 返す.
+    ///
+    /// # エラー
+    ///
+    /// `path` にファイルシステムがマウントされていない場合は `NotFound` を、
+    /// `path` の下に別のファイルシステムがマウントされている場合は
+    /// `PermissionDenied` を返す。
+    pub fn unmount(&mut self, path: &Path) -> io::Result<Rc<dyn FileSystem>> {
+        let path = normalize(path)?;
+        let index = match self.mounts.iter().position(|m| m.path == path) {
+            Some(index) => index,
+            None => return ioerr!(NotFound, "not a mount point"),
+        };
+        if self.mounts.iter().any(|m| m.path != path && m.path.starts_with(&path)) {
+            return ioerr!(PermissionDenied, "file system is busy");
+        }
+
+        Ok(self.mounts.remove(index).fs)
+    }
+
+    /// マウントされているファイルシステムの一覧を返す.
+    pub fn mounts(&self) -> &[Mount] {
+        &self.mounts
+    }
+
+    /// `path` を含むファイルシステムを探し、そのマウントとマウント
+    /// ポイントからの相対パスを返す. マウントポイントが最も長く一致する
+    /// ファイルシステムが選ばれる。
+    ///
+    /// # エラー
+    ///
+    /// `path` が絶対パスでない場合は `InvalidInput` を、`path` を含む
+    /// ファイルシステムがない場合は `NotFound` を返す。
+    pub fn find(&self, path: &Path) -> io::Result<(Mount, PathBuf)> {
+        let path = normalize(path)?;
+        let mount = match self.mounts.iter()
+            .filter(|m| path.starts_with(&m.path))
+            .max_by_key(|m| m.path.components().count()) {
+            Some(mount) => mount,
+            None => return ioerr!(NotFound, "no file system mounted"),
+        };
+
+        let rest = path.strip_prefix(&mount.path).expect("prefix matched").to_path_buf();
+        Ok((mount.clone(), rest))
+    }
+
+    /// `path` がマウントポイントか否かを返す.
+    pub fn is_mount_point(&self, path: &Path) -> bool {
+        match normalize(path) {
+            Ok(path) => self.mounts.iter().any(|m| m.path == path),
+            Err(_) => false,
+        }
+    }
+
+    /// ディレクトリ `dir` の直下にあるマウントポイントの名前を返す.
+    pub fn children(&self, dir: &Path) -> Vec<String> {
+        let dir = match normalize(dir) {
+            Ok(dir) => dir,
+            Err(_) => return Vec::new(),
+        };
+        self.mounts.iter()
+            .filter(|m| m.path.parent() == Some(dir.as_path()))
+            .filter_map(|m| m.path.file_name().and_then(|n| n.to_str()).map(String::from))
+            .collect()
+    }
+}
+
+impl Mount {
+    /// マウントポイントからの相対パス `rest` のノードを、ファイルシステムの
+    /// ルートからコンポーネントごとに辿って返す.
+    ///
+    /// # エラー
+    ///
+    /// ノードが存在しない場合は `NotFound` を返す。
+    pub fn lookup(&self, rest: &Path) -> io::Result<InodeRef> {
+        let mut inode = self.fs.root()?;
+        for component in rest.components() {
+            let name = component.as_os_str().to_str().ok_or(io::ErrorKind::InvalidInput)?;
+            inode = inode.lookup(name)?;
+        }
+        Ok(inode)
+    }
+}
@@ -28,6 +28,7 @@ pub mod param;
 pub mod percore;
 pub mod process;
 pub mod shell;
+pub mod single_core;
 pub mod traps;
 pub mod vm;
 
@@ -13,7 +13,7 @@ use aarch64::affinity;
 
 use smoltcp::iface::{EthernetInterfaceBuilder, NeighborCache};
 use smoltcp::phy::{self, Device, DeviceCapabilities};
-use smoltcp::socket::{SocketHandle, SocketRef, TcpSocketBuffer};
+use smoltcp::socket::{Socket, SocketHandle, SocketRef, TcpSocketBuffer};
 use smoltcp::time::Instant;
 use smoltcp::wire::{IpAddress, IpCidr};
 
@@ -253,6 +253,14 @@ impl EthernetDriver {
         self.socket_set.add(tcp_socket)
     }
 
+    /// 内部ソケットセットのすべてのTCPソケットを走査するイテレータを返す.
+    pub fn tcp_sockets(&self) -> impl Iterator<Item = &TcpSocket> + '_ {
+        self.socket_set.iter().filter_map(|socket| match socket {
+            Socket::Tcp(socket) => Some(socket),
+            _ => None,
+        })
+    }
+
     /// 内部ソケットセットからソケットを解放する.
     pub fn release(&mut self, handle: SocketHandle) {
         self.socket_set.release(handle);
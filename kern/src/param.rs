@@ -27,6 +27,13 @@ pub const USER_MAX_VM_SIZE: usize = 0x4000_0000;
 pub const USER_MAX_VM: usize = 0xFFFF_FFFF_FFFF_FFFF;
 // 0xFFFF_FFFF_FFFF_0000
 pub const USER_STACK_BASE: usize = USER_MAX_VM & PAGE_MASK;
+/// ユーザスタックの最大サイズ (1MiB). `USER_STACK_BASE` のページ以外は
+/// スタックが伸びて最初にアクセスしたときに割り当てる。
+pub const USER_STACK_SIZE: usize = 16 * PAGE_SIZE;
+/// ユーザスタックが伸びることのできる最下位のアドレス.
+pub const USER_STACK_LIMIT: usize = USER_MAX_VM - USER_STACK_SIZE + 1;
+/// プロセスのヒープと `mmap` で割り当てる領域の合計サイズの上限 (256MiB).
+pub const USER_MAX_DATA_SIZE: usize = 256 * 1024 * 1024;
 
 const_assert_eq!(USER_IMG_BASE.wrapping_add(USER_MAX_VM_SIZE), 0);
 
@@ -45,3 +52,15 @@ pub const USPI_TIMER_HZ: usize = 10;
 // Match this value with `USPI_FRAME_BUFFER_SIZE` in `uspi.h`
 pub const USPI_FRAME_BUFFER_SIZE: u32 = 1600;
 pub const MTU: u32 = 1500;
+
+/// `/tmp` にマウントするtmpfsの容量 (16MiB).
+pub const TMPFS_LIMIT: u64 = 16 * 1024 * 1024;
+
+/// プロセスが同時にオープンできるファイルディスクリプタの数.
+pub const MAX_FDS: usize = 64;
+
+/// 最初に起動するプロセスのID. 親プロセスが終了したプロセスを引き継ぐ。
+pub const INIT_PID: u64 = 1;
+
+/// `exec` でプロセスに渡す引数の合計サイズ (文字列とポインタの配列) の上限.
+pub const ARG_MAX: usize = PAGE_SIZE / 4;
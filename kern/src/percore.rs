@@ -37,6 +37,24 @@ static PER_CORE_DATA: [PerCore; NCORES] = [
     },
 ];
 
+/// コアの状態のスナップショット.
+#[derive(Debug, Clone, Copy)]
+pub struct CoreInfo {
+    /// コアで保持しているロックの数
+    pub preemption: i64,
+    /// コアのMMUは初期化されているか?
+    pub mmu_ready: bool,
+}
+
+/// コア `cpu` の状態を返す.
+pub fn core_info(cpu: usize) -> CoreInfo {
+    let data = &PER_CORE_DATA[cpu];
+    CoreInfo {
+        preemption: data.preemption.load(Ordering::Relaxed),
+        mmu_ready: data.mmu_ready.load(Ordering::Relaxed),
+    }
+}
+
 /// このコアの現在のプリエンプションカウンタを返す.
 pub fn get_preemptive_counter() -> i64 {
     let cpu = aarch64::affinity();
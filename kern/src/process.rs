@@ -1,9 +1,14 @@
+mod elf;
+mod fd;
 mod process;
+mod region;
 mod scheduler;
 mod stack;
 mod state;
 
-pub use self::process::{Id, Process};
+pub use self::fd::{socket_error, to_api_stat, Descriptor, DescriptorRef, FdTable};
+pub use self::process::{Id, Image, Process, Zombie};
+pub use self::region::{Region, RegionKind};
 pub use self::scheduler::GlobalScheduler;
 pub use self::stack::Stack;
 pub use self::state::State;
@@ -0,0 +1,217 @@
+use alloc::vec::Vec;
+use core::cmp::{max, min};
+use core::mem;
+
+use shim::const_assert_size;
+use shim::io::{self, Read, Seek, SeekFrom};
+
+use kernel_api::{OsError, OsResult};
+
+use crate::allocator::util::align_down;
+use crate::param::{PAGE_SIZE, USER_IMG_BASE, USER_STACK_LIMIT};
+use crate::process::{Region, RegionKind};
+use crate::vm::{PagePerm, UserPageTable, VirtualAddr};
+
+/// `e_ident` の先頭のマジックナンバー.
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+/// `e_ident[EI_CLASS]`: 64ビットオブジェクト.
+const ELFCLASS64: u8 = 2;
+/// `e_ident[EI_DATA]`: リトルエンディアン.
+const ELFDATA2LSB: u8 = 1;
+/// `e_ident[EI_VERSION]` と `e_version`: 現行バージョン.
+const EV_CURRENT: u8 = 1;
+/// `e_type`: 実行可能ファイル.
+const ET_EXEC: u16 = 2;
+/// `e_machine`: AArch64.
+const EM_AARCH64: u16 = 183;
+
+/// `p_type`: ロードするセグメント.
+const PT_LOAD: u32 = 1;
+/// `p_flags`: 実行可能.
+const PF_X: u32 = 1;
+/// `p_flags`: 書き込み可能.
+const PF_W: u32 = 2;
+
+/// ELF64ファイルヘッダ.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ElfHeader {
+    ident: [u8; 16],
+    kind: u16,
+    machine: u16,
+    version: u32,
+    entry: u64,
+    phoff: u64,
+    shoff: u64,
+    flags: u32,
+    ehsize: u16,
+    phentsize: u16,
+    phnum: u16,
+    shentsize: u16,
+    shnum: u16,
+    shstrndx: u16,
+}
+const_assert_size!(ElfHeader, 64);
+
+/// ELF64プログラムヘッダ.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ProgramHeader {
+    kind: u32,
+    flags: u32,
+    offset: u64,
+    vaddr: u64,
+    paddr: u64,
+    filesz: u64,
+    memsz: u64,
+    align: u64,
+}
+const_assert_size!(ProgramHeader, 56);
+
+/// `buf` を埋めるまで読み込む. ファイルが途中で終わっている場合は
+/// `OsError::InvalidArgument` を返す。
+fn read_exact<R: Read + ?Sized>(file: &mut R, buf: &mut [u8]) -> OsResult<()> {
+    file.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => OsError::InvalidArgument,
+        _ => OsError::from(e),
+    })
+}
+
+impl ElfHeader {
+    /// ファイルの先頭からヘッダを読み込み、AArch64の実行可能ファイルで
+    /// あることを検証する.
+    fn read<R: Read + Seek + ?Sized>(file: &mut R) -> OsResult<ElfHeader> {
+        let mut buf = [0u8; 64];
+        file.seek(SeekFrom::Start(0))?;
+        read_exact(file, &mut buf)?;
+        let header: ElfHeader = unsafe { mem::transmute(buf) };
+
+        if header.ident[..4] != ELF_MAGIC
+            || header.ident[4] != ELFCLASS64
+            || header.ident[5] != ELFDATA2LSB
+            || header.ident[6] != EV_CURRENT
+            || header.kind != ET_EXEC
+            || header.machine != EM_AARCH64
+            || header.version != EV_CURRENT as u32
+            || header.phentsize as usize != mem::size_of::<ProgramHeader>()
+        {
+            return Err(OsError::InvalidArgument);
+        }
+        Ok(header)
+    }
+
+    /// プログラムヘッダをすべて読み込む.
+    fn program_headers<R: Read + Seek + ?Sized>(&self, file: &mut R)
+        -> OsResult<Vec<ProgramHeader>> {
+        file.seek(SeekFrom::Start(self.phoff))?;
+        let mut headers = Vec::with_capacity(self.phnum as usize);
+        for _ in 0..self.phnum {
+            let mut buf = [0u8; 56];
+            read_exact(file, &mut buf)?;
+            headers.push(unsafe { mem::transmute::<_, ProgramHeader>(buf) });
+        }
+        Ok(headers)
+    }
+}
+
+impl ProgramHeader {
+    /// セグメントをロードする仮想アドレスの範囲 `[start, end)` を返す.
+    /// 範囲がスタックを除くユーザ空間に収まらない場合や、ファイル上の
+    /// サイズがメモリ上のサイズより大きい場合は `OsError::InvalidArgument`
+    /// を返す。
+    fn range(&self) -> OsResult<(usize, usize)> {
+        let start = self.vaddr as usize;
+        let end = start.checked_add(self.memsz as usize).ok_or(OsError::InvalidArgument)?;
+        if self.filesz > self.memsz || start < USER_IMG_BASE || end > USER_STACK_LIMIT {
+            return Err(OsError::InvalidArgument);
+        }
+        Ok((start, end))
+    }
+
+    /// `p_flags` に対応するページの権限. テキストは `RX`、データは `RW` になる。
+    fn perm(&self) -> PagePerm {
+        PagePerm::new(self.flags & PF_W != 0, self.flags & PF_X != 0)
+    }
+}
+
+/// `file` のELF64実行可能ファイルの `PT_LOAD` セグメントを `vmap` に
+/// ロードし、エントリポイントとセグメントの領域を返す. `.bss` のように
+/// ファイル上にない部分のページは割り当てず、最初にアクセスしたときに
+/// 領域から0で埋めたページを割り当てる。
+///
+/// # エラー
+///
+/// AArch64の実行可能ファイルでない場合、セグメントがユーザ空間に
+/// 収まらない場合、エントリポイントが実行可能なセグメントにない場合は
+/// `OsError::InvalidArgument` を、ページを割り当てられない場合は
+/// `OsError::NoMemory` を返す。
+pub fn load<R: Read + Seek + ?Sized>(file: &mut R, vmap: &mut UserPageTable)
+    -> OsResult<(VirtualAddr, Vec<Region>)> {
+    let header = ElfHeader::read(file)?;
+    let segments: Vec<ProgramHeader> = header.program_headers(file)?
+        .into_iter()
+        .filter(|ph| ph.kind == PT_LOAD)
+        .collect();
+
+    let entry = header.entry as usize;
+    let mut entry_found = false;
+    for ph in segments.iter() {
+        let (start, end) = ph.range()?;
+        if ph.flags & PF_X != 0 && start <= entry && entry < end {
+            entry_found = true;
+        }
+    }
+    if !entry_found {
+        return Err(OsError::InvalidArgument);
+    }
+
+    let mut regions = Vec::with_capacity(segments.len());
+    for ph in segments.iter() {
+        load_segment(file, vmap, ph)?;
+        let (start, end) = ph.range()?;
+        regions.push(Region::new(start, end - start, ph.perm(), RegionKind::Image));
+    }
+    // 複数のセグメントが共有するページは両方のセグメントの権限を合わせた
+    // 権限にする
+    for region in regions.iter() {
+        for page in (region.start..region.start + region.size).step_by(PAGE_SIZE) {
+            let va = VirtualAddr::from(page);
+            if let Some(perm) = vmap.perm(va) {
+                vmap.set_perm(va, perm.union(region.perm));
+            }
+        }
+    }
+    Ok((VirtualAddr::from(entry), regions))
+}
+
+/// セグメント `ph` のファイル上にある部分を含むページを割り当て、ファイルの
+/// 内容をコピーする. 他のセグメントとページを共有する場合は割り当て済みの
+/// ページを使う。
+fn load_segment<R: Read + Seek + ?Sized>(file: &mut R, vmap: &mut UserPageTable,
+    ph: &ProgramHeader) -> OsResult<()> {
+    let (start, end) = ph.range()?;
+    let file_end = start + ph.filesz as usize;
+
+    for page_start in (align_down(start, PAGE_SIZE)..end).step_by(PAGE_SIZE) {
+        let va = VirtualAddr::from(page_start);
+        let copy_start = max(start, page_start);
+        let copy_end = min(file_end, page_start + PAGE_SIZE);
+        if !vmap.is_mapped(va) {
+            // ファイル上にない部分だけのページはデマンドページングに任せる
+            if copy_start >= copy_end {
+                continue;
+            }
+            let bytes = vmap.alloc(va, ph.perm()).ok_or(OsError::NoMemory)?;
+            for byte in bytes.iter_mut() {
+                *byte = 0;
+            }
+        }
+        let page = vmap.get_page_mut(va).unwrap();
+
+        if copy_start < copy_end {
+            file.seek(SeekFrom::Start(ph.offset + (copy_start - start) as u64))?;
+            read_exact(file, &mut page[copy_start - page_start..copy_end - page_start])?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,283 @@
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::fmt;
+
+use shim::io::{self, SeekFrom};
+use shim::path::Path;
+use smoltcp::socket::SocketHandle;
+
+use kernel_api::{FileKind, OsError, OsResult, O_CREAT};
+
+use crate::fs::vfs::{DirEntry, FileObject, FileType, Stat};
+use crate::mutex::Mutex;
+use crate::param::MAX_FDS;
+use crate::single_core::SingleCore;
+use crate::{ETHERNET, FILESYSTEM};
+
+/// ファイルディスクリプタが指すオブジェクト.
+pub enum Descriptor {
+    /// オープンされたファイルまたはデバイス.
+    File(Box<dyn FileObject>),
+    /// オープンされたディレクトリ. エントリの一覧はオープン時に取得する。
+    Dir {
+        stat: Stat,
+        entries: Vec<DirEntry>,
+        next: usize,
+    },
+    /// TCPソケット.
+    Socket(SocketHandle),
+}
+
+/// 複数のディスクリプタで共有されるオブジェクトへの参照.
+pub type DescriptorRef = Rc<Mutex<Descriptor>>;
+
+/// `io::Result` を `OsResult` に変換する.
+fn os<T>(result: io::Result<T>) -> OsResult<T> {
+    result.map_err(OsError::from)
+}
+
+/// smoltcpのエラーを `OsError` に変換する.
+pub fn socket_error(error: smoltcp::Error) -> OsError {
+    match error {
+        smoltcp::Error::Illegal => OsError::IllegalSocketOperation,
+        smoltcp::Error::Unaddressable => OsError::BadAddress,
+        _ => OsError::Unknown,
+    }
+}
+
+/// `base` に符号付きの `delta` を加える. 結果が負またはオーバーフローする
+/// 場合は `None` を返す。
+fn add_signed(base: u64, delta: i64) -> Option<u64> {
+    if delta >= 0 {
+        base.checked_add(delta as u64)
+    } else {
+        base.checked_sub(delta.wrapping_neg() as u64)
+    }
+}
+
+impl Descriptor {
+    /// パス `path` のファイル、デバイス、またはディレクトリをオープンする.
+    /// `flags` に `O_CREAT` が含まれる場合、存在しないファイルは作成する。
+    pub fn open<P: AsRef<Path>>(path: P, flags: u64) -> OsResult<Descriptor> {
+        let path = path.as_ref();
+        match FILESYSTEM.stat(path) {
+            Ok(stat) if stat.is_dir() => Ok(Descriptor::Dir {
+                stat,
+                entries: os(FILESYSTEM.readdir(path))?,
+                next: 0,
+            }),
+            Ok(_) => Ok(Descriptor::File(os(FILESYSTEM.open(path))?)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound && flags & O_CREAT != 0 => {
+                Ok(Descriptor::File(os(FILESYSTEM.create_file(path))?))
+            }
+            Err(e) => Err(OsError::from(e)),
+        }
+    }
+
+    /// `buf` に読み込み、読み込んだバイト数を返す. ソケットからは
+    /// 受信済みのデータを読み込む。
+    pub fn read(&mut self, buf: &mut [u8]) -> OsResult<usize> {
+        match self {
+            Descriptor::File(file) => os(file.read(buf)),
+            Descriptor::Dir { .. } => Err(OsError::InvalidArgument),
+            Descriptor::Socket(handle) => ETHERNET.critical(|driver| {
+                driver.get_socket(*handle).recv_slice(buf).map_err(socket_error)
+            }),
+        }
+    }
+
+    /// `buf` を書き込み、書き込んだバイト数を返す. ソケットには送信
+    /// バッファに入るだけ書き込む。
+    pub fn write(&mut self, buf: &[u8]) -> OsResult<usize> {
+        match self {
+            Descriptor::File(file) => os(file.write(buf)),
+            Descriptor::Dir { .. } => Err(OsError::InvalidArgument),
+            Descriptor::Socket(handle) => ETHERNET.critical(|driver| {
+                driver.get_socket(*handle).send_slice(buf).map_err(socket_error)
+            }),
+        }
+    }
+
+    /// 読み書きの位置を変更し、新しい位置を返す. 通常のファイルでは
+    /// ファイルの終端より後ろにはシークできない。
+    pub fn seek(&mut self, pos: SeekFrom) -> OsResult<u64> {
+        let file = match self {
+            Descriptor::File(file) => file,
+            _ => return Err(OsError::InvalidArgument),
+        };
+        let stat = os(file.stat())?;
+        if stat.kind != FileType::File {
+            return os(file.seek(pos));
+        }
+
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => {
+                let current = os(file.seek(SeekFrom::Current(0)))?;
+                add_signed(current, delta)
+            }
+            SeekFrom::End(delta) => add_signed(stat.size, delta),
+        };
+        match target {
+            Some(target) if target <= stat.size => os(file.seek(SeekFrom::Start(target))),
+            _ => Err(OsError::InvalidArgument),
+        }
+    }
+
+    /// 属性を返す.
+    pub fn stat(&self) -> OsResult<kernel_api::Stat> {
+        match self {
+            Descriptor::File(file) => Ok(to_api_stat(&os(file.stat())?)),
+            Descriptor::Dir { stat, .. } => Ok(to_api_stat(stat)),
+            Descriptor::Socket(_) => Ok(kernel_api::Stat {
+                kind: FileKind::Socket,
+                ..kernel_api::Stat::default()
+            }),
+        }
+    }
+
+    /// ディレクトリの次のエントリを返す. 最後のエントリを読み終えている
+    /// 場合は `None` を返す。
+    pub fn next_entry(&mut self) -> OsResult<Option<DirEntry>> {
+        match self {
+            Descriptor::Dir { entries, next, .. } => {
+                let entry = entries.get(*next).cloned();
+                if entry.is_some() {
+                    *next += 1;
+                }
+                Ok(entry)
+            }
+            _ => Err(OsError::InvalidArgument),
+        }
+    }
+}
+
+impl Drop for Descriptor {
+    fn drop(&mut self) {
+        if let Descriptor::Socket(handle) = *self {
+            ETHERNET.critical(|driver| {
+                driver.get_socket(handle).close();
+                driver.release(handle);
+                driver.prune();
+            });
+        }
+    }
+}
+
+impl fmt::Debug for Descriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Descriptor::File(_) => write!(f, "File"),
+            Descriptor::Dir { next, entries, .. } => {
+                write!(f, "Dir({}/{})", next, entries.len())
+            }
+            Descriptor::Socket(handle) => write!(f, "Socket({:?})", handle),
+        }
+    }
+}
+
+/// VFSのノードの属性をシステムコールで返す属性に変換する.
+pub fn to_api_stat(stat: &Stat) -> kernel_api::Stat {
+    kernel_api::Stat {
+        kind: match stat.kind {
+            FileType::File => FileKind::File,
+            FileType::Dir => FileKind::Dir,
+            FileType::Device => FileKind::Device,
+        },
+        read_only: stat.read_only,
+        hidden: stat.hidden,
+        size: stat.size,
+    }
+}
+
+/// プロセスのファイルディスクリプタの表. ディスクリプタは表の添字である。
+#[derive(Debug, Default)]
+pub struct FdTable {
+    fds: SingleCore<Vec<Option<DescriptorRef>>>,
+}
+
+impl FdTable {
+    pub fn new() -> FdTable {
+        FdTable { fds: SingleCore::new(Vec::new()) }
+    }
+
+    /// `/dev/console` を標準入力、標準出力、標準エラー出力として
+    /// オープンした表を返す.
+    pub fn with_console() -> OsResult<FdTable> {
+        let console = Rc::new(Mutex::new(Descriptor::open("/dev/console", 0)?));
+        let mut table = FdTable::new();
+        for _ in 0..3 {
+            table.insert_ref(console.clone())?;
+        }
+        Ok(table)
+    }
+
+    /// `fork` で作成する子プロセスの表を返す. 各ディスクリプタは
+    /// この表と同じオブジェクトを指す。
+    pub fn fork(&self) -> FdTable {
+        FdTable { fds: self.fds.clone() }
+    }
+
+    /// `descriptor` を空いている最小のディスクリプタに割り当てて返す.
+    ///
+    /// # エラー
+    ///
+    /// ディスクリプタが `MAX_FDS` 個使用中の場合は
+    /// `OsError::TooManyDescriptors` を返す。
+    pub fn insert(&mut self, descriptor: Descriptor) -> OsResult<u64> {
+        self.insert_ref(Rc::new(Mutex::new(descriptor)))
+    }
+
+    /// 共有されているオブジェクト `descriptor` を空いている最小の
+    /// ディスクリプタに割り当てて返す.
+    pub fn insert_ref(&mut self, descriptor: DescriptorRef) -> OsResult<u64> {
+        if let Some(fd) = self.fds.iter().position(|d| d.is_none()) {
+            self.fds[fd] = Some(descriptor);
+            return Ok(fd as u64);
+        }
+        if self.fds.len() >= MAX_FDS {
+            return Err(OsError::TooManyDescriptors);
+        }
+        self.fds.push(Some(descriptor));
+        Ok((self.fds.len() - 1) as u64)
+    }
+
+    /// ディスクリプタ `fd` が指すオブジェクトを返す.
+    ///
+    /// # エラー
+    ///
+    /// `fd` がオープンされていない場合は `OsError::BadDescriptor` を返す。
+    pub fn get(&self, fd: u64) -> OsResult<DescriptorRef> {
+        match self.fds.get(fd as usize) {
+            Some(Some(descriptor)) => Ok(descriptor.clone()),
+            _ => Err(OsError::BadDescriptor),
+        }
+    }
+
+    /// ディスクリプタ `fd` を閉じ、指していたオブジェクトを返す.
+    /// オブジェクトは最後の参照がなくなったときに解放される。
+    pub fn remove(&mut self, fd: u64) -> OsResult<DescriptorRef> {
+        match self.fds.get_mut(fd as usize).and_then(|d| d.take()) {
+            Some(descriptor) => Ok(descriptor),
+            None => Err(OsError::BadDescriptor),
+        }
+    }
+
+    /// ソケットを指すディスクリプタの数を返す.
+    ///
+    /// コンソールの読み込みなどでブロックしているオブジェクトを待たない
+    /// よう、ロックされているオブジェクトは数えない。
+    pub fn socket_count(&self) -> usize {
+        self.fds.iter()
+            .flatten()
+            .filter(|d| match d.try_lock() {
+                Some(guard) => match *guard {
+                    Descriptor::Socket(_) => true,
+                    _ => false,
+                },
+                None => false,
+            })
+            .count()
+    }
+}
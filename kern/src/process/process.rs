@@ -1,26 +1,46 @@
 #![feature(new_uninit)]
 use alloc::boxed::Box;
+use alloc::string::String;
 use alloc::vec::Vec;
 use core::mem;
 //use shim::io;
-use shim::path::Path;
-use fat32::traits::{Entry, File, FileSystem};
+use shim::path::{Path, PathBuf};
 
 use aarch64::*;
-use smoltcp::socket::SocketHandle;
 
 use crate::{param::*, FILESYSTEM};
-use crate::process::State;
+use crate::process::{elf, FdTable, Region, RegionKind, State};
 use crate::traps::TrapFrame;
 use crate::vm::*;
-use kernel_api::{OsError, OsResult};
+use kernel_api::{OsError, OsResult, AT_ENTRY, AT_NULL, AT_PAGESZ};
 //use crate::console::kprintln;
-use crate::allocator::util::align_down;
-use shim::io::Read;
+use crate::allocator::util::{align_down, align_up};
 
 /// プロセスID型用のType alias.
 pub type Id = u64;
 
+/// 終了したが親プロセスにまだ回収されていない子プロセス.
+#[derive(Debug, Clone, Copy)]
+pub struct Zombie {
+    /// 子プロセスのID.
+    pub id: Id,
+    /// `exit` に渡された終了ステータス.
+    pub status: i32,
+}
+
+/// `Process::load_image()` がロードしたプログラムのイメージ.
+#[derive(Debug)]
+pub struct Image {
+    /// プログラムをロードしたページテーブル.
+    pub vmap: Box<UserPageTable>,
+    /// プログラムの実行を開始するトラップフレーム.
+    pub context: Box<TrapFrame>,
+    /// プログラムのセグメント、ヒープ、スタックの領域.
+    pub regions: Vec<Region>,
+    /// 初期のプログラムブレーク. ヒープの先頭である。
+    pub brk: usize,
+}
+
 /// プロセスの全状態を表す構造体.
 #[derive(Debug)]
 pub struct Process {
@@ -31,11 +51,24 @@ pub struct Process {
     // pub stack: Stack,
     /// プロセスの仮想メモリを記述するページテーブル
     pub vmap: Box<UserPageTable>,
+    /// アクセスできる仮想アドレスの領域. 領域内の割り当てられていない
+    /// ページはページフォールトで割り当てる。
+    pub regions: Vec<Region>,
+    /// プログラムブレーク. ヒープ領域の終端であり、`brk` で変更する。
+    pub brk: usize,
     /// プロセスのスケジューリング状態.
     pub state: State,
-    // Lab 5 2.C
-    /// カレントプロセスが保持するSocketハンドル
-    pub sockets: Vec<SocketHandle>,
+    /// ファイルやソケットを指すファイルディスクリプタの表.
+    pub fds: FdTable,
+    /// カレントディレクトリ. 正規化された絶対パスである。
+    pub cwd: PathBuf,
+    /// 親プロセスのID. initプロセスと、initプロセスが終了した後の
+    /// 孤児プロセスでは `None`.
+    pub parent: Option<Id>,
+    /// 実行中の子プロセスのID.
+    pub children: Vec<Id>,
+    /// 終了した子プロセス. `wait` で回収される。
+    pub zombies: Vec<Zombie>,
 }
 
 impl Process {
@@ -59,65 +92,385 @@ impl Process {
     }
 */
 
-    /// `do_load()`メソッドを呼び出すことにより指定のパスに
-    /// 格納されているプログラムをロードする.
-    /// そのページテーブルに対応してトラップフレームの`context`をセットする.
+    /// `load_image()` メソッドを呼び出すことにより指定のパスに
+    /// 格納されているプログラムを親プロセスのない新しいプロセスとして
+    /// 引数 `args` と空の環境変数でロードする. 標準入出力には
+    /// `/dev/console` をオープンする.
+    ///
+    /// load_image が失敗した場合は OSError を返す.
+    pub fn load<P: AsRef<Path>>(pn: P, args: &[String]) -> OsResult<Process> {
+        let image = Process::load_image(pn, args, &[])?;
+        Ok(Process {
+            context: image.context,
+            vmap: image.vmap,
+            regions: image.regions,
+            brk: image.brk,
+            state: State::Ready,
+            fds: FdTable::with_console()?,
+            cwd: PathBuf::from("/"),
+            parent: None,
+            children: Vec::new(),
+            zombies: Vec::new(),
+        })
+    }
+
+    /// 指定のパスに格納されているプログラムをロードしたページテーブルと、
+    /// そのページテーブルに対応するトラップフレーム、アクセスできる領域を
+    /// 返す.
     /// `sp` - スタックの先頭アドレス
-    /// `elr` - イメージのベースアドレス.
+    /// `elr` - ELFヘッダのエントリポイント.
     /// `ttbr0` - カーネルページテーブルのベースアドレス
     /// `ttbr1` - ユーザページテーブルのベースアドレス
     /// `spsr` - `F`, `A`, `D` ビットをセットする必要がある.
+    /// `x0` から `x3` - 引数の数 (argc)、引数の配列 (argv)、環境変数の
+    /// 配列 (envp)、補助ベクタ (auxv) のアドレス
+    ///
+    /// 引数 `args` と `KEY=VALUE` 形式の環境変数 `envs` は `push_args()` で
+    /// スタックに置く。
+    ///
+    /// # エラー
     ///
-    /// do_load が失敗した場合は OSError を返す.
-    pub fn load<P: AsRef<Path>>(pn: P) -> OsResult<Process> {
-        let mut p = Process::do_load(pn)?;
+    /// 引数と環境変数の合計サイズが `ARG_MAX` を超える場合は
+    /// `OsError::InvalidArgument` を返す。
+    pub fn load_image<P: AsRef<Path>>(pn: P, args: &[String], envs: &[String])
+        -> OsResult<Image> {
+        let (mut vmap, entry, regions) = Process::do_load(pn)?;
 
         //FIXME: Set trapframe for the process.
-        let mut tf = &mut p.context;
-        tf.elr   = Process::get_image_base().as_u64();
+        let mut tf = Box::new(TrapFrame::default());
+        tf.elr   = entry.as_u64();
         tf.spsr  = (SPSR_EL1::M & 0b0000) | SPSR_EL1::F | SPSR_EL1::A | SPSR_EL1::D;
-        tf.sp    = Process::get_stack_top().as_u64();
         tf.ttbr0 = crate::VMM.get_baddr().as_u64();
-        tf.ttbr1 = p.vmap.get_baddr().as_u64();
+        tf.ttbr1 = vmap.get_baddr().as_u64();
+        Process::push_args(&mut vmap, &mut tf, args, envs)?;
 
-        Ok(p)
+        let brk = regions.iter().find(|r| r.kind == RegionKind::Heap).map_or(0, |r| r.start);
+        Ok(Image { vmap, context: tf, regions, brk })
     }
 
-    /// プロセスを作成して、指定されたパスのファイルをオープンする.
-    /// スタック用にread/write権限のページを1ページ割り当て、ファイル
-    /// コンテンツのロード用にread/write/execute権限のページをNページ
-    /// 割り当てる.
-    fn do_load<P: AsRef<Path>>(pn: P) -> OsResult<Process> {
+    /// 引数 `args` と環境変数 `envs` をスタックページに置き、`tf` の `sp` と
+    /// `x0` から `x3` をセットする. スタックの内容は上位アドレスから順に
+    /// 次のとおりである。
+    ///
+    /// - 引数と環境変数のNUL終端文字列
+    /// - 補助ベクタ: `AT_PAGESZ`、`AT_ENTRY` の種類と値の組と `AT_NULL`
+    /// - 環境変数の文字列へのポインタの配列 (NULL終端)
+    /// - 引数の文字列へのポインタの配列 (NULL終端)
+    /// - 引数の数 (`sp` が指す)
+    fn push_args(vmap: &mut UserPageTable, tf: &mut TrapFrame, args: &[String],
+        envs: &[String]) -> OsResult<()> {
+        let word = mem::size_of::<u64>();
+        let auxv = [AT_PAGESZ, PAGE_SIZE as u64, AT_ENTRY, tf.elr, AT_NULL, 0];
+        let strings: usize = args.iter().chain(envs).map(|s| s.len() + 1).sum();
+        let words = 1 + (args.len() + 1) + (envs.len() + 1) + auxv.len();
+        if strings + words * word + 16 > ARG_MAX {
+            return Err(OsError::InvalidArgument);
+        }
+
+        let base = Process::get_stack_base().as_usize();
+        let stack = vmap.get_page_mut(Process::get_stack_base()).ok_or(OsError::NoVmSpace)?;
+        let mut sp = Process::get_stack_top().as_usize();
+        let mut strs = Vec::with_capacity(args.len() + envs.len());
+        for s in args.iter().chain(envs) {
+            sp -= s.len() + 1;
+            let offset = sp - base;
+            stack[offset..offset + s.len()].copy_from_slice(s.as_bytes());
+            stack[offset + s.len()] = 0;
+            strs.push(sp as u64);
+        }
+        let (arg_addrs, env_addrs) = strs.split_at(args.len());
+
+        let mut table = Vec::with_capacity(words);
+        table.push(args.len() as u64);
+        table.extend_from_slice(arg_addrs);
+        table.push(0);
+        table.extend_from_slice(env_addrs);
+        table.push(0);
+        table.extend_from_slice(&auxv);
+
+        sp = align_down(sp - words * word, 16);
+        for (i, value) in table.iter().enumerate() {
+            let offset = sp - base + i * word;
+            stack[offset..offset + word].copy_from_slice(&value.to_le_bytes());
+        }
+
+        tf.sp = sp as u64;
+        tf.xn[0] = args.len() as u64;
+        tf.xn[1] = (sp + word) as u64;
+        tf.xn[2] = (sp + word * (args.len() + 2)) as u64;
+        tf.xn[3] = (sp + word * (args.len() + envs.len() + 3)) as u64;
+        Ok(())
+    }
+
+    /// 実行中のイメージを `load_image()` でロードした `image` に置き換える.
+    /// プロセスID、ファイルディスクリプタ、カレントディレクトリ、親子関係は
+    /// 引き継ぐ。
+    pub fn exec(&mut self, mut image: Image) {
+        image.context.tpidr = self.context.tpidr;
+        self.context = image.context;
+        self.vmap = image.vmap;
+        self.regions = image.regions;
+        self.brk = image.brk;
+    }
+
+    /// 仮想アドレス `va` へのアクセスで発生した変換フォールトを処理する.
+    /// `va` がこのプロセスの領域にあり、そのページが割り当てられていない
+    /// 場合は0で埋めたページを領域の権限で割り当てて `true` を返す。セグメントの
+    /// 領域のようにページを共有する領域が複数ある場合はそれらの権限を合わせる。
+    /// それ以外の場合、およびページを割り当てられない場合は `false` を返す。
+    pub fn handle_page_fault(&mut self, va: VirtualAddr) -> bool {
+        let mut regions = self.regions.iter().filter(|r| r.contains(va.as_usize()));
+        let perm = match regions.next() {
+            Some(region) => regions.fold(region.perm, |perm, r| perm.union(r.perm)),
+            None => return false,
+        };
+        if self.vmap.is_mapped(va) {
+            return false;
+        }
+        let page = VirtualAddr::from(align_down(va.as_usize(), PAGE_SIZE));
+        match self.vmap.alloc(page, perm) {
+            Some(bytes) => {
+                for byte in bytes.iter_mut() {
+                    *byte = 0;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// プログラムブレークを `addr` に変更してヒープ領域を伸縮し、新しい
+    /// ブレークを返す. `addr` が0の場合は現在のブレークを返す。ヒープを
+    /// 縮めた場合は範囲外になったページを解放する。
+    ///
+    /// # エラー
+    ///
+    /// `addr` がヒープの先頭より前の場合は `OsError::InvalidArgument` を、
+    /// ヒープが他の領域と重なる場合、ヒープと `mmap()` の領域の合計が
+    /// `USER_MAX_DATA_SIZE` を超える場合は `OsError::NoMemory` を返す。
+    pub fn set_brk(&mut self, addr: usize) -> OsResult<usize> {
+        if addr == 0 {
+            return Ok(self.brk);
+        }
+        let index = self.regions.iter()
+            .position(|r| r.kind == RegionKind::Heap)
+            .ok_or(OsError::NoMemory)?;
+        let heap = self.regions[index];
+        if addr < heap.start {
+            return Err(OsError::InvalidArgument);
+        }
+        let size = align_up(addr - heap.start, PAGE_SIZE);
+        if self.regions.iter().any(|r| r.kind != RegionKind::Heap && r.overlaps(heap.start, size))
+            || self.data_size() - heap.size + size > USER_MAX_DATA_SIZE {
+            return Err(OsError::NoMemory);
+        }
+        if size < heap.size {
+            self.free_pages(heap.start + size, heap.size - size);
+        }
+        self.regions[index].size = size;
+        self.brk = addr;
+        Ok(addr)
+    }
+
+    /// `len` バイトの無名メモリの領域を権限 `perm` で作成し、先頭アドレスを
+    /// 返す. 領域はスタックの下から順に空いている範囲を探して配置し、
+    /// ページは最初にアクセスしたときに割り当てる。
+    ///
+    /// # エラー
+    ///
+    /// `len` が0の場合は `OsError::InvalidArgument` を、ヒープと `mmap()` の
+    /// 領域の合計が `USER_MAX_DATA_SIZE` を超える場合は `OsError::NoMemory` を、
+    /// 空いている範囲がない場合は `OsError::NoVmSpace` を返す。
+    pub fn mmap(&mut self, len: usize, perm: PagePerm) -> OsResult<usize> {
+        if len == 0 || len > USER_MAX_VM_SIZE {
+            return Err(OsError::InvalidArgument);
+        }
+        let size = align_up(len, PAGE_SIZE);
+        if self.data_size() + size > USER_MAX_DATA_SIZE {
+            return Err(OsError::NoMemory);
+        }
+        let mut top = USER_STACK_LIMIT;
+        loop {
+            let start = match top.checked_sub(size) {
+                Some(start) if start >= USER_IMG_BASE => start,
+                _ => return Err(OsError::NoVmSpace),
+            };
+            match self.regions.iter().filter(|r| r.overlaps(start, size)).map(|r| r.start).min() {
+                Some(below) => top = below,
+                None => {
+                    self.regions.push(Region::new(start, size, perm, RegionKind::Mmap));
+                    return Ok(start);
+                }
+            }
+        }
+    }
+
+    /// `mmap()` で作成した領域から `[addr, addr + len)` の範囲を取り除き、
+    /// 割り当てられていたページを解放する. 範囲の一部だけを取り除いた
+    /// 領域は分割する。
+    ///
+    /// # エラー
+    ///
+    /// `addr` がページ境界にない場合、`len` が0の場合、範囲が `mmap()` で
+    /// 作成した領域以外と重なる場合は `OsError::InvalidArgument` を返す。
+    pub fn munmap(&mut self, addr: usize, len: usize) -> OsResult<()> {
+        let size = Process::page_range(addr, len)?;
+        if self.regions.iter().any(|r| r.kind != RegionKind::Mmap && r.overlaps(addr, size)) {
+            return Err(OsError::InvalidArgument);
+        }
+        self.split_regions(addr, size);
+        self.regions.retain(|r| !r.overlaps(addr, size));
+        self.free_pages(addr, size);
+        Ok(())
+    }
+
+    /// `[addr, addr + len)` の範囲の領域と割り当て済みのページの権限を
+    /// `perm` に変更する. 範囲の一部だけを含む領域は分割する。
+    ///
+    /// # エラー
+    ///
+    /// `addr` がページ境界にない場合、`len` が0の場合、範囲がヒープと
+    /// 重なる場合は `OsError::InvalidArgument` を、範囲にどの領域にもない
+    /// ページがある場合は `OsError::BadAddress` を返す。
+    pub fn mprotect(&mut self, addr: usize, len: usize, perm: PagePerm) -> OsResult<()> {
+        let size = Process::page_range(addr, len)?;
+        if self.regions.iter().any(|r| r.kind == RegionKind::Heap && r.overlaps(addr, size)) {
+            return Err(OsError::InvalidArgument);
+        }
+        let pages = move || (0..size).step_by(PAGE_SIZE).map(move |offset| addr + offset);
+        if !pages().all(|page| self.regions.iter().any(|r| r.contains(page))) {
+            return Err(OsError::BadAddress);
+        }
+
+        self.split_regions(addr, size);
+        for region in self.regions.iter_mut().filter(|r| r.overlaps(addr, size)) {
+            region.perm = perm;
+        }
+        for page in pages() {
+            self.vmap.set_perm(VirtualAddr::from(page), perm);
+        }
+        Ok(())
+    }
+
+    /// ヒープと `mmap()` の領域の合計バイト数を返す.
+    fn data_size(&self) -> usize {
+        self.regions.iter()
+            .filter(|r| r.kind == RegionKind::Heap || r.kind == RegionKind::Mmap)
+            .map(|r| r.size)
+            .sum()
+    }
+
+    /// ページ境界にある `addr` から `len` バイトの範囲がユーザ空間に
+    /// 収まることを確認し、ページサイズに切り上げたバイト数を返す.
+    fn page_range(addr: usize, len: usize) -> OsResult<usize> {
+        if addr % PAGE_SIZE != 0 || addr < USER_IMG_BASE || len == 0 || len > USER_MAX_VM_SIZE {
+            return Err(OsError::InvalidArgument);
+        }
+        let size = align_up(len, PAGE_SIZE);
+        addr.checked_add(size - 1).ok_or(OsError::InvalidArgument)?;
+        Ok(size)
+    }
+
+    /// `[addr, addr + size)` の境界をまたぐ領域を境界で分割する.
+    fn split_regions(&mut self, addr: usize, size: usize) {
+        self.split_region_at(addr);
+        if let Some(end) = addr.checked_add(size) {
+            self.split_region_at(end);
+        }
+    }
+
+    /// アドレス `boundary` をまたぐ領域があれば `boundary` の前後の2つの
+    /// 領域に分割する.
+    fn split_region_at(&mut self, boundary: usize) {
+        let index = match self.regions.iter().position(|r| r.contains(boundary) && r.start != boundary) {
+            Some(index) => index,
+            None => return,
+        };
+        let region = self.regions[index];
+        let lower = boundary - region.start;
+        self.regions[index].size = lower;
+        self.regions.push(Region { start: boundary, size: region.size - lower, ..region });
+    }
+
+    /// `[start, start + size)` にある割り当て済みのページを解放する.
+    fn free_pages(&mut self, start: usize, size: usize) {
+        for page in (start..start + size).step_by(PAGE_SIZE) {
+            self.vmap.dealloc(VirtualAddr::from(page));
+        }
+    }
+
+    /// 子プロセス `pid` を回収する. `pid` が `None` の場合は任意の子プロセスを
+    /// 回収する。該当する子プロセスが終了していれば `Some` を、まだ実行中で
+    /// あれば `None` を返す。
+    ///
+    /// # エラー
+    ///
+    /// 該当する子プロセスがない場合は `OsError::NoChild` を返す。
+    pub fn reap(&mut self, pid: Option<Id>) -> OsResult<Option<Zombie>> {
+        let matches = |id: Id| pid.map_or(true, |pid| pid == id);
+        if let Some(index) = self.zombies.iter().position(|z| matches(z.id)) {
+            return Ok(Some(self.zombies.remove(index)));
+        }
+        if self.children.iter().any(|&id| matches(id)) {
+            Ok(None)
+        } else {
+            Err(OsError::NoChild)
+        }
+    }
+
+    /// スタック用にread/write権限のページを1ページ割り当て、ELF実行可能
+    /// ファイルの `PT_LOAD` セグメントをロードしたページテーブルと
+    /// エントリポイント、セグメント、ヒープ、スタックの領域を返す.
+    /// スタックの残りのページはスタックが伸びたときに割り当てる。
+    /// ヒープは最後のセグメントの次のページから始まる空の領域である。
+    fn do_load<P: AsRef<Path>>(pn: P)
+        -> OsResult<(Box<UserPageTable>, VirtualAddr, Vec<Region>)> {
         // 1. UserPageTableを作成
         let mut vmap = Box::new(UserPageTable::new());
         // 2. スタックを作成
-        let stack = vmap.alloc(Process::get_stack_base(), PagePerm::RW);
+        let stack = vmap.alloc(Process::get_stack_base(), PagePerm::RW)
+            .ok_or(OsError::NoMemory)?;
         // 2.1 スタックを0クリア
         for byte in stack.iter_mut() {
             *byte = 0;
         }
         // 3. ファイルをオープン
-        let entry = FILESYSTEM.open(pn)?;
-        let mut file = entry.into_file().ok_or(OsError::NoEntry)?;
-        // 4. ファイルを読み込む
-        let mut addr = Process::get_image_base();
-        let size = file.size() as usize;
-        for _i in 0..size / PAGE_SIZE {
-            let mut page = vmap.alloc(addr, PagePerm::RWX);
-            file.read_exact(&mut page)?;
-            addr += VirtualAddr::from(PAGE_SIZE);
-        }
-        if size % PAGE_SIZE != 0 {
-            let page = vmap.alloc(addr, PagePerm::RWX);
-            file.read_exact(&mut page[..size % PAGE_SIZE])?;
-        }
-        Ok(Process {
-            context: Box::new(TrapFrame::default()),
+        let mut file = FILESYSTEM.open(pn)?;
+        // 4. セグメントをロードする
+        let (entry, mut regions) = elf::load(&mut *file, &mut vmap)?;
+        let heap_start = regions.iter().map(|r| r.start + r.size).max().unwrap_or(USER_IMG_BASE);
+        regions.push(Region::new(heap_start, 0, PagePerm::RW, RegionKind::Heap));
+        regions.push(Region::new(USER_STACK_LIMIT, USER_STACK_SIZE, PagePerm::RW,
+            RegionKind::Stack));
+        Ok((vmap, entry, regions))
+    }
+
+    /// このプロセスを複製した子プロセスを返す. 子プロセスはトラップフレーム
+    /// `tf` から実行を再開し、`fork` の戻り値として0を受け取る。アドレス
+    /// 空間は書き込み時にコピーし、ファイルディスクリプタとカレント
+    /// ディレクトリは親プロセスから引き継ぐ。
+    ///
+    /// 子プロセスのIDはスケジューラに追加するときに割り当てられる。
+    /// 親プロセスの `children` への登録は呼び出し側が行う。
+    pub fn fork(&mut self, tf: &TrapFrame) -> Process {
+        let vmap = Box::new(self.vmap.fork());
+        let mut context = Box::new(*tf);
+        context.ttbr1 = vmap.get_baddr().as_u64();
+        context.xn[0] = 0;
+        context.xn[7] = OsError::Ok as u64;
+
+        Process {
+            context,
             vmap,
+            regions: self.regions.clone(),
+            brk: self.brk,
             state: State::Ready,
-            sockets: Vec::new(),
-        })
-
+            fds: self.fds.fork(),
+            cwd: self.cwd.clone(),
+            parent: Some(tf.tpidr),
+            children: Vec::new(),
+            zombies: Vec::new(),
+        }
     }
 
     /// このシステムにサポートされている最高位の `VirtualAddr` アドレスを返す.
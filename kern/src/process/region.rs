@@ -0,0 +1,51 @@
+use crate::allocator::util::{align_down, align_up};
+use crate::param::PAGE_SIZE;
+use crate::vm::PagePerm;
+
+/// 領域の用途.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegionKind {
+    /// ELF実行可能ファイルの `PT_LOAD` セグメント.
+    Image,
+    /// ユーザスタック.
+    Stack,
+    /// `brk` で伸縮するヒープ.
+    Heap,
+    /// `mmap` で割り当てた無名メモリ.
+    Mmap,
+}
+
+/// プロセスの仮想アドレス空間にあるページ単位の連続した領域.
+/// 領域内の割り当てられていないページには最初にアクセスしたときに
+/// 0で埋めたページを `perm` の権限で割り当てる。
+#[derive(Debug, Clone, Copy)]
+pub struct Region {
+    /// 領域の先頭アドレス. ページ境界にアラインしている。
+    pub start: usize,
+    /// 領域のバイト数. ページサイズの倍数である。
+    pub size: usize,
+    /// 領域のページの権限.
+    pub perm: PagePerm,
+    /// 領域の用途.
+    pub kind: RegionKind,
+}
+
+impl Region {
+    /// `[start, start + size)` を含むページ単位の領域を返す.
+    pub fn new(start: usize, size: usize, perm: PagePerm, kind: RegionKind) -> Region {
+        let aligned = align_down(start, PAGE_SIZE);
+        let size = align_up(start - aligned + size, PAGE_SIZE);
+        Region { start: aligned, size, perm, kind }
+    }
+
+    /// 仮想アドレス `va` がこの領域にある場合は `true` を返す.
+    pub fn contains(&self, va: usize) -> bool {
+        va >= self.start && va - self.start < self.size
+    }
+
+    /// この領域が `[start, start + size)` と重なる場合は `true` を返す.
+    pub fn overlaps(&self, start: usize, size: usize) -> bool {
+        size > 0 && self.size > 0
+            && (self.contains(start) || (start <= self.start && self.start - start < size))
+    }
+}
@@ -1,5 +1,6 @@
 use alloc::boxed::Box;
 use alloc::collections::vec_deque::VecDeque;
+use alloc::string::String;
 use alloc::vec::Vec;
 use pi::timer::current_time;
 
@@ -20,7 +21,7 @@ use crate::net::uspi::TKernelTimerHandle;
 use crate::net::GlobalEthernetDriver;
 use crate::param::*;
 use crate::percore::{get_preemptive_counter, is_mmu_ready, local_irq};
-use crate::process::{Id, Process, State};
+use crate::process::{Id, Process, State, Zombie};
 //use crate::traps::irq::GlobalIrq;
 use crate::traps::irq::IrqHandlerRegistry;
 use crate::traps::TrapFrame;
@@ -104,11 +105,11 @@ impl GlobalScheduler {
         }
     }
 
-    /// 現在実行中のプロセスをkillし、そのプロセスのIDを返す.
-    /// 詳細は `Scheduler::kill()` のドキュメントを参照。
+    /// 現在実行中のプロセスを終了ステータス `status` でkillし、そのプロセスの
+    /// IDを返す. 詳細は `Scheduler::kill()` のドキュメントを参照。
     #[must_use]
-    pub fn kill(&self, tf: &mut TrapFrame) -> Option<Id> {
-        self.critical(|scheduler| scheduler.kill(tf))
+    pub fn kill(&self, tf: &mut TrapFrame, status: i32) -> Option<Id> {
+        self.critical(|scheduler| scheduler.kill(tf, status))
     }
 
     /// タイマー割り込みベースのプリエンプションスケジューリングを
@@ -206,7 +207,7 @@ impl GlobalScheduler {
             self.add(p);
         }
     */
-        let p = Process::load("/echo").expect("load /echo");
+        let p = Process::load("/echo", &[String::from("/echo")]).expect("load /echo");
         self.add(p);
     /*
         let p = Process::load("/fib_20").expect("load /fib_20");
@@ -226,7 +227,8 @@ impl GlobalScheduler {
         use crate::vm::{VirtualAddr, PagePerm};
 
         let page = proc.vmap.alloc(
-            VirtualAddr::from(USER_IMG_BASE as u64), PagePerm::RWX);
+            VirtualAddr::from(USER_IMG_BASE as u64), PagePerm::RX)
+            .expect("allocate test page");
 
             let text = unsafe {
             core::slice::from_raw_parts(test_user_process as *const u8, 24)
@@ -340,21 +342,64 @@ impl Scheduler {
 
     /// 現在のプロセスを `Dead` 状態としてスケジューリングから外すことで
     /// 現在実行中のプロセスをkillする。死んだプロセスをキューから削除し、
+    /// 終了ステータス `status` を親プロセスの `zombies` に登録し、
     /// 死んだプロセスのインスタンスをdropし、死んだプロセスのプロセスIDを
     /// 返す。
-    fn kill(&mut self, tf: &mut TrapFrame) -> Option<Id> {
+    ///
+    /// 死んだプロセスの子プロセスと未回収の子プロセスはinitプロセスに
+    /// 引き継ぐ。
+    fn kill(&mut self, tf: &mut TrapFrame, status: i32) -> Option<Id> {
         match self.current_process(tf) {
             Some(index) => {
                 let mut process = self.processes.remove(index).unwrap();
                 self.release_process_resources(tf);
                 process.state = State::Dead;
+                let id = process.context.tpidr;
+                self.reparent(&mut process);
+                if let Some(parent) = process.parent.and_then(|pid| self.get_mut(pid)) {
+                    parent.children.retain(|&child| child != id);
+                    parent.zombies.push(Zombie { id, status });
+                }
                 //trace!("[{}]: kill pid={}", affinity(), process.context.tpidr);
-                Some(process.context.tpidr)
+                Some(id)
             }
             None => None,
         }
     }
 
+    /// 終了するプロセス `process` の子プロセスをinitプロセスの子プロセスに
+    /// する. initプロセス自身が終了する場合は親のないプロセスにし、未回収の
+    /// 子プロセスは捨てる。
+    fn reparent(&mut self, process: &mut Process) {
+        let children = mem::replace(&mut process.children, Vec::new());
+        let zombies = mem::replace(&mut process.zombies, Vec::new());
+        let init = if process.context.tpidr == INIT_PID { None } else { Some(INIT_PID) };
+
+        for &child in children.iter() {
+            if let Some(p) = self.get_mut(child) {
+                p.parent = init;
+            }
+        }
+        if let Some(init) = init.and_then(|pid| self.get_mut(pid)) {
+            init.children.extend(children);
+            init.zombies.extend(zombies);
+        }
+    }
+
+    /// カレントプロセスを複製した子プロセスをキューに追加し、子プロセスの
+    /// IDを返す. プロセスIDを割り当てられない場合は `None` を返す。
+    pub fn fork(&mut self, tf: &TrapFrame) -> Option<Id> {
+        let child = self.find_process(tf).fork(tf);
+        let id = self.add(child)?;
+        self.find_process(tf).children.push(id);
+        Some(id)
+    }
+
+    /// プロセスID `id` のプロセスを返す.
+    fn get_mut(&mut self, id: Id) -> Option<&mut Process> {
+        self.processes.iter_mut().find(|p| p.context.tpidr == id)
+    }
+
     /// カレントプロセスのキューでのインデックスを返す
     fn current_process(&mut self, tf: &mut TrapFrame) -> Option<usize> {
         let mut index: usize = 0;
@@ -378,20 +423,18 @@ impl Scheduler {
         // Lab 5 2.C
         match self.current_process(tf) {
             Some(index) => {
-                let mut process = self.processes.remove(index).unwrap();
-                for handle in process.sockets.iter_mut() {
-                    ETHERNET.critical(|driver| {
-                        driver.get_socket(*handle).close();
-                        driver.release(*handle);
-                        driver.prune();
-                    });
-                }
-                ()
+                // ファイルディスクリプタ表とともにソケットなども解放される
+                drop(self.processes.remove(index));
             }
             None => (),
         }
     }
 
+    /// キューにあるすべてのプロセスを走査するイテレータを返す.
+    pub fn processes(&self) -> impl Iterator<Item = &Process> {
+        self.processes.iter()
+    }
+
     /// トラップフレームに保存されているtpidrに対応するプロセスを見つけつ.
     /// 検索に失敗したらパニック.
     pub fn find_process(&mut self, tf: &TrapFrame) -> &mut Process {
@@ -23,6 +23,18 @@ pub enum State {
     Dead,
 }
 
+impl State {
+    /// 状態の名前を返す.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            State::Ready => "Ready",
+            State::Running => "Running",
+            State::Waiting(_) => "Waiting",
+            State::Dead => "Dead",
+        }
+    }
+}
+
 impl fmt::Debug for State {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
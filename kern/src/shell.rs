@@ -2,6 +2,7 @@ use shim::io;
 use shim::path::PathBuf;
 //use shim::ffi::{OsStr, OsString};
 use alloc::string::String;
+use alloc::vec::Vec;
 
 use stack_vec::StackVec;
 use core::str;
@@ -9,14 +10,12 @@ use core::str;
 
 //use pi::atags::Atags;
 
-use fat32::traits::FileSystem;
-use fat32::traits::{Dir, Entry, File};
-use fat32::traits::metadata::Metadata;
-use crate::fs::PiVFatHandle;
+use crate::fs::vfs::{DirEntry, FileType};
 
 use crate::console::{kprint, kprintln, CONSOLE};
 //use crate::ALLOCATOR;
-use crate::FILESYSTEM;
+use crate::process::Process;
+use crate::{FILESYSTEM, SCHEDULER};
 
 
 /// `Command`のパースに失敗した際のエラー型.
@@ -73,46 +72,48 @@ fn canonicalize(cwd: &PathBuf) -> PathBuf {
     path
 }
 
-fn print_ls_entry(entry: fat32::vfat::Entry<PiVFatHandle>, show_all: bool)  {
-    if !show_all && (entry.metadata().attributes.hidden() || entry.name() == "." || entry.name() == "..") {
+fn print_ls_entry(entry: &DirEntry, show_all: bool)  {
+    if !show_all && (entry.stat.hidden || entry.name == "." || entry.name == "..") {
         return;
     }
 
     let mut line = String::new();
-    if entry.is_dir() {
-        line.push('d');
-    } else {
-        line.push('-');
+    match entry.stat.kind {
+        FileType::Dir => line.push('d'),
+        FileType::Device => line.push('c'),
+        FileType::File => line.push('-'),
     }
 
-    if entry.metadata().hidden() {
+    if entry.stat.hidden {
         line.push('h');
     } else {
         line.push('-');
     }
-    if entry.metadata().attributes.read_only() {
+    if entry.stat.read_only {
         line.push('r');
     } else {
         line.push('-');
     }
 
-    let size = if entry.is_file() { entry.as_file().unwrap().size() } else { 0_u64 };
-
-    kprintln!("{} {:>10} {} {}", line, size, entry.metadata().modified(), entry.name());
+    kprintln!("{} {:>10} {} {}", line, entry.stat.size, entry.stat.modified, entry.name);
 }
 
 fn do_ls(cwd: &PathBuf, show_all: bool)  {
     let path = canonicalize(cwd);
-    if let Ok(entry) = FILESYSTEM.open(path) {
-        if entry.is_dir() {
-            for e in entry.as_dir().unwrap().entries().unwrap() {
-               print_ls_entry(e, show_all);
+    match FILESYSTEM.stat(&path) {
+        Ok(ref stat) if stat.is_dir() => match FILESYSTEM.readdir(&path) {
+            Ok(entries) => {
+                for e in entries.iter() {
+                    print_ls_entry(e, show_all);
+                }
             }
-        } else {
-            print_ls_entry(entry, show_all);
+            Err(e) => kprintln!("ls: {}: {:?}", path.display(), e),
+        },
+        Ok(stat) => {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("/");
+            print_ls_entry(&DirEntry { name: name.into(), stat }, show_all);
         }
-    } else {
-        kprintln!("invalid path: {}", canonicalize(cwd).display());
+        Err(_) => kprintln!("invalid path: {}", path.display()),
     }
 }
 
@@ -120,19 +121,24 @@ fn do_cat(path: &PathBuf)  {
     use io::Read;
     use io::Write;
 
-    if let Ok(entry) = FILESYSTEM.open(canonicalize(path)) {
-        if entry.is_file() {
+    let path = canonicalize(path);
+    match FILESYSTEM.stat(&path) {
+        Ok(ref stat) if stat.is_dir() => kprintln!("{} is not file", path.display()),
+        Ok(_) => {
+            let mut file = match FILESYSTEM.open(&path) {
+                Ok(file) => file,
+                Err(e) => {
+                    kprintln!("{}: {:?}", path.display(), e);
+                    return;
+                }
+            };
             let mut buf = [0_u8; 512];
-            let mut file = entry.into_file().unwrap();
             loop {
-                match &file.read(&mut buf) {
+                match file.read(&mut buf) {
+                    Ok(0) => break,
                     Ok(bytes) => {
-                        if *bytes == 0_usize {
-                            break;
-                        } else {
-                            let mut console = CONSOLE.lock();
-                            console.write(&buf[0..*bytes]).unwrap();
-                        }
+                        let mut console = CONSOLE.lock();
+                        console.write(&buf[0..bytes]).unwrap();
                     }
                     Err(_) => {
                         kprintln!("read error occured");
@@ -141,11 +147,35 @@ fn do_cat(path: &PathBuf)  {
                 }
             }
             kprintln!("");
-        } else {
-            kprintln!("{} is not file", canonicalize(path).display())
         }
-    } else {
-        kprintln!("{} is not exist", canonicalize(path).display());
+        Err(_) => kprintln!("{} is not exist", path.display()),
+    }
+}
+
+fn do_df() {
+    kprintln!("{:<8} {:>12} {:>12} {:>12} {:>8} {}",
+        "Type", "Size", "Used", "Avail", "Block", "Mounted on");
+    for mount in FILESYSTEM.mounts() {
+        match mount.fs.statfs() {
+            Ok(stats) => kprintln!("{:<8} {:>12} {:>12} {:>12} {:>8} {}",
+                mount.fs.name(), stats.total_bytes(), stats.used_bytes(),
+                stats.free_bytes(), stats.block_size, mount.path.display()),
+            Err(e) => kprintln!("df: {}: {:?}", mount.path.display(), e),
+        }
+    }
+}
+
+/// プログラム `args[0]` を引数 `args` で新しいプロセスとしてロードし、
+/// スケジューラに追加する.
+fn do_run(cwd: &PathBuf, args: &[&str]) {
+    let path = canonicalize(&cwd.join(args[0]));
+    let args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+    match Process::load(&path, &args) {
+        Ok(process) => match SCHEDULER.add(process) {
+            Some(id) => kprintln!("pid {}", id),
+            None => kprintln!("run: no process id available"),
+        },
+        Err(e) => kprintln!("run: {}: {:?}", path.display(), e),
     }
 }
 
@@ -238,6 +268,18 @@ pub fn shell(prefix: &str) -> () {
                                     _ => kprintln!("too many args"),
                                 }
                             }
+                            &"df" => {
+                                kprint!("\n");
+                                do_df();
+                            }
+                            &"run" => {
+                                kprint!("\n");
+                                if command.args.len() < 2 {
+                                    kprintln!("run requires <program> [args...]");
+                                } else {
+                                    do_run(&cwd, &command.args.as_slice()[1..]);
+                                }
+                            }
                     /*
                             &"sleep" => {
                                 kprint!("\n");
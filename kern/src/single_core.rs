@@ -0,0 +1,36 @@
+use core::ops::{Deref, DerefMut};
+
+/// 内部の値が `Send` や `Sync` でなくても `Send` と `Sync` を実装する
+/// ラッパ. `Rc` を保持するカーネルの共有データをグローバルな `Mutex` に
+/// 置くために使用する。
+///
+/// この実装は *不健全* である。本来は `Rc` ではなく `Arc` を使うべきだが、
+/// `Arc` はアトミックメモリアクセスを使用するため、ARMアーキテクチャでは
+/// MMUを初期化する必要がある。私たちはボード上の1つのコアしか有効にして
+/// いないので、この不健全な実装は今のところ直ちに害を及ぼすことはない。
+/// 複数のコアを有効にする際には `Arc` に置き換えてこの型を削除する。
+#[derive(Clone, Debug, Default)]
+pub struct SingleCore<T>(T);
+
+unsafe impl<T> Send for SingleCore<T> {}
+unsafe impl<T> Sync for SingleCore<T> {}
+
+impl<T> SingleCore<T> {
+    pub const fn new(val: T) -> SingleCore<T> {
+        SingleCore(val)
+    }
+}
+
+impl<T> Deref for SingleCore<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for SingleCore<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
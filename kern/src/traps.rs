@@ -11,11 +11,12 @@ use aarch64::{affinity, enable_fiq_interrupt, disable_fiq_interrupt};
 use pi::interrupt::{Controller, Interrupt};
 use pi::local_interrupt::{LocalController, LocalInterrupt};
 
-use self::syndrome::Syndrome;
+use self::syndrome::{Fault, Syndrome};
 use self::syscall::handle_syscall;
 use crate::percore;
 use crate::traps::irq::IrqHandlerRegistry;
-use crate::{GLOBAL_IRQ, FIQ};
+use crate::vm::VirtualAddr;
+use crate::{GLOBAL_IRQ, FIQ, SCHEDULER};
 
 #[repr(u16)]
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
@@ -50,7 +51,8 @@ pub extern "C" fn handle_exception(info: Info, esr: u32, tf: &mut TrapFrame, far
     //kprintln!("info: {:?}, esr: 0x{:x}", info, esr);
     match info.kind {
         Kind::Synchronous => {
-            match Syndrome::from(esr) {
+            let syndrome = Syndrome::from(esr);
+            match syndrome {
                 Syndrome::Brk(_n) => {
                     // kprintln!("Syndrome::Brk({})", n);
                     // kprintln!("  ELR: 0x{:x}", tf.elr);
@@ -63,6 +65,11 @@ pub extern "C" fn handle_exception(info: Info, esr: u32, tf: &mut TrapFrame, far
                     handle_syscall(n as u16, tf);
                     disable_fiq_interrupt();
                 }
+                Syndrome::DataAbort { .. } | Syndrome::InstructionAbort { .. }
+                    if info.source == Source::LowerAArch64 =>
+                {
+                    handle_user_abort(syndrome, tf, far);
+                }
                 s => panic!("Unexpected syndrome: {:?}\ninfo: {:x?}\nesr : 0x{:08X}\nfar : 0x{:016X}\ntf:\n{:?}", s, info, esr, far, tf),
             }
         }
@@ -98,3 +105,31 @@ pub extern "C" fn handle_exception(info: Info, esr: u32, tf: &mut TrapFrame, far
         }
     }
 }
+
+/// ユーザプロセスがアドレス `far` へのアクセスで起こしたアボートを処理する.
+/// 書き込み時にコピーするページへの書き込みであればページをコピーし、
+/// プロセスの領域にある割り当てられていないページであれば0で埋めたページを
+/// 割り当てて、同じ命令から実行を再開する。それ以外の場合はフォールトの
+/// 内容を出力してプロセスをkillする。実行できないページの実行もkillする。
+fn handle_user_abort(syndrome: Syndrome, tf: &mut TrapFrame, far: u64) {
+    let va = VirtualAddr::from(far);
+    let handled = SCHEDULER.critical(|scheduler| {
+        let process = scheduler.find_process(tf);
+        match syndrome {
+            Syndrome::DataAbort { kind: Fault::Permission, .. } => {
+                process.vmap.handle_cow_fault(va)
+            }
+            Syndrome::DataAbort { kind: Fault::Translation, .. }
+            | Syndrome::InstructionAbort { kind: Fault::Translation, .. } => {
+                process.handle_page_fault(va)
+            }
+            _ => false,
+        }
+    });
+    if !handled {
+        info!("pid {} killed: {:?} at 0x{:016X}, elr: 0x{:016X}", tf.tpidr, syndrome, far, tf.elr);
+        // 異常終了したことを終了ステータス -1 で親プロセスに知らせる
+        let _ = SCHEDULER.kill(tf, -1);
+        let _ = SCHEDULER.switch_to(tf);
+    }
+}
@@ -1,13 +1,23 @@
 use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem;
 //use core::time::Duration;
 
+use shim::io::SeekFrom;
+use shim::path::{Path, PathBuf};
+use smoltcp::socket::SocketHandle;
 use smoltcp::wire::{IpAddress, IpEndpoint};
 
-use crate::console::kprint;
-use crate::param::USER_IMG_BASE;
-use crate::process::State;
+use crate::console::{kprint, CONSOLE};
+use crate::fs::vfs;
+use crate::allocator::util::align_down;
+use crate::param::{ARG_MAX, PAGE_SIZE, USER_IMG_BASE};
+use crate::process::{socket_error, to_api_stat, Descriptor, DescriptorRef, Id, Process, State,
+    Zombie};
 use crate::traps::TrapFrame;
-use crate::{ETHERNET, SCHEDULER};
+use crate::vm::{PagePerm, VirtualAddr};
+use crate::{ETHERNET, FILESYSTEM, SCHEDULER};
 
 use kernel_api::*;
 use pi::timer::current_time;
@@ -51,24 +61,16 @@ pub fn sys_time(tf: &mut TrapFrame) {
     tf.xn[7] = OsError::Ok as u64;
 }
 
-/// カレントプロセスをkillする.
+/// カレントプロセスをkillする. 終了ステータスは親プロセスが `wait` で
+/// 回収する。
 ///
-/// このシステムコールはパラメタを取らず、どのような値も返さない.
-pub fn sys_exit(tf: &mut TrapFrame) {
-    let _ = SCHEDULER.kill(tf);
+/// このシステムコールは第1パラメタとして終了ステータスを取り、どのような値も
+/// 返さない.
+pub fn sys_exit(status: i32, tf: &mut TrapFrame) {
+    let _ = SCHEDULER.kill(tf, status);
     let _ = SCHEDULER.switch_to(tf);
 }
 
-/// コンソールに書き出す.
-///
-/// このシステムコールはパラメタを1つ受け取る: 出力する u8 文字.
-///
-/// 通常の状態値だけを返す.
-pub fn sys_write(b: u8, tf: &mut TrapFrame) {
-    kprint!{"{}", b as char};
-    tf.xn[7] = OsError::Ok as u64;
-}
-
 /// カレントプロセスのIDを返す.
 ///
 /// このシステムコールはパラメタを取らない.
@@ -80,35 +82,271 @@ pub fn sys_getpid(tf: &mut TrapFrame) {
     tf.xn[7] = OsError::Ok as u64;
 }
 
-/// ソケットを作成してソケットハンドルをカレントプロセスの
-/// ソケットリストに保存する.
+/// カレントプロセスを複製した子プロセスを作成する.
+///
+/// 子プロセスのアドレス空間は書き込み時にコピーされ、ファイル
+/// ディスクリプタは親プロセスと同じオブジェクトを共有する。
+///
+/// このシステムコールはパラメタを取らない.
+///
+/// このシステムコールは通常のステータス値に加えて、親プロセスには子プロセスの
+/// IDを、子プロセスには0を返す。
+///
+/// # エラー
+/// プロセスIDを割り当てられない場合は `OsError::Unknown` を返す。
+pub fn sys_fork(tf: &mut TrapFrame) {
+    let result = SCHEDULER.critical(|scheduler| scheduler.fork(tf)).ok_or(OsError::Unknown);
+    set_result(result, tf);
+}
+
+/// カレントプロセスのイメージを指定のプログラムに置き換える.
+///
+/// このシステムコールは第1パラメタとしてパスのアドレス、第2パラメタとして
+/// パスの長さ、第3パラメタとして引数 (`&str` の配列) のアドレス、第4パラメタ
+/// として引数の数、第5パラメタとして `KEY=VALUE` 形式の環境変数 (`&str` の
+/// 配列) のアドレス、第6パラメタとして環境変数の数を取る。相対パスは
+/// カレントディレクトリを基準とする。ファイルディスクリプタとカレント
+/// ディレクトリは引き継ぐ。
+///
+/// 成功した場合、このシステムコールは復帰せず、新しいプログラムが `x0` から
+/// `x3` に引数の数と引数、環境変数、補助ベクタの配列のアドレスを受け取って
+/// 実行を開始する。
+///
+/// # エラー
+/// この関数は次のエラーを返すことができる:
+///
+/// - `OsError::BadAddress`: パスまたは引数が正しいユーザ空間スライスを形成しない
+/// - `OsError::NoEntry`: パスが存在しない
+/// - `OsError::InvalidArgument`: 引数がUTF-8でないか、合計サイズが `ARG_MAX` を超えるか、
+///   プログラムが正しいELF実行可能ファイルでない
+/// - ファイルシステムからのその他のI/Oエラー
+pub fn sys_exec(va: usize, len: usize, argv_va: usize, argc: usize, envp_va: usize,
+    envc: usize, tf: &mut TrapFrame) {
+    let result = resolve_user_path(va, len, tf).and_then(|path| {
+        // プログラムの読み込みはスケジューラのロックの外で行う
+        let args = unsafe { to_user_args(argv_va, argc, tf) }?;
+        let envs = unsafe { to_user_args(envp_va, envc, tf) }?;
+        let image = Process::load_image(path, &args, &envs)?;
+        Ok(SCHEDULER.critical(|scheduler| {
+            let process = scheduler.find_process(tf);
+            process.exec(image);
+            *process.context
+        }))
+    });
+    match result {
+        Ok(context) => *tf = context,
+        Err(error) => set_result(Err(error), tf),
+    }
+}
+
+/// ユーザ空間にある `argc` 個の `&str` の配列 `va` をコピーして返す.
+///
+/// # エラー
+/// 配列または文字列が完全にユーザ空間にない場合は `Err(OsError::BadAddress)`
+/// を、配列が `ARG_MAX` より大きいか、文字列がUTF-8でない場合は
+/// `Err(OsError::InvalidArgument)` を返す。
+unsafe fn to_user_args(va: usize, argc: usize, tf: &TrapFrame) -> OsResult<Vec<String>> {
+    let size = argc.checked_mul(mem::size_of::<&str>()).ok_or(OsError::InvalidArgument)?;
+    if size > ARG_MAX {
+        return Err(OsError::InvalidArgument);
+    }
+    if va % mem::align_of::<&str>() != 0 {
+        return Err(OsError::BadAddress);
+    }
+    let bytes = to_user_slice(va, size, tf)?;
+    let argv = core::slice::from_raw_parts(bytes.as_ptr() as *const &str, argc);
+    argv.iter()
+        .map(|arg| {
+            let bytes = to_user_slice(arg.as_ptr() as usize, arg.len(), tf)?;
+            core::str::from_utf8(bytes).map(String::from).map_err(|_| OsError::InvalidArgument)
+        })
+        .collect()
+}
+
+/// 回収した子プロセス `zombie` をシステムコールの結果として書き込む.
+fn set_wait_result(zombie: Zombie, tf: &mut TrapFrame) {
+    tf.xn[0] = zombie.id;
+    tf.xn[1] = zombie.status as u64;
+    tf.xn[7] = OsError::Ok as u64;
+}
+
+/// 子プロセス `pid` (`None` の場合は任意の子プロセス) が終了するまで
+/// 待機し、回収する.
+fn wait_child(pid: Option<Id>, tf: &mut TrapFrame) {
+    match SCHEDULER.critical(|scheduler| scheduler.find_process(tf).reap(pid)) {
+        Ok(Some(zombie)) => set_wait_result(zombie, tf),
+        Ok(None) => {
+            SCHEDULER.switch(
+                State::Waiting(Box::new(move |p| match p.reap(pid) {
+                    Ok(Some(zombie)) => {
+                        set_wait_result(zombie, &mut p.context);
+                        true
+                    }
+                    Ok(None) => false,
+                    Err(error) => {
+                        p.context.xn[7] = error as u64;
+                        true
+                    }
+                })),
+                tf,
+            );
+        }
+        Err(error) => set_result(Err(error), tf),
+    }
+}
+
+/// いずれかの子プロセスが終了するまで待機し、回収する.
+///
+/// このシステムコールはパラメタを取らない.
+///
+/// このシステムコールは通常のステータス値に加えて、回収した子プロセスの
+/// IDと終了ステータスの2つを返す。
+///
+/// # エラー
+/// 子プロセスがない場合は `OsError::NoChild` を返す。
+pub fn sys_wait(tf: &mut TrapFrame) {
+    wait_child(None, tf);
+}
+
+/// 子プロセス `pid` が終了するまで待機し、回収する.
+///
+/// このシステムコールは第1パラメタとして子プロセスのIDを取る。
+///
+/// このシステムコールは通常のステータス値に加えて、子プロセスのIDと
+/// 終了ステータスの2つを返す。
+///
+/// # エラー
+/// `pid` がカレントプロセスの子プロセスでない場合は `OsError::NoChild` を返す。
+pub fn sys_waitpid(pid: u64, tf: &mut TrapFrame) {
+    wait_child(Some(pid), tf);
+}
+
+/// プログラムブレーク (ヒープの終端) を変更する.
+///
+/// このシステムコールは第1パラメタとして新しいブレークのアドレスを取る。
+/// 0の場合はブレークを変更しない。ヒープのページは最初にアクセスした
+/// ときに割り当てる。
+///
+/// このシステムコールは通常のステータス値に加えて新しいブレークを返す。
+///
+/// # エラー
+/// この関数は次のエラーを返すことができる:
+///
+/// - `OsError::InvalidArgument`: アドレスがヒープの先頭より前にある
+/// - `OsError::NoMemory`: ヒープが他の領域と重なるか、ヒープと `mmap` で
+///   割り当てたメモリの合計が上限を超える
+pub fn sys_brk(addr: usize, tf: &mut TrapFrame) {
+    let result = SCHEDULER.critical(|scheduler| scheduler.find_process(tf).set_brk(addr));
+    set_result(result.map(|brk| brk as u64), tf);
+}
+
+/// `mmap` と `mprotect` の保護フラグ `prot` を対応するページの権限に変換する.
+/// 読み込みできない権限は表せないので `OsError::InvalidArgument` を返す。
+fn to_page_perm(prot: u64) -> OsResult<PagePerm> {
+    if prot & PROT_READ == 0 || prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+        return Err(OsError::InvalidArgument);
+    }
+    Ok(PagePerm::new(prot & PROT_WRITE != 0, prot & PROT_EXEC != 0))
+}
+
+/// 無名メモリを割り当てる.
+///
+/// このシステムコールは第1パラメタとしてバイト数、第2パラメタとして
+/// 保護フラグ (`PROT_READ`、`PROT_WRITE`、`PROT_EXEC` の組み合わせ) を
+/// 取る。ページは最初にアクセスしたときに0で埋めて割り当てる。
+///
+/// このシステムコールは通常のステータス値に加えてページ境界にアラインした
+/// 先頭アドレスを返す。
+///
+/// # エラー
+/// この関数は次のエラーを返すことができる:
+///
+/// - `OsError::InvalidArgument`: バイト数が0であるか、保護フラグが正しくない
+/// - `OsError::NoMemory`: ヒープと `mmap` で割り当てたメモリの合計が上限を超える
+/// - `OsError::NoVmSpace`: 仮想アドレス空間に空きがない
+pub fn sys_mmap(len: usize, prot: u64, tf: &mut TrapFrame) {
+    let result = to_page_perm(prot).and_then(|perm| {
+        SCHEDULER.critical(|scheduler| scheduler.find_process(tf).mmap(len, perm))
+    });
+    set_result(result.map(|addr| addr as u64), tf);
+}
+
+/// `mmap` で割り当てたメモリの割り当てを解除する.
+///
+/// このシステムコールは第1パラメタとしてページ境界にアラインしたアドレス、
+/// 第2パラメタとしてバイト数を取る。
+///
+/// この関数は通常のステータス値だけを返す。
+///
+/// # エラー
+/// この関数は次のエラーを返すことができる:
+///
+/// - `OsError::InvalidArgument`: アドレスがページ境界にないか、バイト数が0であるか、
+///   範囲が `mmap` で割り当てたメモリ以外を含む
+pub fn sys_munmap(addr: usize, len: usize, tf: &mut TrapFrame) {
+    let result = SCHEDULER.critical(|scheduler| scheduler.find_process(tf).munmap(addr, len));
+    set_result(result.map(|_| 0), tf);
+}
+
+/// メモリの保護フラグを変更する.
+///
+/// このシステムコールは第1パラメタとしてページ境界にアラインしたアドレス、
+/// 第2パラメタとしてバイト数、第3パラメタとして保護フラグ (`PROT_READ`、
+/// `PROT_WRITE`、`PROT_EXEC` の組み合わせ) を取る。範囲はプログラムの
+/// セグメント、スタック、`mmap` で割り当てたメモリになければならない。
+///
+/// この関数は通常のステータス値だけを返す。
+///
+/// # エラー
+/// この関数は次のエラーを返すことができる:
+///
+/// - `OsError::InvalidArgument`: アドレスがページ境界にないか、バイト数が0であるか、
+///   保護フラグが正しくないか、範囲がヒープを含む
+/// - `OsError::BadAddress`: 範囲に割り当てられていないアドレスがある
+pub fn sys_mprotect(addr: usize, len: usize, prot: u64, tf: &mut TrapFrame) {
+    let result = to_page_perm(prot).and_then(|perm| {
+        SCHEDULER.critical(|scheduler| scheduler.find_process(tf).mprotect(addr, len, perm))
+    });
+    set_result(result.map(|_| 0), tf);
+}
+
+/// ソケットを作成してカレントプロセスのファイルディスクリプタ表に
+/// 登録する.
 ///
 /// この関数は引数を取らない。
 ///
 /// このシステムコールは通常のステータス値に加えてソケットディスクリプタを
-/// 返す。
+/// 返す。ソケットはファイルと同じディスクリプタ空間にあり、`close` で
+/// 解放する。
 ///
-/// NOTE: オリジナル注記には「この関数は通常のステータス値以外は何も
-/// 返さない」とあるがソケットディスクリプタを返さないと以後この
-/// ソケットは使えないはずなのでソケットディスクリプタを返す仕様にした
+/// # エラー
+/// この関数は次のエラーを返すことができる:
 ///
-/// FIXME: SocketHandleの定義は`struct SocketHandle(usize)`である。
-/// self.0をディスクリプタに使いたいがプライベートフィールドで
-/// アクセスできない。ここではprocess.socketsのindexをディスクリプタ
-/// として使うことにした。これは一度pushしたハンドルを削除されなければ
-/// 問題ないが、削除されたら意味をなくしまう。close()システムコールは
-/// 実装しなくても良いとあるので実装しなければ問題ないか?
+/// - `OsError::TooManyDescriptors`: ディスクリプタをこれ以上割り当てられない
 pub fn sys_sock_create(tf: &mut TrapFrame) {
     // Lab 5 2.D
     trace!("sys_sock_create called");
-    SCHEDULER.critical(|scheduler| {
-        let process = scheduler.find_process(tf);
-        let handle = ETHERNET.add_socket();
-        process.sockets.push(handle);
-        tf.xn[0] = (process.sockets.len() - 1) as u64;
+    let handle = ETHERNET.add_socket();
+    let result = SCHEDULER.critical(|scheduler| {
+        scheduler.find_process(tf).fds.insert(Descriptor::Socket(handle))
     });
+    set_result(result, tf);
+}
 
-    tf.xn[7] = OsError::Ok as u64;
+/// カレントプロセスのディスクリプタ `fd` が指すオブジェクトを返す.
+fn descriptor(fd: u64, tf: &TrapFrame) -> OsResult<DescriptorRef> {
+    SCHEDULER.critical(|scheduler| scheduler.find_process(tf).fds.get(fd))
+}
+
+/// カレントプロセスのディスクリプタ `fd` が指すソケットのハンドルを返す.
+///
+/// # エラー
+/// `fd` がソケットを指していない場合は `OsError::InvalidSocket` を返す。
+fn socket_handle(fd: u64, tf: &TrapFrame) -> OsResult<SocketHandle> {
+    match *descriptor(fd, tf).map_err(|_| OsError::InvalidSocket)?.lock() {
+        Descriptor::Socket(handle) => Ok(handle),
+        _ => Err(OsError::InvalidSocket),
+    }
 }
 
 /// ソケットのステータスを返す。
@@ -126,24 +364,19 @@ pub fn sys_sock_create(tf: &mut TrapFrame) {
 /// # エラー
 /// 指定されたディスクリプタに対応するソケットが見つからなかった場合、
 /// この関数は `OsError::InvalidSocket` を返す。
-pub fn sys_sock_status(sock_idx: usize, tf: &mut TrapFrame) {
+pub fn sys_sock_status(fd: u64, tf: &mut TrapFrame) {
     // Lab 5 2.D
-    SCHEDULER.critical(|scheduler| {
-        let process = scheduler.find_process(tf);
-        if process.sockets.len() <= sock_idx {
-            tf.xn[7] = OsError::InvalidSocket as u64;
-            return;
-        }
-        let handle = process.sockets[sock_idx];
-        ETHERNET.critical(|driver| {
+    match socket_handle(fd, tf) {
+        Ok(handle) => ETHERNET.critical(|driver| {
             let socket = driver.get_socket(handle);
             tf.xn[0] = socket.is_active() as u64;
             tf.xn[1] = socket.is_listening() as u64;
             tf.xn[2] = socket.can_send() as u64;
             tf.xn[3] = socket.can_recv() as u64;
             tf.xn[7] = OsError::Ok as u64;
-        });
-    });
+        }),
+        Err(error) => tf.xn[7] = error as u64,
+    }
 }
 
 /// ソケットを使ってローカルエフェメラルポートをリモートIPエンドポイントに
@@ -167,41 +400,21 @@ pub fn sys_sock_status(sock_idx: usize, tf: &mut TrapFrame) {
 /// - `OsError::BadAddress`: `connect()` が `smoltcp::Error::Unaddressable` を返した
 /// - `OsError::Unknown`: `connect()`の呼び出しによるその他のすべてのエラー
 pub fn sys_sock_connect(
-    sock_idx: usize,
+    fd: u64,
     remote_endpoint: impl Into<IpEndpoint>,
     tf: &mut TrapFrame,
 ) {
     // Lab 5 2.D
-    trace!("sys_sock_connect called with: idx {}", sock_idx);
-    SCHEDULER.critical(|scheduler| {
-        let process = scheduler.find_process(tf);
-        if process.sockets.len() <= sock_idx {
-            tf.xn[7] = OsError::InvalidSocket as u64;
-            return;
-        }
-        let handle = process.sockets[sock_idx];
-
-        let port: u16;
-        if let Some(eport) = ETHERNET.critical(|driver| driver.get_ephemeral_port()) {
-            port = eport;
-        } else {
-            tf.xn[7] = OsError::NoEntry as u64;
-            return;
-        }
-
+    trace!("sys_sock_connect called with: fd {}", fd);
+    let result = socket_handle(fd, tf).and_then(|handle| {
         ETHERNET.critical(|driver| {
-            let mut socket = driver.get_socket(handle);
-            match socket.connect(remote_endpoint, port) {
-                Ok(_) => {
-                    ETHERNET.critical(|driver| driver.mark_port(port));
-                    tf.xn[7] = OsError::Ok as u64;
-                }
-                Err(smoltcp::Error::Illegal) => tf.xn[7] = OsError::IllegalSocketOperation as u64,
-                Err(smoltcp::Error::Unaddressable) => tf.xn[7] = OsError::BadAddress as u64,
-                Err(_) => tf.xn[7] = OsError::Unknown as u64,
-            }
-        });
+            let port = driver.get_ephemeral_port().ok_or(OsError::NoEntry)?;
+            driver.get_socket(handle).connect(remote_endpoint, port).map_err(socket_error)?;
+            driver.mark_port(port);
+            Ok(0)
+        })
     });
+    set_result(result, tf);
 }
 
 /// ローカルポートで着信接続をリッスンする。
@@ -218,55 +431,68 @@ pub fn sys_sock_connect(
 /// - `OsError::IllegalSocketOperation`: `listen()` が `smoltcp::Error::Illegal` を返した
 /// - `OsError::BadAddress`: `listen()` が `smoltcp::Error::Unaddressable` を返した
 /// - `OsError::Unknown`: `listen()`の呼び出しによるその他のすべてのエラー
-pub fn sys_sock_listen(sock_idx: usize, local_port: u16, tf: &mut TrapFrame) {
-    trace!("sys_sock_listen called with idx {}, port {}", sock_idx, local_port);
+pub fn sys_sock_listen(fd: u64, local_port: u16, tf: &mut TrapFrame) {
+    trace!("sys_sock_listen called with fd {}, port {}", fd, local_port);
     // Lab 5 2.D
+    let result = socket_handle(fd, tf).and_then(|handle| {
+        ETHERNET.critical(|driver| {
+            driver.get_socket(handle).listen(local_port).map_err(socket_error)
+        })
+    });
+    set_result(result.map(|_| 0), tf);
+}
+
+/// ユーザ空間の `[va, va + len)` にあるカレントプロセスのページを、カーネルが
+/// アクセスする前に用意する. 割り当てられていないページはプロセスの領域に
+/// あれば割り当てる。`write` が `true` の場合は書き込み時にコピーする
+/// ページをコピーし、すべてのページが書き込み可能であることを確認する。
+///
+/// # エラー
+/// 範囲が完全にユーザ空間にないか、アクセスできないページを含む場合は
+/// `Err(OsError::BadAddress)` を返す。
+fn fault_in_user(va: usize, len: usize, write: bool, tf: &TrapFrame) -> OsResult<()> {
+    let end = va.checked_add(len).ok_or(OsError::BadAddress)?;
+    if va < USER_IMG_BASE {
+        return Err(OsError::BadAddress);
+    }
     SCHEDULER.critical(|scheduler| {
         let process = scheduler.find_process(tf);
-        if process.sockets.len() <= sock_idx {
-            tf.xn[7] = OsError::InvalidSocket as u64;
-            return;
-        }
-        let handle = process.sockets[sock_idx];
-        //let ipaddr = ETHERNET.critical(|driver| driver.get_ipaddress());
-        //let local_endpoint = IpEndpoint::new(ipaddr, local_port);
-        ETHERNET.critical(|driver| {
-            let mut socket = driver.get_socket(handle);
-            match socket.listen(local_port) {
-                Ok(_) => tf.xn[7] = OsError::Ok as u64,
-                Err(smoltcp::Error::Illegal) => tf.xn[7] = OsError::IllegalSocketOperation as u64,
-                Err(smoltcp::Error::Unaddressable) => tf.xn[7] = OsError::BadAddress as u64,
-                Err(_) => tf.xn[7] = OsError::Unknown as u64,
+        for page in (align_down(va, PAGE_SIZE)..end).step_by(PAGE_SIZE) {
+            let page = VirtualAddr::from(page);
+            if !process.vmap.is_mapped(page) && !process.handle_page_fault(page) {
+                return Err(OsError::BadAddress);
             }
-        });
-    });
+            if write {
+                process.vmap.handle_cow_fault(page);
+                if !process.vmap.is_writable(page) {
+                    return Err(OsError::BadAddress);
+                }
+            }
+        }
+        Ok(())
+    })
 }
 
 /// 仮想アドレスと長さからスライスを返す.
 ///
 /// # エラー
-/// この関数はスライスが完全にユーザ空間にない場合は
-/// `Err(OsError::BadAddress)`を返す。
-unsafe fn to_user_slice<'a>(va: usize, len: usize) -> OsResult<&'a [u8]> {
-    let overflow = va.checked_add(len).is_none();
-    if va >= USER_IMG_BASE && !overflow {
-        Ok(core::slice::from_raw_parts(va as *const u8, len))
-    } else {
-        Err(OsError::BadAddress)
-    }
+/// この関数はスライスが完全にユーザ空間にないか、アクセスできないページを
+/// 含む場合は `Err(OsError::BadAddress)`を返す。
+unsafe fn to_user_slice<'a>(va: usize, len: usize, tf: &TrapFrame) -> OsResult<&'a [u8]> {
+    fault_in_user(va, len, false, tf)?;
+    Ok(core::slice::from_raw_parts(va as *const u8, len))
 }
-/// 仮想アドレスと長さから可変スライスを返す.
+
+/// 仮想アドレスと長さから可変スライスを返す. スライスにある書き込み時に
+/// コピーするページは、カーネルが書き込む前にカレントプロセスのページに
+/// コピーしておく。
 ///
 /// # エラー
-/// この関数はスライスが完全にユーザ空間にない場合は
-/// `Err(OsError::BadAddress)`を返す。
-unsafe fn to_user_slice_mut<'a>(va: usize, len: usize) -> OsResult<&'a mut [u8]> {
-    let overflow = va.checked_add(len).is_none();
-    if va >= USER_IMG_BASE && !overflow {
-        Ok(core::slice::from_raw_parts_mut(va as *mut u8, len))
-    } else {
-        Err(OsError::BadAddress)
-    }
+/// この関数はスライスが完全にユーザ空間にないか、書き込みできないページを
+/// 含む場合は `Err(OsError::BadAddress)`を返す。
+unsafe fn to_user_slice_mut<'a>(va: usize, len: usize, tf: &TrapFrame) -> OsResult<&'a mut [u8]> {
+    fault_in_user(va, len, true, tf)?;
+    Ok(core::slice::from_raw_parts_mut(va as *mut u8, len))
 }
 
 /// 接続されたソケットを使ってデータを送信する.
@@ -284,34 +510,16 @@ unsafe fn to_user_slice_mut<'a>(va: usize, len: usize) -> OsResult<&'a mut [u8]>
 /// - `OsError::BadAddress`: アドレスと長さのペアが有効なユーザアドレス空間のスライスとならない
 /// - `OsError::IllegalSocketOperation`: `send_slice()` が `smoltcp::Error::Illegal` を返した。
 /// - `OsError::Unknown`: smoltcpからのその他のすべてのエラー
-pub fn sys_sock_send(sock_idx: usize, va: usize, len: usize, tf: &mut TrapFrame) {
+pub fn sys_sock_send(fd: u64, va: usize, len: usize, tf: &mut TrapFrame) {
     // Lab 5 2.D
-    trace!("sys_sock_send called with idx {}", sock_idx);
-    SCHEDULER.critical(|scheduler| {
-        let process = scheduler.find_process(tf);
-        if process.sockets.len() <= sock_idx {
-            tf.xn[7] = OsError::InvalidSocket as u64;
-            return;
-        }
-
-        let handle = process.sockets[sock_idx];
+    trace!("sys_sock_send called with fd {}", fd);
+    let result = socket_handle(fd, tf).and_then(|handle| {
+        let data = unsafe { to_user_slice(va, len, tf) }?;
         ETHERNET.critical(|driver| {
-            let mut socket = driver.get_socket(handle);
-            match unsafe { to_user_slice(va, len) } {
-                Ok(data) => {
-                    match socket.send_slice(data) {
-                        Ok(size) => {
-                            tf.xn[1] = size as u64;
-                            tf.xn[7] = OsError::Ok as u64;
-                        }
-                        Err(smoltcp::Error::Illegal) => tf.xn[7] = OsError::IllegalSocketOperation as u64,
-                        Err(_) => tf.xn[7] = OsError::Unknown as u64,
-                    }
-                }
-                Err(error) => tf.xn[7] = error as u64,
-            };
-        });
+            driver.get_socket(handle).send_slice(data).map_err(socket_error)
+        })
     });
+    set_result(result.map(|size| size as u64), tf);
 }
 
 /// 接続されたソケットからデータを受信する.
@@ -329,33 +537,265 @@ pub fn sys_sock_send(sock_idx: usize, va: usize, len: usize, tf: &mut TrapFrame)
 /// - `OsError::BadAddress`: アドレスと長さのペアが有効なユーザアドレス空間のスライスとならない
 /// - `OsError::IllegalSocketOperation`: `recv_slice()` が `smoltcp::Error::Illegal` を返した。
 /// - `OsError::Unknown`: smoltcpからのその他のすべてのエラー
-pub fn sys_sock_recv(sock_idx: usize, va: usize, len: usize, tf: &mut TrapFrame) {
+pub fn sys_sock_recv(fd: u64, va: usize, len: usize, tf: &mut TrapFrame) {
     // Lab 5 2.D
-    trace!("sys_sock_recv called with idx {}", sock_idx);
-    SCHEDULER.critical(|scheduler| {
-        let process = scheduler.find_process(tf);
-        if process.sockets.len() <= sock_idx {
-            tf.xn[7] = OsError::InvalidSocket as u64;
-            return;
-        }
-        let handle = process.sockets[sock_idx];
+    trace!("sys_sock_recv called with fd {}", fd);
+    let result = socket_handle(fd, tf).and_then(|handle| {
+        let data = unsafe { to_user_slice_mut(va, len, tf) }?;
         ETHERNET.critical(|driver| {
-            let mut socket = driver.get_socket(handle);
-            match unsafe { to_user_slice_mut(va, len) } {
-                Ok(data) => {
-                    match socket.recv_slice(data) {
-                        Ok(size) => {
-                            tf.xn[1] = size as u64;
-                            tf.xn[7] = OsError::Ok as u64;
-                        }
-                        Err(smoltcp::Error::Illegal) => tf.xn[7] = OsError::IllegalSocketOperation as u64,
-                        Err(_) => tf.xn[7] = OsError::Unknown as u64,
-                    }
-                }
-                Err(error) => tf.xn[7] = error as u64,
-            };
-        });
+            driver.get_socket(handle).recv_slice(data).map_err(socket_error)
+        })
+    });
+    set_result(result.map(|size| size as u64), tf);
+}
+
+/// ユーザ空間の文字列 `va` と長さ `len` からパスを返す.
+///
+/// # エラー
+/// この関数は次のエラーを返すことができる:
+///
+/// - `OsError::BadAddress`: アドレスと長さの組が正しいユーザ空間スライスを形成しない.
+/// - `OsError::InvalidArgument`: パスが UTF-8 エンコードでない.
+unsafe fn to_user_path<'a>(va: usize, len: usize, tf: &TrapFrame) -> OsResult<&'a Path> {
+    let bytes = to_user_slice(va, len, tf)?;
+    core::str::from_utf8(bytes).map(Path::new).map_err(|_| OsError::InvalidArgument)
+}
+
+/// ユーザ空間のパス `va` と長さ `len` を、相対パスはカレントプロセスの
+/// カレントディレクトリを基準にして、正規化された絶対パスに変換する.
+///
+/// # エラー
+/// `to_user_path()` と同様.
+fn resolve_user_path(va: usize, len: usize, tf: &TrapFrame) -> OsResult<PathBuf> {
+    let path = unsafe { to_user_path(va, len, tf) }?;
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        SCHEDULER.critical(|scheduler| scheduler.find_process(tf).cwd.join(path))
+    };
+    vfs::normalize(&path).map_err(OsError::from)
+}
+
+/// ユーザ空間のアドレス `va` にある `T` への可変参照を返す.
+///
+/// # エラー
+/// `T` の領域が完全にユーザ空間にないか、`va` が `T` のアラインメントを
+/// 満たさない場合は `Err(OsError::BadAddress)` を返す。
+unsafe fn to_user_mut<'a, T>(va: usize, tf: &TrapFrame) -> OsResult<&'a mut T> {
+    if va % mem::align_of::<T>() != 0 {
+        return Err(OsError::BadAddress);
+    }
+    let slice = to_user_slice_mut(va, mem::size_of::<T>(), tf)?;
+    Ok(&mut *(slice.as_mut_ptr() as *mut T))
+}
+
+/// システムコールの結果 `result` をトラップフレームに書き込む. 成功した
+/// 場合は値を `x0` に書き込む。
+fn set_result(result: OsResult<u64>, tf: &mut TrapFrame) {
+    match result {
+        Ok(value) => {
+            tf.xn[0] = value;
+            tf.xn[7] = OsError::Ok as u64;
+        }
+        Err(error) => tf.xn[7] = error as u64,
+    }
+}
+
+/// ファイル、デバイス、またはディレクトリをオープンする.
+///
+/// このシステムコールは第1パラメタとしてパスのアドレス、第2パラメタとして
+/// パスの長さ、第3パラメタとしてフラグを取る。相対パスはカレント
+/// ディレクトリを基準とする。フラグに `O_CREAT` が含まれる場合、存在しないファイルは作成する。
+///
+/// このシステムコールは通常のステータス値に加えてファイルディスクリプタを
+/// 返す。
+///
+/// # エラー
+/// この関数は次のエラーを返すことができる:
+///
+/// - `OsError::BadAddress`: アドレスと長さの組が正しいユーザ空間スライスを形成しない
+/// - `OsError::NoEntry`: パスが存在しない
+/// - `OsError::TooManyDescriptors`: ディスクリプタをこれ以上割り当てられない
+/// - ファイルシステムからのその他のI/Oエラー
+pub fn sys_open(va: usize, len: usize, flags: u64, tf: &mut TrapFrame) {
+    let result = resolve_user_path(va, len, tf)
+        .and_then(|path| Descriptor::open(path, flags))
+        .and_then(|descriptor| SCHEDULER.critical(|scheduler| {
+            scheduler.find_process(tf).fds.insert(descriptor)
+        }));
+    set_result(result, tf);
+}
+
+/// ファイルディスクリプタから読み込む.
+///
+/// このシステムコールは第1パラメタとしてファイルディスクリプタ、
+/// 第2パラメタとしてバッファアドレス、第3パラメタとしてバッファ長を取る。
+/// ソケットからは受信済みのデータを読み込む。コンソールからの読み込みは
+/// 入力があるまでプロセスを待機させる。
+///
+/// このシステムコールは通常のステータス値に加えて読み込んだバイト長を
+/// 返す。0はファイルの終端を表す。
+///
+/// # エラー
+/// この関数は次のエラーを返すことができる:
+///
+/// - `OsError::BadDescriptor`: ディスクリプタがオープンされていない
+/// - `OsError::BadAddress`: アドレスと長さの組が正しいユーザ空間スライスを形成しない
+/// - `OsError::InvalidArgument`: ディスクリプタがディレクトリを指している
+pub fn sys_read(fd: u64, va: usize, len: usize, tf: &mut TrapFrame) {
+    // ファイルの読み込みは時間がかかるので、スケジューラのロックの外で行う
+    let result = descriptor(fd, tf).and_then(|descriptor| {
+        let buf = unsafe { to_user_slice_mut(va, len, tf) }?;
+        let size = descriptor.lock().read(buf)?;
+        Ok(size as u64)
     });
+    match result {
+        // `WouldBlock` を返すのはコンソールだけである. 入力があるまで待機し、
+        // `svc` 命令からシステムコールをやり直す
+        Err(OsError::IoErrorWouldBlock) => {
+            tf.elr -= 4;
+            SCHEDULER.switch(State::Waiting(Box::new(|_| CONSOLE.lock().has_byte())), tf);
+        }
+        result => set_result(result, tf),
+    }
+}
+
+/// ファイルディスクリプタに書き込む.
+///
+/// このシステムコールは第1パラメタとしてファイルディスクリプタ、
+/// 第2パラメタとしてバッファアドレス、第3パラメタとしてバッファ長を取る。
+///
+/// このシステムコールは通常のステータス値に加えて書き込んだバイト長を
+/// 返す。
+///
+/// # エラー
+/// `sys_read()` と同様.
+pub fn sys_write(fd: u64, va: usize, len: usize, tf: &mut TrapFrame) {
+    let result = descriptor(fd, tf).and_then(|descriptor| {
+        let buf = unsafe { to_user_slice(va, len, tf) }?;
+        let size = descriptor.lock().write(buf)?;
+        Ok(size as u64)
+    });
+    set_result(result, tf);
+}
+
+/// ファイルディスクリプタを閉じる. ディスクリプタが指していたオブジェクトは
+/// 最後の参照がなくなったときに解放される。
+///
+/// このシステムコールは第1パラメタとしてファイルディスクリプタを取る。
+///
+/// この関数は通常のステータス値だけを返す。
+///
+/// # エラー
+/// ディスクリプタがオープンされていない場合は `OsError::BadDescriptor` を返す。
+pub fn sys_close(fd: u64, tf: &mut TrapFrame) {
+    let result = SCHEDULER.critical(|scheduler| scheduler.find_process(tf).fds.remove(fd));
+    // ソケットの解放はスケジューラのロックの外で行う
+    set_result(result.map(|_| 0), tf);
+}
+
+/// ファイルの読み書きの位置を変更する.
+///
+/// このシステムコールは第1パラメタとしてファイルディスクリプタ、
+/// 第2パラメタとして基準位置 (`SEEK_SET`, `SEEK_CUR`, `SEEK_END`)、
+/// 第3パラメタとしてオフセットを取る。`SEEK_CUR` と `SEEK_END` の
+/// オフセットは符号付きである。
+///
+/// このシステムコールは通常のステータス値に加えて新しい位置を返す。
+///
+/// # エラー
+/// この関数は次のエラーを返すことができる:
+///
+/// - `OsError::BadDescriptor`: ディスクリプタがオープンされていない
+/// - `OsError::InvalidArgument`: 基準位置が不正であるか、ディスクリプタが
+///   ファイルを指していないか、新しい位置がファイルの先頭より前または
+///   終端より後ろにある
+pub fn sys_seek(fd: u64, whence: u64, offset: u64, tf: &mut TrapFrame) {
+    let pos = match whence {
+        SEEK_SET => Ok(SeekFrom::Start(offset)),
+        SEEK_CUR => Ok(SeekFrom::Current(offset as i64)),
+        SEEK_END => Ok(SeekFrom::End(offset as i64)),
+        _ => Err(OsError::InvalidArgument),
+    };
+    let result = pos.and_then(|pos| descriptor(fd, tf)?.lock().seek(pos));
+    set_result(result, tf);
+}
+
+/// パスの属性を返す.
+///
+/// このシステムコールは第1パラメタとしてパスのアドレス、第2パラメタとして
+/// パスの長さ、第3パラメタとして属性 (`kernel_api::Stat`) を書き込む
+/// アドレスを取る。
+///
+/// この関数は通常のステータス値だけを返す。
+///
+/// # エラー
+/// この関数は次のエラーを返すことができる:
+///
+/// - `OsError::BadAddress`: アドレスが正しいユーザ空間の領域を指していない
+/// - `OsError::NoEntry`: パスが存在しない
+pub fn sys_stat(va: usize, len: usize, stat_va: usize, tf: &mut TrapFrame) {
+    let result = resolve_user_path(va, len, tf).and_then(|path| {
+        let stat = FILESYSTEM.stat(path).map_err(OsError::from)?;
+        *unsafe { to_user_mut::<Stat>(stat_va, tf) }? = to_api_stat(&stat);
+        Ok(0)
+    });
+    set_result(result, tf);
+}
+
+/// ファイルディスクリプタが指すオブジェクトの属性を返す.
+///
+/// このシステムコールは第1パラメタとしてファイルディスクリプタ、
+/// 第2パラメタとして属性 (`kernel_api::Stat`) を書き込むアドレスを取る。
+///
+/// この関数は通常のステータス値だけを返す。
+///
+/// # エラー
+/// この関数は次のエラーを返すことができる:
+///
+/// - `OsError::BadDescriptor`: ディスクリプタがオープンされていない
+/// - `OsError::BadAddress`: アドレスが正しいユーザ空間の領域を指していない
+pub fn sys_fstat(fd: u64, stat_va: usize, tf: &mut TrapFrame) {
+    let result = descriptor(fd, tf).and_then(|descriptor| {
+        let stat = descriptor.lock().stat()?;
+        *unsafe { to_user_mut::<Stat>(stat_va, tf) }? = stat;
+        Ok(0)
+    });
+    set_result(result, tf);
+}
+
+/// ディレクトリの次のエントリを読み込む.
+///
+/// このシステムコールは第1パラメタとしてディレクトリを指すファイル
+/// ディスクリプタ、第2パラメタとしてエントリ (`kernel_api::DirEnt`) を
+/// 書き込むアドレスを取る。
+///
+/// このシステムコールは通常のステータス値に加えて、エントリを読み込んだ
+/// 場合は1を、最後のエントリを読み終えている場合は0を返す。
+///
+/// # エラー
+/// この関数は次のエラーを返すことができる:
+///
+/// - `OsError::BadDescriptor`: ディスクリプタがオープンされていない
+/// - `OsError::BadAddress`: アドレスが正しいユーザ空間の領域を指していない
+/// - `OsError::InvalidArgument`: ディスクリプタがディレクトリを指していない
+pub fn sys_readdir(fd: u64, entry_va: usize, tf: &mut TrapFrame) {
+    let result = descriptor(fd, tf).and_then(|descriptor| {
+        let dirent = unsafe { to_user_mut::<DirEnt>(entry_va, tf) }?;
+        let entry = match descriptor.lock().next_entry()? {
+            Some(entry) => entry,
+            None => return Ok(0),
+        };
+
+        let name = entry.name.as_bytes();
+        let len = core::cmp::min(name.len(), NAME_MAX);
+        dirent.stat = to_api_stat(&entry.stat);
+        dirent.name_len = len as u64;
+        dirent.name_buf[..len].copy_from_slice(&name[..len]);
+        dirent.name_buf[len] = 0;
+        Ok(1)
+    });
+    set_result(result, tf);
 }
 
 /// UTF-8文字列をコンソールに出力する.
@@ -371,7 +811,7 @@ pub fn sys_sock_recv(sock_idx: usize, va: usize, len: usize, tf: &mut TrapFrame)
 /// - `OsError::BadAddress`: アドレスと長さの組が正しいユーザ空間スライスを形成しない.
 /// - `OsError::InvalidArgument`: 指定のバッファは UTF-8 エンコードでない.
 pub fn sys_write_str(va: usize, len: usize, tf: &mut TrapFrame) {
-    let result = unsafe { to_user_slice(va, len) }
+    let result = unsafe { to_user_slice(va, len, tf) }
         .and_then(|slice| core::str::from_utf8(slice).map_err(|_| OsError::InvalidArgument));
 
     match result {
@@ -387,6 +827,147 @@ pub fn sys_write_str(va: usize, len: usize, tf: &mut TrapFrame) {
     }
 }
 
+/// カレントディレクトリのパスを返す.
+///
+/// このシステムコールは第1パラメタとしてバッファアドレス、第2パラメタとして
+/// バッファ長を取る。
+///
+/// このシステムコールは通常のステータス値に加えてバッファに書き込んだ
+/// パスのバイト長を返す。
+///
+/// # エラー
+/// この関数は次のエラーを返すことができる:
+///
+/// - `OsError::BadAddress`: アドレスと長さの組が正しいユーザ空間スライスを形成しない
+/// - `OsError::InvalidArgument`: バッファがパスより短い
+pub fn sys_getcwd(va: usize, len: usize, tf: &mut TrapFrame) {
+    let cwd = SCHEDULER.critical(|scheduler| scheduler.find_process(tf).cwd.clone());
+    let result = unsafe { to_user_slice_mut(va, len, tf) }.and_then(|buf| {
+        let path = cwd.to_str().ok_or(OsError::InvalidArgument)?.as_bytes();
+        if buf.len() < path.len() {
+            return Err(OsError::InvalidArgument);
+        }
+        buf[..path.len()].copy_from_slice(path);
+        Ok(path.len() as u64)
+    });
+    set_result(result, tf);
+}
+
+/// カレントディレクトリを変更する.
+///
+/// このシステムコールは第1パラメタとしてパスのアドレス、第2パラメタとして
+/// パスの長さを取る。
+///
+/// この関数は通常のステータス値だけを返す。
+///
+/// # エラー
+/// この関数は次のエラーを返すことができる:
+///
+/// - `OsError::BadAddress`: アドレスと長さの組が正しいユーザ空間スライスを形成しない
+/// - `OsError::NoEntry`: パスが存在しない
+/// - `OsError::InvalidArgument`: パスがディレクトリでない
+pub fn sys_chdir(va: usize, len: usize, tf: &mut TrapFrame) {
+    let result = resolve_user_path(va, len, tf).and_then(|path| {
+        if !FILESYSTEM.stat(&path).map_err(OsError::from)?.is_dir() {
+            return Err(OsError::InvalidArgument);
+        }
+        SCHEDULER.critical(|scheduler| scheduler.find_process(tf).cwd = path);
+        Ok(0)
+    });
+    set_result(result, tf);
+}
+
+/// 空のディレクトリを作成する.
+///
+/// このシステムコールは第1パラメタとしてパスのアドレス、第2パラメタとして
+/// パスの長さを取る。
+///
+/// この関数は通常のステータス値だけを返す。
+///
+/// # エラー
+/// この関数は次のエラーを返すことができる:
+///
+/// - `OsError::BadAddress`: アドレスと長さの組が正しいユーザ空間スライスを形成しない
+/// - `OsError::NoEntry`: 親ディレクトリが存在しない
+/// - `OsError::FileExists`: パスがすでに存在する
+/// - `OsError::NoAccess`: ファイルシステムが読み込み専用である
+pub fn sys_mkdir(va: usize, len: usize, tf: &mut TrapFrame) {
+    let result = resolve_user_path(va, len, tf)
+        .and_then(|path| FILESYSTEM.create_dir(path).map_err(OsError::from));
+    set_result(result.map(|_| 0), tf);
+}
+
+/// 空のディレクトリを削除する.
+///
+/// このシステムコールは第1パラメタとしてパスのアドレス、第2パラメタとして
+/// パスの長さを取る。
+///
+/// この関数は通常のステータス値だけを返す。
+///
+/// # エラー
+/// この関数は次のエラーを返すことができる:
+///
+/// - `OsError::BadAddress`: アドレスと長さの組が正しいユーザ空間スライスを形成しない
+/// - `OsError::NoEntry`: パスが存在しない
+/// - `OsError::InvalidArgument`: パスがディレクトリでない
+/// - `OsError::NoAccess`: ディレクトリが空でないか、マウントポイントである
+pub fn sys_rmdir(va: usize, len: usize, tf: &mut TrapFrame) {
+    let result = resolve_user_path(va, len, tf).and_then(|path| {
+        if !FILESYSTEM.stat(&path).map_err(OsError::from)?.is_dir() {
+            return Err(OsError::InvalidArgument);
+        }
+        FILESYSTEM.remove(path).map_err(OsError::from)
+    });
+    set_result(result.map(|_| 0), tf);
+}
+
+/// ファイルを削除する.
+///
+/// このシステムコールは第1パラメタとしてパスのアドレス、第2パラメタとして
+/// パスの長さを取る。
+///
+/// この関数は通常のステータス値だけを返す。
+///
+/// # エラー
+/// この関数は次のエラーを返すことができる:
+///
+/// - `OsError::BadAddress`: アドレスと長さの組が正しいユーザ空間スライスを形成しない
+/// - `OsError::NoEntry`: パスが存在しない
+/// - `OsError::NoAccess`: パスがディレクトリであるか、ファイルシステムが
+///   読み込み専用である
+pub fn sys_unlink(va: usize, len: usize, tf: &mut TrapFrame) {
+    let result = resolve_user_path(va, len, tf).and_then(|path| {
+        if FILESYSTEM.stat(&path).map_err(OsError::from)?.is_dir() {
+            return Err(OsError::NoAccess);
+        }
+        FILESYSTEM.remove(path).map_err(OsError::from)
+    });
+    set_result(result.map(|_| 0), tf);
+}
+
+/// ファイルまたはディレクトリを移動する.
+///
+/// このシステムコールは第1、第2パラメタとして移動元のパスのアドレスと長さ、
+/// 第3、第4パラメタとして移動先のパスのアドレスと長さを取る。
+///
+/// この関数は通常のステータス値だけを返す。
+///
+/// # エラー
+/// この関数は次のエラーを返すことができる:
+///
+/// - `OsError::BadAddress`: アドレスと長さの組が正しいユーザ空間スライスを形成しない
+/// - `OsError::NoEntry`: 移動元が存在しない
+/// - `OsError::FileExists`: 移動先がすでに存在する
+/// - `OsError::IoErrorInvalidInput`: 移動元と移動先が異なるファイルシステムにある
+pub fn sys_rename(from_va: usize, from_len: usize, to_va: usize, to_len: usize,
+    tf: &mut TrapFrame) {
+    let result = resolve_user_path(from_va, from_len, tf).and_then(|from| {
+        let to = resolve_user_path(to_va, to_len, tf)?;
+        FILESYSTEM.rename(from, to).map_err(OsError::from)
+    });
+    set_result(result.map(|_| 0), tf);
+}
+
 // システムコールを処理する
 pub fn handle_syscall(num: u16, tf: &mut TrapFrame) {
 /*
@@ -397,12 +978,42 @@ pub fn handle_syscall(num: u16, tf: &mut TrapFrame) {
     match num as usize {
         NR_SLEEP => sys_sleep(tf.xn[0] as u32, tf),
         NR_TIME => sys_time(tf),
-        NR_EXIT => sys_exit(tf),
-        NR_WRITE => sys_write(tf.xn[0] as u8, tf),
+        NR_EXIT => sys_exit(tf.xn[0] as i32, tf),
+        NR_WRITE => sys_write(tf.xn[0], tf.xn[1] as usize, tf.xn[2] as usize, tf),
         NR_GETPID => sys_getpid(tf),
         NR_WRITE_STR => sys_write_str(tf.xn[0] as usize, tf.xn[1] as usize, tf),
+        NR_FORK => sys_fork(tf),
+        NR_EXEC => {
+            let (va, len) = (tf.xn[0] as usize, tf.xn[1] as usize);
+            let (argv_va, argc) = (tf.xn[2] as usize, tf.xn[3] as usize);
+            let (envp_va, envc) = (tf.xn[4] as usize, tf.xn[5] as usize);
+            sys_exec(va, len, argv_va, argc, envp_va, envc, tf);
+        }
+        NR_WAIT => sys_wait(tf),
+        NR_WAITPID => sys_waitpid(tf.xn[0], tf),
+        NR_BRK => sys_brk(tf.xn[0] as usize, tf),
+        NR_MMAP => sys_mmap(tf.xn[0] as usize, tf.xn[1], tf),
+        NR_MUNMAP => sys_munmap(tf.xn[0] as usize, tf.xn[1] as usize, tf),
+        NR_MPROTECT => sys_mprotect(tf.xn[0] as usize, tf.xn[1] as usize, tf.xn[2], tf),
+        NR_OPEN => sys_open(tf.xn[0] as usize, tf.xn[1] as usize, tf.xn[2], tf),
+        NR_READ => sys_read(tf.xn[0], tf.xn[1] as usize, tf.xn[2] as usize, tf),
+        NR_CLOSE => sys_close(tf.xn[0], tf),
+        NR_SEEK => sys_seek(tf.xn[0], tf.xn[1], tf.xn[2], tf),
+        NR_STAT => sys_stat(tf.xn[0] as usize, tf.xn[1] as usize, tf.xn[2] as usize, tf),
+        NR_FSTAT => sys_fstat(tf.xn[0], tf.xn[1] as usize, tf),
+        NR_READDIR => sys_readdir(tf.xn[0], tf.xn[1] as usize, tf),
+        NR_GETCWD => sys_getcwd(tf.xn[0] as usize, tf.xn[1] as usize, tf),
+        NR_CHDIR => sys_chdir(tf.xn[0] as usize, tf.xn[1] as usize, tf),
+        NR_MKDIR => sys_mkdir(tf.xn[0] as usize, tf.xn[1] as usize, tf),
+        NR_RMDIR => sys_rmdir(tf.xn[0] as usize, tf.xn[1] as usize, tf),
+        NR_UNLINK => sys_unlink(tf.xn[0] as usize, tf.xn[1] as usize, tf),
+        NR_RENAME => {
+            let (from_va, from_len) = (tf.xn[0] as usize, tf.xn[1] as usize);
+            let (to_va, to_len) = (tf.xn[2] as usize, tf.xn[3] as usize);
+            sys_rename(from_va, from_len, to_va, to_len, tf);
+        }
         NR_SOCK_CREATE => sys_sock_create(tf),
-        NR_SOCK_STATUS => sys_sock_status(tf.xn[0] as usize, tf),
+        NR_SOCK_STATUS => sys_sock_status(tf.xn[0], tf),
         NR_SOCK_CONNECT => {
         /*
             let ipaddr_be = tf.xn[1];
@@ -417,11 +1028,11 @@ pub fn handle_syscall(num: u16, tf: &mut TrapFrame) {
             let port = tf.xn[2] as u16;
             //trace!("connect from 0x{:x} ({}.{}.{}.{}): {}", ipaddr_be, a0, a1, a2, a3, port);
             trace!("connect from 0x{:x} ({}.{}.{}.{}): {}", tf.xn[1], bytes[0], bytes[1], bytes[2], bytes[3], port);
-            sys_sock_connect(tf.xn[0] as usize, IpEndpoint::new(ipaddr, port), tf);
+            sys_sock_connect(tf.xn[0], IpEndpoint::new(ipaddr, port), tf);
         }
-        NR_SOCK_LISTEN => sys_sock_listen(tf.xn[0] as usize, tf.xn[1] as u16, tf),
-        NR_SOCK_SEND => sys_sock_send(tf.xn[0] as usize, tf.xn[1] as usize, tf.xn[2] as usize, tf),
-        NR_SOCK_RECV => sys_sock_recv(tf.xn[0] as usize, tf.xn[1] as usize, tf.xn[2] as usize, tf),
+        NR_SOCK_LISTEN => sys_sock_listen(tf.xn[0], tf.xn[1] as u16, tf),
+        NR_SOCK_SEND => sys_sock_send(tf.xn[0], tf.xn[1] as usize, tf.xn[2] as usize, tf),
+        NR_SOCK_RECV => sys_sock_recv(tf.xn[0], tf.xn[1] as usize, tf.xn[2] as usize, tf),
         _ => unimplemented!("syscall {}", num),
     }
 }
@@ -3,16 +3,19 @@ use core::ops::{Deref, DerefMut, Drop};
 use core::slice::Iter;
 
 use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
 use alloc::fmt;
 use core::alloc::{GlobalAlloc, Layout};
 
 use crate::allocator;
+use crate::mutex::Mutex;
 use crate::param::*;
 use crate::vm::{PhysicalAddr, VirtualAddr};
 use crate::ALLOCATOR;
 //use crate::console::kprintln;
 
 use aarch64::vmsa::*;
+use aarch64::{tlb_invalidate_all, tlb_invalidate_va};
 use shim::const_assert_size;
 use pi::common::{IO_BASE, IO_BASE_END};
 
@@ -69,13 +72,57 @@ impl L3Entry {
     /// `PhysicalAddr` を、そうでなければ `None` を返す.
     fn get_page_addr(&self) -> Option<PhysicalAddr> {
         if self.is_valid() {
-            Some(PhysicalAddr::from(self.0.get_value(RawL3Entry::ADDR)))
+            Some(PhysicalAddr::from(self.0.get_masked(RawL3Entry::ADDR)))
         } else {
             None
         }
     }
+
+    /// 書き込み時にコピーするページを指している場合は `true` を返す.
+    fn is_cow(&self) -> bool {
+        self.is_valid() && self.0.get_value(RawL3Entry::COW) == 1
+    }
+}
+
+/// 複数のユーザページテーブルで共有されている物理ページの参照数.
+/// 表にないページは1つのページテーブルだけが所有している。
+struct PageRefs(Option<BTreeMap<u64, usize>>);
+
+impl PageRefs {
+    fn map(&mut self) -> &mut BTreeMap<u64, usize> {
+        self.0.get_or_insert_with(BTreeMap::new)
+    }
+
+    /// 物理ページ `addr` の参照数を返す.
+    fn count(&mut self, addr: u64) -> usize {
+        *self.map().get(&addr).unwrap_or(&1)
+    }
+
+    /// 物理ページ `addr` の参照数を1増やす.
+    fn get(&mut self, addr: u64) {
+        *self.map().entry(addr).or_insert(1) += 1;
+    }
+
+    /// 物理ページ `addr` の参照数を1減らす. 最後の参照だった場合は
+    /// `true` を返し、呼び出し側がページを解放する。
+    fn put(&mut self, addr: u64) -> bool {
+        let map = self.map();
+        match map.get_mut(&addr) {
+            Some(count) if *count > 2 => {
+                *count -= 1;
+                false
+            }
+            Some(_) => {
+                map.remove(&addr);
+                false
+            }
+            None => true,
+        }
+    }
 }
 
+static PAGE_REFS: Mutex<PageRefs> = Mutex::new(PageRefs(None));
+
 #[repr(C)]
 #[repr(align(65536))]
 pub struct L3PageTable {
@@ -200,6 +247,9 @@ impl KernPageTable {
     /// 各 L3 エントリにはアドレス [47:16] だけでなく、下位の属性[10:0] にも
     /// 正しい値をセットする必要がある。詳細は `vmsa.rs` にある `RawL3Entry` の
     /// 定義を参照.
+    ///
+    /// ユーザがカーネルのメモリを実行できないようにすべてのエントリに
+    /// `UXN` を、ペリフェラルのエントリには `PXN` もセットする。
     pub fn new() -> KernPageTable {
         let mut pt = PageTable::new(EntryPerm::KERN_RW);
         let (_start_addr, end_addr) = allocator::memory_map().unwrap();
@@ -214,6 +264,7 @@ impl KernPageTable {
             entry.set_value(EntryPerm::KERN_RW, RawL3Entry::AP);
             entry.set_value(EntryAttr::Mem, RawL3Entry::ATTR);
             entry.set_value(EntryType::Table, RawL3Entry::TYPE);
+            entry.set_bit(RawL3Entry::UXN);
             entry.set_bit(RawL3Entry::VALID);
             pt.set_entry(va, entry);
         }
@@ -226,6 +277,8 @@ impl KernPageTable {
             entry.set_value(EntryPerm::KERN_RW, RawL3Entry::AP);
             entry.set_value(EntryAttr::Dev, RawL3Entry::ATTR);
             entry.set_value(EntryType::Table, RawL3Entry::TYPE);
+            entry.set_bit(RawL3Entry::UXN);
+            entry.set_bit(RawL3Entry::PXN);
             entry.set_bit(RawL3Entry::VALID);
             pt.set_entry(va, entry);
         }
@@ -244,12 +297,73 @@ impl KernPageTable {
     }
 }
 
+/// ユーザページの権限. どの権限でもユーザは読み込みでき、カーネルは
+/// 実行できない。
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PagePerm {
     RW,
     RO,
+    RX,
     RWX,
 }
 
+impl PagePerm {
+    /// 書き込み可否と実行可否から権限を返す.
+    pub fn new(writable: bool, executable: bool) -> PagePerm {
+        match (writable, executable) {
+            (false, false) => PagePerm::RO,
+            (true, false) => PagePerm::RW,
+            (false, true) => PagePerm::RX,
+            (true, true) => PagePerm::RWX,
+        }
+    }
+
+    /// ユーザが書き込める場合は `true` を返す.
+    pub fn is_writable(self) -> bool {
+        match self {
+            PagePerm::RW | PagePerm::RWX => true,
+            PagePerm::RO | PagePerm::RX => false,
+        }
+    }
+
+    /// ユーザが実行できる場合は `true` を返す.
+    pub fn is_executable(self) -> bool {
+        match self {
+            PagePerm::RX | PagePerm::RWX => true,
+            PagePerm::RO | PagePerm::RW => false,
+        }
+    }
+
+    /// `self` と `other` の両方で許されている操作をすべて許す権限を返す.
+    pub fn union(self, other: PagePerm) -> PagePerm {
+        PagePerm::new(self.is_writable() || other.is_writable(),
+            self.is_executable() || other.is_executable())
+    }
+}
+
+/// L3エントリ `entry` の `AP` と `UXN` を権限 `perm` に合わせてセットし、
+/// カーネルがユーザページを実行しないように `PXN` をセットする. `shared`
+/// が `true` の場合、書き込み可能な権限でも読み込み専用にして書き込み時に
+/// コピーするページにする。
+fn set_user_perm(entry: &mut RawL3Entry, perm: PagePerm, shared: bool) {
+    if perm.is_writable() && !shared {
+        entry.set_value(EntryPerm::USER_RW, RawL3Entry::AP);
+    } else {
+        entry.set_value(EntryPerm::USER_RO, RawL3Entry::AP);
+    }
+    if perm.is_writable() && shared {
+        entry.set_bit(RawL3Entry::COW);
+    } else {
+        entry.clear_bit(RawL3Entry::COW);
+    }
+    if perm.is_executable() {
+        entry.clear_bit(RawL3Entry::UXN);
+    } else {
+        entry.set_bit(RawL3Entry::UXN);
+    }
+    entry.set_bit(RawL3Entry::PXN);
+}
+
 pub struct UserPageTable(Box<PageTable>);
 
 impl UserPageTable {
@@ -259,17 +373,14 @@ impl UserPageTable {
     }
 
     /// ページを割り当て、指定の仮想アドレスを割り当てたページの物理
-    /// アドレスに変換するL3エントリをセットする. 割り当てたページを
-    /// 返す.
+    /// アドレスに権限 `perm` で変換するL3エントリをセットする.
+    /// 割り当てたページを返す. アロケータがページの割当に失敗した場合は
+    /// `None` を返す。
     ///
     /// # パニック
     /// 仮想アドレスが `USER_IMG_BASE` 未満の場合はパニック.
     /// 仮想アドレスがすでに割り当てられていた場合はパニック.
-    /// アロケータがページの割当に失敗した場合はパニック.
-    ///
-    /// TODO. use Result<T> and make it failurable
-    /// TODO. use perm properly
-    pub fn alloc(&mut self, va: VirtualAddr, _perm: PagePerm) -> &mut [u8] {
+    pub fn alloc(&mut self, va: VirtualAddr, perm: PagePerm) -> Option<&mut [u8]> {
         if va.as_usize() < USER_IMG_BASE {
             panic!("va < USER_IMG_BASE: 0x{:x}", va.as_u64());
         }
@@ -281,20 +392,174 @@ impl UserPageTable {
         let addr = unsafe { ALLOCATOR.alloc(Page::layout()) as u64 };
         //kprintln!("allocated at 0x{:x}", addr);
         if addr == 0 {
-            panic!("allocation failed");
+            return None;
         }
         let mut entry = RawL3Entry::new(0);
         entry.set_masked(addr, RawL3Entry::ADDR);
         entry.set_bit(RawL3Entry::AF);
         entry.set_value(EntrySh::ISh, RawL3Entry::SH);
-        entry.set_value(EntryPerm::USER_RW, RawL3Entry::AP);
+        set_user_perm(&mut entry, perm, false);
         entry.set_value(EntryAttr::Mem, RawL3Entry::ATTR);
         entry.set_value(PageType::Page, RawL3Entry::TYPE);
         entry.set_bit(RawL3Entry::VALID);
 
         //kprintln!("{:?}", &entry);
         self.0.set_entry(va_offset, entry);
-        unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, Page::SIZE) }
+        Some(unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, Page::SIZE) })
+    }
+}
+
+impl UserPageTable {
+    /// このページテーブルの複製を返す. ページはコピーせずに両方の
+    /// ページテーブルで共有し、書き込み可能なページは読み込み専用に
+    /// してから最初の書き込み時に `handle_cow_fault()` でコピーする。
+    pub fn fork(&mut self) -> UserPageTable {
+        let mut child = UserPageTable::new();
+        let mut refs = PAGE_REFS.lock();
+        for i in 0..2 {
+            for j in 0..self.0.l3[i].entries.len() {
+                let entry = &mut self.0.l3[i].entries[j];
+                let addr = match entry.get_page_addr() {
+                    Some(addr) => addr.as_u64(),
+                    None => continue,
+                };
+                if entry.0.get_value(RawL3Entry::AP) == EntryPerm::USER_RW {
+                    entry.0.set_value(EntryPerm::USER_RO, RawL3Entry::AP);
+                    entry.0.set_bit(RawL3Entry::COW);
+                }
+                child.0.l3[i].entries[j] = *entry;
+                refs.get(addr);
+            }
+        }
+        // 書き込み可能だったページのTLBエントリが残らないようにする
+        tlb_invalidate_all();
+        child
+    }
+
+    /// 仮想アドレス `va` への書き込みで発生した権限フォールトを処理する.
+    /// `va` が書き込み時にコピーするページにある場合、ページが他の
+    /// ページテーブルと共有されていればコピーし、ページを書き込み可能に
+    /// して `true` を返す。それ以外の場合は `false` を返す。
+    pub fn handle_cow_fault(&mut self, va: VirtualAddr) -> bool {
+        if va.as_usize() < USER_IMG_BASE {
+            return false;
+        }
+        let page = VirtualAddr::from(va.as_usize() & !(PAGE_SIZE - 1));
+        let (l2_index, l3_index) = PageTable::locate(page - VirtualAddr::from(USER_IMG_BASE));
+        let entry = &mut self.0.l3[l2_index].entries[l3_index];
+        if !entry.is_cow() {
+            return false;
+        }
+
+        let addr = entry.get_page_addr().unwrap().as_u64();
+        let mut refs = PAGE_REFS.lock();
+        if refs.count(addr) > 1 {
+            let copy = unsafe { ALLOCATOR.alloc(Page::layout()) };
+            if copy.is_null() {
+                return false;
+            }
+            unsafe {
+                core::ptr::copy_nonoverlapping(addr as *const u8, copy, Page::SIZE);
+            }
+            refs.put(addr);
+            entry.0.set_masked(copy as u64, RawL3Entry::ADDR);
+        }
+        entry.0.set_value(EntryPerm::USER_RW, RawL3Entry::AP);
+        entry.0.clear_bit(RawL3Entry::COW);
+        tlb_invalidate_va(page.as_u64());
+        true
+    }
+
+    /// 仮想アドレス `va` を含む割り当て済みのページを返す. ページが書き込み時に
+    /// コピーするページの場合は先にコピーする。割り当てられていない場合は
+    /// `None` を返す。
+    pub fn get_page_mut(&mut self, va: VirtualAddr) -> Option<&mut [u8]> {
+        if va.as_usize() < USER_IMG_BASE {
+            return None;
+        }
+        self.handle_cow_fault(va);
+        let page = VirtualAddr::from(va.as_usize() & !(PAGE_SIZE - 1));
+        let (l2_index, l3_index) = PageTable::locate(page - VirtualAddr::from(USER_IMG_BASE));
+        let addr = self.0.l3[l2_index].entries[l3_index].get_page_addr()?;
+        Some(unsafe { core::slice::from_raw_parts_mut(addr.as_u64() as *mut u8, Page::SIZE) })
+    }
+
+    /// 仮想アドレス `va` を含むページが割り当て済みの場合は `true` を返す.
+    pub fn is_mapped(&self, va: VirtualAddr) -> bool {
+        if va.as_usize() < USER_IMG_BASE {
+            return false;
+        }
+        let page = VirtualAddr::from(va.as_usize() & !(PAGE_SIZE - 1));
+        self.0.is_valid(page - VirtualAddr::from(USER_IMG_BASE))
+    }
+
+    /// 仮想アドレス `va` を含むページが割り当て済みで、ユーザが書き込み
+    /// 可能な場合は `true` を返す. 書き込み時にコピーするページは
+    /// `handle_cow_fault()` でコピーするまで書き込み可能ではない。
+    pub fn is_writable(&self, va: VirtualAddr) -> bool {
+        if !self.is_mapped(va) {
+            return false;
+        }
+        let page = VirtualAddr::from(va.as_usize() & !(PAGE_SIZE - 1));
+        let (l2_index, l3_index) = PageTable::locate(page - VirtualAddr::from(USER_IMG_BASE));
+        self.0.l3[l2_index].entries[l3_index].0.get_value(RawL3Entry::AP) == EntryPerm::USER_RW
+    }
+
+    /// 仮想アドレス `va` を含む割り当て済みのページの権限を返す.
+    /// 書き込み時にコピーするページは書き込み可能とみなす。割り当てられて
+    /// いない場合は `None` を返す。
+    pub fn perm(&self, va: VirtualAddr) -> Option<PagePerm> {
+        if !self.is_mapped(va) {
+            return None;
+        }
+        let page = VirtualAddr::from(va.as_usize() & !(PAGE_SIZE - 1));
+        let (l2_index, l3_index) = PageTable::locate(page - VirtualAddr::from(USER_IMG_BASE));
+        let entry = &self.0.l3[l2_index].entries[l3_index];
+        let writable = entry.0.get_value(RawL3Entry::AP) == EntryPerm::USER_RW || entry.is_cow();
+        let executable = entry.0.get_value(RawL3Entry::UXN) == 0;
+        Some(PagePerm::new(writable, executable))
+    }
+
+    /// 仮想アドレス `va` を含む割り当て済みのページの権限を `perm` に変更する.
+    /// 他のページテーブルと共有しているページを書き込み可能にする場合は
+    /// 書き込み時にコピーするページにする。割り当てられていない場合は
+    /// 何もしない。
+    pub fn set_perm(&mut self, va: VirtualAddr, perm: PagePerm) {
+        if !self.is_mapped(va) {
+            return;
+        }
+        let page = VirtualAddr::from(va.as_usize() & !(PAGE_SIZE - 1));
+        let (l2_index, l3_index) = PageTable::locate(page - VirtualAddr::from(USER_IMG_BASE));
+        let entry = &mut self.0.l3[l2_index].entries[l3_index];
+        let addr = entry.get_page_addr().unwrap().as_u64();
+        let shared = PAGE_REFS.lock().count(addr) > 1;
+        set_user_perm(&mut entry.0, perm, shared);
+        tlb_invalidate_va(page.as_u64());
+    }
+
+    /// 仮想アドレス `va` を含むページの割り当てを解除する. 他のページ
+    /// テーブルと共有しているページは最後の参照がなくなったときに解放する。
+    /// 割り当てられていない場合は何もしない。
+    pub fn dealloc(&mut self, va: VirtualAddr) {
+        if !self.is_mapped(va) {
+            return;
+        }
+        let page = VirtualAddr::from(va.as_usize() & !(PAGE_SIZE - 1));
+        let (l2_index, l3_index) = PageTable::locate(page - VirtualAddr::from(USER_IMG_BASE));
+        let entry = &mut self.0.l3[l2_index].entries[l3_index];
+        let addr = entry.get_page_addr().unwrap().as_u64();
+        *entry = L3Entry::new();
+        tlb_invalidate_va(page.as_u64());
+        if PAGE_REFS.lock().put(addr) {
+            unsafe {
+                ALLOCATOR.dealloc(addr as *mut u8, Page::layout());
+            }
+        }
+    }
+
+    /// 割り当て済みのページ数を返す.
+    pub fn page_count(&self) -> usize {
+        self.0.into_iter().filter(|entry| entry.is_valid()).count()
     }
 }
 
@@ -328,12 +593,15 @@ impl DerefMut for UserPageTable {
 
 // FIXME: Implement `Drop` for `UserPageTable`.
 impl Drop for UserPageTable {
+    /// 他のページテーブルと共有していないページを解放する.
     fn drop(&mut self) {
+        let mut refs = PAGE_REFS.lock();
         for entry in self.0.into_iter() {
-            if entry.is_valid() {
-                let addr = entry.get_page_addr().unwrap();
-                unsafe {
-                    ALLOCATOR.dealloc(addr.as_u64() as *mut u8, Page::layout());
+            if let Some(addr) = entry.get_page_addr() {
+                if refs.put(addr.as_u64()) {
+                    unsafe {
+                        ALLOCATOR.dealloc(addr.as_u64() as *mut u8, Page::layout());
+                    }
                 }
             }
         }
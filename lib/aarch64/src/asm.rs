@@ -64,6 +64,32 @@ pub fn dmb() {
     unsafe { asm!("dmb sy" ::: "memory" : "volatile") };
 }
 
+/// 仮想アドレス `va` を含むページのEL1&0のTLBエントリを全ASID、全コアで
+/// 無効化する. ページテーブルエントリを変更した後に呼び出す。
+#[inline(always)]
+pub fn tlb_invalidate_va(va: u64) {
+    let operand = (va >> 12) & ((1 << 44) - 1);
+    unsafe {
+        asm!("dsb ishst
+              tlbi vaae1is, $0
+              dsb ish
+              isb"
+             :: "r"(operand) : "memory" : "volatile");
+    }
+}
+
+/// EL1&0のTLBエントリを全コアで無効化する.
+#[inline(always)]
+pub fn tlb_invalidate_all() {
+    unsafe {
+        asm!("dsb ishst
+              tlbi vmalle1is
+              dsb ish
+              isb"
+             ::: "memory" : "volatile");
+    }
+}
+
 /// Enable (unmask) interrupts
 #[inline(always)]
 pub fn enable_irq_interrupt() {
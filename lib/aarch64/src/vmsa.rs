@@ -61,6 +61,9 @@ defbit!(
 defbit!(
     RawL3Entry,
     [
+        COW[55 - 55],
+        UXN[54 - 54],
+        PXN[53 - 53],
         ADDR[47 - 16],
         AF[10 - 10],
         SH[09 - 08],
@@ -207,6 +210,18 @@ impl fmt::Debug for RawL3Entry {
             }
         )?;
 
+        if self.get_value(RawL3Entry::UXN) == 1 {
+            write!(f, "|UXN")?;
+        }
+
+        if self.get_value(RawL3Entry::PXN) == 1 {
+            write!(f, "|PXN")?;
+        }
+
+        if self.get_value(RawL3Entry::COW) == 1 {
+            write!(f, "|COW")?;
+        }
+
         // NS    [05-05],
 
         write!(f, "-> 0x{:08X}", self.get_masked(RawL3Entry::ADDR))
@@ -0,0 +1,101 @@
+use core::fmt;
+use shim::const_assert_size;
+
+use crate::traits::BlockDevice;
+use crate::vfat::Error;
+
+/// exFATのブートセクタ（メインブートセクタ）.
+#[repr(C, packed)]
+pub struct BootSector {
+    _jump_boot: [u8; 3],
+    pub file_system_name: [u8; 8],
+    _must_be_zero: [u8; 53],
+    pub partition_offset: u64,
+    pub volume_length: u64,
+    /// ボリュームの先頭から最初のFATまでのセクタ数.
+    pub fat_offset: u32,
+    /// 1つのFATのセクタ数.
+    pub fat_length: u32,
+    /// ボリュームの先頭からクラスタヒープまでのセクタ数.
+    pub cluster_heap_offset: u32,
+    pub cluster_count: u32,
+    pub first_cluster_of_root_directory: u32,
+    pub volume_serial_number: u32,
+    pub file_system_revision: u16,
+    pub volume_flags: u16,
+    pub bytes_per_sector_shift: u8,
+    pub sectors_per_cluster_shift: u8,
+    pub number_of_fats: u8,
+    pub drive_select: u8,
+    pub percent_in_use: u8,
+    _reserved: [u8; 7],
+    _boot_code: [u8; 390],
+    boot_signature: [u8; 2],
+}
+
+const_assert_size!(BootSector, 512);
+
+/// exFATのブートセクタのファイルシステム名.
+pub const FILE_SYSTEM_NAME: &[u8; 8] = b"EXFAT   ";
+
+impl BootSector {
+    /// デバイス `device` のセクタ `sector` からexFATのブートセクタを
+    /// 読み込む.
+    ///
+    /// # エラー
+    ///
+    /// ブートシグネチャまたはファイルシステム名が不正な場合、あるいは
+    /// ジオメトリが仕様の範囲外の場合は `BadSignature` エラーを返す。
+    pub fn from<T: BlockDevice>(mut device: T, sector: u64) -> Result<BootSector, Error> {
+        let mut block = [0_u8; 512];
+        device.read_sector(sector, &mut block).map_err(|e| Error::Io(e))?;
+
+        let boot: BootSector = unsafe { core::mem::transmute(block) };
+        if boot.boot_signature != [0x55_u8, 0xAA_u8] || &boot.file_system_name != FILE_SYSTEM_NAME {
+            return Err(Error::BadSignature);
+        }
+
+        // セクタは512バイトから4KiB、クラスタは32MiBまで
+        let bytes_per_sector_shift = boot.bytes_per_sector_shift;
+        let sectors_per_cluster_shift = boot.sectors_per_cluster_shift;
+        if bytes_per_sector_shift < 9 || bytes_per_sector_shift > 12
+            || bytes_per_sector_shift + sectors_per_cluster_shift > 25
+            || boot.number_of_fats == 0 || boot.number_of_fats > 2 {
+            return Err(Error::BadSignature);
+        }
+        Ok(boot)
+    }
+
+    pub fn bytes_per_sector(&self) -> u32 {
+        1 << self.bytes_per_sector_shift
+    }
+
+    pub fn sectors_per_cluster(&self) -> u32 {
+        1 << self.sectors_per_cluster_shift
+    }
+
+    /// 使用中のFATのインデックス (`VolumeFlags` の `ActiveFat`) を返す.
+    pub fn active_fat(&self) -> u8 {
+        if self.number_of_fats == 2 && self.volume_flags & 1 != 0 { 1 } else { 0 }
+    }
+}
+
+impl fmt::Debug for BootSector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BootSector")
+            .field("partition_offset", &{ self.partition_offset })
+            .field("volume_length", &{ self.volume_length })
+            .field("fat_offset", &{ self.fat_offset })
+            .field("fat_length", &{ self.fat_length })
+            .field("cluster_heap_offset", &{ self.cluster_heap_offset })
+            .field("cluster_count", &{ self.cluster_count })
+            .field("first_cluster_of_root_directory", &{ self.first_cluster_of_root_directory })
+            .field("volume_serial_number", &{ self.volume_serial_number })
+            .field("file_system_revision", &{ self.file_system_revision })
+            .field("volume_flags", &{ self.volume_flags })
+            .field("bytes_per_sector_shift", &{ self.bytes_per_sector_shift })
+            .field("sectors_per_cluster_shift", &{ self.sectors_per_cluster_shift })
+            .field("number_of_fats", &{ self.number_of_fats })
+            .finish()
+    }
+}
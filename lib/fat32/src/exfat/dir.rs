@@ -0,0 +1,221 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use shim::const_assert_size;
+use shim::ffi::OsStr;
+use shim::io;
+use shim::ioerr;
+
+use crate::exfat::{Entry, ExFatHandle, File, Stream};
+use crate::traits;
+use crate::vfat::{Attributes, Metadata, Timestamp};
+
+#[derive(Debug)]
+pub struct Dir<HANDLE: ExFatHandle> {
+    pub exfat: HANDLE,
+    pub name: String,
+    pub metadata: Metadata,
+    stream: Stream,
+}
+
+/// ファイルディレクトリエントリ (タイプ 0x85).
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+struct ExFatFileDirEntry {
+    entry_type: u8,
+    secondary_count: u8,
+    set_checksum: u16,
+    file_attributes: u16,
+    __reserved_0: u16,
+    create_timestamp: u32,
+    last_modified_timestamp: u32,
+    last_accessed_timestamp: u32,
+    __reserved_1: [u8; 12],
+}
+
+const_assert_size!(ExFatFileDirEntry, 32);
+
+/// ストリーム拡張ディレクトリエントリ (タイプ 0xC0).
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+struct ExFatStreamDirEntry {
+    entry_type: u8,
+    general_flags: u8,
+    __reserved_0: u8,
+    name_length: u8,
+    name_hash: u16,
+    __reserved_1: u16,
+    valid_data_length: u64,
+    __reserved_2: u32,
+    first_cluster: u32,
+    data_length: u64,
+}
+
+const_assert_size!(ExFatStreamDirEntry, 32);
+
+/// ファイル名ディレクトリエントリ (タイプ 0xC1).
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+struct ExFatNameDirEntry {
+    entry_type: u8,
+    general_flags: u8,
+    file_name: [u16; 15],
+}
+
+const_assert_size!(ExFatNameDirEntry, 32);
+
+const ENTRY_SIZE: usize = 32;
+
+const FILE_ENTRY: u8 = 0x85;
+const STREAM_ENTRY: u8 = 0xC0;
+const NAME_ENTRY: u8 = 0xC1;
+
+/// ストリーム拡張エントリの `NoFatChain` フラグ.
+const NO_FAT_CHAIN: u8 = 0x02;
+
+/// ディレクトリ属性.
+const ATTR_DIRECTORY: u16 = 0x10;
+
+/// 32バイトのエントリ `bytes` を `T` として読み込む.
+fn read_entry<T: Copy>(bytes: &[u8]) -> T {
+    assert!(bytes.len() >= size_of::<T>());
+    unsafe { core::ptr::read_unaligned(bytes.as_ptr() as *const T) }
+}
+
+/// ディレクトリエントリセット `set` のチェックサムを計算する.
+/// 先頭エントリのチェックサムフィールド (バイト2,3) は計算から除く。
+fn set_checksum(set: &[u8]) -> u16 {
+    set.iter().enumerate()
+        .filter(|&(i, _)| i != 2 && i != 3)
+        .fold(0_u16, |sum, (_, &byte)| sum.rotate_right(1).wrapping_add(byte as u16))
+}
+
+/// ディレクトリの中身 `buf` を解析してエントリのリストを返す.
+///
+/// 削除済みのエントリ (使用中ビット 0x80 が0のもの) やファイル以外の
+/// プライマリエントリ (ボリュームラベル、アロケーションビットマップなど)
+/// は読み飛ばす。
+/// チェックサムが一致しないエントリセットはエラーとする。
+fn parse_entries<HANDLE: ExFatHandle>(exfat: &HANDLE, buf: &[u8]) -> io::Result<Vec<Entry<HANDLE>>> {
+    let mut entries = Vec::new();
+    let mut i = 0;
+
+    while i + ENTRY_SIZE <= buf.len() {
+        let entry_type = buf[i];
+        // タイプ0はディレクトリの終わり
+        if entry_type == 0 {
+            break;
+        }
+        if entry_type != FILE_ENTRY {
+            i += ENTRY_SIZE;
+            continue;
+        }
+
+        let file: ExFatFileDirEntry = read_entry(&buf[i..]);
+        let set_len = (file.secondary_count as usize + 1) * ENTRY_SIZE;
+        if file.secondary_count < 2 || i + set_len > buf.len() {
+            return ioerr!(InvalidData, "malformed directory entry set");
+        }
+        let set = &buf[i..i + set_len];
+        if set_checksum(set) != file.set_checksum {
+            return ioerr!(InvalidData, "directory entry set checksum mismatch");
+        }
+
+        let stream: ExFatStreamDirEntry = read_entry(&set[ENTRY_SIZE..]);
+        if stream.entry_type != STREAM_ENTRY {
+            return ioerr!(InvalidData, "missing stream extension entry");
+        }
+
+        let mut name_units: Vec<u16> = Vec::new();
+        for raw in set[2 * ENTRY_SIZE..].chunks(ENTRY_SIZE) {
+            if raw[0] != NAME_ENTRY {
+                continue;
+            }
+            let name: ExFatNameDirEntry = read_entry(raw);
+            name_units.extend_from_slice(&{ name.file_name });
+        }
+        name_units.truncate(stream.name_length as usize);
+        let name: String = core::char::decode_utf16(name_units.iter().cloned())
+            .map(|c| c.unwrap_or(core::char::REPLACEMENT_CHARACTER))
+            .collect();
+
+        let metadata = Metadata {
+            attributes: Attributes::from(file.file_attributes as u8),
+            created: Timestamp::from_raw(file.create_timestamp),
+            accessed: Timestamp::from_raw(file.last_accessed_timestamp),
+            modified: Timestamp::from_raw(file.last_modified_timestamp),
+        };
+        let data = Stream {
+            first_cluster: stream.first_cluster,
+            data_length: Some(stream.data_length),
+            valid_data_length: stream.valid_data_length,
+            no_fat_chain: stream.general_flags & NO_FAT_CHAIN != 0,
+        };
+
+        if file.file_attributes & ATTR_DIRECTORY != 0 {
+            entries.push(Entry::Dir(Dir {
+                exfat: exfat.clone(),
+                name,
+                metadata,
+                stream: data,
+            }));
+        } else {
+            entries.push(Entry::File(File::new(exfat.clone(), name, metadata, data)));
+        }
+
+        i += set_len;
+    }
+
+    Ok(entries)
+}
+
+impl<HANDLE: ExFatHandle> Dir<HANDLE> {
+    /// ルートディレクトリを返す
+    pub fn root(exfat: HANDLE) -> Dir<HANDLE> {
+        let stream = exfat.lock(|fs| fs.root_stream());
+
+        Dir {
+            exfat,
+            name: String::from("/"),
+            metadata: Default::default(),
+            stream,
+        }
+    }
+
+    /// `self` からエントリ名 `name` を見つけてそれを返す.
+    /// 比較は大文字小文字を区別しない。
+    ///
+    /// # エラー
+    ///
+    /// `self` にエントリ名 `name` がない場合はエラー `NotFound` を
+    /// 返す。
+    ///
+    /// `name` に不正なUTF-8文字が含まれている場合はエラー
+    /// `InvalidInput` を返す。
+    pub fn find<P: AsRef<OsStr>>(&self, name: P) -> io::Result<Entry<HANDLE>> {
+        use crate::traits::{Dir, Entry};
+
+        let name = name.as_ref().to_str().ok_or(io::ErrorKind::InvalidInput)?;
+
+        for entry in self.entries()? {
+            if str::eq_ignore_ascii_case(entry.name(), name) {
+                return Ok(entry);
+            }
+        }
+
+        ioerr!(NotFound, "file not found")
+    }
+}
+
+impl<HANDLE: ExFatHandle> traits::Dir for Dir<HANDLE> {
+    type Entry = Entry<HANDLE>;
+    type Iter = alloc::vec::IntoIter<Entry<HANDLE>>;
+
+    /// このディレクトリのエントリを走査するイテレータを返す.
+    fn entries(&self) -> io::Result<Self::Iter> {
+        let mut buf = Vec::new();
+        self.exfat.lock(|fs| fs.read_all(&self.stream, &mut buf))?;
+        Ok(parse_entries(&self.exfat, &buf)?.into_iter())
+    }
+}
@@ -0,0 +1,256 @@
+use core::fmt::Debug;
+use core::marker::PhantomData;
+
+use alloc::vec::Vec;
+
+use shim::io;
+use shim::ioerr;
+use shim::path::{Component, Path};
+
+use crate::exfat::boot::{BootSector, FILE_SYSTEM_NAME};
+use crate::exfat::{Dir, Entry, File};
+use crate::traits::{BlockDevice, FileSystem};
+use crate::vfat::vfat::find_partition;
+use crate::vfat::{CachedPartition, Error, Partition};
+
+/// クロージャとしてクリティカルセクションを処理するジェネリックトレイト
+pub trait ExFatHandle: Clone + Debug + Send + Sync {
+    fn new(val: ExFat<Self>) -> Self;
+    fn lock<R>(&self, f: impl FnOnce(&mut ExFat<Self>) -> R) -> R;
+}
+
+/// exFATのMBRパーティションタイプ (NTFSと共用).
+const EXFAT_PARTITION_TYPE: u8 = 0x07;
+
+/// チェーンの終端を表すFATエントリの値.
+const EOC_MARKER: u32 = 0xFFFF_FFFF;
+
+/// 読み込み専用のexFATファイルシステム.
+#[derive(Debug)]
+pub struct ExFat<HANDLE: ExFatHandle> {
+    phantom: PhantomData<HANDLE>,
+    device: CachedPartition,
+    bytes_per_sector: u32,
+    sectors_per_cluster: u32,
+    fat_start_sector: u64,
+    heap_start_sector: u64,
+    cluster_count: u32,
+    root_cluster: u32,
+}
+
+/// クラスタヒープ上に置かれたデータ（ファイルまたはディレクトリの中身）.
+#[derive(Copy, Clone, Debug)]
+pub struct Stream {
+    pub first_cluster: u32,
+    /// データのバイト数. `None` の場合はFATのチェーン全体 (ルート
+    /// ディレクトリ).
+    pub data_length: Option<u64>,
+    /// 書き込み済みのバイト数. これ以降は0として読まれる.
+    pub valid_data_length: u64,
+    /// `true` の場合、クラスタは連続しておりFATは参照しない.
+    pub no_fat_chain: bool,
+}
+
+/// `Stream` 内の位置とそれに対応するクラスタ.
+#[derive(Copy, Clone, Debug)]
+pub struct StreamHandle {
+    /// ストリームの先頭からのクラスタのインデックス.
+    pub index: u64,
+    pub cluster: u32,
+}
+
+/// exFATボリュームへのハンドル. `FileSystem` はこの型の参照に対して
+/// 実装される.
+#[derive(Clone, Debug)]
+pub struct Volume<HANDLE: ExFatHandle>(pub HANDLE);
+
+impl<HANDLE: ExFatHandle> ExFat<HANDLE> {
+    /// `device` からexFATパーティションを探し、そのボリュームを返す.
+    ///
+    /// MBRでフォーマットされたディスクではパーティションタイプが 0x07 の、
+    /// GPTでフォーマットされたディスクでは Basic Data タイプのパーティション
+    /// のうち、ブートセクタのファイルシステム名が "EXFAT" であるものが
+    /// 使用される。
+    pub fn from<T>(mut device: T) -> Result<Volume<HANDLE>, Error>
+    where
+        T: BlockDevice + 'static,
+    {
+        let (start, num_sectors) = find_partition(&mut device, &[EXFAT_PARTITION_TYPE],
+            |boot| &boot[3..11] == FILE_SYSTEM_NAME)?;
+
+        let boot = BootSector::from(&mut device, start)?;
+        let fat_start_sector = boot.fat_offset as u64
+            + boot.active_fat() as u64 * boot.fat_length as u64;
+
+        Ok(Volume(HANDLE::new(ExFat {
+            phantom: PhantomData,
+            device: CachedPartition::new(device, Partition {
+                start,
+                num_sectors,
+                sector_size: boot.bytes_per_sector() as u64,
+            }),
+            bytes_per_sector: boot.bytes_per_sector(),
+            sectors_per_cluster: boot.sectors_per_cluster(),
+            fat_start_sector,
+            heap_start_sector: boot.cluster_heap_offset as u64,
+            cluster_count: boot.cluster_count,
+            root_cluster: boot.first_cluster_of_root_directory,
+        })))
+    }
+
+    /// クラスタのバイト単位のサイズを返す
+    pub fn cluster_size_bytes(&self) -> usize {
+        (self.bytes_per_sector * self.sectors_per_cluster) as usize
+    }
+
+    /// ルートディレクトリの `Stream` を返す.
+    pub fn root_stream(&self) -> Stream {
+        Stream {
+            first_cluster: self.root_cluster,
+            data_length: None,
+            valid_data_length: u64::max_value(),
+            no_fat_chain: false,
+        }
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster - 2 < self.cluster_count
+    }
+
+    /// クラスタ `cluster` の開始セクタを返す
+    fn cluster_start(&self, cluster: u32) -> u64 {
+        self.heap_start_sector + (cluster as u64 - 2) * self.sectors_per_cluster as u64
+    }
+
+    /// チェーンの中で `cluster` の次のクラスタを返す. `cluster` が
+    /// チェーンの最後のクラスタの場合は `None` を返す.
+    fn next_cluster(&mut self, cluster: u32) -> io::Result<Option<u32>> {
+        let offset = cluster as u64 * 4;
+        let bytes_per_sector = self.bytes_per_sector as u64;
+        let sector = self.device.get(self.fat_start_sector + offset / bytes_per_sector)?;
+        let pos = (offset % bytes_per_sector) as usize;
+        let next = u32::from_le_bytes([sector[pos], sector[pos + 1], sector[pos + 2], sector[pos + 3]]);
+
+        match next {
+            EOC_MARKER => Ok(None),
+            next if self.is_valid_cluster(next) => Ok(Some(next)),
+            _ => ioerr!(Other, "unexpected fat entry"),
+        }
+    }
+
+    /// `stream` の `index` 番目のクラスタを返す. `hint` が `index` より
+    /// 前の位置を指している場合はそこからチェーンをたどる.
+    ///
+    /// チェーンが `index` に達する前に終わった場合は `None` を返す.
+    fn stream_cluster(&mut self, stream: &Stream, index: u64,
+        hint: Option<StreamHandle>) -> io::Result<Option<StreamHandle>> {
+        if stream.no_fat_chain {
+            let cluster = stream.first_cluster as u64 + index;
+            if cluster > u32::max_value() as u64 || !self.is_valid_cluster(cluster as u32) {
+                return ioerr!(Other, "stream exceeds cluster heap");
+            }
+            return Ok(Some(StreamHandle { index, cluster: cluster as u32 }));
+        }
+
+        let mut handle = match hint {
+            Some(hint) if hint.index <= index => hint,
+            _ => StreamHandle { index: 0, cluster: stream.first_cluster },
+        };
+        while handle.index < index {
+            match self.next_cluster(handle.cluster)? {
+                Some(next) => handle = StreamHandle { index: handle.index + 1, cluster: next },
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(handle))
+    }
+
+    /// `stream` の先頭から `offset` バイトの位置から `buf` に読み込む.
+    /// 読み込んだバイト数と最後に読み込んだクラスタの位置を返す.
+    ///
+    /// `valid_data_length` 以降のデータは0として読まれる。
+    pub fn read_stream(&mut self, stream: &Stream, offset: u64, buf: &mut [u8],
+        mut hint: Option<StreamHandle>) -> io::Result<(usize, Option<StreamHandle>)> {
+        let cluster_size = self.cluster_size_bytes() as u64;
+        let bytes_per_sector = self.bytes_per_sector as u64;
+        let end = match stream.data_length {
+            Some(length) => core::cmp::min(length, offset + buf.len() as u64),
+            None => offset + buf.len() as u64,
+        };
+
+        let mut pos = offset;
+        while pos < end {
+            let handle = match self.stream_cluster(stream, pos / cluster_size, hint)? {
+                Some(handle) => handle,
+                None => break,
+            };
+            hint = Some(handle);
+
+            let in_cluster = pos % cluster_size;
+            let sector = self.cluster_start(handle.cluster) + in_cluster / bytes_per_sector;
+            let in_sector = (in_cluster % bytes_per_sector) as usize;
+            let amt = core::cmp::min(bytes_per_sector as usize - in_sector, (end - pos) as usize);
+
+            let dst = &mut buf[(pos - offset) as usize..][..amt];
+            if pos >= stream.valid_data_length {
+                for byte in dst.iter_mut() {
+                    *byte = 0;
+                }
+            } else {
+                dst.copy_from_slice(&self.device.get(sector)?[in_sector..in_sector + amt]);
+                let valid = stream.valid_data_length.saturating_sub(pos);
+                if (amt as u64) > valid {
+                    for byte in dst[valid as usize..].iter_mut() {
+                        *byte = 0;
+                    }
+                }
+            }
+            pos += amt as u64;
+        }
+
+        Ok(((pos - offset) as usize, hint))
+    }
+
+    /// `stream` のデータをすべてベクタに読み込む.
+    pub fn read_all(&mut self, stream: &Stream, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let cluster_size = self.cluster_size_bytes();
+        let mut hint = None;
+        let mut total = 0;
+        loop {
+            let start = buf.len();
+            buf.resize(start + cluster_size, 0);
+            let (read, next) = self.read_stream(stream, total as u64, &mut buf[start..], hint)?;
+            buf.truncate(start + read);
+            total += read;
+            hint = next;
+            if read < cluster_size {
+                return Ok(total);
+            }
+        }
+    }
+}
+
+impl<'a, HANDLE: ExFatHandle> FileSystem for &'a Volume<HANDLE> {
+    type File = File<HANDLE>;
+    type Dir = Dir<HANDLE>;
+    type Entry = Entry<HANDLE>;
+
+    fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry> {
+        use crate::traits::Entry as TraitEntry;
+
+        let mut entry: Entry<HANDLE> = Entry::Dir(Dir::root(self.0.clone()));
+
+        for component in path.as_ref().components() {
+            match component {
+                Component::RootDir => entry = Entry::Dir(Dir::root(self.0.clone())),
+                Component::Normal(s) => match entry.as_dir() {
+                    Some(d) => entry = d.find(s)?,
+                    None => return ioerr!(PermissionDenied, "found file in path traversal"),
+                }
+                _ => return ioerr!(InvalidInput, "unexpected path item"),
+            }
+        }
+
+        Ok(entry)
+    }
+}
@@ -0,0 +1,94 @@
+use alloc::string::String;
+
+use shim::io::{self, SeekFrom};
+use shim::ioerr;
+
+use crate::exfat::exfat::StreamHandle;
+use crate::exfat::{ExFatHandle, Stream};
+use crate::traits;
+use crate::vfat::Metadata;
+
+#[derive(Debug)]
+pub struct File<HANDLE: ExFatHandle> {
+    pub exfat: HANDLE,
+    pub name: String,
+    pub metadata: Metadata,
+    stream: Stream,
+    offset: u64,
+    pointer: Option<StreamHandle>,
+}
+
+impl<HANDLE: ExFatHandle> File<HANDLE> {
+    pub(crate) fn new(exfat: HANDLE, name: String, metadata: Metadata,
+        stream: Stream) -> File<HANDLE> {
+        File {
+            exfat,
+            name,
+            metadata,
+            stream,
+            offset: 0,
+            pointer: None,
+        }
+    }
+}
+
+impl<HANDLE: ExFatHandle> traits::File for File<HANDLE> {
+    /// 読み込み専用なので書き出すデータはない.
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// ファイルのバイト単位のサイズを返す.
+    fn size(&self) -> u64 {
+        self.stream.data_length.unwrap_or(0)
+    }
+}
+
+impl<HANDLE: ExFatHandle> io::Write for File<HANDLE> {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        ioerr!(PermissionDenied, "read only file system")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<HANDLE: ExFatHandle> io::Read for File<HANDLE> {
+    /// ファイルの現在位置から `buf` に読み込む. `valid_data_length`
+    /// 以降の領域は0として読まれる。
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (read, pointer) = self.exfat.lock(|fs|
+            fs.read_stream(&self.stream, self.offset, buf, self.pointer))?;
+        self.offset += read as u64;
+        self.pointer = pointer;
+        Ok(read)
+    }
+}
+
+impl<HANDLE: ExFatHandle> io::Seek for File<HANDLE> {
+    /// ファイルのオフセット `pos` にシークする.
+    ///
+    /// ファイルの末尾へのシークを可能にする。ファイル終端を
+    /// _超える_ シークは エラー `InvalidInput` を返す。
+    ///
+    /// # エラー
+    ///
+    /// ファイルの開始点の前、または終端の後ろにシークしようとすると
+    /// エラー `InvalidInput` となる。
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        use crate::traits::File;
+
+        let offset = match pos {
+            SeekFrom::Start(start) => start as i64,
+            SeekFrom::End(end) => self.size() as i64 + end,
+            SeekFrom::Current(current) => self.offset as i64 + current,
+        };
+        if offset < 0 || offset as u64 > self.size() {
+            return ioerr!(InvalidInput, "cannot seek outside of file");
+        }
+
+        self.offset = offset as u64;
+        Ok(self.offset)
+    }
+}
@@ -0,0 +1,310 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem::size_of;
+use shim::const_assert_size;
+use shim::io;
+
+use crate::mbr::{self, MasterBootRecord};
+use crate::traits::BlockDevice;
+
+/// GUID. ディスク上の表現（先頭3フィールドがリトルエンディアン）の
+/// バイト列をそのまま保持する.
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Guid(pub [u8; 16]);
+
+impl Guid {
+    /// 未使用のパーティションエントリを表すGUID.
+    pub const UNUSED: Guid = Guid([0; 16]);
+
+    /// Basic Data パーティション (EBD0A0A2-B9E5-4433-87C0-68B6B72699C7).
+    /// FATファイルシステムは通常このタイプのパーティションに置かれる。
+    pub const BASIC_DATA: Guid = Guid([
+        0xA2, 0xA0, 0xD0, 0xEB, 0xE5, 0xB9, 0x33, 0x44,
+        0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7,
+    ]);
+}
+
+impl fmt::Debug for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let b = &self.0;
+        write!(
+            f,
+            "{:02X}{:02X}{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-",
+            b[3], b[2], b[1], b[0], b[5], b[4], b[7], b[6], b[8], b[9]
+        )?;
+        for byte in b[10..].iter() {
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+const_assert_size!(Guid, 16);
+
+/// GPTヘッダ.
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct GptHeader {
+    signature: [u8; 8],
+    pub revision: u32,
+    pub header_size: u32,
+    header_crc32: u32,
+    _reserved: u32,
+    /// このヘッダ自身が置かれているLBA.
+    pub current_lba: u64,
+    /// もう一方（プライマリならバックアップ）のヘッダが置かれているLBA.
+    pub backup_lba: u64,
+    pub first_usable_lba: u64,
+    pub last_usable_lba: u64,
+    pub disk_guid: Guid,
+    /// パーティションエントリ配列の開始LBA.
+    pub partition_entry_lba: u64,
+    pub num_partition_entries: u32,
+    pub partition_entry_size: u32,
+    partition_entries_crc32: u32,
+}
+
+impl fmt::Debug for GptHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GptHeader")
+            .field("revision", &{ self.revision })
+            .field("current_lba", &{ self.current_lba })
+            .field("backup_lba", &{ self.backup_lba })
+            .field("first_usable_lba", &{ self.first_usable_lba })
+            .field("last_usable_lba", &{ self.last_usable_lba })
+            .field("disk_guid", &{ self.disk_guid })
+            .field("partition_entry_lba", &{ self.partition_entry_lba })
+            .field("num_partition_entries", &{ self.num_partition_entries })
+            .field("partition_entry_size", &{ self.partition_entry_size })
+            .finish()
+    }
+}
+
+const_assert_size!(GptHeader, 92);
+
+/// GPTのパーティションエントリ.
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct GptPartitionEntry {
+    pub type_guid: Guid,
+    pub unique_guid: Guid,
+    pub first_lba: u64,
+    /// パーティションの最後のLBA（このLBAを含む）.
+    pub last_lba: u64,
+    pub attributes: u64,
+    name: [u16; 36],
+}
+
+impl GptPartitionEntry {
+    /// このエントリが使用されている場合は `true` を返す.
+    pub fn is_used(&self) -> bool {
+        self.type_guid != Guid::UNUSED
+    }
+
+    /// パーティションのセクタ数を返す.
+    pub fn num_sectors(&self) -> u64 {
+        (self.last_lba + 1).saturating_sub(self.first_lba)
+    }
+
+    /// UTF-16LEで格納されているパーティション名を返す.
+    pub fn name(&self) -> String {
+        let name = self.name;
+        let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+        core::char::decode_utf16(name[..len].iter().cloned())
+            .map(|c| c.unwrap_or(core::char::REPLACEMENT_CHARACTER))
+            .collect()
+    }
+}
+
+impl fmt::Debug for GptPartitionEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GptPartitionEntry")
+            .field("type_guid", &{ self.type_guid })
+            .field("unique_guid", &{ self.unique_guid })
+            .field("first_lba", &{ self.first_lba })
+            .field("last_lba", &{ self.last_lba })
+            .field("attributes", &{ self.attributes })
+            .field("name", &self.name())
+            .finish()
+    }
+}
+
+const_assert_size!(GptPartitionEntry, 128);
+
+/// 保護MBRのパーティションタイプ.
+pub const PROTECTIVE_PARTITION_TYPE: u8 = 0xEE;
+
+/// GPTヘッダのマジックシグネチャ.
+const SIGNATURE: &[u8; 8] = b"EFI PART";
+
+/// 受け付けるパーティションエントリの最大数. 通常のGPTは128個である。
+const MAX_PARTITION_ENTRIES: u32 = 1024;
+
+/// 受け付けるパーティションエントリの最大バイト数.
+const MAX_PARTITION_ENTRY_SIZE: usize = 4096;
+
+#[derive(Debug)]
+pub enum Error {
+    /// GPTの読み込み中にI/Oエラーがあった.
+    Io(io::Error),
+    /// 保護MBRの読み込みに失敗した.
+    Mbr(mbr::Error),
+    /// MBRが保護MBR (タイプ 0xEE のパーティション) ではない.
+    NotProtective,
+    /// GPTヘッダのマジックシグネチャが無効.
+    BadSignature,
+    /// GPTヘッダのサイズ、位置、エントリサイズ、エントリ数が不正.
+    BadHeader,
+    /// GPTヘッダのCRC32が一致しない.
+    BadHeaderChecksum,
+    /// パーティションエントリ配列のCRC32が一致しない.
+    BadEntriesChecksum,
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+impl From<mbr::Error> for Error {
+    fn from(error: mbr::Error) -> Error {
+        Error::Mbr(error)
+    }
+}
+
+/// GUIDパーティションテーブル (GPT).
+#[derive(Debug)]
+pub struct GuidPartitionTable {
+    pub header: GptHeader,
+    pub partitions: Vec<GptPartitionEntry>,
+    /// プライマリヘッダが壊れていたためバックアップヘッダから
+    /// 読み込んだ場合は `true`.
+    pub from_backup: bool,
+}
+
+impl GuidPartitionTable {
+    /// `device` からGPTを読み込んで返す.
+    ///
+    /// まずセクタ0の保護MBRを読み込み、続けてLBA 1のプライマリヘッダと
+    /// そのパーティションエントリ配列を読み込む。プライマリヘッダまたは
+    /// エントリ配列が壊れていた場合はディスク末尾のバックアップヘッダから
+    /// 読み込む。
+    ///
+    /// # エラー
+    ///
+    /// 保護MBRが存在しない場合は `NotProtective` を返す。プライマリと
+    /// バックアップの両方が壊れている場合はプライマリを読み込んだ際の
+    /// エラーを返す。
+    pub fn from<T: BlockDevice>(mut device: T) -> Result<GuidPartitionTable, Error> {
+        let mbr = MasterBootRecord::from(&mut device)?;
+        GuidPartitionTable::from_mbr(device, &mbr)
+    }
+
+    /// 読み込み済みの保護MBR `mbr` を使って `device` からGPTを読み込む.
+    ///
+    /// # エラー
+    ///
+    /// `from()` と同様.
+    pub fn from_mbr<T: BlockDevice>(
+        mut device: T,
+        mbr: &MasterBootRecord,
+    ) -> Result<GuidPartitionTable, Error> {
+        let protective = mbr.partitions.iter()
+            .find(|p| p.partition_type == PROTECTIVE_PARTITION_TYPE)
+            .ok_or(Error::NotProtective)?;
+
+        let primary_error = match read_table(&mut device, 1) {
+            Ok((header, partitions)) => {
+                return Ok(GuidPartitionTable { header, partitions, from_backup: false });
+            }
+            Err(e) => e,
+        };
+
+        // 保護パーティションはLBA 1からディスクの最後までを覆っている
+        let last_lba = (protective.relative_sector as u64 + protective.total_sectores as u64)
+            .saturating_sub(1);
+        match read_table(&mut device, last_lba) {
+            Ok((header, partitions)) => {
+                Ok(GuidPartitionTable { header, partitions, from_backup: true })
+            }
+            Err(Error::Io(e)) => Err(Error::Io(e)),
+            Err(_) => Err(primary_error),
+        }
+    }
+
+    /// 使用されているパーティションエントリのイテレータを返す.
+    pub fn used_partitions(&self) -> impl Iterator<Item = &GptPartitionEntry> {
+        self.partitions.iter().filter(|p| p.is_used())
+    }
+}
+
+/// LBA `lba` のGPTヘッダとそのパーティションエントリ配列を読み込み、
+/// それぞれのCRC32を検証する.
+fn read_table<T: BlockDevice>(
+    device: &mut T,
+    lba: u64,
+) -> Result<(GptHeader, Vec<GptPartitionEntry>), Error> {
+    let sector_size = device.sector_size() as usize;
+    let mut sector = vec![0u8; sector_size];
+    device.read_sector(lba, &mut sector)?;
+
+    let header: GptHeader = unsafe {
+        core::ptr::read_unaligned(sector.as_ptr() as *const GptHeader)
+    };
+    if &header.signature != SIGNATURE {
+        return Err(Error::BadSignature);
+    }
+
+    let header_size = header.header_size as usize;
+    // 別の位置にあるべきヘッダのコピーは受け付けない
+    if header_size < size_of::<GptHeader>() || header_size > sector_size
+        || header.current_lba != lba {
+        return Err(Error::BadHeader);
+    }
+    // CRC32はヘッダのCRCフィールドを0として計算する
+    sector[16..20].copy_from_slice(&[0; 4]);
+    if crc32(&sector[..header_size]) != header.header_crc32 {
+        return Err(Error::BadHeaderChecksum);
+    }
+
+    let entry_size = header.partition_entry_size as usize;
+    if entry_size < size_of::<GptPartitionEntry>() || entry_size > MAX_PARTITION_ENTRY_SIZE
+        || entry_size % 8 != 0 || header.num_partition_entries > MAX_PARTITION_ENTRIES {
+        return Err(Error::BadHeader);
+    }
+
+    let array_size = (header.num_partition_entries as usize)
+        .checked_mul(entry_size)
+        .ok_or(Error::BadHeader)?;
+    let num_sectors = (array_size + sector_size - 1) / sector_size;
+    let mut array = vec![0u8; num_sectors * sector_size];
+    device.read_sectors(header.partition_entry_lba, &mut array)?;
+    if crc32(&array[..array_size]) != header.partition_entries_crc32 {
+        return Err(Error::BadEntriesChecksum);
+    }
+
+    let partitions = array[..array_size]
+        .chunks(entry_size)
+        .map(|entry| unsafe {
+            core::ptr::read_unaligned(entry.as_ptr() as *const GptPartitionEntry)
+        })
+        .collect();
+
+    Ok((header, partitions))
+}
+
+/// `data` のCRC32 (IEEE 802.3, 反転多項式 0xEDB88320) を計算する.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
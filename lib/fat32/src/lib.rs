@@ -15,6 +15,10 @@ mod mbr;
 mod tests;
 mod util;
 
+#[cfg(feature = "exfat")]
+pub mod exfat;
+pub mod gpt;
+pub mod mkfs;
 pub mod traits;
 pub mod vfat;
 
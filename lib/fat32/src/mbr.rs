@@ -15,6 +15,9 @@ pub struct CHS {
 }
 
 impl CHS {
+    /// LBAでのみアドレス指定されるパーティションに用いるCHS値.
+    pub const LBA_ONLY: CHS = CHS { bytes: [0xFE, 0xFF, 0xFF] };
+
     fn cylinder(&self) -> u16 {
         (self.bytes[2] as u16) | ((self.bytes[1] & 0b1100_0000_u8) as u16) << 2
     }
@@ -68,6 +71,34 @@ impl fmt::Debug for PartitionEntry {
 
 const_assert_size!(PartitionEntry, 16);
 
+impl PartitionEntry {
+    /// セクタ `relative_sector` から始まる `total_sectors` セクタの
+    /// タイプ `partition_type` のパーティションエントリを作成する.
+    /// CHSアドレスは使用しない。
+    pub fn new(partition_type: u8, relative_sector: u32, total_sectors: u32) -> PartitionEntry {
+        PartitionEntry {
+            boot_indicator: 0,
+            start: CHS::LBA_ONLY,
+            partition_type,
+            end: CHS::LBA_ONLY,
+            relative_sector,
+            total_sectores: total_sectors,
+        }
+    }
+
+    /// 未使用のパーティションエントリを返す.
+    pub fn unused() -> PartitionEntry {
+        PartitionEntry {
+            boot_indicator: 0,
+            start: CHS { bytes: [0; 3] },
+            partition_type: 0,
+            end: CHS { bytes: [0; 3] },
+            relative_sector: 0,
+            total_sectores: 0,
+        }
+    }
+}
+
 /// マスターブートレコード (MBR).
 #[repr(C, packed)]
 pub struct MasterBootRecord {
@@ -102,6 +133,27 @@ pub enum Error {
 }
 
 impl MasterBootRecord {
+    /// ディスクID `disk_id` とパーティション `partitions` を持つ、
+    /// ブートコードが空のMBRを作成する.
+    pub fn new(disk_id: u32, partitions: [PartitionEntry; 4]) -> MasterBootRecord {
+        // ディスクシグネチャはオフセット440に置かれる
+        let mut id = [0_u8; 10];
+        id[4..8].copy_from_slice(&disk_id.to_le_bytes());
+        MasterBootRecord {
+            bootstrap: [0; 436],
+            disk_id: id,
+            partitions,
+            signature: [0x55, 0xAA],
+        }
+    }
+
+    /// MBRのオンディスク表現（512バイト）を返す.
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            core::slice::from_raw_parts(self as *const _ as *const u8, core::mem::size_of::<Self>())
+        }
+    }
+
     /// `device` からマスターブートレコードを読み込んで返す.
     ///
     /// # エラー
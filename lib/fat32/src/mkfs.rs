@@ -0,0 +1,205 @@
+use alloc::vec::Vec;
+use shim::io;
+use shim::ioerr;
+
+use crate::mbr::{MasterBootRecord, PartitionEntry};
+use crate::traits::BlockDevice;
+use crate::vfat::{BiosParameterBlock, FsInfo};
+
+/// FAT32 (LBA) のMBRパーティションタイプ.
+pub const FAT32_PARTITION_TYPE: u8 = 0x0C;
+
+/// パーティションの開始位置のアライメント (1MiB).
+const PARTITION_ALIGNMENT: u64 = 2048;
+
+/// FAT32の予約セクタ数.
+const RESERVED_SECTORS: u16 = 32;
+
+/// FAT32のボリュームに必要な最小のクラスタ数. これより少ないボリュームは
+/// クラスタ数からFAT12かFAT16と判定される。
+const MIN_CLUSTERS: u64 = 65525;
+
+/// 一度に書き出すセクタ数.
+const ZERO_CHUNK_SECTORS: usize = 64;
+
+/// `format()` のオプション.
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    /// クラスタあたりのセクタ数. `None` の場合はボリュームサイズから
+    /// 決定する。
+    pub sectors_per_cluster: Option<u8>,
+    /// FATのコピーの数.
+    pub fat_count: u8,
+    /// ボリュームのシリアル番号. MBRのディスクシグネチャにも使用する。
+    pub volume_id: u32,
+    /// 空白で埋められたボリュームラベル.
+    pub volume_label: [u8; 11],
+}
+
+impl Default for FormatOptions {
+    fn default() -> FormatOptions {
+        FormatOptions {
+            sectors_per_cluster: None,
+            fat_count: 2,
+            volume_id: 0x1234_5678,
+            volume_label: *b"NO NAME    ",
+        }
+    }
+}
+
+/// `format()` が作成したボリュームのジオメトリ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    /// パーティションの開始セクタ.
+    pub partition_start: u64,
+    /// パーティションのセクタ数.
+    pub partition_sectors: u32,
+    pub sectors_per_cluster: u8,
+    pub sectors_per_fat: u32,
+    /// データ領域のクラスタ数.
+    pub cluster_count: u32,
+}
+
+/// ボリュームのバイト数から、Microsoftの推奨値に従ってクラスタの
+/// バイト数を決定する.
+fn default_cluster_bytes(volume_bytes: u64) -> u64 {
+    const MIB: u64 = 1024 * 1024;
+    match volume_bytes {
+        b if b <= 260 * MIB => 512,
+        b if b <= 8 * 1024 * MIB => 4096,
+        b if b <= 16 * 1024 * MIB => 8192,
+        b if b <= 32 * 1024 * MIB => 16384,
+        _ => 32768,
+    }
+}
+
+/// `total_sectors` セクタの `device` をFAT32でフォーマットする.
+///
+/// セクタ0にパーティションを1つ持つMBRを書き込み、そのパーティションに
+/// BPB、FSInfoセクタ、それらのバックアップ、ゼロで埋めたFATとルート
+/// ディレクトリのクラスタを書き込む。パーティションは1MiB境界から
+/// 始まる（ディスクが小さい場合はセクタ1から）。
+///
+/// # エラー
+///
+/// ディスクがFAT32のボリュームを置くには小さすぎる、または大きすぎる
+/// 場合、クラスタ数が65525未満になる場合、あるいはクラスタサイズが
+/// 不正な場合は `InvalidInput` エラーを返す。デバイスへの書き込みに失敗した場合はそのエラーを返す。
+pub fn format<T: BlockDevice>(mut device: T, total_sectors: u64,
+    options: &FormatOptions) -> io::Result<Geometry> {
+    let sector_size = device.sector_size();
+    if sector_size > u16::max_value() as u64 {
+        return ioerr!(InvalidInput, "unsupported sector size");
+    }
+
+    let partition_start = if total_sectors >= PARTITION_ALIGNMENT * 4 {
+        PARTITION_ALIGNMENT
+    } else {
+        1
+    };
+    let partition_sectors = total_sectors.saturating_sub(partition_start);
+    if partition_sectors > u32::max_value() as u64 {
+        return ioerr!(InvalidInput, "disk too large for an MBR partition");
+    }
+
+    let sectors_per_cluster = match options.sectors_per_cluster {
+        Some(n) => n as u64,
+        None => core::cmp::max(1, default_cluster_bytes(partition_sectors * sector_size)
+            / sector_size),
+    };
+    if !sectors_per_cluster.is_power_of_two() || sectors_per_cluster > 128
+        || options.fat_count == 0 {
+        return ioerr!(InvalidInput, "invalid cluster size or fat count");
+    }
+
+    // FATのサイズはクラスタ数に依存し、クラスタ数はFATのサイズに依存する.
+    // FATの領域を含めずに見積もったクラスタ数から求めたFATのサイズは
+    // 必要なサイズ以上になる。
+    let reserved = RESERVED_SECTORS as u64;
+    let fat_count = options.fat_count as u64;
+    let entries_per_sector = sector_size / 4;
+    let max_clusters = partition_sectors.saturating_sub(reserved) / sectors_per_cluster;
+    let sectors_per_fat = (max_clusters + 2 + entries_per_sector - 1) / entries_per_sector;
+    let data_start = reserved + fat_count * sectors_per_fat;
+    let cluster_count = partition_sectors.saturating_sub(data_start) / sectors_per_cluster;
+    if cluster_count < MIN_CLUSTERS {
+        return ioerr!(InvalidInput, "too few clusters for a FAT32 volume");
+    }
+    if cluster_count > 0x0FFF_FFF5 {
+        return ioerr!(InvalidInput, "too many clusters for FAT32");
+    }
+
+    let mbr = MasterBootRecord::new(options.volume_id, [
+        PartitionEntry::new(FAT32_PARTITION_TYPE, partition_start as u32,
+            partition_sectors as u32),
+        PartitionEntry::unused(),
+        PartitionEntry::unused(),
+        PartitionEntry::unused(),
+    ]);
+    write_block(&mut device, 0, mbr.as_bytes())?;
+
+    // 予約領域、FAT、ルートディレクトリのクラスタをゼロで埋める
+    zero_sectors(&mut device, partition_start, data_start + sectors_per_cluster)?;
+
+    let bpb = BiosParameterBlock::new_fat32(
+        sector_size as u16,
+        sectors_per_cluster as u8,
+        RESERVED_SECTORS,
+        options.fat_count,
+        sectors_per_fat as u32,
+        partition_start as u32,
+        partition_sectors as u32,
+        options.volume_id,
+        options.volume_label,
+    );
+    // ルートディレクトリがクラスタ2を使用し、次の空きはクラスタ3
+    let fsinfo = FsInfo::new(cluster_count as u32 - 1, 3);
+    for &base in [0, bpb.backup_boot_sector as u64].iter() {
+        write_block(&mut device, partition_start + base, bpb.as_bytes())?;
+        write_block(&mut device, partition_start + base + bpb.fsinfo_sector as u64,
+            fsinfo.as_bytes())?;
+        // FAT32の3つ目のブートセクタはシグネチャのみを持つ
+        let mut third = vec![0u8; sector_size as usize];
+        third[510..512].copy_from_slice(&[0x55, 0xAA]);
+        write_block(&mut device, partition_start + base + 2, &third)?;
+    }
+
+    // クラスタ0はメディア記述子、クラスタ1はチェーン終端、クラスタ2は
+    // ルートディレクトリ
+    let mut fat = vec![0u8; sector_size as usize];
+    fat[..12].copy_from_slice(&[0xF8, 0xFF, 0xFF, 0x0F, 0xFF, 0xFF, 0xFF, 0x0F,
+        0xFF, 0xFF, 0xFF, 0x0F]);
+    for copy in 0..fat_count {
+        write_block(&mut device, partition_start + reserved + copy * sectors_per_fat, &fat)?;
+    }
+
+    Ok(Geometry {
+        partition_start,
+        partition_sectors: partition_sectors as u32,
+        sectors_per_cluster: sectors_per_cluster as u8,
+        sectors_per_fat: sectors_per_fat as u32,
+        cluster_count: cluster_count as u32,
+    })
+}
+
+/// `data` を1セクタに満たない場合はゼロで埋めてセクタ `n` に書き込む.
+fn write_block<T: BlockDevice>(device: &mut T, n: u64, data: &[u8]) -> io::Result<()> {
+    let mut sector = vec![0u8; device.sector_size() as usize];
+    sector[..data.len()].copy_from_slice(data);
+    device.write_sector(n, &sector)?;
+    Ok(())
+}
+
+/// セクタ `start` から `count` セクタをゼロで埋める.
+fn zero_sectors<T: BlockDevice>(device: &mut T, start: u64, count: u64) -> io::Result<()> {
+    let sector_size = device.sector_size() as usize;
+    let zeros: Vec<u8> = vec![0; sector_size * ZERO_CHUNK_SECTORS];
+
+    let mut done = 0;
+    while done < count {
+        let n = core::cmp::min(count - done, ZERO_CHUNK_SECTORS as u64);
+        device.write_sectors(start + done, &zeros[..n as usize * sector_size])?;
+        done += n;
+    }
+    Ok(())
+}
@@ -7,6 +7,7 @@ use std::io::Cursor;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 
+use crate::gpt;
 use crate::mbr;
 use crate::traits::*;
 use crate::vfat;
@@ -132,6 +133,214 @@ fn test_mbr() {
     MasterBootRecord::from(Cursor::new(&mut data[..])).expect("valid MBR");
 }
 
+const GPT_DISK_SECTORS: u64 = 4096;
+const GPT_FIRST_LBA: u64 = 2048;
+
+/// Writes a GPT header and its entry array at `header_lba`/`entries_lba`.
+fn write_gpt_header(data: &mut [u8], header_lba: u64, backup_lba: u64, entries_lba: u64) {
+    let entries = &data[entries_lba as usize * 512..][..128 * 128];
+    let entries_crc = gpt::crc32(entries);
+
+    let mut header = [0u8; 92];
+    header[0..8].copy_from_slice(b"EFI PART");
+    header[8..12].copy_from_slice(&0x0001_0000u32.to_le_bytes());
+    header[12..16].copy_from_slice(&92u32.to_le_bytes());
+    header[24..32].copy_from_slice(&header_lba.to_le_bytes());
+    header[32..40].copy_from_slice(&backup_lba.to_le_bytes());
+    header[40..48].copy_from_slice(&34u64.to_le_bytes());
+    header[48..56].copy_from_slice(&(GPT_DISK_SECTORS - 34).to_le_bytes());
+    header[72..80].copy_from_slice(&entries_lba.to_le_bytes());
+    header[80..84].copy_from_slice(&128u32.to_le_bytes());
+    header[84..88].copy_from_slice(&128u32.to_le_bytes());
+    header[88..92].copy_from_slice(&entries_crc.to_le_bytes());
+    let header_crc = gpt::crc32(&header);
+    header[16..20].copy_from_slice(&header_crc.to_le_bytes());
+
+    data[header_lba as usize * 512..][..92].copy_from_slice(&header);
+}
+
+/// Builds a GPT disk with a single Basic Data partition at `GPT_FIRST_LBA`
+/// that extends to the last usable LBA.
+fn gpt_image() -> Vec<u8> {
+    let mut data = vec![0u8; GPT_DISK_SECTORS as usize * 512];
+
+    // protective MBR
+    data[446 + 4] = gpt::PROTECTIVE_PARTITION_TYPE;
+    data[446 + 8..446 + 12].copy_from_slice(&1u32.to_le_bytes());
+    data[446 + 12..446 + 16].copy_from_slice(&(GPT_DISK_SECTORS as u32 - 1).to_le_bytes());
+    data[510..512].copy_from_slice(&[0x55, 0xAA]);
+
+    let mut entry = [0u8; 128];
+    entry[0..16].copy_from_slice(&gpt::Guid::BASIC_DATA.0);
+    entry[16] = 1;
+    entry[32..40].copy_from_slice(&GPT_FIRST_LBA.to_le_bytes());
+    entry[40..48].copy_from_slice(&(GPT_DISK_SECTORS - 34).to_le_bytes());
+    for (i, c) in "data".encode_utf16().enumerate() {
+        entry[56 + i * 2..58 + i * 2].copy_from_slice(&c.to_le_bytes());
+    }
+
+    let last = GPT_DISK_SECTORS - 1;
+    for &(header_lba, backup_lba, entries_lba) in [(1, last, 2), (last, 1, last - 32)].iter() {
+        data[entries_lba as usize * 512..][..128].copy_from_slice(&entry);
+        write_gpt_header(&mut data, header_lba, backup_lba, entries_lba);
+    }
+    data
+}
+
+/// Formats a minimal, empty FAT volume of `sectors` sectors at `start`.
+/// FAT12/16 volumes get a fixed root directory of `root_entries` entries.
+fn format_fat(data: &mut [u8], start: u64, sectors: u32, fat_type: vfat::FatType,
+    root_entries: u16) {
+    let (reserved, sectors_per_fat): (u16, u32) = match fat_type {
+        vfat::FatType::Fat12 => (1, 12),
+        vfat::FatType::Fat16 => (1, 32),
+        vfat::FatType::Fat32 => (32, 16),
+    };
+
+    let volume = &mut data[start as usize * 512..];
+    volume[0x0B..0x0D].copy_from_slice(&512u16.to_le_bytes());
+    volume[0x0D] = 1;
+    volume[0x0E..0x10].copy_from_slice(&reserved.to_le_bytes());
+    volume[0x10] = 2;
+    volume[0x20..0x24].copy_from_slice(&sectors.to_le_bytes());
+    volume[0x1FE..0x200].copy_from_slice(&[0x55, 0xAA]);
+    if fat_type == vfat::FatType::Fat32 {
+        volume[0x24..0x28].copy_from_slice(&sectors_per_fat.to_le_bytes());
+        volume[0x2C..0x30].copy_from_slice(&2u32.to_le_bytes());
+    } else {
+        volume[0x11..0x13].copy_from_slice(&root_entries.to_le_bytes());
+        volume[0x16..0x18].copy_from_slice(&(sectors_per_fat as u16).to_le_bytes());
+    }
+
+    let media: &[u8] = match fat_type {
+        vfat::FatType::Fat12 => &[0xF8, 0xFF, 0xFF],
+        vfat::FatType::Fat16 => &[0xF8, 0xFF, 0xFF, 0xFF],
+        // the root directory occupies cluster 2
+        vfat::FatType::Fat32 => &[0xF8, 0xFF, 0xFF, 0x0F, 0xFF, 0xFF, 0xFF, 0x0F,
+            0xFF, 0xFF, 0xFF, 0x0F],
+    };
+    for fat in 0..2 {
+        let offset = (reserved as usize + fat * sectors_per_fat as usize) * 512;
+        volume[offset..offset + media.len()].copy_from_slice(media);
+    }
+}
+
+/// Builds an MBR disk of `sectors` sectors with a single partition of type
+/// `partition_type` starting at LBA 1.
+fn mbr_image(partition_type: u8, sectors: u32) -> Vec<u8> {
+    let mut data = vec![0u8; sectors as usize * 512];
+    data[446 + 4] = partition_type;
+    data[446 + 8..446 + 12].copy_from_slice(&1u32.to_le_bytes());
+    data[446 + 12..446 + 16].copy_from_slice(&(sectors - 1).to_le_bytes());
+    data[510..512].copy_from_slice(&[0x55, 0xAA]);
+    data
+}
+
+#[test]
+fn check_gpt_size() {
+    check_size!(gpt::GptHeader, 92);
+    check_size!(gpt::GptPartitionEntry, 128);
+}
+
+#[test]
+fn test_gpt() {
+    let mut data = gpt_image();
+    let table = gpt::GuidPartitionTable::from(Cursor::new(&mut data[..])).expect("valid GPT");
+    assert!(!table.from_backup);
+    assert_eq!(table.partitions.len(), 128);
+
+    let used: Vec<_> = table.used_partitions().collect();
+    assert_eq!(used.len(), 1);
+    assert_eq!(used[0].type_guid, gpt::Guid::BASIC_DATA);
+    assert_eq!({ used[0].first_lba }, GPT_FIRST_LBA);
+    assert_eq!(used[0].name(), "data");
+    assert_eq!(
+        format!("{:?}", used[0].type_guid),
+        "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7"
+    );
+}
+
+#[test]
+fn test_gpt_falls_back_to_backup() {
+    // corrupt primary header
+    let mut data = gpt_image();
+    data[512 + 40] ^= 0xFF;
+    let table = gpt::GuidPartitionTable::from(Cursor::new(&mut data[..])).expect("backup GPT");
+    assert!(table.from_backup);
+    assert_eq!(table.used_partitions().count(), 1);
+
+    // corrupt primary entry array
+    let mut data = gpt_image();
+    data[2 * 512 + 32] ^= 0xFF;
+    let table = gpt::GuidPartitionTable::from(Cursor::new(&mut data[..])).expect("backup GPT");
+    assert!(table.from_backup);
+    assert_eq!({ table.used_partitions().next().unwrap().first_lba }, GPT_FIRST_LBA);
+
+    // both copies of the entry array are corrupt
+    let backup_entries = (GPT_DISK_SECTORS as usize - 33) * 512;
+    data[backup_entries + 32] ^= 0xFF;
+    let e = gpt::GuidPartitionTable::from(Cursor::new(&mut data[..])).unwrap_err();
+    expect_variant!(e, gpt::Error::BadEntriesChecksum);
+
+    // a valid backup header copied to LBA 1 is not a primary header
+    let mut data = gpt_image();
+    let last = GPT_DISK_SECTORS as usize - 1;
+    let backup: Vec<u8> = data[last * 512..(last + 1) * 512].to_vec();
+    data[512..1024].copy_from_slice(&backup);
+    let table = gpt::GuidPartitionTable::from(Cursor::new(&mut data[..])).expect("backup GPT");
+    assert!(table.from_backup);
+
+    // ...and with both headers swapped, neither is accepted
+    let primary: Vec<u8> = gpt_image()[512..1024].to_vec();
+    data[last * 512..(last + 1) * 512].copy_from_slice(&primary);
+    let e = gpt::GuidPartitionTable::from(Cursor::new(&mut data[..])).unwrap_err();
+    expect_variant!(e, gpt::Error::BadHeader);
+}
+
+#[test]
+fn test_gpt_rejects_oversized_entry_array() {
+    // Rewrite the entry count of the header at `lba` and fix up its CRC.
+    fn set_entry_count(data: &mut [u8], lba: usize, count: u32) {
+        let header = &mut data[lba * 512..][..92];
+        header[80..84].copy_from_slice(&count.to_le_bytes());
+        header[16..20].copy_from_slice(&[0; 4]);
+        let crc = gpt::crc32(header);
+        header[16..20].copy_from_slice(&crc.to_le_bytes());
+    }
+
+    let mut data = gpt_image();
+    set_entry_count(&mut data, 1, 0xFFFF_FFFF);
+    let table = gpt::GuidPartitionTable::from(Cursor::new(&mut data[..])).expect("backup GPT");
+    assert!(table.from_backup);
+    assert_eq!(table.partitions.len(), 128);
+
+    set_entry_count(&mut data, GPT_DISK_SECTORS as usize - 1, 1025);
+    let e = gpt::GuidPartitionTable::from(Cursor::new(&mut data[..])).unwrap_err();
+    expect_variant!(e, gpt::Error::BadHeader);
+}
+
+#[test]
+fn test_gpt_requires_protective_mbr() {
+    let mut data = [0u8; 512];
+    data[510..].copy_from_slice(&[0x55, 0xAA]);
+    let e = gpt::GuidPartitionTable::from(Cursor::new(&mut data[..])).unwrap_err();
+    expect_variant!(e, gpt::Error::NotProtective);
+}
+
+#[test]
+fn test_vfat_from_gpt() {
+    let mut data = gpt_image();
+    let sectors = (GPT_DISK_SECTORS - 34 - GPT_FIRST_LBA + 1) as u32;
+    format_fat(&mut data, GPT_FIRST_LBA, sectors, vfat::FatType::Fat32, 0);
+    // invalidate the primary header to exercise the backup path as well
+    data[512] = 0;
+
+    let vfat = VFat::<StdVFatHandle>::from(Cursor::new(data)).expect("valid vfat");
+    assert_eq!(vfat.open_dir("/").unwrap().entries().unwrap().count(), 0);
+    vfat.create_file("/HELLO.TXT").expect("create file");
+    assert!(vfat.open_file("/HELLO.TXT").is_ok());
+}
+
 #[test]
 fn check_ebpb_size() {
     check_size!(BiosParameterBlock, 512);
@@ -474,3 +683,892 @@ fn shuffle_test() {
     let hash = hash_files_recursive_from(vfat, "/");
     assert_hash_eq!("mock 1 file hashes", hash, hash_for!("files-1"));
 }
+
+#[derive(Clone)]
+struct SharedDevice {
+    data: Arc<Mutex<Cursor<Vec<u8>>>>,
+    writes: Arc<Mutex<u64>>,
+    reads: Arc<Mutex<u64>>,
+}
+
+impl SharedDevice {
+    fn new(data: Vec<u8>) -> Self {
+        SharedDevice {
+            data: Arc::new(Mutex::new(Cursor::new(data))),
+            writes: Arc::new(Mutex::new(0)),
+            reads: Arc::new(Mutex::new(0)),
+        }
+    }
+
+    fn writes(&self) -> u64 {
+        *self.writes.lock().unwrap()
+    }
+
+    /// Number of read requests, counting a multi-sector read once.
+    fn reads(&self) -> u64 {
+        *self.reads.lock().unwrap()
+    }
+
+    fn contents(&self) -> Vec<u8> {
+        self.data.lock().unwrap().get_ref().clone()
+    }
+}
+
+impl BlockDevice for SharedDevice {
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        *self.reads.lock().unwrap() += 1;
+        self.data.lock().unwrap().read_sector(n, buf)
+    }
+
+    fn read_sectors(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        *self.reads.lock().unwrap() += 1;
+        self.data.lock().unwrap().read_sectors(n, buf)
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        *self.writes.lock().unwrap() += 1;
+        self.data.lock().unwrap().write_sector(n, buf)
+    }
+}
+
+macro shared_from_resource($name:expr) {{
+    let mut data = Vec::new();
+    resource!($name)
+        .read_to_end(&mut data)
+        .expect("read resource data");
+    SharedDevice::new(data)
+}}
+
+fn read_file_from(vfat: &StdVFatHandle, path: &str) -> Vec<u8> {
+    let mut file = vfat.open_file(path).expect("file exists");
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).expect("read file");
+    assert_eq!(data.len() as u64, file.size());
+    data
+}
+
+#[test]
+fn test_write_overwrite_in_place() {
+    let device = shared_from_resource!("mock1.fat32.img");
+    let path = "/NOTES/LEC2/CODE/CODE.RS";
+
+    let vfat = VFat::<StdVFatHandle>::from(device.clone()).expect("valid vfat");
+    let mut expected = read_file_from(&vfat, path);
+    let mut file = vfat.open_file(path).expect("file exists");
+    file.write_all(b"overwritten").expect("write");
+    file.sync().expect("sync");
+    expected[..11].copy_from_slice(b"overwritten");
+
+    let vfat = VFat::<StdVFatHandle>::from(device).expect("valid vfat");
+    assert_eq!(read_file_from(&vfat, path), expected);
+}
+
+#[test]
+fn test_write_append_extends_chain() {
+    let device = shared_from_resource!("mock1.fat32.img");
+    let path = "/NOTES/LEC2/CODE/CODE.RS";
+
+    let vfat = VFat::<StdVFatHandle>::from(device.clone()).expect("valid vfat");
+    let mut expected = read_file_from(&vfat, path);
+    let appended: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+
+    let mut file = vfat.open_file(path).expect("file exists");
+    file.seek(io::SeekFrom::End(0)).expect("seek to end");
+    file.write_all(&appended).expect("write");
+    file.flush().expect("flush");
+    expected.extend_from_slice(&appended);
+
+    let vfat = VFat::<StdVFatHandle>::from(device).expect("valid vfat");
+    assert_eq!(read_file_from(&vfat, path), expected);
+
+    // The untouched files must survive the new cluster allocations.
+    let hash = hash_files_recursive_from(vfat, "/NOTES/LEC1");
+    let expected_hash = hash_for!("files-1");
+    let line = expected_hash
+        .lines()
+        .find(|l| l.starts_with("/NOTES/LEC1/"))
+        .unwrap();
+    assert_hash_eq!("mock 1 untouched file", hash, line);
+}
+
+#[test]
+fn test_sync_flushes_only_dirty_sectors() {
+    let device = shared_from_resource!("mock1.fat32.img");
+    let vfat = VFat::<StdVFatHandle>::from(device.clone()).expect("valid vfat");
+
+    let mut file = vfat.open_file("/NOTES/LEC2/CODE/CODE.RS").expect("file exists");
+    file.sync().expect("sync");
+    let clean_writes = device.writes();
+
+    file.write_all(b"x").expect("write");
+    file.sync().expect("sync");
+    let dirty_writes = device.writes() - clean_writes;
+    assert!(dirty_writes > 0 && dirty_writes <= 2, "wrote {} sectors", dirty_writes);
+
+    file.sync().expect("sync");
+    assert_eq!(device.writes() - clean_writes, dirty_writes);
+}
+
+fn entry_names<P: AsRef<Path>>(vfat: &StdVFatHandle, path: P) -> Vec<String> {
+    let mut names: Vec<String> = vfat
+        .open_dir(path)
+        .expect("directory exists")
+        .entries()
+        .expect("entries interator")
+        .map(|e| e.name().to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_create_file_and_dir() {
+    let device = shared_from_resource!("mock1.fat32.img");
+    let vfat = VFat::<StdVFatHandle>::from(device.clone()).expect("valid vfat");
+
+    vfat.create_dir("/NOTES/new dir").expect("create dir");
+    let mut file = vfat
+        .create_file("/NOTES/new dir/a long file name.txt")
+        .expect("create file");
+    file.write_all(b"hello, world").expect("write");
+    file.sync().expect("sync");
+
+    let e = vfat.create_file("/NOTES/NEW DIR/A LONG FILE NAME.TXT").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+    let e = vfat.create_file("/NOTES/new dir/bad:name").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+    let vfat = VFat::<StdVFatHandle>::from(device).expect("valid vfat");
+    assert_eq!(
+        entry_names(&vfat, "/NOTES/new dir"),
+        vec![".", "..", "a long file name.txt"]
+    );
+    assert_eq!(
+        read_file_from(&vfat, "/NOTES/new dir/a long file name.txt"),
+        b"hello, world"
+    );
+}
+
+#[test]
+fn test_create_many_files_grows_dir() {
+    let device = shared_from_resource!("mock1.fat32.img");
+    let vfat = VFat::<StdVFatHandle>::from(device.clone()).expect("valid vfat");
+
+    vfat.create_dir("/many").expect("create dir");
+    for i in 0..200 {
+        vfat.create_file(format!("/many/generated file number {}.data", i))
+            .expect("create file");
+    }
+    vfat.lock(|fs| fs.flush()).expect("flush");
+
+    let vfat = VFat::<StdVFatHandle>::from(device).expect("valid vfat");
+    let names = entry_names(&vfat, "/many");
+    assert_eq!(names.len(), 202);
+    for i in 0..200 {
+        vfat.open_file(format!("/many/generated file number {}.data", i))
+            .expect("file exists");
+    }
+}
+
+#[test]
+fn test_remove() {
+    let device = shared_from_resource!("mock1.fat32.img");
+    let vfat = VFat::<StdVFatHandle>::from(device.clone()).expect("valid vfat");
+
+    let e = vfat.remove("/NOTES").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+    let e = vfat.remove("/").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+
+    vfat.remove("/NOTES/LEC2/CODE/CODE.RS").expect("remove file");
+    vfat.remove("/NOTES/LEC2/CODE/CODE.PDF").expect("remove file");
+    vfat.remove("/NOTES/LEC2/CODE").expect("remove empty dir");
+    vfat.lock(|fs| fs.flush()).expect("flush");
+
+    let vfat = VFat::<StdVFatHandle>::from(device).expect("valid vfat");
+    let e = vfat.open("/NOTES/LEC2/CODE").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::NotFound);
+    assert_eq!(entry_names(&vfat, "/NOTES/LEC2"), vec![".", "..", "PAPER.PDF"]);
+}
+
+#[test]
+fn test_rename() {
+    let device = shared_from_resource!("mock1.fat32.img");
+    let vfat = VFat::<StdVFatHandle>::from(device.clone()).expect("valid vfat");
+    let code = read_file_from(&vfat, "/NOTES/LEC2/CODE/CODE.RS");
+
+    vfat.rename("/NOTES/LEC2/CODE/CODE.RS", "/NOTES/LEC2/CODE/code.rs")
+        .expect("rename in place");
+    vfat.rename("/NOTES/LEC2/CODE/code.rs", "/renamed code.rs")
+        .expect("move file");
+    vfat.rename("/NOTES/LEC2", "/solutions/lecture 2")
+        .expect("move dir");
+
+    let e = vfat.rename("/NOTES", "/NOTES/LEC1/inner").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    let e = vfat.rename("/notes", "/NOTES/LEC1/inner").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    let e = vfat.rename("/NOTES", "/Notes/lec1/inner").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    let e = vfat.rename("/renamed code.rs", "/CS140E").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+    let e = vfat.rename("/", "/ROOT").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+    vfat.lock(|fs| fs.flush()).expect("flush");
+
+    let vfat = VFat::<StdVFatHandle>::from(device).expect("valid vfat");
+    assert_eq!(read_file_from(&vfat, "/renamed code.rs"), code);
+    assert_eq!(
+        entry_names(&vfat, "/solutions/lecture 2"),
+        vec![".", "..", "CODE", "PAPER.PDF"]
+    );
+    let e = vfat.open("/NOTES/LEC2").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::NotFound);
+
+    let dotdot = vfat
+        .open_dir("/solutions/lecture 2")
+        .expect("directory exists")
+        .entries()
+        .expect("entries interator")
+        .find(|e| e.name() == "..")
+        .expect("dotdot entry");
+    let solutions = vfat.open_dir("/solutions").expect("directory exists");
+    assert_eq!(dotdot.as_dir().unwrap().cluster, solutions.cluster);
+}
+
+#[test]
+fn test_rename_open_file() {
+    let device = shared_from_resource!("mock1.fat32.img");
+    let vfat = VFat::<StdVFatHandle>::from(device.clone()).expect("valid vfat");
+
+    let mut file = vfat.create_file("/OPEN.TXT").expect("create");
+    file.write_all(b"hello").expect("write");
+    vfat.rename("/OPEN.TXT", "/NOTES/moved open.txt").expect("move file");
+    // Writes through the open file update the moved entry, not the old slot.
+    file.write_all(&[b'x'; 5000]).expect("write after move");
+    vfat.rename("/NOTES/moved open.txt", "/NOTES/Moved Open.txt").expect("rename in place");
+    file.write_all(b"bye").expect("write after rename");
+    drop(file);
+    vfat.lock(|fs| fs.flush()).expect("flush");
+
+    let mut expected = b"hello".to_vec();
+    expected.extend_from_slice(&[b'x'; 5000]);
+    expected.extend_from_slice(b"bye");
+    let vfat = VFat::<StdVFatHandle>::from(device).expect("valid vfat");
+    assert_eq!(read_file_from(&vfat, "/NOTES/Moved Open.txt"), expected);
+    let e = vfat.open("/OPEN.TXT").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn test_fat16_fixed_root() {
+    let mut data = mbr_image(0x06, 8193);
+    format_fat(&mut data, 1, 8192, vfat::FatType::Fat16, 512);
+    let device = SharedDevice::new(data);
+
+    let vfat = VFat::<StdVFatHandle>::from(device.clone()).expect("valid vfat");
+    assert_eq!(vfat.lock(|fs| fs.fat_type()), vfat::FatType::Fat16);
+    assert_eq!(entry_names(&vfat, "/"), Vec::<String>::new());
+
+    let contents: Vec<u8> = (0..5000).map(|i| (i % 253) as u8).collect();
+    let mut file = vfat.create_file("/a long file name.bin").expect("create file");
+    file.write_all(&contents).expect("write");
+    file.sync().expect("sync");
+    vfat.create_dir("/SUB").expect("create dir");
+    vfat.create_file("/SUB/INNER.TXT").expect("create file");
+    vfat.lock(|fs| fs.flush()).expect("flush");
+
+    let vfat = VFat::<StdVFatHandle>::from(device).expect("valid vfat");
+    assert_eq!(entry_names(&vfat, "/"), vec!["SUB", "a long file name.bin"]);
+    assert_eq!(read_file_from(&vfat, "/a long file name.bin"), contents);
+    // ".." of a directory in the root refers to cluster 0, the fixed root
+    let parent = vfat.open_dir("/SUB").unwrap().find("..").unwrap().into_dir().unwrap();
+    assert_eq!(parent.entries().unwrap().count(), 2);
+
+    vfat.rename("/SUB/INNER.TXT", "/OUTER.TXT").expect("rename");
+    vfat.remove("/SUB").expect("remove");
+    assert_eq!(entry_names(&vfat, "/"), vec!["OUTER.TXT", "a long file name.bin"]);
+}
+
+#[test]
+fn test_fat12_chains_and_full_root() {
+    let mut data = mbr_image(0x01, 2049);
+    format_fat(&mut data, 1, 2048, vfat::FatType::Fat12, 16);
+    let device = SharedDevice::new(data);
+
+    let vfat = VFat::<StdVFatHandle>::from(device.clone()).expect("valid vfat");
+    assert_eq!(vfat.lock(|fs| fs.fat_type()), vfat::FatType::Fat12);
+
+    // interleave two files so that their chains use both odd and even
+    // 12-bit entries and cross FAT sector boundaries
+    let mut a = vfat.create_file("/A.BIN").expect("create file");
+    let mut b = vfat.create_file("/B.BIN").expect("create file");
+    let chunk: Vec<u8> = (0..512).map(|i| (i % 7) as u8).collect();
+    for _ in 0..400 {
+        a.write_all(&chunk).expect("write");
+        b.write_all(&chunk[..300]).expect("write");
+    }
+    a.sync().expect("sync");
+    b.sync().expect("sync");
+
+    let vfat = VFat::<StdVFatHandle>::from(device).expect("valid vfat");
+    let a = read_file_from(&vfat, "/A.BIN");
+    let b = read_file_from(&vfat, "/B.BIN");
+    assert_eq!(a.len(), 400 * 512);
+    assert_eq!(b.len(), 400 * 300);
+    assert!(a.chunks(512).all(|c| c == &chunk[..]));
+    assert!(b.chunks(300).all(|c| c == &chunk[..300]));
+
+    // the fixed root directory holds 16 entries and cannot grow
+    for i in 2..16 {
+        vfat.create_file(format!("/F{}.TXT", i)).expect("create file");
+    }
+    let e = vfat.create_file("/FULL.TXT").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::Other);
+    vfat.remove("/A.BIN").expect("remove");
+    vfat.create_file("/FULL.TXT").expect("create file");
+}
+
+/// Overwrites entry `cluster` of the given FAT `copies` of the FAT32 volume
+/// built by `format_fat` at LBA 1.
+fn set_raw_fat32_entry(device: &SharedDevice, copies: &[usize], cluster: u32, value: u32) {
+    let mut data = device.data.lock().unwrap();
+    for copy in copies {
+        let offset = (1 + 32 + copy * 16) * 512 + cluster as usize * 4;
+        data.get_mut()[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+#[test]
+fn test_check_and_repair() {
+    use crate::vfat::{Cluster, Problem, Status};
+
+    let mut data = mbr_image(0x0C, 2049);
+    format_fat(&mut data, 1, 2048, vfat::FatType::Fat32, 0);
+    let device = SharedDevice::new(data);
+
+    let a: Vec<u8> = (0..1500).map(|i| (i % 241) as u8).collect();
+    let vfat = VFat::<StdVFatHandle>::from(device.clone()).expect("valid vfat");
+    vfat.create_file("/A.TXT").unwrap().write_all(&a).expect("write");
+    vfat.create_file("/B.TXT").unwrap().write_all(&[b'b'; 600]).expect("write");
+    vfat.create_dir("/DIR").expect("create dir");
+    vfat.create_file("/DIR/C.TXT").unwrap().write_all(&[b'c'; 100]).expect("write");
+    vfat.lock(|fs| fs.flush()).expect("flush");
+
+    let report = vfat.lock(|fs| fs.check(false)).expect("check");
+    assert!(report.is_clean(), "{:?}", report.problems);
+    assert_eq!((report.files, report.dirs, report.used_clusters), (3, 2, 8));
+    // A: 3 -> 4 -> 5, B: 6 -> 7, DIR: 8, C: 9
+    vfat.lock(|fs| {
+        assert_eq!(fs.fat_entry(Cluster::from(5)).unwrap().status(), Status::Eoc(0x0FFF_FFFF));
+        assert_eq!(fs.fat_entry(Cluster::from(6)).unwrap().status(), Status::Data(Cluster::from(7)));
+        assert_eq!(fs.fat_entry(Cluster::from(9)).unwrap().status(), Status::Eoc(0x0FFF_FFFF));
+    });
+
+    set_raw_fat32_entry(&device, &[0, 1], 5, 30);
+    set_raw_fat32_entry(&device, &[0, 1], 30, 0x0FFF_FFFF);
+    set_raw_fat32_entry(&device, &[0, 1], 7, 5);
+    set_raw_fat32_entry(&device, &[0, 1], 9, 0);
+    set_raw_fat32_entry(&device, &[0, 1], 20, 21);
+    set_raw_fat32_entry(&device, &[0, 1], 21, 0x0FFF_FFFF);
+    set_raw_fat32_entry(&device, &[1], 100, 0x1234);
+
+    let vfat = VFat::<StdVFatHandle>::from(device.clone()).expect("valid vfat");
+    let report = vfat.lock(|fs| fs.check(false)).expect("check");
+    assert_eq!(report.problems, vec![
+        Problem::FatMismatch { copy: 1, sector: 0 },
+        Problem::SizeMismatch { path: "/A.TXT".into(), size: 1500, chain_bytes: 2048 },
+        Problem::CrossLinked { cluster: 5, first: "/A.TXT".into(), second: "/B.TXT".into() },
+        Problem::BadChainEnd { path: "/DIR/C.TXT".into(), cluster: 9, value: 0 },
+        Problem::LostChain { start: 20, clusters: 2 },
+    ]);
+    assert!(!report.repaired);
+
+    let report = vfat.lock(|fs| fs.check(true)).expect("repair");
+    assert!(report.repaired);
+    assert_eq!(report.problems.len(), 5);
+
+    let vfat = VFat::<StdVFatHandle>::from(device).expect("valid vfat");
+    let report = vfat.lock(|fs| fs.check(false)).expect("check");
+    assert!(report.is_clean(), "{:?}", report.problems);
+    assert_eq!(read_file_from(&vfat, "/A.TXT"), a);
+    assert_eq!(read_file_from(&vfat, "/B.TXT"), vec![b'b'; 600]);
+    assert_eq!(read_file_from(&vfat, "/DIR/C.TXT"), vec![b'c'; 100]);
+}
+
+fn cache_test_partition(capacity: usize) -> (SharedDevice, vfat::CachedPartition) {
+    let data: Vec<u8> = (0..64 * 512).map(|i| (i / 512) as u8).collect();
+    let device = SharedDevice::new(data);
+    let partition = vfat::Partition { start: 0, num_sectors: 64, sector_size: 512 };
+    let cache = vfat::CachedPartition::with_capacity(device.clone(), partition, capacity);
+    (device, cache)
+}
+
+/// Formats an empty 40 MiB disk as FAT32 with the default options and
+/// mounts it.
+fn formatted_vfat() -> (SharedDevice, StdVFatHandle, crate::mkfs::Geometry) {
+    use crate::mkfs::{self, FormatOptions};
+
+    let total_sectors = 40 * 2048;
+    let mut cursor = Cursor::new(vec![0u8; total_sectors as usize * 512]);
+    let geometry = mkfs::format(&mut cursor, total_sectors, &FormatOptions::default())
+        .expect("format");
+    let device = SharedDevice::new(cursor.into_inner());
+    let vfat = VFat::<StdVFatHandle>::from(device.clone()).expect("valid vfat");
+    (device, vfat, geometry)
+}
+
+#[test]
+fn test_cache_evicts_and_writes_back() {
+    let (device, mut cache) = cache_test_partition(4);
+
+    for sector in 0..10u64 {
+        cache.get_mut(sector).expect("sector")[0] = 0xF0 | sector as u8;
+    }
+    // only 4 sectors fit; the other dirty sectors were written back on eviction
+    let stats = cache.stats();
+    assert_eq!(stats.misses, 10);
+    assert_eq!(stats.evictions, 6);
+    assert_eq!(stats.write_backs, 6);
+    assert_eq!(device.writes(), 6);
+
+    assert_eq!(cache.get(9).expect("sector")[0], 0xF9);
+    assert_eq!(cache.stats().hits, 1);
+    assert_eq!(cache.get(0).expect("sector")[0], 0xF0);
+    assert_eq!(cache.stats().misses, 11);
+
+    cache.flush().expect("flush");
+    let data = device.contents();
+    for sector in 0..10usize {
+        assert_eq!(data[sector * 512], 0xF0 | sector as u8);
+        assert_eq!(data[sector * 512 + 1], sector as u8);
+    }
+
+    cache.set_capacity(2).expect("shrink");
+    assert_eq!(cache.get(3).expect("sector")[1], 3);
+}
+
+#[test]
+fn test_cache_read_ahead() {
+    let (device, mut cache) = cache_test_partition(16);
+    cache.set_read_ahead(4);
+
+    for sector in 0..32u64 {
+        assert!(cache.get(sector).expect("sector").iter().all(|&b| b == sector as u8));
+    }
+
+    // sector 0 is read alone; every later miss also loads the next 4 sectors
+    let stats = cache.stats();
+    assert_eq!(stats.misses, 8);
+    assert_eq!(stats.hits, 24);
+    assert_eq!(stats.read_ahead, 28);
+    assert_eq!(device.reads(), 8);
+
+    // random access does not trigger read-ahead
+    cache.get(50).expect("sector");
+    assert_eq!(cache.stats().read_ahead, 28);
+    assert!(cache.get(60).expect("sector").iter().all(|&b| b == 60));
+}
+
+#[cfg(feature = "exfat")]
+mod exfat_tests {
+    use super::*;
+    use crate::exfat::{ExFat, ExFatHandle};
+
+    #[derive(Clone)]
+    struct StdExFatHandle(Arc<Mutex<ExFat<Self>>>);
+
+    impl Debug for StdExFatHandle {
+        fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+            write!(f, "StdExFatHandle")
+        }
+    }
+
+    impl ExFatHandle for StdExFatHandle {
+        fn new(val: ExFat<StdExFatHandle>) -> Self {
+            StdExFatHandle(Arc::new(Mutex::new(val)))
+        }
+
+        fn lock<R>(&self, f: impl FnOnce(&mut ExFat<StdExFatHandle>) -> R) -> R {
+            f(&mut self.0.lock().expect("all okay"))
+        }
+    }
+
+    const VOLUME_SECTORS: u32 = 512;
+    const FAT_OFFSET: usize = 24;
+    const HEAP_OFFSET: usize = 32;
+    const ROOT_CLUSTER: u32 = 2;
+
+    /// Returns the byte offset of `cluster` on the disk built by `exfat_image`.
+    /// Clusters are one sector large and the volume starts at LBA 1.
+    fn cluster_offset(cluster: u32) -> usize {
+        (1 + HEAP_OFFSET + cluster as usize - 2) * 512
+    }
+
+    fn set_fat(data: &mut [u8], cluster: u32, next: u32) {
+        let offset = (1 + FAT_OFFSET) * 512 + cluster as usize * 4;
+        data[offset..offset + 4].copy_from_slice(&next.to_le_bytes());
+    }
+
+    /// Builds a file directory entry set (file, stream extension and a single
+    /// file name entry) with a valid set checksum.
+    fn entry_set(name: &str, attributes: u16, first_cluster: u32, no_fat_chain: bool,
+        valid_length: u64, length: u64) -> Vec<u8> {
+        let mut set = vec![0u8; 96];
+        set[0] = 0x85;
+        set[1] = 2;
+        set[4..6].copy_from_slice(&attributes.to_le_bytes());
+        // modified on 2020-01-02 at 03:04:10
+        let date = ((2020 - 1980) << 9) | (1 << 5) | 2;
+        let time = (3 << 11) | (4 << 5) | 5;
+        set[12..16].copy_from_slice(&((date << 16) | time as u32).to_le_bytes());
+
+        set[32] = 0xC0;
+        set[33] = if no_fat_chain { 0x03 } else { 0x01 };
+        set[35] = name.encode_utf16().count() as u8;
+        set[40..48].copy_from_slice(&valid_length.to_le_bytes());
+        set[52..56].copy_from_slice(&first_cluster.to_le_bytes());
+        set[56..64].copy_from_slice(&length.to_le_bytes());
+
+        set[64] = 0xC1;
+        for (i, c) in name.encode_utf16().enumerate() {
+            set[66 + i * 2..68 + i * 2].copy_from_slice(&c.to_le_bytes());
+        }
+
+        let checksum = set.iter().enumerate()
+            .filter(|&(i, _)| i != 2 && i != 3)
+            .fold(0u16, |sum, (_, &b)| sum.rotate_right(1).wrapping_add(b as u16));
+        set[2..4].copy_from_slice(&checksum.to_le_bytes());
+        set
+    }
+
+    /// Builds an MBR disk holding an exFAT volume with 512-byte clusters:
+    ///
+    ///   /hello.txt        contiguous (NoFatChain), 1300 bytes, 1000 valid
+    ///   /Sub/chained.bin  FAT chain 30 -> 40 -> 35, 1500 bytes
+    ///
+    /// The root directory spans clusters 2 and 3, and `Sub` is stored in the
+    /// root's second cluster.
+    fn exfat_image() -> Vec<u8> {
+        let mut data = mbr_image(0x07, VOLUME_SECTORS + 1);
+        let boot = &mut data[512..1024];
+        boot[..3].copy_from_slice(&[0xEB, 0x76, 0x90]);
+        boot[3..11].copy_from_slice(b"EXFAT   ");
+        boot[64..72].copy_from_slice(&1u64.to_le_bytes());
+        boot[72..80].copy_from_slice(&(VOLUME_SECTORS as u64).to_le_bytes());
+        boot[80..84].copy_from_slice(&(FAT_OFFSET as u32).to_le_bytes());
+        boot[84..88].copy_from_slice(&8u32.to_le_bytes());
+        boot[88..92].copy_from_slice(&(HEAP_OFFSET as u32).to_le_bytes());
+        boot[92..96].copy_from_slice(&(VOLUME_SECTORS - HEAP_OFFSET as u32).to_le_bytes());
+        boot[96..100].copy_from_slice(&ROOT_CLUSTER.to_le_bytes());
+        boot[104..106].copy_from_slice(&0x0100u16.to_le_bytes());
+        boot[108] = 9;
+        boot[109] = 0;
+        boot[110] = 1;
+        boot[510..512].copy_from_slice(&[0x55, 0xAA]);
+
+        set_fat(&mut data, 0, 0xFFFF_FFF8);
+        set_fat(&mut data, 1, 0xFFFF_FFFF);
+        set_fat(&mut data, 2, 3);
+        set_fat(&mut data, 3, 0xFFFF_FFFF);
+        set_fat(&mut data, 20, 0xFFFF_FFFF);
+        set_fat(&mut data, 30, 40);
+        set_fat(&mut data, 40, 35);
+        set_fat(&mut data, 35, 0xFFFF_FFFF);
+
+        // root cluster 2: allocation bitmap, a deleted file, hello.txt, and
+        // deleted entries up to the end of the cluster
+        let root = cluster_offset(2);
+        data[root] = 0x81;
+        let mut deleted = entry_set("gone.txt", 0x20, 50, true, 10, 10);
+        deleted[0] = 0x05;
+        data[root + 32..root + 128].copy_from_slice(&deleted);
+        data[root + 128..root + 224]
+            .copy_from_slice(&entry_set("hello.txt", 0x20, 10, true, 1000, 1300));
+        for slot in 7..16 {
+            data[root + slot * 32] = 0x40;
+        }
+        let root = cluster_offset(3);
+        data[root..root + 96].copy_from_slice(&entry_set("Sub", 0x10, 20, false, 512, 512));
+
+        let sub = cluster_offset(20);
+        data[sub..sub + 96]
+            .copy_from_slice(&entry_set("chained.bin", 0x20, 30, false, 1500, 1500));
+
+        // bytes past the valid data length must read as zero
+        let hello = cluster_offset(10);
+        for i in 0..1300 {
+            data[hello + i] = if i < 1000 { (i % 251) as u8 } else { 0xAA };
+        }
+        for (n, &cluster) in [30u32, 40, 35].iter().enumerate() {
+            let offset = cluster_offset(cluster);
+            for i in 0..512 {
+                data[offset + i] = ((n * 512 + i) % 249) as u8;
+            }
+        }
+
+        data
+    }
+
+    #[test]
+    fn check_exfat_boot_sector_size() {
+        check_size!(crate::exfat::BootSector, 512);
+    }
+
+    #[test]
+    fn test_exfat_read() {
+        let volume = ExFat::<StdExFatHandle>::from(Cursor::new(exfat_image()))
+            .expect("valid exfat");
+
+        let names: Vec<String> = (&volume).open_dir("/").expect("root")
+            .entries().expect("entries")
+            .map(|e| e.name().to_string())
+            .collect();
+        assert_eq!(names, vec!["hello.txt", "Sub"]);
+
+        let entry = (&volume).open("/SUB/CHAINED.BIN").expect("case-insensitive open");
+        assert_eq!(entry.name(), "chained.bin");
+        assert_eq!(entry.metadata().modified().year(), 2020);
+        assert_eq!(entry.metadata().modified().second(), 10);
+
+        let mut file = entry.into_file().expect("file");
+        assert_eq!(file.size(), 1500);
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).expect("read");
+        let expected: Vec<u8> = (0..1500).map(|i| (i % 249) as u8).collect();
+        assert_eq!(contents, expected);
+
+        file.seek(io::SeekFrom::Start(1200)).expect("seek");
+        let mut buf = [0u8; 100];
+        file.read_exact(&mut buf).expect("read after seek");
+        assert_eq!(&buf[..], &expected[1200..1300]);
+        assert!(file.seek(io::SeekFrom::Start(1501)).is_err());
+        assert_eq!(file.write(b"x").unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+
+        let mut contents = Vec::new();
+        (&volume).open_file("/hello.txt").expect("file")
+            .read_to_end(&mut contents).expect("read");
+        assert_eq!(contents.len(), 1300);
+        assert!(contents[..1000].iter().enumerate().all(|(i, &b)| b == (i % 251) as u8));
+        assert!(contents[1000..].iter().all(|&b| b == 0));
+
+        assert!((&volume).open("/gone.txt").is_err());
+        assert_eq!((&volume).create_file("/new.txt").unwrap_err().kind(),
+            io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn test_exfat_rejects_fat32() {
+        let mut data = mbr_image(0x0C, 2049);
+        format_fat(&mut data, 1, 2048, vfat::FatType::Fat32, 0);
+        assert!(ExFat::<StdExFatHandle>::from(Cursor::new(data)).is_err());
+    }
+}
+
+#[test]
+fn test_mkfs() {
+    use crate::mkfs::{self, FormatOptions};
+
+    // 40 MiB: the partition is aligned to 1 MiB and uses 512-byte clusters.
+    let total_sectors = 40 * 2048;
+    let mut cursor = Cursor::new(vec![0u8; total_sectors as usize * 512]);
+    let options = FormatOptions { volume_label: *b"MKFS TEST  ", ..Default::default() };
+    let geometry = mkfs::format(&mut cursor, total_sectors, &options).expect("format");
+    assert_eq!(geometry.partition_start, 2048);
+    assert_eq!(geometry.sectors_per_cluster, 1);
+    assert!(geometry.cluster_count >= 65525);
+
+    let data = cursor.into_inner();
+    let mbr = MasterBootRecord::from(Cursor::new(data.clone())).expect("valid mbr");
+    assert_eq!(mbr.partitions[0].partition_type, mkfs::FAT32_PARTITION_TYPE);
+    let bpb_start = geometry.partition_start as usize * 512;
+    let bpb = BiosParameterBlock::from(Cursor::new(data.clone()), geometry.partition_start)
+        .expect("valid bpb");
+    assert_eq!(&data[bpb_start..bpb_start + 512], &data[bpb_start + 6 * 512..bpb_start + 7 * 512]);
+    assert_eq!(bpb.sectors_per_fat(), geometry.sectors_per_fat);
+    let fsinfo = vfat::FsInfo::from(Cursor::new(data.clone()), geometry.partition_start + 1)
+        .expect("valid fsinfo");
+    assert_eq!({ fsinfo.free_count }, geometry.cluster_count - 1);
+
+    let device = SharedDevice::new(data);
+    let vfat = VFat::<StdVFatHandle>::from(device.clone()).expect("valid vfat");
+    vfat.lock(|fs| assert_eq!(fs.fat_type(), vfat::FatType::Fat32));
+    let report = vfat.lock(|fs| fs.check(false)).expect("check");
+    assert!(report.is_clean(), "{:?}", report.problems);
+    assert_eq!((report.files, report.dirs, report.used_clusters), (0, 1, 1));
+    assert!(entry_names(&vfat, "/").is_empty());
+
+    let contents: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+    vfat.create_file("/HELLO.TXT").unwrap().write_all(&contents).expect("write");
+    vfat.lock(|fs| fs.flush()).expect("flush");
+    let vfat = VFat::<StdVFatHandle>::from(device).expect("valid vfat");
+    assert_eq!(read_file_from(&vfat, "/HELLO.TXT"), contents);
+
+    // A disk too small for a FAT32 volume is rejected.
+    let mut cursor = Cursor::new(vec![0u8; 16 * 512]);
+    assert!(mkfs::format(&mut cursor, 16, &FormatOptions::default()).is_err());
+
+    // So is one that would have fewer than 65525 clusters, which readers
+    // would take for FAT16.
+    let total_sectors = 32 * 2048;
+    let mut cursor = Cursor::new(vec![0u8; total_sectors as usize * 512]);
+    let err = mkfs::format(&mut cursor, total_sectors, &FormatOptions::default()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    let options = FormatOptions { sectors_per_cluster: Some(8), ..Default::default() };
+    let total_sectors = 40 * 2048;
+    let mut cursor = Cursor::new(vec![0u8; total_sectors as usize * 512]);
+    assert!(mkfs::format(&mut cursor, total_sectors, &options).is_err());
+}
+
+#[test]
+fn test_fsinfo_and_statfs() {
+    use crate::vfat::{FsInfo, Problem};
+
+    let (device, vfat, geometry) = formatted_vfat();
+    let fsinfo_sector = geometry.partition_start + 1;
+    let read_fsinfo = |device: &SharedDevice| {
+        FsInfo::from(Cursor::new(device.contents()), fsinfo_sector).expect("valid fsinfo")
+    };
+
+    let stats = vfat.lock(|fs| fs.statfs()).expect("statfs");
+    assert_eq!(stats.cluster_size, 512);
+    assert_eq!(stats.total_clusters, geometry.cluster_count);
+    assert_eq!(stats.free_clusters, geometry.cluster_count - 1);
+    assert_eq!(stats.used_bytes(), 512);
+
+    // 5000 bytes take 10 clusters, allocated from the next-free hint (3).
+    vfat.create_file("/A.TXT").unwrap().write_all(&[b'a'; 5000]).expect("write");
+    vfat.lock(|fs| fs.flush()).expect("flush");
+    let fsinfo = read_fsinfo(&device);
+    assert_eq!({ fsinfo.free_count }, geometry.cluster_count - 11);
+    assert_eq!({ fsinfo.next_free }, 13);
+
+    let vfat = VFat::<StdVFatHandle>::from(device.clone()).expect("valid vfat");
+    assert_eq!(vfat.lock(|fs| fs.statfs()).unwrap().free_clusters, geometry.cluster_count - 11);
+    vfat.remove("/A.TXT").expect("remove");
+    vfat.lock(|fs| fs.flush()).expect("flush");
+    assert_eq!({ read_fsinfo(&device).free_count }, geometry.cluster_count - 1);
+
+    // A wrong free count is reported and repaired by the checker.
+    let offset = fsinfo_sector as usize * 512 + FsInfo::FREE_COUNT_OFFSET;
+    let mut data = device.contents();
+    data[offset..offset + 4].copy_from_slice(&100u32.to_le_bytes());
+    let device = SharedDevice::new(data);
+    let vfat = VFat::<StdVFatHandle>::from(device.clone()).expect("valid vfat");
+    let report = vfat.lock(|fs| fs.check(true)).expect("check");
+    assert_eq!(report.problems, vec![
+        Problem::FreeCountMismatch { recorded: 100, actual: geometry.cluster_count - 1 },
+    ]);
+    assert_eq!({ read_fsinfo(&device).free_count }, geometry.cluster_count - 1);
+
+    // An FSInfo sector with a bad signature is ignored and the FAT is scanned.
+    let mut data = device.contents();
+    data[fsinfo_sector as usize * 512] = 0;
+    let vfat = VFat::<StdVFatHandle>::from(SharedDevice::new(data)).expect("valid vfat");
+    assert_eq!(vfat.lock(|fs| fs.statfs()).unwrap().free_clusters, geometry.cluster_count - 1);
+}
+
+#[test]
+fn test_seek_uses_cached_chain() {
+    use std::io::SeekFrom;
+
+    let (_, vfat, _) = formatted_vfat();
+
+    // Interleave writes to two files so that their chains are fragmented.
+    let big: Vec<u8> = (0..200 * 512).map(|i| (i / 512) as u8 ^ (i % 251) as u8).collect();
+    let mut a = vfat.create_file("/A.BIN").unwrap();
+    let mut b = vfat.create_file("/B.BIN").unwrap();
+    for chunk in big.chunks(1536) {
+        a.write_all(chunk).expect("write");
+        b.write_all(&[b'b'; 512]).expect("write");
+    }
+
+    let mut buf = [0u8; 700];
+    for &offset in [199 * 512 + 10, 0, 100 * 512 - 3, 5, 150 * 512, 511].iter() {
+        assert_eq!(a.seek(SeekFrom::Start(offset as u64)).unwrap(), offset as u64);
+        let n = a.read(&mut buf).expect("read");
+        let end = std::cmp::min(offset + buf.len(), big.len());
+        assert_eq!(&buf[..n], &big[offset..end]);
+    }
+
+    // Once the whole chain has been walked, seeking no longer touches the FAT.
+    a.seek(SeekFrom::End(0)).unwrap();
+    let before = vfat.lock(|fs| fs.cache_stats());
+    for offset in (0..big.len()).rev().step_by(997) {
+        a.seek(SeekFrom::Start(offset as u64)).unwrap();
+    }
+    a.seek(SeekFrom::End(0)).unwrap();
+    let after = vfat.lock(|fs| fs.cache_stats());
+    assert_eq!(after.hits + after.misses, before.hits + before.misses);
+
+    // Appending after a seek extends the cached chain.
+    a.write_all(&[0xAA; 1000]).expect("append");
+    a.seek(SeekFrom::Start(big.len() as u64 + 600)).unwrap();
+    assert_eq!(a.read(&mut buf).unwrap(), 400);
+    assert_eq!(&buf[..400], &[0xAA; 400][..]);
+}
+
+#[test]
+fn test_seek_past_end_then_write() {
+    use std::io::SeekFrom;
+
+    let (_, vfat, _) = formatted_vfat();
+
+    let mut file = vfat.create_file("/EMPTY.BIN").unwrap();
+    let err = file.seek(SeekFrom::Start(10000)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(file.seek(SeekFrom::Current(1)).is_err());
+    assert!(file.seek(SeekFrom::End(1)).is_err());
+
+    // The pointer is unchanged, so the write goes to the start of the file.
+    file.write_all(b"hello").expect("write after rejected seek");
+    assert_eq!(file.seek(SeekFrom::End(0)).unwrap(), 5);
+    assert!(file.seek(SeekFrom::Start(6)).is_err());
+    file.write_all(b" world").expect("append");
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents).expect("read");
+    assert_eq!(contents, b"hello world");
+}
+
+#[test]
+fn test_case_insensitive_lookup() {
+    use crate::vfat::dir::VFatRegularDirEntry;
+    use crate::vfat::{Attributes, Cluster};
+
+    let (_, vfat, _) = formatted_vfat();
+
+    let names = ["readme.txt", "data.TXT", "Makefile", "Ünïcödé 😀.txt"];
+    for name in names.iter() {
+        vfat.create_file(format!("/{}", name)).expect("create");
+    }
+    let mut sorted: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+    sorted.sort();
+    assert_eq!(entry_names(&vfat, "/"), sorted);
+
+    // All-lowercase base names and extensions are stored with the NT flags
+    // instead of LFN entries.
+    let mut root = Vec::new();
+    let root_cluster = vfat.lock(|fs| fs.root_cluster());
+    vfat.lock(|fs| fs.read_chain(root_cluster, &mut root)).expect("read root");
+    assert_eq!((&root[0..11], root[12]), (&b"README  TXT"[..], 0x18));
+    assert_eq!((&root[32..43], root[44]), (&b"DATA    TXT"[..], 0x08));
+    assert_eq!(root[64 + 11], 0x0F);
+
+    for &(query, expected) in [
+        ("README.TXT", "readme.txt"),
+        ("Data.txt", "data.TXT"),
+        ("MAKEFILE", "Makefile"),
+        ("makefi~1", "Makefile"),
+        ("ünÏcÖdé 😀.TXT", "Ünïcödé 😀.txt"),
+    ].iter() {
+        let entry = vfat.open(format!("/{}", query)).expect(query);
+        assert_eq!(entry.name(), expected);
+    }
+    assert!(vfat.create_file("/MakeFile").is_err());
+
+    // Short names are decoded as OEM code page 437.
+    let entry = VFatRegularDirEntry::new(*b"\x05T\x82     TXT", Attributes::default(),
+        Cluster::from(0), 0);
+    assert_eq!(entry.basic_name(), "σTé.TXT");
+}
@@ -56,9 +56,50 @@ pub trait BlockDevice: Send {
     /// `buf` の長さが `self.sector_size()` より短い場合、
     /// `UnexpectedEof` エラーを返す。
     fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize>;
+
+    /// セクタ番号 `n` から始まる連続したセクタを `buf` に読み込む.
+    ///
+    /// `buf.len() / self.sector_size()` セクタを読み込み、読み込んだ
+    /// バイト数を返す。デフォルトの実装は `read_sector()` を繰り返し
+    /// 呼び出す。複数のセクタをまとめて転送できるデバイスはこのメソッドを
+    /// オーバーライドすること。
+    ///
+    /// # エラー
+    ///
+    /// いずれかのセクタの読み込みに失敗した場合はエラーを返す。
+    fn read_sectors(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let sector_size = self.sector_size() as usize;
+        let mut read = 0;
+        for (i, chunk) in buf.chunks_exact_mut(sector_size).enumerate() {
+            read += self.read_sector(n + i as u64, chunk)?;
+        }
+        Ok(read)
+    }
+
+    /// `buf` の内容でセクタ番号 `n` から始まる連続したセクタを上書きする.
+    ///
+    /// `buf.len() / self.sector_size()` セクタを書き出し、書き出した
+    /// バイト数を返す。デフォルトの実装は `write_sector()` を繰り返し
+    /// 呼び出す。
+    ///
+    /// # エラー
+    ///
+    /// いずれかのセクタの書き出しに失敗した場合はエラーを返す。
+    fn write_sectors(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        let sector_size = self.sector_size() as usize;
+        let mut written = 0;
+        for (i, chunk) in buf.chunks_exact(sector_size).enumerate() {
+            written += self.write_sector(n + i as u64, chunk)?;
+        }
+        Ok(written)
+    }
 }
 
 impl<'a, T: BlockDevice> BlockDevice for &'a mut T {
+    fn sector_size(&self) -> u64 {
+        (**self).sector_size()
+    }
+
     fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
         (*self).read_sector(n, buf)
     }
@@ -66,6 +107,14 @@ impl<'a, T: BlockDevice> BlockDevice for &'a mut T {
     fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
         (*self).write_sector(n, buf)
     }
+
+    fn read_sectors(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        (*self).read_sectors(n, buf)
+    }
+
+    fn write_sectors(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        (*self).write_sectors(n, buf)
+    }
 }
 
 macro impl_for_read_write_seek($(<$($gen:tt),*>)* $T:path) {
@@ -93,5 +142,5 @@ macro impl_for_read_write_seek($(<$($gen:tt),*>)* $T:path) {
 impl_for_read_write_seek!(<'a> shim::io::Cursor<&'a mut [u8]>);
 impl_for_read_write_seek!(shim::io::Cursor<Vec<u8>>);
 impl_for_read_write_seek!(shim::io::Cursor<Box<[u8]>>);
-#[cfg(test)]
+#[cfg(not(feature = "no_std"))]
 impl_for_read_write_seek!(::std::fs::File);
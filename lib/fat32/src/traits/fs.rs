@@ -1,4 +1,4 @@
-use shim::{io, path::Path};
+use shim::{io, ioerr, path::Path};
 
 use crate::traits::Metadata;
 
@@ -120,4 +120,62 @@ pub trait FileSystem: Sized {
             .into_dir()
             .ok_or(io::Error::new(io::ErrorKind::Other, "not a directory"))
     }
+
+    /// `path` に空の通常ファイルを作成してオープンする. `path` は絶対パスで
+    /// なければならない。
+    ///
+    /// # エラー
+    ///
+    /// `path` の最後のコンポーネント以外が既存のディレクトリを参照して
+    /// いない場合、`InvalidInput` という種類のエラーが返される。
+    ///
+    /// `path` にすでにエントリがある場合、`AlreadyExists` という種類の
+    /// エラーが返される。
+    ///
+    /// デフォルトの実装は読み込み専用のファイルシステム用であり、
+    /// 常に `PermissionDenied` という種類のエラーを返す。
+    fn create_file<P: AsRef<Path>>(self, _path: P) -> io::Result<Self::File> {
+        ioerr!(PermissionDenied, "read only file system")
+    }
+
+    /// `path` に空のディレクトリを作成してオープンする. `path` は絶対パスで
+    /// なければならない。
+    ///
+    /// # エラー
+    ///
+    /// エラー条件は `create_file()` と同じである。
+    fn create_dir<P: AsRef<Path>>(self, _path: P) -> io::Result<Self::Dir> {
+        ioerr!(PermissionDenied, "read only file system")
+    }
+
+    /// `path` にあるファイルまたは空のディレクトリを削除する. `path` は
+    /// 絶対パスでなければならない。
+    ///
+    /// # エラー
+    ///
+    /// `path` にエントリがない場合、`NotFound` という種類のエラーが
+    /// 返される。
+    ///
+    /// `path` がルートディレクトリまたは空でないディレクトリの場合、
+    /// `PermissionDenied` という種類のエラーが返される。
+    fn remove<P: AsRef<Path>>(self, _path: P) -> io::Result<()> {
+        ioerr!(PermissionDenied, "read only file system")
+    }
+
+    /// `from` にあるエントリの名前を `to` に変更する. 異なるディレクトリへの
+    /// 移動も可能である。`from` と `to` は絶対パスでなければならない。
+    ///
+    /// # エラー
+    ///
+    /// `from` にエントリがない場合、`NotFound` という種類のエラーが
+    /// 返される。
+    ///
+    /// `to` にすでにエントリがある場合、`AlreadyExists` という種類の
+    /// エラーが返される。
+    ///
+    /// ディレクトリを自身の配下に移動しようとした場合、`InvalidInput`
+    /// という種類のエラーが返される。
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(self, _from: P, _to: Q) -> io::Result<()> {
+        ioerr!(PermissionDenied, "read only file system")
+    }
 }
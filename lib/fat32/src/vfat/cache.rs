@@ -9,8 +9,27 @@ use crate::traits::BlockDevice;
 
 #[derive(Debug)]
 struct CacheEntry {
+    /// このエントリが保持している論理セクタ.
+    sector: u64,
     data: Vec<u8>,
     dirty: bool,
+    /// CLOCKアルゴリズムの参照ビット.
+    referenced: bool,
+}
+
+/// `CachedPartition` の統計情報.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CacheStats {
+    /// キャッシュ上で見つかったアクセスの回数.
+    pub hits: u64,
+    /// ディスクからの読み込みが必要だったアクセスの回数.
+    pub misses: u64,
+    /// 先読みでディスクから読み込んだセクタ数.
+    pub read_ahead: u64,
+    /// 追い出されたセクタ数.
+    pub evictions: u64,
+    /// 追い出しの際にディスクに書き戻したダーティなセクタ数.
+    pub write_backs: u64,
 }
 
 pub struct Partition {
@@ -22,9 +41,24 @@ pub struct Partition {
     pub sector_size: u64,
 }
 
+/// キャッシュに保持する論理セクタ数のデフォルト値.
+pub const DEFAULT_CACHE_CAPACITY: usize = 512;
+
 pub struct CachedPartition {
     device: Box<dyn BlockDevice>,
-    cache: HashMap<u64, CacheEntry>,
+    /// 論理セクタから `entries` のインデックスへのマップ.
+    cache: HashMap<u64, usize>,
+    entries: Vec<CacheEntry>,
+    /// キャッシュに保持する最大のセクタ数.
+    capacity: usize,
+    /// CLOCKアルゴリズムの針. 次に追い出しの候補となる `entries` の
+    /// インデックス.
+    hand: usize,
+    /// 連続したアクセスの際に先読みするセクタ数.
+    read_ahead: usize,
+    /// 最後にアクセスされた論理セクタ.
+    last_access: Option<u64>,
+    stats: CacheStats,
     partition: Partition,
 }
 
@@ -32,7 +66,8 @@ impl CachedPartition {
     /// `device` からセクタを透過的にキャッシュし、物理セクタを
     /// `partition` 内の論理セクタにマップする新しい `CachedPartition` を
     /// 作成する。`CacheDevice` からの読み込みと書き出しはすべて
-    /// インメモリキャッシュで行われる。
+    /// インメモリキャッシュで行われる。キャッシュには最大
+    /// `DEFAULT_CACHE_CAPACITY` 個のセクタが保持される。
     ///
     /// `partition` パラメータは論理セクタのサイズと論理セクタの開始位置を
     /// 決定する。セクタ番号 `0` へのアクセスは物理セクタ `partition.start`
@@ -47,18 +82,74 @@ impl CachedPartition {
     /// パーティションのセクタサイズがデバイスのセクタサイズより小さい
     /// 場合はパニックになる。
     pub fn new<T>(device: T, partition: Partition) -> CachedPartition
+    where
+        T: BlockDevice + 'static,
+    {
+        CachedPartition::with_capacity(device, partition, DEFAULT_CACHE_CAPACITY)
+    }
+
+    /// 最大 `capacity` 個のセクタを保持する `CachedPartition` を作成する.
+    /// それ以外は `new()` と同じである。
+    ///
+    /// # パニック
+    ///
+    /// `new()` の条件に加え、`capacity` が0の場合はパニックになる。
+    pub fn with_capacity<T>(device: T, partition: Partition, capacity: usize) -> CachedPartition
     where
         T: BlockDevice + 'static,
     {
         assert!(partition.sector_size >= device.sector_size());
+        assert!(capacity > 0);
 
         CachedPartition {
             device: Box::new(device),
             cache: HashMap::new(),
+            entries: Vec::new(),
+            capacity,
+            hand: 0,
+            read_ahead: 0,
+            last_access: None,
+            stats: CacheStats::default(),
             partition: partition,
         }
     }
 
+    /// キャッシュに保持する最大のセクタ数を `capacity` に変更する.
+    ///
+    /// 現在のセクタ数より小さくする場合はダーティなセクタをすべて
+    /// 書き戻してからキャッシュを空にする。
+    ///
+    /// # パニック
+    ///
+    /// `capacity` が0の場合はパニックになる。
+    pub fn set_capacity(&mut self, capacity: usize) -> io::Result<()> {
+        assert!(capacity > 0);
+
+        if capacity < self.entries.len() {
+            self.flush()?;
+            self.cache.clear();
+            self.entries.clear();
+            self.hand = 0;
+        }
+        self.capacity = capacity;
+        self.set_read_ahead(self.read_ahead);
+        Ok(())
+    }
+
+    /// 連続したセクタへのアクセスを検出した際に、キャッシュされていない
+    /// セクタを読み込むのと同時に後続の最大 `sectors` 個のセクタを
+    /// 先読みするようにする. 0の場合は先読みを行わない。
+    ///
+    /// 先読みするセクタ数はキャッシュの容量未満に制限される。
+    pub fn set_read_ahead(&mut self, sectors: usize) {
+        self.read_ahead = core::cmp::min(sectors, self.capacity - 1);
+    }
+
+    /// キャッシュの統計情報を返す.
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
     /// 論理セクタあたりの物理セクタ数を返すr.
     fn factor(&self) -> u64 {
         self.partition.sector_size / self.device.sector_size()
@@ -77,36 +168,110 @@ impl CachedPartition {
         Some(physical_sector)
     }
 
-    fn load_sector(&mut self, buf: &mut Vec<u8>, sector: u64) -> io::Result<()> {
-        buf.clear();
-        buf.reserve(self.partition.sector_size as usize);
-
+    /// 論理セクタ `sector` から `buf.len()` バイト分の連続したセクタを
+    /// 読み込む.
+    fn load_sectors(&mut self, buf: &mut [u8], sector: u64) -> io::Result<()> {
         let physical_sector = self.virtual_to_physical(sector)
             .ok_or(io::ErrorKind::InvalidInput)?;
 
-        for i in 0..self.factor() {
-            let mut raw = [0_u8; 512];
-            self.device.read_sector(physical_sector + i, &mut raw)?;
+        self.device.read_sectors(physical_sector, buf)?;
+        Ok(())
+    }
+
+    /// 新しいエントリを置く `entries` のインデックスを返す.
+    ///
+    /// キャッシュが満杯の場合はCLOCKアルゴリズムで選んだエントリを
+    /// 追い出す。追い出したエントリがダーティな場合はディスクに書き戻す。
+    fn free_slot(&mut self) -> io::Result<usize> {
+        if self.entries.len() < self.capacity {
+            return Ok(self.entries.len());
+        }
+
+        let factor = self.factor();
+        loop {
+            let index = self.hand;
+            self.hand = (self.hand + 1) % self.entries.len();
+
+            let entry = &mut self.entries[index];
+            if entry.referenced {
+                entry.referenced = false;
+                continue;
+            }
 
-            for c in raw.iter() {
-                buf.push(*c);
+            if entry.dirty {
+                let physical_sector = self.partition.start + entry.sector * factor;
+                self.device.write_sectors(physical_sector, &entry.data)?;
+                entry.dirty = false;
+                self.stats.write_backs += 1;
             }
+
+            self.cache.remove(&entry.sector);
+            self.stats.evictions += 1;
+            return Ok(index);
         }
-        Ok(())
     }
 
-    fn get_entry(&mut self, sector: u64) -> io::Result<&mut CacheEntry> {
-        if let None = self.cache.get_mut(&sector) {
-            let mut buf: Vec<u8> = Vec::new();
-            self.load_sector(&mut buf, sector)?;
-
-            self.cache.insert(sector, CacheEntry {
-                dirty: false,
-                data: buf,
-            });
+    /// `sector` の内容 `data` をキャッシュに追加してそのインデックスを返す.
+    fn insert(&mut self, sector: u64, data: Vec<u8>, referenced: bool) -> io::Result<usize> {
+        let index = self.free_slot()?;
+        let entry = CacheEntry { sector, data, dirty: false, referenced };
+        if index == self.entries.len() {
+            self.entries.push(entry);
+        } else {
+            self.entries[index] = entry;
+        }
+        self.cache.insert(sector, index);
+        Ok(index)
+    }
+
+    /// 直前のアクセスに続くセクタ `sector` へのアクセスの場合、`sector`
+    /// の後に先読みできるセクタ数を返す.
+    fn read_ahead_count(&self, sector: u64) -> usize {
+        if self.read_ahead == 0 || sector == 0 || self.last_access != Some(sector - 1) {
+            return 0;
         }
 
-        Ok(self.cache.get_mut(&sector).unwrap())
+        let mut count = 0;
+        while count < self.read_ahead {
+            let next = sector + count as u64 + 1;
+            if next >= self.partition.num_sectors || self.cache.contains_key(&next) {
+                break;
+            }
+            count += 1;
+        }
+        count
+    }
+
+    fn get_entry(&mut self, sector: u64) -> io::Result<&mut CacheEntry> {
+        let index = match self.cache.get(&sector) {
+            Some(&index) => {
+                self.stats.hits += 1;
+                index
+            }
+            None => {
+                self.stats.misses += 1;
+
+                let sector_size = self.partition.sector_size as usize;
+                let count = self.read_ahead_count(sector);
+                let mut buf: Vec<u8> = vec![0; sector_size * (count + 1)];
+                self.load_sectors(&mut buf, sector)?;
+
+                // 要求されたセクタを先に追加する. 先読みしたセクタの追加で
+                // 針が一周することはないのでこのセクタは追い出されない
+                let rest = buf.split_off(sector_size);
+                let index = self.insert(sector, buf, true)?;
+                for (i, data) in rest.chunks(sector_size).enumerate() {
+                    self.insert(sector + i as u64 + 1, data.to_vec(), false)?;
+                }
+                self.stats.read_ahead += count as u64;
+                index
+            }
+        };
+
+        self.last_access = Some(sector);
+        let entry = &mut self.entries[index];
+        entry.referenced = true;
+        Ok(entry)
     }
 
     /// キャッシュされたセクタ `sector` への可変参照を返す。
@@ -139,6 +304,30 @@ impl CachedPartition {
     pub fn get(&mut self, sector: u64) -> io::Result<&[u8]> {
         self.get_entry(sector).map(|entry| entry.data.as_slice())
     }
+
+    /// ダーティなセクタをすべてデバイスに書き戻す.
+    ///
+    /// 書き戻したセクタはクリーンとして扱われ、次回以降の `flush()` では
+    /// 再度変更されない限り書き出されない。
+    ///
+    /// # エラー
+    ///
+    /// セクタをディスクに書き出す際にエラーが発生した場合はエラーを
+    /// 返す。
+    pub fn flush(&mut self) -> io::Result<()> {
+        let factor = self.factor();
+
+        for entry in self.entries.iter_mut() {
+            if !entry.dirty {
+                continue;
+            }
+
+            let physical_sector = self.partition.start + entry.sector * factor;
+            self.device.write_sectors(physical_sector, &entry.data)?;
+            entry.dirty = false;
+        }
+        Ok(())
+    }
 }
 
 // FIXME: Implement `BlockDevice` for `CacheDevice`. The `read_sector` and
@@ -175,7 +364,9 @@ impl fmt::Debug for CachedPartition {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("CachedPartition")
             .field("device", &"<block device>")
-            .field("cache", &self.cache)
+            .field("capacity", &self.capacity)
+            .field("cached", &self.entries.len())
+            .field("stats", &self.stats)
             .finish()
     }
 }
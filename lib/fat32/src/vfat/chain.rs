@@ -0,0 +1,134 @@
+use alloc::vec::Vec;
+
+use shim::io;
+use shim::ioerr;
+
+use crate::vfat::vfat::SeekHandle;
+use crate::vfat::{Cluster, Status, VFat, VFatHandle};
+
+/// チェーン内で連続した番号を持つクラスタの並び.
+#[derive(Debug, Clone, Copy)]
+struct Extent {
+    /// 最初のクラスタのチェーン内でのインデックス.
+    index: u32,
+    /// 最初のクラスタ番号.
+    start: u32,
+    /// クラスタ数.
+    count: u32,
+}
+
+/// ファイルのクラスタチェーンのキャッシュ.
+///
+/// チェーンの先頭から必要になった位置までFATを辿り、その結果を
+/// エクステントの列として保持する。一度辿った位置へのシークはFATを
+/// 読まずに二分探索で求められる。チェーンの途中は変更されないことを
+/// 前提とし、末尾への追加は `invalidate_tail()` で通知する。
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ClusterChain {
+    extents: Vec<Extent>,
+    /// キャッシュしたクラスタ数.
+    len: u32,
+    /// チェーンの終端まで辿り終えている.
+    complete: bool,
+}
+
+impl ClusterChain {
+    pub fn new() -> ClusterChain {
+        ClusterChain::default()
+    }
+
+    /// キャッシュを破棄する. チェーンの開始クラスタが変わった場合に使用する.
+    pub fn reset(&mut self) {
+        self.extents.clear();
+        self.len = 0;
+        self.complete = false;
+    }
+
+    /// チェーンの末尾にクラスタが追加された可能性があることを通知する.
+    pub fn invalidate_tail(&mut self) {
+        self.complete = false;
+    }
+
+    fn push(&mut self, cluster: Cluster) {
+        let raw = cluster.raw();
+        match self.extents.last_mut() {
+            Some(last) if last.start + last.count == raw => last.count += 1,
+            _ => self.extents.push(Extent { index: self.len, start: raw, count: 1 }),
+        }
+        self.len += 1;
+    }
+
+    /// キャッシュ済みの最後のクラスタとそのインデックスを返す.
+    fn last(&self) -> Option<(u32, Cluster)> {
+        self.extents.last()
+            .map(|e| (e.index + e.count - 1, Cluster::from(e.start + e.count - 1)))
+    }
+
+    /// キャッシュ済みのインデックス `index` のクラスタを返す.
+    fn lookup(&self, index: u32) -> Option<Cluster> {
+        if index >= self.len {
+            return None;
+        }
+
+        let i = match self.extents.binary_search_by_key(&index, |e| e.index) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+        let extent = &self.extents[i];
+        Some(Cluster::from(extent.start + (index - extent.index)))
+    }
+
+    /// `start` から始まるチェーンの `index` 番目のクラスタを返す.
+    /// チェーンが `index` 番目まで続いていない場合は `None` を返す。
+    ///
+    /// # エラー
+    ///
+    /// チェーンが不正なFATエントリで終わっている、またはループしている
+    /// 場合はエラー `Other` を返す。
+    pub fn get<HANDLE: VFatHandle>(&mut self, fs: &mut VFat<HANDLE>, start: Cluster,
+        index: u32) -> io::Result<Option<Cluster>> {
+        if start.raw() == 0 {
+            return Ok(None);
+        }
+        if self.len == 0 {
+            self.push(start);
+        }
+
+        while index >= self.len && !self.complete {
+            let (_, last) = self.last().expect("chain is not empty");
+            match fs.fat_entry(last)?.status() {
+                Status::Data(next) => self.push(next),
+                Status::Eoc(_) => self.complete = true,
+                _ => return ioerr!(Other, "unexpected fat entry"),
+            }
+            if self.len > fs.cluster_count {
+                return ioerr!(Other, "cluster chain loops");
+            }
+        }
+        Ok(self.lookup(index))
+    }
+
+    /// `start` から始まるチェーンの先頭から `offset` バイトの位置を指す
+    /// `SeekHandle` を返す.
+    ///
+    /// `offset` がチェーンの末尾を超える場合は最後のクラスタからの
+    /// オフセットとして表す。
+    pub fn seek<HANDLE: VFatHandle>(&mut self, fs: &mut VFat<HANDLE>, start: Cluster,
+        offset: usize) -> io::Result<SeekHandle> {
+        let cluster_size = fs.cluster_size_bytes();
+        let index = offset / cluster_size;
+        let (index, cluster) = match self.get(fs, start, index as u32)? {
+            Some(cluster) => (index, cluster),
+            None => match self.last() {
+                Some((last, cluster)) => (last as usize, cluster),
+                None => (0, start),
+            },
+        };
+
+        Ok(SeekHandle {
+            cluster,
+            offset: offset - index * cluster_size,
+            total_offset: offset,
+        })
+    }
+}
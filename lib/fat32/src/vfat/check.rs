@@ -0,0 +1,398 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem::size_of;
+
+use shim::io;
+
+use crate::util::VecExt;
+use crate::vfat::dir::{parse_slot, VFatDirEntry};
+use crate::vfat::vfat::{EntryLocation, EOC_MARKER};
+use crate::vfat::{Cluster, Status, VFat, VFatHandle};
+
+/// `VFat::check()` が検出した問題.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// クラスタ `cluster` が `first` と `second` の2つのチェーンから
+    /// 参照されている. `first` と `second` が同じ場合はチェーンが
+    /// ループしている。
+    CrossLinked { cluster: u32, first: String, second: String },
+    /// どのエントリからも参照されていない、`start` から始まる
+    /// `clusters` 個のクラスタからなるチェーン.
+    LostChain { start: u32, clusters: u32 },
+    /// `path` のチェーンがクラスタ `cluster` で終端ではなく空き、予約済み、
+    /// 不良またはボリューム外を指すFATエントリ `value` で終わっている.
+    BadChainEnd { path: String, cluster: u32, value: u32 },
+    /// `path` のファイルサイズ `size` がチェーンの長さ `chain_bytes` と
+    /// 一致しない.
+    SizeMismatch { path: String, size: u32, chain_bytes: u64 },
+    /// FATのコピー `copy` のセクタ `sector` (FATの先頭からの番号) が
+    /// 最初のFATと一致しない.
+    FatMismatch { copy: u8, sector: u32 },
+    /// FSInfoセクタの空きクラスタ数 `recorded` が実際の空きクラスタ数
+    /// `actual` と一致しない.
+    FreeCountMismatch { recorded: u32, actual: u32 },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::CrossLinked { cluster, first, second } if first == second => {
+                write!(f, "{}: chain loops at cluster {}", first, cluster)
+            }
+            Problem::CrossLinked { cluster, first, second } => {
+                write!(f, "{} and {} are cross-linked at cluster {}", first, second, cluster)
+            }
+            Problem::LostChain { start, clusters } => {
+                write!(f, "lost chain of {} clusters starting at cluster {}", clusters, start)
+            }
+            Problem::BadChainEnd { path, cluster, value } => {
+                write!(f, "{}: chain ends at cluster {} with FAT entry {:#x}", path, cluster, value)
+            }
+            Problem::SizeMismatch { path, size, chain_bytes } => {
+                write!(f, "{}: size is {} bytes but chain holds {} bytes", path, size, chain_bytes)
+            }
+            Problem::FatMismatch { copy, sector } => {
+                write!(f, "FAT copy {} differs from the first FAT at sector {}", copy, sector)
+            }
+            Problem::FreeCountMismatch { recorded, actual } => {
+                write!(f, "FSInfo records {} free clusters but {} are free", recorded, actual)
+            }
+        }
+    }
+}
+
+/// `VFat::check()` の結果.
+#[derive(Debug, Default, Clone)]
+pub struct CheckReport {
+    /// 検出した問題.
+    pub problems: Vec<Problem>,
+    /// 走査したファイル数.
+    pub files: usize,
+    /// 走査したディレクトリ数 (ルートディレクトリを含む).
+    pub dirs: usize,
+    /// エントリから参照されているクラスタ数.
+    pub used_clusters: u32,
+    /// 問題を修復した場合は `true`.
+    pub repaired: bool,
+}
+
+impl CheckReport {
+    /// 問題が見つからなかった場合は `true` を返す.
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// チェックの途中状態. `owners[n]` はクラスタ `n` を参照しているチェーンの
+/// `paths` 内のインデックス + 1 (0は未参照).
+struct Checker {
+    repair: bool,
+    owners: Vec<u32>,
+    paths: Vec<String>,
+    report: CheckReport,
+}
+
+/// チェーンをたどった結果.
+struct Chain {
+    clusters: Vec<Cluster>,
+    /// 先頭のクラスタがすでに他のチェーンに属していた場合は `true`.
+    shared_start: bool,
+}
+
+impl<HANDLE: VFatHandle> VFat<HANDLE> {
+    /// ファイルシステムの整合性を検査する.
+    ///
+    /// 次の問題を検出する:
+    ///
+    ///   * FATのコピー間の不一致
+    ///   * 複数のチェーンから参照されるクラスタ（クロスリンク）
+    ///   * 空き、予約済み、不良クラスタで終わるチェーン
+    ///   * サイズがチェーンの長さと一致しないファイル
+    ///   * どのエントリからも参照されていないクラスタ（ロストクラスタ）
+    ///   * FSInfoセクタの空きクラスタ数の誤り
+    ///
+    /// `repair` が `true` の場合は見つかった問題を修復してディスクに
+    /// 書き出す。FATのコピーは最初のFATで上書きし、壊れたチェーンは
+    /// 直前のクラスタで終端させ、ファイルサイズはチェーンの長さに
+    /// 合わせる。ロストクラスタは解放し、空きクラスタ数は数え直す。
+    ///
+    /// # エラー
+    ///
+    /// ディスクの読み書きに失敗した場合はエラーを返す。
+    pub fn check(&mut self, repair: bool) -> io::Result<CheckReport> {
+        let mut checker = Checker {
+            repair,
+            owners: vec![0; self.cluster_count as usize + 2],
+            paths: Vec::new(),
+            report: CheckReport::default(),
+        };
+
+        // 以降の修復はすべてのコピーに書き込まれるので、先にコピーを揃える
+        self.check_fat_copies(&mut checker)?;
+
+        let root = self.root_cluster();
+        let mut dirs = Vec::new();
+        let root_clusters = if self.is_fixed_root(root) {
+            vec![root]
+        } else {
+            self.check_chain(&mut checker, root, "/")?.clusters
+        };
+        dirs.push((String::from(""), root_clusters));
+
+        while let Some((path, clusters)) = dirs.pop() {
+            checker.report.dirs += 1;
+            self.check_dir(&mut checker, &path, &clusters, &mut dirs)?;
+        }
+
+        self.check_lost_clusters(&mut checker)?;
+        self.check_free_count(&mut checker)?;
+
+        checker.report.used_clusters = checker.owners.iter().filter(|&&o| o != 0).count() as u32;
+        if repair && !checker.report.problems.is_empty() {
+            self.flush()?;
+            checker.report.repaired = true;
+        }
+        Ok(checker.report)
+    }
+
+    /// 2つ目以降のFATのコピーを最初のFATと比較する.
+    fn check_fat_copies(&mut self, checker: &mut Checker) -> io::Result<()> {
+        let sector_size = self.bytes_per_sector as usize;
+        let mut first = vec![0u8; sector_size];
+
+        for sector in 0..self.sectors_per_fat {
+            first.copy_from_slice(self.device.get(self.fat_start_sector + sector as u64)?);
+            for copy in 1..self.fat_count {
+                let n = self.fat_start_sector
+                    + copy as u64 * self.sectors_per_fat as u64 + sector as u64;
+                if self.device.get(n)? == &first[..] {
+                    continue;
+                }
+
+                checker.report.problems.push(Problem::FatMismatch { copy, sector });
+                if checker.repair {
+                    self.device.get_mut(n)?.copy_from_slice(&first);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// `start` から始まる `path` のチェーンをたどり、各クラスタの所有者を
+    /// 記録する. 他のチェーンとのクロスリンクや不正な終端を見つけた場合は
+    /// 直前のクラスタでチェーンを打ち切る。
+    fn check_chain(&mut self, checker: &mut Checker, start: Cluster,
+        path: &str) -> io::Result<Chain> {
+        checker.paths.push(String::from(path));
+        let owner = checker.paths.len() as u32;
+
+        let mut chain = Chain { clusters: Vec::new(), shared_start: false };
+        let mut cluster = start;
+        loop {
+            let raw = cluster.raw();
+            if raw < 2 || raw as usize >= checker.owners.len() {
+                // 開始クラスタがボリューム外の場合
+                checker.report.problems.push(Problem::BadChainEnd {
+                    path: String::from(path),
+                    cluster: raw,
+                    value: raw,
+                });
+                break;
+            }
+
+            let previous = checker.owners[raw as usize];
+            if previous != 0 {
+                checker.report.problems.push(Problem::CrossLinked {
+                    cluster: raw,
+                    first: checker.paths[previous as usize - 1].clone(),
+                    second: String::from(path),
+                });
+                chain.shared_start = chain.clusters.is_empty();
+                if checker.repair {
+                    if let Some(&last) = chain.clusters.last() {
+                        self.set_fat_entry(last, EOC_MARKER)?;
+                    }
+                }
+                break;
+            }
+            checker.owners[raw as usize] = owner;
+            chain.clusters.push(cluster);
+
+            let entry = self.fat_entry(cluster)?;
+            match entry.status() {
+                Status::Eoc(_) => break,
+                Status::Data(next) if (next.raw() as usize) < checker.owners.len() => {
+                    cluster = next;
+                }
+                _ => {
+                    checker.report.problems.push(Problem::BadChainEnd {
+                        path: String::from(path),
+                        cluster: raw,
+                        value: entry.0,
+                    });
+                    if checker.repair {
+                        self.set_fat_entry(cluster, EOC_MARKER)?;
+                    }
+                    break;
+                }
+            }
+        }
+
+        Ok(chain)
+    }
+
+    /// クラスタ `clusters` からなるディレクトリ `path` のエントリを検査する.
+    /// サブディレクトリは `dirs` に追加される。
+    fn check_dir(&mut self, checker: &mut Checker, path: &str, clusters: &[Cluster],
+        dirs: &mut Vec<(String, Vec<Cluster>)>) -> io::Result<()> {
+        let mut buf: Vec<u8> = Vec::new();
+        for &cluster in clusters {
+            let start = buf.len();
+            buf.resize(start + self.cluster_len(cluster), 0);
+            self.read_cluster(cluster, 0, &mut buf[start..])?;
+        }
+        let slot_size = size_of::<VFatDirEntry>();
+        buf.truncate(buf.len() / slot_size * slot_size);
+        let slots: Vec<VFatDirEntry> = unsafe { buf.cast() };
+
+        let dir_cluster = clusters[0];
+        let cluster_size = self.cluster_size_bytes() as u64;
+        let mut index = 0;
+        while let Some(slot) = parse_slot(&slots, &mut index) {
+            let attributes = slot.entry.metadata().attributes;
+            if attributes.volume_id() || slot.name == "." || slot.name == ".." {
+                continue;
+            }
+
+            let entry_path = format!("{}/{}", path, slot.name);
+            let location = EntryLocation { dir_cluster, offset: slot.index * slot_size };
+            let start = slot.entry.cluster();
+
+            if attributes.directory() {
+                let chain = self.check_chain(checker, start, &entry_path)?;
+                // 他のディレクトリと共有されている場合は二重に走査しない
+                if !chain.shared_start && !chain.clusters.is_empty() {
+                    dirs.push((entry_path, chain.clusters));
+                }
+                continue;
+            }
+
+            checker.report.files += 1;
+            let size = slot.entry.file_size();
+            let chain = if start.raw() == 0 {
+                Chain { clusters: Vec::new(), shared_start: false }
+            } else {
+                self.check_chain(checker, start, &entry_path)?
+            };
+
+            if chain.shared_start {
+                // 先頭から共有されているチェーンは他のファイルのものとする
+                if checker.repair {
+                    self.update_entry(location, Cluster::from(0), 0)?;
+                }
+                continue;
+            }
+
+            let chain_bytes = chain.clusters.len() as u64 * cluster_size;
+            let needed = (size as u64 + cluster_size - 1) / cluster_size;
+            if needed == chain.clusters.len() as u64 {
+                continue;
+            }
+
+            checker.report.problems.push(Problem::SizeMismatch {
+                path: entry_path,
+                size,
+                chain_bytes,
+            });
+            if !checker.repair {
+                continue;
+            }
+
+            if needed < chain.clusters.len() as u64 {
+                // 余分なクラスタを解放する
+                let keep = needed as usize;
+                for &cluster in chain.clusters[keep..].iter() {
+                    self.set_fat_entry(cluster, 0)?;
+                    checker.owners[cluster.raw() as usize] = 0;
+                }
+                match keep {
+                    0 => self.update_entry(location, Cluster::from(0), 0)?,
+                    _ => self.set_fat_entry(chain.clusters[keep - 1], EOC_MARKER)?,
+                }
+            } else {
+                // 開始クラスタが不正な場合はチェーンは空になる
+                let start = if chain.clusters.is_empty() { Cluster::from(0) } else { start };
+                let size = core::cmp::min(chain_bytes, u32::max_value() as u64) as u32;
+                self.update_entry(location, start, size)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// どのチェーンからも参照されていない使用中のクラスタを探す.
+    fn check_lost_clusters(&mut self, checker: &mut Checker) -> io::Result<()> {
+        let count = checker.owners.len();
+        let mut lost = vec![false; count];
+        let mut next = vec![0u32; count];
+        let mut is_target = vec![false; count];
+
+        for raw in 2..count as u32 {
+            if checker.owners[raw as usize] != 0 {
+                continue;
+            }
+            match self.fat_entry(Cluster::from(raw))?.status() {
+                Status::Data(n) => {
+                    lost[raw as usize] = true;
+                    if (n.raw() as usize) < count {
+                        next[raw as usize] = n.raw();
+                        is_target[n.raw() as usize] = true;
+                    }
+                }
+                Status::Eoc(_) => lost[raw as usize] = true,
+                _ => {}
+            }
+        }
+
+        // 他のロストクラスタから参照されていないクラスタをチェーンの先頭とし、
+        // 残りはループしているチェーンとして扱う
+        let heads = (2..count).filter(|&c| lost[c] && !is_target[c])
+            .chain(2..count)
+            .collect::<Vec<_>>();
+        for head in heads {
+            if !lost[head] {
+                continue;
+            }
+
+            let mut clusters = 0;
+            let mut cluster = head;
+            while cluster != 0 && lost[cluster] {
+                lost[cluster] = false;
+                clusters += 1;
+                if checker.repair {
+                    self.set_fat_entry(Cluster::from(cluster as u32), 0)?;
+                }
+                cluster = next[cluster] as usize;
+            }
+            checker.report.problems.push(Problem::LostChain { start: head as u32, clusters });
+        }
+        Ok(())
+    }
+
+    /// 記録されている空きクラスタ数をFATから数えた値と比較する.
+    fn check_free_count(&mut self, checker: &mut Checker) -> io::Result<()> {
+        let recorded = match self.free_count() {
+            Some(recorded) => recorded,
+            None => return Ok(()),
+        };
+
+        let actual = self.count_free_clusters()?;
+        if recorded != actual {
+            checker.report.problems.push(Problem::FreeCountMismatch { recorded, actual });
+            if checker.repair {
+                self.set_free_count(actual);
+            }
+        }
+        Ok(())
+    }
+}
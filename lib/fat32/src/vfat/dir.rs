@@ -1,5 +1,6 @@
 use alloc::string::String;
 use alloc::vec::Vec;
+use core::mem::size_of;
 
 use shim::const_assert_size;
 use shim::ffi::OsStr;
@@ -10,6 +11,8 @@ use crate::traits;
 use crate::util::VecExt;
 use crate::vfat::{Attributes, Date, Metadata, Time, Timestamp};
 use crate::vfat::{Cluster, Entry, File, VFatHandle};
+use crate::vfat::name::{eq_ignore_case, oem_to_char};
+use crate::vfat::vfat::EntryLocation;
 
 #[derive(Debug)]
 pub struct Dir<HANDLE: VFatHandle> {
@@ -26,7 +29,8 @@ pub struct VFatRegularDirEntry {
     name: [u8; 8],
     ext: [u8; 3],
     attributes: Attributes,
-    __reserve: u8,
+    /// Windows NT が使用する名前の大文字小文字のフラグ.
+    nt_case: u8,
     creation_time_tenth: u8,
     creation_time: Time,
     creation_date: Date,
@@ -93,7 +97,63 @@ impl VFatDirEntry {
     }
 }
 
+impl VFatDirEntry {
+    /// エントリのオンディスク表現（32バイト）を返す.
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self as *const _ as *const u8, size_of::<Self>()) }
+    }
+}
+
+impl From<VFatRegularDirEntry> for VFatDirEntry {
+    fn from(regular: VFatRegularDirEntry) -> VFatDirEntry {
+        VFatDirEntry { regular }
+    }
+}
+
+impl From<VFatLfnDirEntry> for VFatDirEntry {
+    fn from(long_filename: VFatLfnDirEntry) -> VFatDirEntry {
+        VFatDirEntry { long_filename }
+    }
+}
+
 impl VFatRegularDirEntry {
+    /// 短い名前 `short_name` を持つ新しい通常エントリを作成する.
+    /// タイムスタンプはすべてゼロになる.
+    pub fn new(short_name: [u8; 11], attributes: Attributes, cluster: Cluster,
+        size: u32) -> VFatRegularDirEntry {
+        let mut entry = VFatRegularDirEntry {
+            name: [0; 8],
+            ext: [0; 3],
+            attributes,
+            nt_case: 0,
+            creation_time_tenth: 0,
+            creation_time: Default::default(),
+            creation_date: Default::default(),
+            accessed_date: Default::default(),
+            cluster_high: 0,
+            modified_time: Default::default(),
+            modified_date: Default::default(),
+            cluster_low: 0,
+            file_size: size,
+        };
+        entry.set_short_name(short_name);
+        entry.set_cluster(cluster);
+        entry
+    }
+
+    /// 空白で埋められた8.3形式の短い名前を返す.
+    pub fn short_name(&self) -> [u8; 11] {
+        let mut short_name = [0_u8; 11];
+        short_name[..8].copy_from_slice(&self.name);
+        short_name[8..].copy_from_slice(&self.ext);
+        short_name
+    }
+
+    pub fn set_short_name(&mut self, short_name: [u8; 11]) {
+        self.name.copy_from_slice(&short_name[..8]);
+        self.ext.copy_from_slice(&short_name[8..]);
+    }
+
     pub fn metadata(&self) -> Metadata {
         Metadata {
             attributes: self.attributes,
@@ -116,29 +176,220 @@ impl VFatRegularDirEntry {
         Cluster::from((self.cluster_high as u32) << 16 | self.cluster_low as u32)
     }
 
+    pub fn set_cluster(&mut self, cluster: Cluster) {
+        self.cluster_high = (cluster.raw() >> 16) as u16;
+        self.cluster_low = (cluster.raw() & 0xFFFF) as u16;
+    }
+
+    pub fn file_size(&self) -> u32 {
+        self.file_size
+    }
+
+    pub fn set_file_size(&mut self, size: u32) {
+        self.file_size = size;
+    }
+
+    /// 短い名前を表示される形式で返す.
+    ///
+    /// 名前はOEMコードページ437として復号し、NTの小文字フラグが
+    /// 立っている場合は基本名または拡張子を小文字にする。
     pub fn basic_name(&self) -> String {
-        let mut s = String::new();
-        for c in self.name.iter().take_while(|c| ![b'\0', b' '].contains(c)) {
-            s.push((*c).into());
-        }
-        let mut added_dot = false;
-        for c in self.ext.iter().take_while(|c| ![b'\0', b' '].contains(c)) {
-            if !added_dot {
-                s.push('.');
-                added_dot = true;
+        let decode = |bytes: &[u8], lower: bool, s: &mut String| {
+            for (i, &b) in bytes.iter().enumerate() {
+                if b == b'\0' || b == b' ' {
+                    break;
+                }
+                // 0xE5で始まる名前は削除済みと区別するため0x05で格納される
+                let c = oem_to_char(if i == 0 && b == 0x05 { 0xE5 } else { b });
+                s.push(if lower { c.to_ascii_lowercase() } else { c });
             }
-            s.push((*c).into())
+        };
+
+        let mut s = String::new();
+        decode(&self.name, self.nt_case & NT_LOWER_BASE != 0, &mut s);
+        let mut ext = String::new();
+        decode(&self.ext, self.nt_case & NT_LOWER_EXT != 0, &mut ext);
+        if !ext.is_empty() {
+            s.push('.');
+            s.push_str(&ext);
         }
         s
     }
 }
 
 impl VFatLfnDirEntry {
+    /// 名前の断片 `chars` (UTF-16で13文字分) を持つ新しいLFNエントリを
+    /// 作成する.
+    pub fn new(sequence_number: u8, checksum: u8, chars: &[u16; 13]) -> VFatLfnDirEntry {
+        let mut bytes = [0_u8; 26];
+        for (i, c) in chars.iter().enumerate() {
+            bytes[i * 2..i * 2 + 2].copy_from_slice(&c.to_le_bytes());
+        }
+
+        let mut entry = VFatLfnDirEntry {
+            sequence_number,
+            name_set_1: [0; 10],
+            attributes: 0x0F,
+            lfn_type: 0,
+            name_checksum: checksum,
+            name_set_2: [0; 12],
+            __reserve: 0,
+            name_set_3: [0; 4],
+        };
+        entry.name_set_1.copy_from_slice(&bytes[..10]);
+        entry.name_set_2.copy_from_slice(&bytes[10..22]);
+        entry.name_set_3.copy_from_slice(&bytes[22..]);
+        entry
+    }
+
     pub fn sequence_number(&self) -> u8 {
         self.sequence_number & 0b1_1111
     }
 }
 
+/// 短い名前の基本名が小文字であることを表すNTのフラグ.
+const NT_LOWER_BASE: u8 = 0x08;
+
+/// 短い名前の拡張子が小文字であることを表すNTのフラグ.
+const NT_LOWER_EXT: u8 = 0x10;
+
+/// LFNエントリの最後のエントリを表すシーケンス番号のフラグ.
+const LFN_LAST_ENTRY: u8 = 0x40;
+
+/// 1つのLFNエントリに格納できるUTF-16の文字数.
+const LFN_CHARS_PER_ENTRY: usize = 13;
+
+/// 長いファイル名の最大文字数（UTF-16単位）.
+const LFN_MAX_CHARS: usize = 255;
+
+/// 短い名前 `short_name` のLFNチェックサムを計算する.
+fn lfn_checksum(short_name: &[u8; 11]) -> u8 {
+    short_name.iter().fold(0_u8, |sum, c| {
+        ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(*c)
+    })
+}
+
+/// 長いファイル名 `name` を格納するLFNエントリをディスク上の順序
+/// （最後の断片が先頭）で返す.
+fn lfn_entries(name: &str, checksum: u8) -> Vec<VFatLfnDirEntry> {
+    let chars: Vec<u16> = name.encode_utf16().collect();
+    let count = (chars.len() + LFN_CHARS_PER_ENTRY - 1) / LFN_CHARS_PER_ENTRY;
+
+    let mut entries = Vec::with_capacity(count);
+    for seq in (1..=count).rev() {
+        // 名前の終わりの後はNULで終端し、残りを0xFFFFで埋める
+        let mut fragment = [0xFFFF_u16; LFN_CHARS_PER_ENTRY];
+        let start = (seq - 1) * LFN_CHARS_PER_ENTRY;
+        for i in 0..LFN_CHARS_PER_ENTRY {
+            match chars.get(start + i) {
+                Some(c) => fragment[i] = *c,
+                None => {
+                    fragment[i] = 0;
+                    break;
+                }
+            }
+        }
+
+        let mut sequence_number = seq as u8;
+        if seq == count {
+            sequence_number |= LFN_LAST_ENTRY;
+        }
+        entries.push(VFatLfnDirEntry::new(sequence_number, checksum, &fragment));
+    }
+    entries
+}
+
+/// 新しいエントリの名前として `name` が有効か否かを返す.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && name.encode_utf16().count() <= LFN_MAX_CHARS
+        && !name.chars().any(|c| c < ' ' || "\"*/:<>?\\|".contains(c))
+}
+
+/// 短い名前 `short_name` をNTの大文字小文字のフラグ `nt_case` に従って
+/// ディレクトリ一覧で表示される形式に変換する.
+fn display_short_name(short_name: &[u8; 11], nt_case: u8) -> String {
+    let mut entry = VFatRegularDirEntry::new(*short_name, Attributes::default(),
+        Cluster::from(0), 0);
+    entry.nt_case = nt_case;
+    entry.basic_name()
+}
+
+/// 名前 `name` から8.3形式の短い名前を生成する.
+///
+/// `name` がそのまま、または基本名と拡張子をそれぞれ小文字にして短い
+/// 名前として表現でき、`existing` に含まれない場合は
+/// `(short_name, nt_case, false)` を返す。`nt_case` はNTの小文字フラグ.
+/// そうでない場合は `existing` と衝突しないように `~N` の数値末尾を
+/// 付加した短い名前を生成し、LFNエントリが必要なことを示す
+/// `(short_name, 0, true)` を返す。
+fn short_name_for(name: &str, existing: &[[u8; 11]]) -> io::Result<([u8; 11], u8, bool)> {
+    fn convert(part: &str, max: usize, lossy: &mut bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        for c in part.chars() {
+            let c = match c {
+                ' ' | '.' => {
+                    *lossy = true;
+                    continue;
+                }
+                'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_uppercase() as u8,
+                '$' | '%' | '\'' | '-' | '_' | '@' | '~' | '`' | '!' | '('
+                | ')' | '{' | '}' | '^' | '#' | '&' => c as u8,
+                _ => {
+                    *lossy = true;
+                    b'_'
+                }
+            };
+            if out.len() == max {
+                *lossy = true;
+                break;
+            }
+            out.push(c);
+        }
+        out
+    }
+
+    let trimmed = name.trim_start_matches('.');
+    let (base, ext) = match trimmed.rfind('.') {
+        Some(i) => (&trimmed[..i], &trimmed[i + 1..]),
+        None => (trimmed, ""),
+    };
+
+    let mut lossy = trimmed.len() != name.len();
+    let base = convert(base, 8, &mut lossy);
+    let ext = convert(ext, 3, &mut lossy);
+
+    let mut short_name = [b' '; 11];
+    short_name[..base.len()].copy_from_slice(&base);
+    short_name[8..8 + ext.len()].copy_from_slice(&ext);
+
+    if !lossy && !base.is_empty() && !existing.contains(&short_name) {
+        let flags = [0, NT_LOWER_BASE, NT_LOWER_EXT, NT_LOWER_BASE | NT_LOWER_EXT];
+        if let Some(&nt_case) = flags.iter()
+            .find(|&&f| display_short_name(&short_name, f) == name) {
+            return Ok((short_name, nt_case, false));
+        }
+    }
+
+    for n in 1..1_000_000_u32 {
+        let tail = format!("~{}", n);
+        let keep = core::cmp::min(base.len(), 8 - tail.len());
+
+        let mut candidate = [b' '; 11];
+        candidate[..keep].copy_from_slice(&base[..keep]);
+        candidate[keep..keep + tail.len()].copy_from_slice(tail.as_bytes());
+        candidate[8..].copy_from_slice(&short_name[8..]);
+
+        if !existing.contains(&candidate) {
+            return Ok((candidate, 0, true));
+        }
+    }
+
+    ioerr!(Other, "no unique short name available")
+}
+
 impl<HANDLE: VFatHandle> Dir<HANDLE> {
     /// ルートディレクトリを返す
     pub fn root(vfat: HANDLE) -> Dir<HANDLE> {
@@ -153,7 +404,8 @@ impl<HANDLE: VFatHandle> Dir<HANDLE> {
     }
 
     /// `self` からエントリ名 `name` を見つけてそれを返す.
-    /// 比較は大文字小文字を区別しない。
+    /// 比較は `find_slot()` と同じく大文字小文字を区別せず、短い名前にも
+    /// 一致する。
     ///
     /// # エラー
     ///
@@ -163,26 +415,205 @@ impl<HANDLE: VFatHandle> Dir<HANDLE> {
     /// `name` に不正なUTF-8文字が含まれている場合はエラー
     /// `InvalidInput` を返す。
     pub fn find<P: AsRef<OsStr>>(&self, name: P) -> io::Result<Entry<HANDLE>> {
-        use crate::traits::{Dir, Entry};
-
         let name = name.as_ref().to_str().ok_or(io::ErrorKind::InvalidInput)?;
+        let slot = self.find_slot(name)?;
+        Ok(slot.into_entry(self.vfat.clone(), self.cluster))
+    }
 
-        for entry in self.entries()? {
-            if str::eq_ignore_ascii_case(entry.name(), name) {
-                return Ok(entry);
+    /// `self` からエントリ名 `name` のスロットを見つけてそれを返す.
+    ///
+    /// Windowsと同様に、比較はUnicodeの大文字変換によって大文字小文字を
+    /// 区別せずに行い、長い名前と短い名前のどちらにも一致する。
+    ///
+    /// # エラー
+    ///
+    /// `self` にエントリ名 `name` がない場合はエラー `NotFound` を
+    /// 返す。
+    pub(crate) fn find_slot(&self, name: &str) -> io::Result<DirSlot> {
+        let mut iter = traits::Dir::entries(self)?;
+        while let Some(slot) = iter.next_slot() {
+            if eq_ignore_case(&slot.name, name)
+                || eq_ignore_case(&slot.entry.basic_name(), name) {
+                return Ok(slot);
             }
         }
 
         ioerr!(NotFound, "file not found")
     }
+
+    /// `self` が `.` と `..` 以外のエントリを含まない場合は `true` を返す.
+    pub fn is_empty(&self) -> io::Result<bool> {
+        let mut iter = traits::Dir::entries(self)?;
+        while let Some(slot) = iter.next_slot() {
+            if slot.name != "." && slot.name != ".." {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// 通常エントリ `entry` を `name` という名前で `self` に追加する.
+    /// 短い名前は `self` 内で一意になるように生成され、必要に応じて
+    /// LFNエントリが追加される。名前の重複は検査しない。
+    ///
+    /// # エラー
+    ///
+    /// `name` がエントリ名として不正な場合はエラー `InvalidInput` を
+    /// 返す。
+    pub(crate) fn insert_entry(&self, name: &str,
+        mut entry: VFatRegularDirEntry) -> io::Result<EntryLocation> {
+        if !is_valid_name(name) {
+            return ioerr!(InvalidInput, "invalid file name");
+        }
+
+        let mut iter = traits::Dir::entries(self)?;
+        let mut existing = Vec::new();
+        while let Some(slot) = iter.next_slot() {
+            existing.push(slot.entry.short_name());
+        }
+
+        let (short_name, nt_case, needs_lfn) = short_name_for(name, &existing)?;
+        entry.set_short_name(short_name);
+        entry.nt_case = nt_case;
+
+        let mut raw: Vec<VFatDirEntry> = Vec::new();
+        if needs_lfn {
+            for lfn in lfn_entries(name, lfn_checksum(&short_name)) {
+                raw.push(lfn.into());
+            }
+        }
+        raw.push(entry.into());
+
+        // 連続した空きスロットを探す. 終端マーカー以降のスロットはすべて空き
+        let buf = &iter.buf;
+        let end = buf.iter().position(|e| e.was_prev_last()).unwrap_or(buf.len());
+        let mut start = buf.len();
+        let mut run = 0;
+        for (i, e) in buf.iter().enumerate() {
+            if i >= end || e.is_deleted() {
+                if run == 0 {
+                    start = i;
+                }
+                run += 1;
+                if run == raw.len() {
+                    break;
+                }
+            } else {
+                run = 0;
+                start = buf.len();
+            }
+        }
+
+        let mut bytes: Vec<u8> = Vec::new();
+        for e in raw.iter() {
+            bytes.extend_from_slice(e.as_bytes());
+        }
+        // 終端マーカーを上書きした場合は直後に新しい終端マーカーを置く
+        let last = start + raw.len();
+        if last > end && last < buf.len() {
+            bytes.extend_from_slice(&[0_u8; size_of::<VFatDirEntry>()]);
+        }
+
+        let slot_size = size_of::<VFatDirEntry>();
+        let slots = buf.len();
+        self.vfat.lock(|fs| -> io::Result<()> {
+            let slots_per_cluster = fs.cluster_size_bytes() / slot_size;
+            let mut slots = slots;
+            while slots < last {
+                fs.extend_chain(self.cluster)?;
+                slots += slots_per_cluster;
+            }
+            fs.write_chain(self.cluster, start * slot_size, &bytes)?;
+            Ok(())
+        })?;
+
+        Ok(EntryLocation {
+            dir_cluster: self.cluster,
+            offset: (last - 1) * slot_size,
+        })
+    }
+
+    /// 通常エントリ `entry` を `name` という名前で `self` に作成する.
+    ///
+    /// # エラー
+    ///
+    /// `self` にエントリ名 `name` がすでにある場合はエラー `AlreadyExists`
+    /// を返す。`name` がエントリ名として不正な場合はエラー `InvalidInput`
+    /// を返す。
+    pub(crate) fn create_entry(&self, name: &str,
+        entry: VFatRegularDirEntry) -> io::Result<EntryLocation> {
+        match self.find_slot(name) {
+            Ok(_) => ioerr!(AlreadyExists, "entry already exists"),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => self.insert_entry(name, entry),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// スロット `slot` が占めるエントリ（LFNエントリを含む）を削除済みに
+    /// する. エントリが参照するクラスタチェーンは解放しない.
+    pub(crate) fn remove_slot(&self, slot: &DirSlot) -> io::Result<()> {
+        let slot_size = size_of::<VFatDirEntry>();
+        self.vfat.lock(|fs| {
+            for i in slot.first..=slot.index {
+                fs.write_chain(self.cluster, i * slot_size, &[0xE5])?;
+            }
+            Ok(())
+        })
+    }
 }
 
 pub struct EntriesIterator<HANDLE: VFatHandle> {
     vfat: HANDLE,
+    cluster: Cluster,
     buf: Vec<VFatDirEntry>,
     index: usize,
 }
 
+/// ディレクトリ内の1つのエントリが占めるスロット.
+///
+/// `first` はLFNエントリを含むエントリの最初のスロット、`index` は通常
+/// エントリのスロットのインデックス.
+#[derive(Clone)]
+pub(crate) struct DirSlot {
+    pub first: usize,
+    pub index: usize,
+    pub name: String,
+    pub entry: VFatRegularDirEntry,
+}
+
+impl DirSlot {
+    /// ディレクトリ `dir_cluster` 内のこのスロットを `Entry` に変換する.
+    pub fn into_entry<HANDLE: VFatHandle>(self, vfat: HANDLE,
+        dir_cluster: Cluster) -> Entry<HANDLE> {
+        let entry = self.entry;
+        if entry.attributes.directory() {
+            // ルートディレクトリを指す ".." のクラスタ番号は0
+            let cluster = match entry.cluster().raw() {
+                0 => vfat.lock(|fs| fs.root_cluster()),
+                _ => entry.cluster(),
+            };
+            Entry::Dir(Dir {
+                vfat,
+                cluster,
+                name: self.name,
+                metadata: entry.metadata(),
+            })
+        } else {
+            Entry::File(File::new(
+                vfat,
+                entry.cluster(),
+                self.name,
+                entry.metadata(),
+                entry.file_size,
+                EntryLocation {
+                    dir_cluster,
+                    offset: self.index * size_of::<VFatDirEntry>(),
+                },
+            ))
+        }
+    }
+}
+
 fn parse_lfns(lfns: &mut Vec<VFatLfnDirEntry>) -> String {
     lfns.sort_by(|a, b| a.sequence_number().cmp(&b.sequence_number()));
 
@@ -206,59 +637,69 @@ fn parse_lfns(lfns: &mut Vec<VFatLfnDirEntry>) -> String {
     String::from_utf16_lossy(chars.as_slice())
 }
 
-impl<HANDLE: VFatHandle> Iterator for EntriesIterator<HANDLE> {
-    type Item = Entry<HANDLE>;
+/// `buf` の `index` 番目のスロットから次のエントリのスロットを探して
+/// 返す. `index` は見つかったエントリの次のスロットに進められる。
+pub(crate) fn parse_slot(buf: &[VFatDirEntry], index: &mut usize) -> Option<DirSlot> {
+    let mut lfns: Vec<VFatLfnDirEntry> = Vec::new();
+    let mut first = *index;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut lfns: Vec<VFatLfnDirEntry> = Vec::new();
+    while *index < buf.len() {
+        let entry = &buf[*index];
 
-        while self.index < self.buf.len() {
-            let entry = &self.buf[self.index];
+        if entry.was_prev_last(){
+            return None;
+        }
 
-            if entry.was_prev_last(){
-                return None;
-            }
+        *index += 1;
 
-            self.index += 1;
+        if entry.is_deleted() {
+            lfns.clear();
+            first = *index;
+            continue;
+        }
 
-            if entry.is_deleted() {
-                continue;
-            }
+        // LFNエントリは複数ありうる
+        if entry.is_lfn() {
+            lfns.push(unsafe { entry.long_filename });
+            continue;
+        }
+        // 通常エントリは1つでLFNに隣接する
+        let entry = unsafe { entry.regular };
+
+        // チェックサムが一致しないLFNエントリは別のエントリの残骸なので
+        // 無視する
+        let checksum = lfn_checksum(&entry.short_name());
+        let name: String;
+        if lfns.len() > 0 && lfns.iter().all(|lfn| lfn.name_checksum == checksum) {
+            name = parse_lfns(&mut lfns);
+        } else {
+            name = entry.basic_name();
+        }
+        lfns.clear();
+
+        return Some(DirSlot {
+            first,
+            index: *index - 1,
+            name,
+            entry,
+        });
+    }
+    None
+}
 
-            // LFNエントリは複数ありうる
-            if entry.is_lfn() {
-                lfns.push(unsafe { entry.long_filename });
-                continue;
-            }
-            // 通常エントリは1つでLFNに隣接する
-            let entry = unsafe { entry.regular };
+impl<HANDLE: VFatHandle> EntriesIterator<HANDLE> {
+    /// 次のエントリのスロットを返す.
+    pub(crate) fn next_slot(&mut self) -> Option<DirSlot> {
+        parse_slot(&self.buf, &mut self.index)
+    }
+}
 
-            let name: String;
-            if lfns.len() > 0 {
-                name = parse_lfns(&mut lfns);
-                lfns.clear();
-            } else {
-                name = entry.basic_name();
-            }
+impl<HANDLE: VFatHandle> Iterator for EntriesIterator<HANDLE> {
+    type Item = Entry<HANDLE>;
 
-            return Some(if entry.attributes.directory() {
-                Entry::Dir(Dir {
-                    vfat: self.vfat.clone(),
-                    cluster: entry.cluster(),
-                    name,
-                    metadata: entry.metadata(),
-                })
-            } else {
-                Entry::File(File::new(
-                    self.vfat.clone(),
-                    entry.cluster(),
-                    name,
-                    entry.metadata(),
-                    entry.file_size,
-                ))
-            });
-        }
-        None
+    fn next(&mut self) -> Option<Self::Item> {
+        let slot = self.next_slot()?;
+        Some(slot.into_entry(self.vfat.clone(), self.cluster))
     }
 }
 
@@ -275,6 +716,7 @@ impl<HANDLE: VFatHandle> traits::Dir for Dir<HANDLE> {
 
         Ok(EntriesIterator {
             vfat: self.vfat.clone(),
+            cluster: self.cluster,
             buf: unsafe { buf.cast() },
             index: 0,
         })
@@ -2,7 +2,7 @@ use core::fmt;
 use shim::const_assert_size;
 
 use crate::traits::BlockDevice;
-use crate::vfat::Error;
+use crate::vfat::{Error, FatType};
 
 #[repr(C, packed)]
 pub struct BiosParameterBlock {
@@ -60,6 +60,60 @@ impl BiosParameterBlock {
         Ok(ebpb)
     }
 
+    /// FAT32ボリュームのBPBを作成する. ルートディレクトリはクラスタ2、
+    /// FSInfoはセクタ1、ブートセクタのバックアップはセクタ6に置かれる。
+    pub fn new_fat32(
+        bytes_per_sector: u16,
+        sectors_per_cluster: u8,
+        reserved_sectors: u16,
+        fat_count: u8,
+        sectors_per_fat: u32,
+        hidden_sectors: u32,
+        total_sectors: u32,
+        volume_id: u32,
+        volume_label: [u8; 11],
+    ) -> BiosParameterBlock {
+        BiosParameterBlock {
+            // jmp short 0x5A; nop
+            _nop: [0xEB, 0x58, 0x90],
+            oem_identifier: *b"MSWIN4.1",
+            bytes_per_sector,
+            sectors_per_cluster,
+            reserved_sectors,
+            fat_count,
+            max_directory_entries: 0,
+            total_logical_sectors: 0,
+            fat_id: 0xF8,
+            sectors_per_fat: 0,
+            sectors_per_track: 63,
+            head_count: 255,
+            hidden_sector_count: hidden_sectors,
+            total_logical_sectors_32: total_sectors,
+            sectors_per_fat_32: sectors_per_fat,
+            flags: 0,
+            fat_version: 0,
+            root_cluster: 2,
+            fsinfo_sector: 1,
+            backup_boot_sector: 6,
+            _reserved_1: [0; 12],
+            drive_number: 0x80,
+            _reserved_2: 0,
+            signature: 0x29,
+            volume_id: volume_id.to_le_bytes(),
+            volume_label,
+            system_identifier_string: *b"FAT32   ",
+            boot_code: [0; 420],
+            boot_signature: [0x55, 0xAA],
+        }
+    }
+
+    /// BPBのオンディスク表現（512バイト）を返す.
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            core::slice::from_raw_parts(self as *const _ as *const u8, core::mem::size_of::<Self>())
+        }
+    }
+
     pub fn sectors_per_fat(&self) -> u32 {
         if self.sectors_per_fat != 0 {
             self.sectors_per_fat as u32
@@ -75,6 +129,53 @@ impl BiosParameterBlock {
             self.total_logical_sectors_32
         }
     }
+
+    /// ジオメトリが有効なFATボリュームのものであれば `true` を返す.
+    pub fn is_valid(&self) -> bool {
+        let bytes_per_sector = self.bytes_per_sector;
+        bytes_per_sector >= 512
+            && bytes_per_sector.is_power_of_two()
+            && self.sectors_per_cluster.is_power_of_two()
+            && self.reserved_sectors != 0
+            && self.fat_count != 0
+            && self.sectors_per_fat() != 0
+    }
+
+    /// FAT12/16 の固定長ルートディレクトリが占めるセクタ数を返す.
+    /// FAT32 では0となる。
+    pub fn root_dir_sectors(&self) -> u32 {
+        let bytes_per_sector = self.bytes_per_sector as u32;
+        (self.max_directory_entries as u32 * 32 + bytes_per_sector - 1) / bytes_per_sector
+    }
+
+    /// ボリュームの先頭からデータ領域までのセクタ数を返す.
+    pub fn data_start_sector(&self) -> u64 {
+        self.reserved_sectors as u64
+            + self.fat_count as u64 * self.sectors_per_fat() as u64
+            + self.root_dir_sectors() as u64
+    }
+
+    /// データ領域のクラスタ数を返す.
+    pub fn cluster_count(&self) -> u32 {
+        let data_sectors = (self.total_logical_sectores() as u64)
+            .saturating_sub(self.data_start_sector());
+        (data_sectors / self.sectors_per_cluster as u64) as u32
+    }
+
+    /// FATの種類を返す.
+    ///
+    /// FAT12/16 のBPBは16ビットのFATサイズを必ず持つので、それが0の
+    /// ボリュームはクラスタ数によらず FAT32 とみなす。それ以外は
+    /// クラスタ数から判定する。
+    pub fn fat_type(&self) -> FatType {
+        if self.sectors_per_fat == 0 {
+            return FatType::Fat32;
+        }
+        match FatType::from_cluster_count(self.cluster_count()) {
+            FatType::Fat32 => FatType::Fat16,
+            fat_type => fat_type,
+        }
+    }
 }
 
 
@@ -1,10 +1,12 @@
 use shim::io;
 
+use crate::gpt;
 use crate::mbr;
 
 #[derive(Debug)]
 pub enum Error {
     Mbr(mbr::Error),
+    Gpt(gpt::Error),
     Io(io::Error),
     BadSignature,
     NotFound,
@@ -16,6 +18,12 @@ impl From<mbr::Error> for Error {
     }
 }
 
+impl From<gpt::Error> for Error {
+    fn from(error: gpt::Error) -> Error {
+        Error::Gpt(error)
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(error: io::Error) -> Error {
         Error::Io(error)
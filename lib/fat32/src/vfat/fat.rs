@@ -19,10 +19,42 @@ pub enum Status {
     Eoc(u32),
 }
 
+/// FATの種類. クラスタ数によって決まる.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatType {
+    /// データ領域のクラスタ数 `cluster_count` からFATの種類を判定する.
+    pub fn from_cluster_count(cluster_count: u32) -> FatType {
+        if cluster_count < 4085 {
+            FatType::Fat12
+        } else if cluster_count < 65525 {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        }
+    }
+}
+
 #[repr(C, packed)]
 pub struct FatEntry(pub u32);
 
 impl FatEntry {
+    /// `fat_type` のFATから読み込んだ値 `raw` を FAT32 と同じ値の範囲に
+    /// 正規化した `FatEntry` を返す. 予約済み、不良、チェーン終端の値は
+    /// 上位ビットを1で埋めてFAT32の対応する値に変換される。
+    pub fn from_raw(fat_type: FatType, raw: u32) -> FatEntry {
+        match fat_type {
+            FatType::Fat12 if raw >= 0xFF0 => FatEntry(raw | 0x0FFF_F000),
+            FatType::Fat16 if raw >= 0xFFF0 => FatEntry(raw | 0x0FFF_0000),
+            _ => FatEntry(raw),
+        }
+    }
+
     /// FATエントリ `self` の `Status` を返す..
     pub fn status(&self) -> Status {
         match self.0 & 0x0FFF_FFFF {
@@ -1,11 +1,13 @@
 use alloc::string::String;
+use alloc::sync::Arc;
 
 use shim::io::{self, SeekFrom};
 use shim::ioerr;
 
 use crate::traits;
 use crate::vfat::{Cluster, Metadata, VFatHandle};
-use crate::vfat::vfat::SeekHandle;
+use crate::vfat::chain::ClusterChain;
+use crate::vfat::vfat::{EntryLocation, SeekHandle, SharedLocation};
 
 #[derive(Debug)]
 pub struct File<HANDLE: VFatHandle> {
@@ -14,23 +16,29 @@ pub struct File<HANDLE: VFatHandle> {
     pub name: String,
     pub metadata: Metadata,
     pub size: u32,
+    /// ディレクトリエントリの位置. `rename()` で移動すると更新される。
+    entry: Arc<SharedLocation>,
     pointer: SeekHandle,
+    chain: ClusterChain,
 }
 
 impl<HANDLE: VFatHandle> File<HANDLE> {
     pub fn new(vfat: HANDLE, cluster: Cluster, name: String,
-        metadata: Metadata, size: u32) -> File<HANDLE> {
+        metadata: Metadata, size: u32, entry: EntryLocation) -> File<HANDLE> {
+        let entry = vfat.lock(|fs| fs.track_entry(entry));
         File {
             vfat,
             cluster,
             name,
             metadata,
             size,
+            entry,
             pointer: SeekHandle {
                 cluster,
                 offset: 0,
                 total_offset: 0,
-            }
+            },
+            chain: ClusterChain::new(),
         }
     }
 }
@@ -39,7 +47,10 @@ impl<HANDLE: VFatHandle> File<HANDLE> {
 impl<HANDLE: VFatHandle> traits::File for File<HANDLE> {
     /// バッファされているデータをディスクに書き出す.
     fn sync(&mut self) -> io::Result<()> {
-        unimplemented!("read only file system")
+        self.vfat.lock(|fs| {
+            fs.update_entry(self.entry.get(), self.cluster, self.size)?;
+            fs.flush()
+        })
     }
 
     /// ファイルのバイト単位のサイズを返す.
@@ -49,12 +60,50 @@ impl<HANDLE: VFatHandle> traits::File for File<HANDLE> {
 }
 
 impl<HANDLE: VFatHandle> io::Write for File<HANDLE> {
-    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
-        unimplemented!("read only file system")
+    /// ファイルの現在位置に `buf` を書き込む.
+    ///
+    /// 必要に応じてクラスタを割り当ててチェーンを延長し、ファイル終端を
+    /// 超えて書き込んだ場合はファイルサイズを更新する。変更はキャッシュに
+    /// 対して行われ、`sync()` または `flush()` でディスクに書き出される。
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let max_file_write = core::cmp::min(
+            (u32::max_value() as usize).saturating_sub(self.pointer.total_offset),
+            buf.len());
+        if max_file_write == 0 {
+            return Ok(0);
+        }
+
+        let (cluster, pointer) = (self.cluster, self.pointer);
+        let (cluster, written, pointer) = self.vfat.lock(|fs| -> io::Result<_> {
+            let mut cluster = cluster;
+            let mut pointer = pointer;
+            // 空のファイルにはクラスタが割り当てられていない
+            if cluster.raw() == 0 {
+                cluster = fs.alloc_cluster(None)?;
+                pointer.cluster = cluster;
+            }
+
+            let (written, pointer) = fs.write_cluster_unaligned(pointer,
+                &buf[..max_file_write])?;
+            Ok((cluster, written, pointer))
+        })?;
+
+        if cluster != self.cluster {
+            self.chain.reset();
+        }
+        self.chain.invalidate_tail();
+        self.cluster = cluster;
+        self.pointer = pointer;
+        if pointer.total_offset > self.size as usize {
+            self.size = pointer.total_offset as u32;
+        }
+
+        self.vfat.lock(|fs| fs.update_entry(self.entry.get(), self.cluster, self.size))?;
+        Ok(written)
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        unimplemented!("read only file system")
+        traits::File::sync(self)
     }
 }
 
@@ -89,21 +138,22 @@ impl<HANDLE: VFatHandle> io::Seek for File<HANDLE> {
     /// ファイルの開始点の前、または終端の後ろにシークしようとすると
     /// エラー `InvalidInput` となる。
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
-        // クロージャ
-        let f = |offset| self.vfat.lock(|fs| {
-            if offset < 0 {
-                return ioerr!(InvalidInput, "cannot seek befor start of file");
-            }
-
-            fs.seek_handle(self.cluster, self.pointer, offset as usize)
-        });
-
-        let cloff = match pos {
-            SeekFrom::Start(start) => f(start as isize)?,
-            SeekFrom::End(end) => f(self.size as isize + end as isize)?,
-            SeekFrom::Current(current) => f(self.pointer.total_offset as isize
-                + current as isize)?,
+        let offset = match pos {
+            SeekFrom::Start(start) => start as isize,
+            SeekFrom::End(end) => self.size as isize + end as isize,
+            SeekFrom::Current(current) => self.pointer.total_offset as isize + current as isize,
         };
+        if offset < 0 {
+            return ioerr!(InvalidInput, "cannot seek befor start of file");
+        }
+        if offset as usize > self.size as usize {
+            return ioerr!(InvalidInput, "cannot seek beyond end of file");
+        }
+
+        // 一度辿ったチェーンはキャッシュされるため、後方へのシークでも
+        // 先頭からFATを辿り直す必要はない
+        let (cluster, chain) = (self.cluster, &mut self.chain);
+        let cloff = self.vfat.lock(|fs| chain.seek(fs, cluster, offset as usize))?;
 
         self.pointer = cloff;
         Ok(cloff.total_offset as u64)
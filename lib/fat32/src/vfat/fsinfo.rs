@@ -0,0 +1,84 @@
+use core::fmt;
+use shim::const_assert_size;
+
+use crate::traits::BlockDevice;
+use crate::vfat::Error;
+
+/// FAT32のFSInfoセクタ. 空きクラスタ数と次に割り当てを試みるクラスタの
+/// ヒントを保持する.
+#[repr(C, packed)]
+pub struct FsInfo {
+    lead_signature: u32,
+    _reserved_0: [u8; 480],
+    struct_signature: u32,
+    /// 空きクラスタ数. 不明な場合は `FsInfo::UNKNOWN`.
+    pub free_count: u32,
+    /// 空きクラスタの検索を開始するクラスタ. 不明な場合は `FsInfo::UNKNOWN`.
+    pub next_free: u32,
+    _reserved_1: [u8; 12],
+    trail_signature: u32,
+}
+
+const_assert_size!(FsInfo, 512);
+
+const LEAD_SIGNATURE: u32 = 0x4161_5252;
+const STRUCT_SIGNATURE: u32 = 0x6141_7272;
+const TRAIL_SIGNATURE: u32 = 0xAA55_0000;
+
+impl FsInfo {
+    /// `free_count` と `next_free` が不明であることを表す値.
+    pub const UNKNOWN: u32 = 0xFFFF_FFFF;
+
+    /// セクタ内の `free_count` のバイトオフセット.
+    pub const FREE_COUNT_OFFSET: usize = 488;
+
+    /// セクタ内の `next_free` のバイトオフセット.
+    pub const NEXT_FREE_OFFSET: usize = 492;
+
+    /// 新しいFSInfoセクタを作成する.
+    pub fn new(free_count: u32, next_free: u32) -> FsInfo {
+        FsInfo {
+            lead_signature: LEAD_SIGNATURE,
+            _reserved_0: [0; 480],
+            struct_signature: STRUCT_SIGNATURE,
+            free_count,
+            next_free,
+            _reserved_1: [0; 12],
+            trail_signature: TRAIL_SIGNATURE,
+        }
+    }
+
+    /// デバイス `device` のセクタ `sector` からFSInfoセクタを読み込む.
+    ///
+    /// # エラー
+    ///
+    /// いずれかのシグネチャが不正な場合は `BadSignature` エラーを返す。
+    pub fn from<T: BlockDevice>(mut device: T, sector: u64) -> Result<FsInfo, Error> {
+        let mut block = [0_u8; 512];
+        device.read_sector(sector, &mut block).map_err(|e| Error::Io(e))?;
+
+        let fsinfo: FsInfo = unsafe { core::mem::transmute(block) };
+        if fsinfo.lead_signature != LEAD_SIGNATURE
+            || fsinfo.struct_signature != STRUCT_SIGNATURE
+            || fsinfo.trail_signature != TRAIL_SIGNATURE {
+            return Err(Error::BadSignature);
+        }
+        Ok(fsinfo)
+    }
+
+    /// FSInfoセクタのオンディスク表現（512バイト）を返す.
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            core::slice::from_raw_parts(self as *const _ as *const u8, core::mem::size_of::<Self>())
+        }
+    }
+}
+
+impl fmt::Debug for FsInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FsInfo")
+            .field("free_count", &{ self.free_count })
+            .field("next_free", &{ self.next_free })
+            .finish()
+    }
+}
@@ -37,6 +37,11 @@ pub struct Metadata {
 }
 
 impl Attributes {
+    /// ディレクトリの属性.
+    pub const DIRECTORY: Attributes = Attributes(0x10);
+    /// 通常ファイルの属性.
+    pub const ARCHIVE: Attributes = Attributes(0x20);
+
     pub fn read_only(&self) -> bool {
         (self.0 & 0x01) != 0
     }
@@ -61,6 +66,23 @@ impl Attributes {
         (self.0 & 0x20) != 0
     }
 }
+
+impl From<u8> for Attributes {
+    fn from(raw: u8) -> Attributes {
+        Attributes(raw)
+    }
+}
+
+impl Timestamp {
+    /// 上位16ビットに日付、下位16ビットに時間を持つ32ビット値
+    /// (exFATのタイムスタンプ形式) から `Timestamp` を作成する.
+    pub fn from_raw(raw: u32) -> Timestamp {
+        Timestamp {
+            date: Date((raw >> 16) as u16),
+            time: Time(raw as u16),
+        }
+    }
+}
 // FIXME: Implement `traits::Timestamp` for `Timestamp`.
 impl traits::Timestamp for Timestamp {
     fn year(&self) -> usize {
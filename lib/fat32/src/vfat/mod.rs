@@ -1,4 +1,6 @@
 pub(crate) mod cache;
+pub(crate) mod chain;
+pub(crate) mod check;
 pub(crate) mod cluster;
 pub(crate) mod dir;
 pub(crate) mod ebpb;
@@ -6,16 +8,22 @@ pub(crate) mod entry;
 pub(crate) mod error;
 pub(crate) mod fat;
 pub(crate) mod file;
+pub(crate) mod fsinfo;
 pub(crate) mod metadata;
+pub(crate) mod name;
 pub(crate) mod vfat;
 
+pub use self::cache::CacheStats;
+pub use self::check::{CheckReport, Problem};
 pub use self::dir::Dir;
 pub use self::ebpb::BiosParameterBlock;
+pub use self::fat::FatType;
 pub use self::entry::Entry;
 pub use self::error::Error;
 pub use self::file::File;
+pub use self::fsinfo::FsInfo;
 pub use self::metadata::{Attributes, Date, Metadata, Time, Timestamp};
-pub use self::vfat::{VFat, VFatHandle};
+pub use self::vfat::{StatFs, VFat, VFatHandle};
 
 pub(crate) use self::cache::{CachedPartition, Partition};
 pub(crate) use self::cluster::Cluster;
@@ -0,0 +1,35 @@
+/// OEMコードページ437の0x80から0xFFまでの文字.
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{A0}',
+];
+
+/// 短い名前のバイト `b` をOEMコードページ437の文字として復号する.
+pub(crate) fn oem_to_char(b: u8) -> char {
+    match b {
+        0x00..=0x7F => b as char,
+        _ => CP437_HIGH[(b - 0x80) as usize],
+    }
+}
+
+/// Windowsの大文字変換表と同様に `c` を大文字に変換する.
+///
+/// 大文字が1文字にならない文字（ドイツ語の `ß` など）は変換しない。
+pub(crate) fn upcase(c: char) -> char {
+    let mut upper = c.to_uppercase();
+    match (upper.next(), upper.next()) {
+        (Some(u), None) => u,
+        _ => c,
+    }
+}
+
+/// FATの名前 `a` と `b` が大文字小文字を区別せずに等しいか否かを返す.
+pub(crate) fn eq_ignore_case(a: &str, b: &str) -> bool {
+    a.chars().map(upcase).eq(b.chars().map(upcase))
+}
@@ -1,7 +1,10 @@
 use core::fmt::Debug;
 use core::marker::PhantomData;
-//use core::mem::size_of;
+use core::mem::size_of;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
 
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 
 use shim::io;
@@ -10,11 +13,14 @@ use shim::ioerr;
 //use shim::path;
 use shim::path::{Component, Path};
 
+use crate::gpt::{self, Guid, GuidPartitionTable};
 use crate::mbr::MasterBootRecord;
 use crate::traits::{BlockDevice, FileSystem};
 //use crate::util::SliceExt;
-use crate::vfat::{BiosParameterBlock, CachedPartition, Partition};
-use crate::vfat::{Cluster, Dir, Entry, Error, FatEntry, File, Status};
+use crate::vfat::{Attributes, BiosParameterBlock, CacheStats, CachedPartition, Partition};
+use crate::vfat::{Cluster, Dir, Entry, Error, FatEntry, FatType, File, FsInfo, Status};
+use crate::vfat::dir::{VFatDirEntry, VFatRegularDirEntry};
+use crate::vfat::name::eq_ignore_case;
 
 /// クロージャとしてクリティカルセクションを処理するジェネリックトレイト
 pub trait VFatHandle: Clone + Debug + Send + Sync {
@@ -25,13 +31,57 @@ pub trait VFatHandle: Clone + Debug + Send + Sync {
 #[derive(Debug)]
 pub struct VFat<HANDLE: VFatHandle> {
     phantom: PhantomData<HANDLE>,
-    device: CachedPartition,
-    bytes_per_sector: u16,
+    pub(crate) device: CachedPartition,
+    fat_type: FatType,
+    pub(crate) bytes_per_sector: u16,
     sectors_per_cluster: u8,
-    sectors_per_fat: u32,
-    fat_start_sector: u64,
+    pub(crate) sectors_per_fat: u32,
+    pub(crate) fat_count: u8,
+    pub(crate) fat_start_sector: u64,
+    /// FAT12/16 の固定長ルートディレクトリのセクタ数.
+    root_dir_sectors: u64,
     data_start_sector: u64,
+    pub(crate) cluster_count: u32,
     rootdir_cluster: Cluster,
+    /// FSInfoセクタの論理セクタ番号. FAT12/16 またはFSInfoセクタが
+    /// 不正な場合は `None`.
+    fsinfo_sector: Option<u64>,
+    /// 空きクラスタ数. 不明な場合は `None`.
+    free_count: Option<u32>,
+    /// 空きクラスタの検索を開始するクラスタ.
+    next_free: u32,
+    /// `free_count` または `next_free` がFSInfoセクタに書き出されていない.
+    fsinfo_dirty: bool,
+    /// オープンされているファイルのディレクトリエントリの位置.
+    open_entries: Vec<Weak<SharedLocation>>,
+}
+
+/// `VFat::statfs()` が返すボリュームの使用状況.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StatFs {
+    /// クラスタのバイト数.
+    pub cluster_size: u64,
+    /// データ領域のクラスタ数.
+    pub total_clusters: u32,
+    /// 空きクラスタ数.
+    pub free_clusters: u32,
+}
+
+impl StatFs {
+    /// データ領域のバイト数を返す.
+    pub fn total_bytes(&self) -> u64 {
+        self.total_clusters as u64 * self.cluster_size
+    }
+
+    /// 空き領域のバイト数を返す.
+    pub fn free_bytes(&self) -> u64 {
+        self.free_clusters as u64 * self.cluster_size
+    }
+
+    /// 使用中の領域のバイト数を返す.
+    pub fn used_bytes(&self) -> u64 {
+        self.total_bytes() - self.free_bytes()
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -41,42 +91,176 @@ pub struct SeekHandle {
     pub total_offset: usize,
 }
 
+/// ディレクトリエントリのディスク上の位置.
+///
+/// `dir_cluster` は親ディレクトリの開始クラスタ、`offset` はそのクラスタ
+/// チェーンの先頭から通常エントリまでのバイト単位のオフセット.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EntryLocation {
+    pub dir_cluster: Cluster,
+    pub offset: usize,
+}
+
+/// オープンされたファイルとファイルシステムが共有するディレクトリエントリの
+/// 位置. `rename()` でエントリが別のスロットに移動すると更新される。
+#[derive(Debug)]
+pub(crate) struct SharedLocation {
+    dir_cluster: AtomicU32,
+    offset: AtomicUsize,
+}
+
+impl SharedLocation {
+    /// 現在の位置を返す.
+    pub(crate) fn get(&self) -> EntryLocation {
+        EntryLocation {
+            dir_cluster: Cluster::from(self.dir_cluster.load(Ordering::SeqCst)),
+            offset: self.offset.load(Ordering::SeqCst),
+        }
+    }
+
+    fn set(&self, loc: EntryLocation) {
+        self.dir_cluster.store(loc.dir_cluster.raw(), Ordering::SeqCst);
+        self.offset.store(loc.offset, Ordering::SeqCst);
+    }
+}
+
+/// クラスタチェーンの終端を表すFATエントリの値.
+pub(crate) const EOC_MARKER: u32 = 0x0FFF_FFFF;
+
 impl<HANDLE: VFatHandle> VFat<HANDLE> {
+    /// `device` からFATパーティションを探し、そのファイルシステムの
+    /// ハンドルを返す.
+    ///
+    /// MBRでフォーマットされたディスクではFAT12/16/32のパーティション
+    /// タイプを持つ、GPTでフォーマットされたディスクでは Basic Data
+    /// タイプの最初のパーティションが使用される。FATの種類はクラスタ数から
+    /// 判定される。
+    ///
+    /// # エラー
+    ///
+    /// FATパーティションが見つからない場合は `NotFound` を、BPBが
+    /// 不正な場合は `BadSignature` を返す。FAT32のFSInfoセクタが不正な
+    /// 場合はエラーにはせず、空きクラスタ数を不明として扱う。
     pub fn from<T>(mut device: T) -> Result<HANDLE, Error>
     where
         T: BlockDevice + 'static,
     {
-        let mbr = MasterBootRecord::from(&mut device)?;
-        for partition in mbr.partitions.iter() {
-            if partition.partition_type == 0xB || partition.partition_type == 0xC {
-                let bpb = BiosParameterBlock::from(&mut device, partition.relative_sector as u64)?;
-                return Ok(HANDLE::new(VFat {
-                    phantom: PhantomData {},
-                    device: CachedPartition::new(device, Partition {
-                        start: partition.relative_sector as u64,
-                        num_sectors: partition.total_sectores as u64,
-                        sector_size: bpb.bytes_per_sector as u64,
-                    }),
-                    bytes_per_sector: bpb.bytes_per_sector,
-                    sectors_per_cluster: bpb.sectors_per_cluster,
-                    sectors_per_fat: bpb.sectors_per_fat(),
-                    fat_start_sector: bpb.reserved_sectors as u64,
-                    data_start_sector: (bpb.reserved_sectors as u64)
-                        + (bpb.fat_count as u64 * bpb.sectors_per_fat() as u64),
-                    rootdir_cluster: Cluster::from(bpb.root_cluster),
-                }));
+        let (start, num_sectors) = find_partition(&mut device, &FAT_PARTITION_TYPES,
+            |boot| &boot[3..11] != b"EXFAT   " && &boot[3..11] != b"NTFS    ")?;
+
+        VFat::from_partition(device, start, num_sectors)
+    }
+
+    /// 物理セクタ `start` から始まる `num_sectors` セクタのパーティションを
+    /// FATファイルシステムとして読み込む.
+    fn from_partition<T>(mut device: T, start: u64, num_sectors: u64) -> Result<HANDLE, Error>
+    where
+        T: BlockDevice + 'static,
+    {
+        let bpb = BiosParameterBlock::from(&mut device, start)?;
+        if !bpb.is_valid() {
+            return Err(Error::BadSignature);
+        }
+
+        let fat_type = bpb.fat_type();
+        let fat_bits = match fat_type {
+            FatType::Fat12 => 12,
+            FatType::Fat16 => 16,
+            FatType::Fat32 => 32,
+        };
+        let fat_capacity = (bpb.sectors_per_fat() as u64 * bpb.bytes_per_sector as u64 * 8
+            / fat_bits).saturating_sub(2);
+        let cluster_count = core::cmp::min(bpb.cluster_count() as u64, fat_capacity) as u32;
+        // FAT12/16 のルートディレクトリはデータ領域の直前の固定領域にあり、
+        // クラスタ番号0で表す
+        let rootdir_cluster = match fat_type {
+            FatType::Fat32 => Cluster::from(bpb.root_cluster),
+            _ => Cluster::from(0),
+        };
+
+        // FSInfoの値は範囲外であればヒントとして使用しない
+        let mut fsinfo_sector = None;
+        let mut free_count = None;
+        let mut next_free = 2;
+        let fsinfo = bpb.fsinfo_sector as u64;
+        if fat_type == FatType::Fat32 && fsinfo != 0 && fsinfo < bpb.reserved_sectors as u64 {
+            let factor = bpb.bytes_per_sector as u64 / device.sector_size();
+            match FsInfo::from(&mut device, start + fsinfo * factor) {
+                Ok(info) => {
+                    fsinfo_sector = Some(fsinfo);
+                    if info.free_count <= cluster_count {
+                        free_count = Some(info.free_count);
+                    }
+                    if info.next_free >= 2 && info.next_free < cluster_count + 2 {
+                        next_free = info.next_free;
+                    }
+                }
+                Err(Error::BadSignature) => {}
+                Err(e) => return Err(e),
             }
         }
-        Err(Error::NotFound)
+
+        Ok(HANDLE::new(VFat {
+            phantom: PhantomData {},
+            device: CachedPartition::new(device, Partition {
+                start,
+                num_sectors,
+                sector_size: bpb.bytes_per_sector as u64,
+            }),
+            fat_type,
+            bytes_per_sector: bpb.bytes_per_sector,
+            sectors_per_cluster: bpb.sectors_per_cluster,
+            sectors_per_fat: bpb.sectors_per_fat(),
+            fat_count: bpb.fat_count,
+            fat_start_sector: bpb.reserved_sectors as u64,
+            root_dir_sectors: bpb.root_dir_sectors() as u64,
+            data_start_sector: bpb.data_start_sector(),
+            cluster_count,
+            rootdir_cluster,
+            fsinfo_sector,
+            free_count,
+            next_free,
+            fsinfo_dirty: false,
+            open_entries: Vec::new(),
+        }))
+    }
+
+    /// このボリュームのFATの種類を返す.
+    pub fn fat_type(&self) -> FatType {
+        self.fat_type
+    }
+
+    /// `cluster` が FAT12/16 の固定長ルートディレクトリを表す場合は
+    /// `true` を返す.
+    pub(crate) fn is_fixed_root(&self, cluster: Cluster) -> bool {
+        self.fat_type != FatType::Fat32 && cluster.raw() == 0
     }
 
     /// クラスタ `cluster` の開始セクタを返す
     fn cluster_start(&self, cluster: Cluster) -> u64 {
+        if self.is_fixed_root(cluster) {
+            return self.data_start_sector - self.root_dir_sectors;
+        }
         self.data_start_sector + (cluster.raw() as u64 - 2) * self.sectors_per_cluster as u64
     }
 
+    /// クラスタ `cluster` のセクタ数を返す. 固定長ルートディレクトリは
+    /// 1つのクラスタとして扱う.
+    fn cluster_sectors(&self, cluster: Cluster) -> u64 {
+        if self.is_fixed_root(cluster) {
+            self.root_dir_sectors
+        } else {
+            self.sectors_per_cluster as u64
+        }
+    }
+
+    /// クラスタ `cluster` のバイト単位のサイズを返す.
+    pub(crate) fn cluster_len(&self, cluster: Cluster) -> usize {
+        self.cluster_sectors(cluster) as usize * self.bytes_per_sector as usize
+    }
+
     /// クラスタのバイト単位のサイズを返す
-    fn cluster_size_bytes(&self) -> usize {
+    pub fn cluster_size_bytes(&self) -> usize {
         self.bytes_per_sector as usize * self.sectors_per_cluster as usize
     }
 
@@ -106,13 +290,13 @@ impl<HANDLE: VFatHandle> VFat<HANDLE> {
 
 
     /// クラスタのオフセットからバッファに読み込む.
-    fn read_cluster(&mut self, cluster: Cluster, offset: usize, buf: &mut [u8]) -> io::Result<usize> {
-        if offset >= self.cluster_size_bytes() {
+    pub(crate) fn read_cluster(&mut self, cluster: Cluster, offset: usize, buf: &mut [u8]) -> io::Result<usize> {
+        if offset >= self.cluster_len(cluster) {
             return Ok(0);
         }
 
         let cluster_start = self.cluster_start(cluster);
-        let cluster_end = cluster_start + self.sectors_per_cluster as u64;
+        let cluster_end = cluster_start + self.cluster_sectors(cluster);
 
         let mut current_cluster = cluster_start + (offset / self.bytes_per_sector as usize) as u64;
         let mut offset = offset % self.bytes_per_sector as usize;
@@ -148,7 +332,7 @@ impl<HANDLE: VFatHandle> VFat<HANDLE> {
             cloff.offset += bytes;
             cloff.total_offset += bytes;
 
-            if cloff.offset == self.cluster_size_bytes() {
+            if cloff.offset == self.cluster_len(cloff.cluster) {
                 match self.fat_entry(cloff.cluster)?.status() {
                     Status::Data(next) => cloff = SeekHandle {
                         cluster: next,
@@ -172,7 +356,7 @@ impl<HANDLE: VFatHandle> VFat<HANDLE> {
 
         'cluster_loop: loop {
             let start = buf.len();
-            buf.resize(start + self.cluster_size_bytes(), 0);
+            buf.resize(start + self.cluster_len(cluster), 0);
             let wrote = self.read_cluster(cluster, 0, &mut buf.as_mut_slice()[start..])?;
             buf.truncate(start + wrote);
 
@@ -186,16 +370,395 @@ impl<HANDLE: VFatHandle> VFat<HANDLE> {
         Ok(buf.len() - initial_size)
     }
 
-    /// クラスタの `FatEntry` への参照を返す.
-    /// この参照は直接キャッシュされたセクタを指している.
-    pub fn fat_entry(&mut self, cluster: Cluster) -> io::Result<&FatEntry> {
-        let entry_location = (self.fat_start_sector * self.bytes_per_sector as u64) + (cluster.raw() * 4) as u64;
+    /// クラスタ `cluster` の `FatEntry` を返す. FAT12/16 のエントリは
+    /// FAT32 と同じ値の範囲に正規化される。固定長ルートディレクトリは
+    /// 1つのクラスタからなるチェーンとして扱われる。
+    pub fn fat_entry(&mut self, cluster: Cluster) -> io::Result<FatEntry> {
+        if self.is_fixed_root(cluster) {
+            return Ok(FatEntry(EOC_MARKER));
+        }
 
-        let sector = self.device.get(entry_location / self.bytes_per_sector as u64)?;
+        let n = cluster.raw() as u64;
+        let raw = match self.fat_type {
+            FatType::Fat12 => {
+                let mut bytes = [0_u8; 2];
+                self.read_fat_bytes(n + n / 2, &mut bytes)?;
+                let value = u16::from_le_bytes(bytes) as u32;
+                if n % 2 == 0 { value & 0xFFF } else { value >> 4 }
+            }
+            FatType::Fat16 => {
+                let mut bytes = [0_u8; 2];
+                self.read_fat_bytes(n * 2, &mut bytes)?;
+                u16::from_le_bytes(bytes) as u32
+            }
+            FatType::Fat32 => {
+                let mut bytes = [0_u8; 4];
+                self.read_fat_bytes(n * 4, &mut bytes)?;
+                u32::from_le_bytes(bytes)
+            }
+        };
 
-        let raw_entry = &sector[(entry_location % self.bytes_per_sector as u64) as usize];
+        Ok(FatEntry::from_raw(self.fat_type, raw))
+    }
 
-        Ok(unsafe { core::mem::transmute::<&u8, &FatEntry>(raw_entry) })
+    /// 最初のFATの先頭から `offset` バイトの位置を `buf` に読み込む.
+    /// FAT12 のエントリはセクタ境界をまたぐことがある。
+    fn read_fat_bytes(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let bytes_per_sector = self.bytes_per_sector as u64;
+        for (i, byte) in buf.iter_mut().enumerate() {
+            let pos = offset + i as u64;
+            let sector = self.fat_start_sector + pos / bytes_per_sector;
+            *byte = self.device.get(sector)?[(pos % bytes_per_sector) as usize];
+        }
+        Ok(())
+    }
+
+    /// すべてのFATコピーの先頭から `offset` バイトの位置に `bytes` を
+    /// 書き込む.
+    fn write_fat_bytes(&mut self, offset: u64, bytes: &[u8]) -> io::Result<()> {
+        let bytes_per_sector = self.bytes_per_sector as u64;
+        for fat in 0..self.fat_count as u64 {
+            let fat_start = self.fat_start_sector + fat * self.sectors_per_fat as u64;
+            for (i, byte) in bytes.iter().enumerate() {
+                let pos = offset + i as u64;
+                let sector = fat_start + pos / bytes_per_sector;
+                self.device.get_mut(sector)?[(pos % bytes_per_sector) as usize] = *byte;
+            }
+        }
+        Ok(())
+    }
+
+    /// クラスタ `cluster` の `FatEntry` を `value` に書き換える.
+    /// 変更はすべてのFATコピーに反映される. `value` はFATの種類に
+    /// 応じたビット幅に切り詰められ、FAT32 の上位4ビットは予約済みの
+    /// ため保持される.
+    pub(crate) fn set_fat_entry(&mut self, cluster: Cluster, value: u32) -> io::Result<()> {
+        if let Some(free) = self.free_count {
+            let was_free = self.fat_entry(cluster)?.status() == Status::Free;
+            let is_free = value & 0x0FFF_FFFF == 0;
+            if was_free != is_free {
+                self.free_count = Some(if is_free { free + 1 } else { free.saturating_sub(1) });
+                self.fsinfo_dirty = true;
+            }
+        }
+
+        let n = cluster.raw() as u64;
+        match self.fat_type {
+            FatType::Fat12 => {
+                let offset = n + n / 2;
+                let mut bytes = [0_u8; 2];
+                self.read_fat_bytes(offset, &mut bytes)?;
+                let old = u16::from_le_bytes(bytes);
+                let value = (value & 0xFFF) as u16;
+                let new = if n % 2 == 0 {
+                    (old & 0xF000) | value
+                } else {
+                    (old & 0x000F) | (value << 4)
+                };
+                self.write_fat_bytes(offset, &new.to_le_bytes())
+            }
+            FatType::Fat16 => {
+                self.write_fat_bytes(n * 2, &((value & 0xFFFF) as u16).to_le_bytes())
+            }
+            FatType::Fat32 => {
+                let mut bytes = [0_u8; 4];
+                self.read_fat_bytes(n * 4, &mut bytes)?;
+                let old = u32::from_le_bytes(bytes);
+                let new = (old & 0xF000_0000) | (value & 0x0FFF_FFFF);
+                self.write_fat_bytes(n * 4, &new.to_le_bytes())
+            }
+        }
+    }
+
+    /// 空きクラスタを探して割り当て、チェーンの終端としてマークする.
+    /// `prev` が指定された場合は割り当てたクラスタを `prev` の次に
+    /// 連結する.
+    ///
+    /// 空きクラスタはFSInfoの次の空きクラスタのヒントから探し始める。
+    ///
+    /// # エラー
+    ///
+    /// 空きクラスタがない場合はエラー `Other` を返す。
+    pub fn alloc_cluster(&mut self, prev: Option<Cluster>) -> io::Result<Cluster> {
+        if self.free_count == Some(0) {
+            return ioerr!(Other, "no free cluster");
+        }
+
+        let start = self.next_free;
+        let end = self.cluster_count + 2;
+        for raw in (start..end).chain(2..start) {
+            let cluster = Cluster::from(raw);
+            if self.fat_entry(cluster)?.status() != Status::Free {
+                continue;
+            }
+
+            self.set_fat_entry(cluster, EOC_MARKER)?;
+            if let Some(prev) = prev {
+                self.set_fat_entry(prev, cluster.raw())?;
+            }
+            self.next_free = if raw + 1 < end { raw + 1 } else { 2 };
+            self.fsinfo_dirty = true;
+            return Ok(cluster);
+        }
+
+        ioerr!(Other, "no free cluster")
+    }
+
+    /// FATを走査して空きクラスタ数を数える.
+    pub(crate) fn count_free_clusters(&mut self) -> io::Result<u32> {
+        let mut free = 0;
+        for raw in 2..self.cluster_count + 2 {
+            if self.fat_entry(Cluster::from(raw))?.status() == Status::Free {
+                free += 1;
+            }
+        }
+        Ok(free)
+    }
+
+    /// 記録されている空きクラスタ数を返す. 不明な場合は `None`.
+    pub(crate) fn free_count(&self) -> Option<u32> {
+        self.free_count
+    }
+
+    /// 空きクラスタ数を `free` に設定する.
+    pub(crate) fn set_free_count(&mut self, free: u32) {
+        self.free_count = Some(free);
+        self.fsinfo_dirty = true;
+    }
+
+    /// ボリュームのサイズ、空き容量、クラスタサイズを返す.
+    ///
+    /// 空きクラスタ数が不明な場合はFATを走査して数え、以降はその値を
+    /// 更新し続ける。
+    ///
+    /// # エラー
+    ///
+    /// FATの読み込みに失敗した場合はエラーを返す。
+    pub fn statfs(&mut self) -> io::Result<StatFs> {
+        let free_clusters = match self.free_count {
+            Some(free) => free,
+            None => {
+                let free = self.count_free_clusters()?;
+                self.set_free_count(free);
+                free
+            }
+        };
+
+        Ok(StatFs {
+            cluster_size: self.cluster_size_bytes() as u64,
+            total_clusters: self.cluster_count,
+            free_clusters,
+        })
+    }
+
+    /// クラスタのオフセットにバッファの内容を書き込む.
+    fn write_cluster(&mut self, cluster: Cluster, offset: usize, buf: &[u8]) -> io::Result<usize> {
+        if offset >= self.cluster_len(cluster) {
+            return Ok(0);
+        }
+
+        let cluster_start = self.cluster_start(cluster);
+        let cluster_end = cluster_start + self.cluster_sectors(cluster);
+
+        let mut current_sector = cluster_start + (offset / self.bytes_per_sector as usize) as u64;
+        let mut offset = offset % self.bytes_per_sector as usize;
+
+        let mut ptr: usize = 0;
+        while ptr < buf.len() && current_sector < cluster_end {
+            let sector_data = &mut self.device.get_mut(current_sector)?[offset..];
+            offset = 0;
+
+            let amt = core::cmp::min(sector_data.len(), buf.len() - ptr);
+            sector_data[..amt].copy_from_slice(&buf[ptr..ptr + amt]);
+            ptr += amt;
+
+            current_sector += 1;
+        }
+
+        Ok(ptr)
+    }
+
+    /// クラスタのオフセット位置から書き込む. チェーンの終端に達した
+    /// 場合は新しいクラスタを割り当ててチェーンを延長する.
+    pub fn write_cluster_unaligned(&mut self, mut cloff: SeekHandle,
+        buf: &[u8]) -> io::Result<(usize, SeekHandle)> {
+        let mut written = 0_usize;
+
+        while written < buf.len() {
+            if cloff.offset == self.cluster_len(cloff.cluster) {
+                if self.is_fixed_root(cloff.cluster) {
+                    return ioerr!(Other, "root directory is full");
+                }
+                let next = match self.fat_entry(cloff.cluster)?.status() {
+                    Status::Data(next) => next,
+                    Status::Eoc(_) => self.alloc_cluster(Some(cloff.cluster))?,
+                    _ => return ioerr!(Other, "unexpected fat entry"),
+                };
+                cloff = SeekHandle {
+                    cluster: next,
+                    offset: 0,
+                    total_offset: cloff.total_offset,
+                };
+            }
+
+            let bytes = self.write_cluster(cloff.cluster, cloff.offset,
+                &buf[written..])?;
+            if bytes == 0 {
+                return ioerr!(InvalidInput, "offset beyond end of cluster chain");
+            }
+            written += bytes;
+            cloff.offset += bytes;
+            cloff.total_offset += bytes;
+        }
+
+        Ok((written, cloff))
+    }
+
+    /// オープンされたファイルのディレクトリエントリの位置 `loc` を登録し、
+    /// エントリが移動したときに更新される共有の位置を返す.
+    pub(crate) fn track_entry(&mut self, loc: EntryLocation) -> Arc<SharedLocation> {
+        self.open_entries.retain(|entry| entry.strong_count() > 0);
+        let shared = Arc::new(SharedLocation {
+            dir_cluster: AtomicU32::new(loc.dir_cluster.raw()),
+            offset: AtomicUsize::new(loc.offset),
+        });
+        self.open_entries.push(Arc::downgrade(&shared));
+        shared
+    }
+
+    /// `from` から `to` に移動したディレクトリエントリを指すオープンされた
+    /// ファイルの位置を `to` に付け替える.
+    fn move_entry(&mut self, from: EntryLocation, to: EntryLocation) {
+        for shared in self.open_entries.iter().filter_map(|entry| entry.upgrade()) {
+            if shared.get() == from {
+                shared.set(to);
+            }
+        }
+    }
+
+    /// `loc` にあるディレクトリエントリの開始クラスタとファイルサイズを
+    /// 更新する.
+    pub fn update_entry(&mut self, loc: EntryLocation, cluster: Cluster,
+        size: u32) -> io::Result<()> {
+        let start = SeekHandle {
+            cluster: loc.dir_cluster,
+            offset: 0,
+            total_offset: 0,
+        };
+        let handle = self.seek_handle(loc.dir_cluster, start, loc.offset)?;
+
+        let bytes_per_sector = self.bytes_per_sector as usize;
+        let sector = self.cluster_start(handle.cluster)
+            + (handle.offset / bytes_per_sector) as u64;
+        let pos = handle.offset % bytes_per_sector;
+
+        // 変更がなければセクタをダーティにしない
+        let data = &self.device.get(sector)?[pos..pos + 32];
+        let entry = unsafe { &*(data.as_ptr() as *const VFatRegularDirEntry) };
+        if entry.cluster() == cluster && entry.file_size() == size {
+            return Ok(());
+        }
+
+        let data = &mut self.device.get_mut(sector)?[pos..pos + 32];
+        let entry = unsafe { &mut *(data.as_mut_ptr() as *mut VFatRegularDirEntry) };
+        entry.set_cluster(cluster);
+        entry.set_file_size(size);
+        Ok(())
+    }
+
+    /// クラスタ `cluster` の内容をゼロで埋める.
+    pub fn clear_cluster(&mut self, cluster: Cluster) -> io::Result<()> {
+        let start = self.cluster_start(cluster);
+        for sector in start..start + self.cluster_sectors(cluster) {
+            for byte in self.device.get_mut(sector)?.iter_mut() {
+                *byte = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// `start` から始まるクラスタチェーンの末尾にゼロで埋めた新しい
+    /// クラスタを連結して、そのクラスタを返す.
+    pub fn extend_chain(&mut self, start: Cluster) -> io::Result<Cluster> {
+        if self.is_fixed_root(start) {
+            return ioerr!(Other, "root directory is full");
+        }
+
+        let mut last = start;
+        loop {
+            match self.fat_entry(last)?.status() {
+                Status::Data(next) => last = next,
+                Status::Eoc(_) => break,
+                _ => return ioerr!(Other, "unexpected fat entry"),
+            }
+        }
+
+        let cluster = self.alloc_cluster(Some(last))?;
+        self.clear_cluster(cluster)?;
+        Ok(cluster)
+    }
+
+    /// `start` から始まるクラスタチェーンのすべてのクラスタを解放する.
+    pub fn free_chain(&mut self, start: Cluster) -> io::Result<()> {
+        let mut cluster = start;
+        loop {
+            let next = match self.fat_entry(cluster)?.status() {
+                Status::Data(next) => Some(next),
+                Status::Eoc(_) => None,
+                _ => return ioerr!(Other, "unexpected fat entry"),
+            };
+            self.set_fat_entry(cluster, 0)?;
+
+            match next {
+                Some(next) => cluster = next,
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// `start` から始まるクラスタチェーンの先頭から `offset` バイトの
+    /// 位置に `buf` を書き込む.
+    pub fn write_chain(&mut self, start: Cluster, offset: usize,
+        buf: &[u8]) -> io::Result<usize> {
+        let handle = SeekHandle {
+            cluster: start,
+            offset: 0,
+            total_offset: 0,
+        };
+        let handle = self.seek_handle(start, handle, offset)?;
+        self.write_cluster_unaligned(handle, buf).map(|(written, _)| written)
+    }
+
+    /// FSInfoセクタを更新し、変更されたセクタをすべてデバイスに書き戻す.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if let (true, Some(sector)) = (self.fsinfo_dirty, self.fsinfo_sector) {
+            let free_count = self.free_count.unwrap_or(FsInfo::UNKNOWN);
+            let data = self.device.get_mut(sector)?;
+            data[FsInfo::FREE_COUNT_OFFSET..][..4].copy_from_slice(&free_count.to_le_bytes());
+            data[FsInfo::NEXT_FREE_OFFSET..][..4].copy_from_slice(&self.next_free.to_le_bytes());
+        }
+        self.fsinfo_dirty = false;
+        self.device.flush()
+    }
+
+    /// セクタキャッシュの統計情報を返す.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.device.stats()
+    }
+
+    /// セクタキャッシュに保持する最大のセクタ数を `capacity` に変更する.
+    ///
+    /// # パニック
+    ///
+    /// `capacity` が0の場合はパニックになる。
+    pub fn set_cache_capacity(&mut self, capacity: usize) -> io::Result<()> {
+        self.device.set_capacity(capacity)
+    }
+
+    /// 連続したセクタを読み込む際に先読みするセクタ数を設定する.
+    /// 0の場合は先読みを行わない。
+    pub fn set_read_ahead(&mut self, sectors: usize) {
+        self.device.set_read_ahead(sectors)
     }
 
     /// ルートディレクトリのクラスタを返す
@@ -203,6 +766,16 @@ impl<HANDLE: VFatHandle> VFat<HANDLE> {
         self.rootdir_cluster
     }
 
+    /// 親ディレクトリ `parent` を指す ".." エントリに格納するクラスタを
+    /// 返す. 親がルートディレクトリの場合は0となる.
+    fn dotdot_cluster(&self, parent: Cluster) -> Cluster {
+        if parent == self.rootdir_cluster {
+            Cluster::from(0)
+        } else {
+            parent
+        }
+    }
+
     pub fn seek_handle(&mut self, start: Cluster, cloff: SeekHandle,
         offset: usize) -> io::Result<SeekHandle> {
         let mut current_cluster: Cluster;
@@ -210,19 +783,20 @@ impl<HANDLE: VFatHandle> VFat<HANDLE> {
 
         if offset > cloff.total_offset {
             current_cluster = cloff.cluster;
-            current_offset = offset - cloff.total_offset;
+            current_offset = cloff.offset + (offset - cloff.total_offset);
         } else {
             current_cluster = start;
             current_offset = offset;
         }
 
-        'cluster_loop: while current_offset >= self.cluster_size_bytes() {
+        'cluster_loop: while current_offset >= self.cluster_len(current_cluster) {
+            let cluster_len = self.cluster_len(current_cluster);
             match self.fat_entry(current_cluster)?.status() {
                 Status::Data(next) => current_cluster = next,
                 Status::Eoc(_) => break 'cluster_loop,
                 _ => return ioerr!(Other, "unexpected fat entry"),
             }
-            current_offset -= self.cluster_size_bytes();
+            current_offset -= cluster_len;
         }
 
         Ok(SeekHandle {
@@ -256,4 +830,190 @@ impl<'a, HANDLE: VFatHandle> FileSystem for &'a HANDLE {
 
         Ok(entry)
     }
+
+    fn create_file<P: AsRef<Path>>(self, path: P) -> io::Result<Self::File> {
+        let (dir, name) = split_path(self, path.as_ref())?;
+
+        let entry = VFatRegularDirEntry::new([b' '; 11], Attributes::ARCHIVE,
+            Cluster::from(0), 0);
+        let loc = dir.create_entry(name, entry)?;
+
+        Ok(File::new(self.clone(), Cluster::from(0), String::from(name),
+            entry.metadata(), 0, loc))
+    }
+
+    fn create_dir<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Dir> {
+        let (parent, name) = split_path(self, path.as_ref())?;
+        if parent.find(name).is_ok() {
+            return ioerr!(AlreadyExists, "entry already exists");
+        }
+
+        let cluster = self.lock(|fs| -> io::Result<Cluster> {
+            let cluster = fs.alloc_cluster(None)?;
+            fs.clear_cluster(cluster)?;
+
+            let dot = VFatRegularDirEntry::new(*b".          ",
+                Attributes::DIRECTORY, cluster, 0);
+            let dotdot = VFatRegularDirEntry::new(*b"..         ",
+                Attributes::DIRECTORY, fs.dotdot_cluster(parent.cluster), 0);
+
+            let mut bytes: Vec<u8> = Vec::new();
+            bytes.extend_from_slice(VFatDirEntry::from(dot).as_bytes());
+            bytes.extend_from_slice(VFatDirEntry::from(dotdot).as_bytes());
+            fs.write_chain(cluster, 0, &bytes)?;
+            Ok(cluster)
+        })?;
+
+        let entry = VFatRegularDirEntry::new([b' '; 11], Attributes::DIRECTORY,
+            cluster, 0);
+        if let Err(e) = parent.create_entry(name, entry) {
+            self.lock(|fs| fs.free_chain(cluster))?;
+            return Err(e);
+        }
+
+        Ok(Dir {
+            vfat: self.clone(),
+            cluster,
+            name: String::from(name),
+            metadata: entry.metadata(),
+        })
+    }
+
+    fn remove<P: AsRef<Path>>(self, path: P) -> io::Result<()> {
+        let (parent, name) = split_path(self, path.as_ref())?;
+
+        let slot = parent.find_slot(name)?;
+        if let Entry::Dir(dir) = slot.clone().into_entry(self.clone(), parent.cluster) {
+            if !dir.is_empty()? {
+                return ioerr!(PermissionDenied, "directory not empty");
+            }
+        }
+        parent.remove_slot(&slot)?;
+
+        let cluster = slot.entry.cluster();
+        if cluster.raw() != 0 {
+            self.lock(|fs| fs.free_chain(cluster))?;
+        }
+        Ok(())
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(self, from: P, to: Q) -> io::Result<()> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        let (src, from_name) = split_path(self, from)?;
+        let (dst, to_name) = split_path(self, to)?;
+
+        let slot = src.find_slot(from_name)?;
+        // 大文字小文字だけを変更する場合は移動先として自身が見つかる
+        match dst.find_slot(to_name) {
+            Ok(ref existing) if src.cluster == dst.cluster && existing.index == slot.index => (),
+            Ok(_) => return ioerr!(AlreadyExists, "entry already exists"),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e),
+        }
+
+        let is_dir = slot.entry.metadata().attributes.directory();
+        if is_dir && is_within(to, from) {
+            return ioerr!(InvalidInput, "cannot move a directory into itself");
+        }
+
+        let to_loc = dst.insert_entry(to_name, slot.entry)?;
+        src.remove_slot(&slot)?;
+        // オープンされているファイルが新しいスロットを更新するようにする
+        let from_loc = EntryLocation {
+            dir_cluster: src.cluster,
+            offset: slot.index * size_of::<VFatDirEntry>(),
+        };
+        self.lock(|fs| fs.move_entry(from_loc, to_loc));
+
+        // 別のディレクトリに移動したディレクトリの ".." を付け替える
+        if is_dir && src.cluster != dst.cluster {
+            self.lock(|fs| {
+                let dotdot = EntryLocation {
+                    dir_cluster: slot.entry.cluster(),
+                    offset: size_of::<VFatDirEntry>(),
+                };
+                let parent = fs.dotdot_cluster(dst.cluster);
+                fs.update_entry(dotdot, parent, 0)
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// パス `path` が `base` 自身またはその下にある場合は `true` を返す.
+/// エントリ名は大文字小文字を区別せずに比較する。
+fn is_within(path: &Path, base: &Path) -> bool {
+    let mut components = path.components();
+    base.components().all(|component| match (components.next(), component) {
+        (Some(Component::Normal(p)), Component::Normal(b)) => match (p.to_str(), b.to_str()) {
+            (Some(p), Some(b)) => eq_ignore_case(p, b),
+            _ => p == b,
+        },
+        (Some(p), b) => p == b,
+        (None, _) => false,
+    })
+}
+
+/// 絶対パス `path` を親ディレクトリとエントリ名に分割する.
+///
+/// # エラー
+///
+/// `path` が絶対パスでない場合はエラー `InvalidInput` を、エントリ名を
+/// 持たない場合（ルートディレクトリなど）はエラー `PermissionDenied` を
+/// 返す。親ディレクトリが存在しない場合は `open_dir()` のエラーを返す。
+fn split_path<'a, HANDLE: VFatHandle>(vfat: &HANDLE,
+    path: &'a Path) -> io::Result<(Dir<HANDLE>, &'a str)> {
+    if !path.is_absolute() {
+        return ioerr!(InvalidInput, "path is not absolute");
+    }
+
+    match (path.parent(), path.file_name().and_then(|name| name.to_str())) {
+        (Some(parent), Some(name)) => Ok((vfat.open_dir(parent)?, name)),
+        _ => ioerr!(PermissionDenied, "path has no entry name"),
+    }
+}
+
+/// FAT12/16/32 のMBRパーティションタイプ.
+const FAT_PARTITION_TYPES: [u8; 6] = [0x01, 0x04, 0x06, 0x0B, 0x0C, 0x0E];
+
+/// `device` のパーティションテーブルから、ブートセクタが `is_volume` を
+/// 満たす最初のパーティションを探し、その開始セクタとセクタ数を返す.
+///
+/// MBRでフォーマットされたディスクではパーティションタイプが `mbr_types`
+/// のいずれかであるパーティションを、GPTでフォーマットされたディスクでは
+/// Basic Data タイプのパーティションを対象とする。
+///
+/// # エラー
+///
+/// 該当するパーティションがない場合は `NotFound` を返す。
+pub(crate) fn find_partition<T: BlockDevice>(
+    device: &mut T,
+    mbr_types: &[u8],
+    is_volume: impl Fn(&[u8]) -> bool,
+) -> Result<(u64, u64), Error> {
+    let mbr = MasterBootRecord::from(&mut *device)?;
+
+    let mut candidates: Vec<(u64, u64)> = Vec::new();
+    if mbr.partitions.iter().any(|p| p.partition_type == gpt::PROTECTIVE_PARTITION_TYPE) {
+        let gpt = GuidPartitionTable::from_mbr(&mut *device, &mbr)?;
+        for partition in gpt.used_partitions().filter(|p| p.type_guid == Guid::BASIC_DATA) {
+            candidates.push((partition.first_lba, partition.num_sectors()));
+        }
+    } else {
+        for partition in mbr.partitions.iter() {
+            if mbr_types.contains(&partition.partition_type) {
+                candidates.push((partition.relative_sector as u64,
+                    partition.total_sectores as u64));
+            }
+        }
+    }
+
+    let mut boot = vec![0_u8; device.sector_size() as usize];
+    for (start, num_sectors) in candidates {
+        device.read_sector(start, &mut boot)?;
+        if is_volume(&boot) {
+            return Ok((start, num_sectors));
+        }
+    }
+    Err(Error::NotFound)
 }
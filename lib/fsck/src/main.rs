@@ -0,0 +1,85 @@
+use std::fmt::{self, Debug};
+use std::fs::OpenOptions;
+use std::path::PathBuf;
+use std::process;
+use std::sync::{Arc, Mutex};
+
+use structopt::StructOpt;
+
+use fat32::vfat::{VFat, VFatHandle};
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "Check a FAT12/16/32 disk image for consistency.")]
+struct Opt {
+    #[structopt(short = "r", long = "repair", help = "Repair the problems found")]
+    repair: bool,
+
+    #[structopt(help = "Path to disk image", parse(from_os_str))]
+    image: PathBuf,
+}
+
+// fsck(8) と同じ終了コード
+const EXIT_OK: i32 = 0;
+const EXIT_REPAIRED: i32 = 1;
+const EXIT_UNCORRECTED: i32 = 4;
+const EXIT_ERROR: i32 = 8;
+
+#[derive(Clone)]
+struct HostVFatHandle(Arc<Mutex<VFat<Self>>>);
+
+impl Debug for HostVFatHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HostVFatHandle")
+    }
+}
+
+impl VFatHandle for HostVFatHandle {
+    fn new(val: VFat<HostVFatHandle>) -> Self {
+        HostVFatHandle(Arc::new(Mutex::new(val)))
+    }
+
+    fn lock<R>(&self, f: impl FnOnce(&mut VFat<HostVFatHandle>) -> R) -> R {
+        f(&mut self.0.lock().expect("vfat lock poisoned"))
+    }
+}
+
+fn main() {
+    let opt = Opt::from_args();
+
+    let file = match OpenOptions::new().read(true).write(opt.repair).open(&opt.image) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("{}: {}", opt.image.display(), e);
+            process::exit(EXIT_ERROR);
+        }
+    };
+
+    let vfat = match VFat::<HostVFatHandle>::from(file) {
+        Ok(vfat) => vfat,
+        Err(e) => {
+            eprintln!("{}: not a FAT volume: {:?}", opt.image.display(), e);
+            process::exit(EXIT_ERROR);
+        }
+    };
+
+    let report = match vfat.lock(|fs| fs.check(opt.repair)) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}: check failed: {}", opt.image.display(), e);
+            process::exit(EXIT_ERROR);
+        }
+    };
+
+    for problem in report.problems.iter() {
+        println!("{}", problem);
+    }
+    println!("{}: {} files, {} directories, {} clusters in use, {} problems{}",
+        opt.image.display(), report.files, report.dirs, report.used_clusters,
+        report.problems.len(), if report.repaired { " (repaired)" } else { "" });
+
+    process::exit(match (report.is_clean(), report.repaired) {
+        (true, _) => EXIT_OK,
+        (false, true) => EXIT_REPAIRED,
+        (false, false) => EXIT_UNCORRECTED,
+    });
+}
@@ -0,0 +1,120 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::cmp::max;
+use core::ptr;
+
+use crate::syscall::{mmap, munmap, sbrk};
+use crate::{PROT_READ, PROT_WRITE};
+
+/// 最小のサイズクラス (2^4 = 16バイト).
+const MIN_CLASS: usize = 4;
+/// ヒープから割り当てる最大のサイズクラス (2^15 = 32KiB). これより
+/// 大きい割り当ては `mmap` で直接割り当てる。
+const MAX_CLASS: usize = 15;
+/// ヒープを一度に伸ばす最小のバイト数.
+const GROW_SIZE: usize = 64 * 1024;
+/// カーネルのページサイズ. `mmap` が返すアドレスはこの値にアラインしている。
+const PAGE_SIZE: usize = 64 * 1024;
+
+/// ユーザプログラム用のアロケータ. `#[global_allocator]` に登録すると
+/// `alloc` クレートの `Vec` や `String` が使えるようになる。
+///
+/// 2のべき乗のサイズクラスごとに解放されたブロックのリストを持ち、
+/// リストが空の場合は `sbrk` で伸ばしたヒープから切り出す。`MAX_CLASS`
+/// より大きい割り当ては `mmap` で割り当て、解放時に `munmap` する。
+/// ユーザプロセスはシングルスレッドなのでロックは取らない。
+pub struct Allocator(UnsafeCell<Heap>);
+
+unsafe impl Sync for Allocator {}
+
+struct Heap {
+    /// サイズクラスごとの解放されたブロックの侵入型リストの先頭.
+    bins: [*mut usize; MAX_CLASS + 1],
+    /// ヒープの未使用部分の先頭.
+    start: usize,
+    /// ヒープの終端.
+    end: usize,
+}
+
+impl Allocator {
+    /// 空のヒープを持つアロケータを返す.
+    pub const fn new() -> Allocator {
+        Allocator(UnsafeCell::new(Heap {
+            bins: [ptr::null_mut(); MAX_CLASS + 1],
+            start: 0,
+            end: 0,
+        }))
+    }
+}
+
+/// `addr` を `align` の倍数に切り上げる.
+fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
+
+/// `layout` のサイズとアラインメントの大きい方を収めるサイズクラスを返す.
+/// サイズクラス `k` のブロックは `2^k` バイトで `2^k` にアラインしている。
+fn size_class(layout: Layout) -> usize {
+    let size = max(max(layout.size(), layout.align()), 1 << MIN_CLASS);
+    size.next_power_of_two().trailing_zeros() as usize
+}
+
+impl Heap {
+    /// サイズクラス `class` のブロックを割り当てる. ヒープを伸ばせない場合は
+    /// ヌルポインタを返す。
+    unsafe fn alloc(&mut self, class: usize) -> *mut u8 {
+        let head = self.bins[class];
+        if !head.is_null() {
+            self.bins[class] = *head as *mut usize;
+            return head as *mut u8;
+        }
+
+        let size = 1 << class;
+        let mut addr = align_up(self.start, size);
+        if addr + size > self.end {
+            // アラインメントで空く分を含めて伸ばす
+            let grow = max(GROW_SIZE, size * 2);
+            let old = match sbrk(grow as isize) {
+                Ok(old) => old,
+                Err(_) => return ptr::null_mut(),
+            };
+            if old != self.end {
+                // 他の誰かがブレークを動かしたので残りは使わない
+                self.start = old;
+            }
+            self.end = old + grow;
+            addr = align_up(self.start, size);
+        }
+        self.start = addr + size;
+        addr as *mut u8
+    }
+
+    /// サイズクラス `class` のブロック `ptr` をリストに戻す.
+    unsafe fn dealloc(&mut self, ptr: *mut u8, class: usize) {
+        let node = ptr as *mut usize;
+        *node = self.bins[class] as usize;
+        self.bins[class] = node;
+    }
+}
+
+unsafe impl GlobalAlloc for Allocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let class = size_class(layout);
+        if class <= MAX_CLASS {
+            return (*self.0.get()).alloc(class);
+        }
+        if layout.align() > PAGE_SIZE {
+            return ptr::null_mut();
+        }
+        mmap(layout.size(), PROT_READ | PROT_WRITE).map_or(ptr::null_mut(), |addr| addr as *mut u8)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let class = size_class(layout);
+        if class <= MAX_CLASS {
+            (*self.0.get()).dealloc(ptr, class);
+        } else {
+            let _ = munmap(ptr as usize, layout.size());
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use core::ptr;
+use core::slice;
+use core::str;
+
+use crate::AT_NULL;
+
+static mut ARGC: usize = 0;
+static mut ARGV: *const *const u8 = ptr::null();
+static mut ENVP: *const *const u8 = ptr::null();
+static mut AUXV: *const u64 = ptr::null();
+
+/// カーネルが `_start` に渡した引数の数と引数、環境変数、補助ベクタの
+/// 配列のアドレスを保存する. `_start` で `.bss` を0クリアした後、
+/// ほかの関数より先に一度だけ呼び出す。
+pub unsafe fn init(argc: usize, argv: *const *const u8, envp: *const *const u8,
+    auxv: *const u64) {
+    ARGC = argc;
+    ARGV = argv;
+    ENVP = envp;
+    AUXV = auxv;
+}
+
+/// NUL終端文字列を `&str` に変換する. UTF-8でない場合は空文字列を返す。
+unsafe fn from_c_str(ptr: *const u8) -> &'static str {
+    let mut len = 0;
+    while *ptr.add(len) != 0 {
+        len += 1;
+    }
+    str::from_utf8(slice::from_raw_parts(ptr, len)).unwrap_or("")
+}
+
+/// `args()` が返すプロセスの引数のイテレータ.
+pub struct Args {
+    next: usize,
+}
+
+impl Iterator for Args {
+    type Item = &'static str;
+
+    fn next(&mut self) -> Option<&'static str> {
+        unsafe {
+            if self.next >= ARGC || ARGV.is_null() {
+                return None;
+            }
+            let arg = from_c_str(*ARGV.add(self.next));
+            self.next += 1;
+            Some(arg)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = unsafe { ARGC }.saturating_sub(self.next);
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for Args {}
+
+/// プロセスの引数を返す. 最初の要素は慣例としてプログラムのパスである。
+pub fn args() -> Args {
+    Args { next: 0 }
+}
+
+/// `vars()` が返す環境変数の名前と値の組のイテレータ.
+pub struct Vars {
+    next: usize,
+}
+
+impl Iterator for Vars {
+    type Item = (&'static str, &'static str);
+
+    fn next(&mut self) -> Option<(&'static str, &'static str)> {
+        unsafe {
+            if ENVP.is_null() || (*ENVP.add(self.next)).is_null() {
+                return None;
+            }
+            let env = from_c_str(*ENVP.add(self.next));
+            self.next += 1;
+            match env.find('=') {
+                Some(i) => Some((&env[..i], &env[i + 1..])),
+                None => Some((env, "")),
+            }
+        }
+    }
+}
+
+/// プロセスの環境変数を返す.
+pub fn vars() -> Vars {
+    Vars { next: 0 }
+}
+
+/// 環境変数 `key` の値を返す. 設定されていない場合は `None`.
+pub fn var(key: &str) -> Option<&'static str> {
+    vars().find(|&(k, _)| k == key).map(|(_, v)| v)
+}
+
+/// 補助ベクタの種類 `kind` (`AT_PAGESZ` など) の値を返す.
+pub fn aux(kind: u64) -> Option<u64> {
+    unsafe {
+        if AUXV.is_null() {
+            return None;
+        }
+        let mut entry = AUXV;
+        while *entry != AT_NULL {
+            if *entry == kind {
+                return Some(*entry.add(1));
+            }
+            entry = entry.add(2);
+        }
+        None
+    }
+}
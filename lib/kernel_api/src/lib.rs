@@ -5,6 +5,10 @@ use core::fmt;
 
 use shim::io;
 
+#[cfg(feature = "user-space")]
+pub mod allocator;
+#[cfg(feature = "user-space")]
+pub mod env;
 #[cfg(feature = "user-space")]
 pub mod syscall;
 
@@ -22,12 +26,16 @@ pub enum OsError {
     BadAddress = 50,
     FileExists = 60,
     InvalidArgument = 70,
+    BadDescriptor = 80,
+    TooManyDescriptors = 81,
+    NoChild = 90,
 
     IoError = 101,
     IoErrorEof = 102,
     IoErrorInvalidData = 103,
     IoErrorInvalidInput = 104,
     IoErrorTimedOut = 105,
+    IoErrorWouldBlock = 106,
 
     InvalidSocket = 200,
     IllegalSocketOperation = 201,
@@ -45,11 +53,16 @@ impl core::convert::From<u64> for OsError {
             50 => OsError::BadAddress,
             60 => OsError::FileExists,
             70 => OsError::InvalidArgument,
+            80 => OsError::BadDescriptor,
+            81 => OsError::TooManyDescriptors,
+            90 => OsError::NoChild,
 
             101 => OsError::IoError,
             102 => OsError::IoErrorEof,
             103 => OsError::IoErrorInvalidData,
             104 => OsError::IoErrorInvalidInput,
+            105 => OsError::IoErrorTimedOut,
+            106 => OsError::IoErrorWouldBlock,
 
             200 => OsError::InvalidSocket,
             201 => OsError::IllegalSocketOperation,
@@ -66,7 +79,10 @@ impl core::convert::From<io::Error> for OsError {
             io::ErrorKind::InvalidData => OsError::IoErrorInvalidData,
             io::ErrorKind::InvalidInput => OsError::IoErrorInvalidInput,
             io::ErrorKind::TimedOut => OsError::IoErrorTimedOut,
+            io::ErrorKind::WouldBlock => OsError::IoErrorWouldBlock,
             io::ErrorKind::NotFound => OsError::NoEntry,
+            io::ErrorKind::AlreadyExists => OsError::FileExists,
+            io::ErrorKind::PermissionDenied => OsError::NoAccess,
             _ => OsError::IoError,
         }
     }
@@ -79,15 +95,134 @@ pub const NR_WRITE: usize = 4;
 pub const NR_GETPID: usize = 5;
 pub const NR_WRITE_STR: usize = 6;
 
-#[derive(Clone, Copy, Debug)]
-pub struct SocketDescriptor(u64);
+pub const NR_OPEN: usize = 10;
+pub const NR_READ: usize = 11;
+pub const NR_CLOSE: usize = 12;
+pub const NR_SEEK: usize = 13;
+pub const NR_STAT: usize = 14;
+pub const NR_FSTAT: usize = 15;
+pub const NR_READDIR: usize = 16;
+
+pub const NR_GETCWD: usize = 30;
+pub const NR_CHDIR: usize = 31;
+pub const NR_MKDIR: usize = 32;
+pub const NR_RMDIR: usize = 33;
+pub const NR_UNLINK: usize = 34;
+pub const NR_RENAME: usize = 35;
+
+pub const NR_FORK: usize = 40;
+pub const NR_EXEC: usize = 41;
+pub const NR_WAIT: usize = 42;
+pub const NR_WAITPID: usize = 43;
+
+pub const NR_BRK: usize = 50;
+pub const NR_MMAP: usize = 51;
+pub const NR_MUNMAP: usize = 52;
+pub const NR_MPROTECT: usize = 53;
+
+/// `mmap` の保護フラグ: 読み込み可能.
+pub const PROT_READ: u64 = 1 << 0;
+/// `mmap` の保護フラグ: 書き込み可能.
+pub const PROT_WRITE: u64 = 1 << 1;
+/// `mmap` の保護フラグ: 実行可能.
+pub const PROT_EXEC: u64 = 1 << 2;
+
+/// 補助ベクタの種類: 終端.
+pub const AT_NULL: u64 = 0;
+/// 補助ベクタの種類: ページサイズ.
+pub const AT_PAGESZ: u64 = 6;
+/// 補助ベクタの種類: プログラムのエントリポイント.
+pub const AT_ENTRY: u64 = 9;
+
+/// ファイル、ディレクトリ、デバイス、ソケットを指すファイルディスクリプタ.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileDescriptor(u64);
+
+impl FileDescriptor {
+    pub const fn from_raw(raw: u64) -> FileDescriptor {
+        FileDescriptor(raw)
+    }
 
-impl SocketDescriptor {
     pub fn raw(&self) -> u64 {
         self.0
     }
 }
 
+/// ソケットはファイルと同じディスクリプタ空間にある.
+pub type SocketDescriptor = FileDescriptor;
+
+pub const STDIN: FileDescriptor = FileDescriptor(0);
+pub const STDOUT: FileDescriptor = FileDescriptor(1);
+pub const STDERR: FileDescriptor = FileDescriptor(2);
+
+/// `open` のフラグ: ファイルが存在しない場合は作成する.
+pub const O_CREAT: u64 = 1 << 0;
+
+/// `seek` の基準位置.
+pub const SEEK_SET: u64 = 0;
+pub const SEEK_CUR: u64 = 1;
+pub const SEEK_END: u64 = 2;
+
+/// ディスクリプタが指すオブジェクトの種類.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileKind {
+    File = 0,
+    Dir = 1,
+    Device = 2,
+    Socket = 3,
+}
+
+/// `stat` と `fstat` が返す属性.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Stat {
+    pub kind: FileKind,
+    pub read_only: bool,
+    pub hidden: bool,
+    pub size: u64,
+}
+
+impl Default for Stat {
+    fn default() -> Stat {
+        Stat { kind: FileKind::File, read_only: false, hidden: false, size: 0 }
+    }
+}
+
+/// ディレクトリのエントリ名の最大バイト数.
+pub const NAME_MAX: usize = 255;
+
+/// `readdir` が返すディレクトリのエントリ.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct DirEnt {
+    pub stat: Stat,
+    pub name_len: u64,
+    pub name_buf: [u8; NAME_MAX + 1],
+}
+
+impl DirEnt {
+    pub fn name(&self) -> &str {
+        let len = core::cmp::min(self.name_len as usize, NAME_MAX);
+        core::str::from_utf8(&self.name_buf[..len]).unwrap_or("")
+    }
+}
+
+impl Default for DirEnt {
+    fn default() -> DirEnt {
+        DirEnt { stat: Stat::default(), name_len: 0, name_buf: [0; NAME_MAX + 1] }
+    }
+}
+
+impl fmt::Debug for DirEnt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DirEnt")
+            .field("stat", &self.stat)
+            .field("name", &self.name())
+            .finish()
+    }
+}
+
 #[derive(Debug)]
 pub struct SocketStatus {
     pub is_active: bool,
@@ -2,6 +2,8 @@ use core::fmt;
 use core::fmt::Write;
 use core::time::Duration;
 
+use shim::io;
+
 use crate::*;
 
 macro_rules! err_or {
@@ -57,27 +59,15 @@ pub fn time() -> Duration {
     Duration::new(secs, nanos as u32)
 }
 
-pub fn exit() -> ! {
-    unsafe {
-        asm!("svc $0"
-             :: "i"(NR_EXIT)
-             :: "volatile");
-    }
-    loop {}
-}
-
-pub fn write(b: u8) {
-    let mut ecode: u64;
-
+pub fn exit(status: i32) -> ! {
     unsafe {
         asm!("mov x0, $1
-              svc $2
-              mov $0, x7"
-             : "=r"(ecode)
-             : "r"(b), "i"(NR_WRITE)
-             : "x7"
+              svc $0"
+             :: "i"(NR_EXIT), "r"(status as u64)
+             : "x0"
              : "volatile");
     }
+    loop {}
 }
 
 pub fn write_str(msg: &str) {
@@ -115,7 +105,461 @@ pub fn getpid() -> u64 {
     pid
 }
 
-pub fn sock_create() -> SocketDescriptor {
+/// カレントプロセスを複製する. 親プロセスには子プロセスのIDを、
+/// 子プロセスには0を返す。
+pub fn fork() -> OsResult<u64> {
+    let mut pid: u64;
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("svc $2
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(pid), "=r"(ecode)
+             : "i"(NR_FORK)
+             : "x0", "x7", "memory"
+             : "volatile");
+    }
+    err_or!(ecode, pid)
+}
+
+/// カレントプロセスのイメージを `path` のプログラムに置き換え、`argv` を
+/// 引数、`KEY=VALUE` 形式の `envp` を環境変数として渡す. 成功した場合は
+/// 復帰しない。
+pub fn exec(path: &str, argv: &[&str], envp: &[&str]) -> OsError {
+    let ptr = path.as_ptr() as u64;
+    let len = path.len();
+    let argv_addr = argv.as_ptr() as u64;
+    let argc = argv.len();
+    let envp_addr = envp.as_ptr() as u64;
+    let envc = envp.len();
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("mov x0, $2
+              mov x1, $3
+              mov x2, $4
+              mov x3, $5
+              mov x4, $6
+              mov x5, $7
+              svc $1
+              mov $0, x7"
+             : "=r"(ecode)
+             : "i"(NR_EXEC), "r"(ptr), "r"(len), "r"(argv_addr), "r"(argc),
+               "r"(envp_addr), "r"(envc)
+             : "x0", "x1", "x2", "x3", "x4", "x5", "x7"
+             : "volatile");
+    }
+    OsError::from(ecode)
+}
+
+/// いずれかの子プロセスが終了するまで待機し、子プロセスのIDと
+/// 終了ステータスを返す.
+pub fn wait() -> OsResult<(u64, i32)> {
+    let mut pid: u64;
+    let mut status: u64;
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("svc $3
+              mov $0, x0
+              mov $1, x1
+              mov $2, x7"
+             : "=r"(pid), "=r"(status), "=r"(ecode)
+             : "i"(NR_WAIT)
+             : "x0", "x1", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, (pid, status as i32))
+}
+
+/// 子プロセス `pid` が終了するまで待機し、終了ステータスを返す.
+pub fn waitpid(pid: u64) -> OsResult<i32> {
+    let mut status: u64;
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("mov x0, $3
+              svc $2
+              mov $0, x1
+              mov $1, x7"
+             : "=r"(status), "=r"(ecode)
+             : "i"(NR_WAITPID), "r"(pid)
+             : "x0", "x1", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, status as i32)
+}
+
+/// プログラムブレーク (ヒープの終端) を `addr` に変更し、新しいブレークを
+/// 返す. `addr` が0の場合は現在のブレークを返す。
+pub fn brk(addr: usize) -> OsResult<usize> {
+    let mut brk: u64;
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("mov x0, $3
+              svc $2
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(brk), "=r"(ecode)
+             : "i"(NR_BRK), "r"(addr)
+             : "x0", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, brk as usize)
+}
+
+/// ヒープを `increment` バイト伸縮し、変更前のブレークを返す.
+pub fn sbrk(increment: isize) -> OsResult<usize> {
+    let old = brk(0)?;
+    if increment == 0 {
+        return Ok(old);
+    }
+    let new = if increment > 0 {
+        old.checked_add(increment as usize)
+    } else {
+        old.checked_sub(increment.wrapping_neg() as usize)
+    };
+    brk(new.ok_or(OsError::InvalidArgument)?)?;
+    Ok(old)
+}
+
+/// `len` バイトの無名メモリを保護フラグ `prot` (`PROT_READ` など) で
+/// 割り当て、先頭アドレスを返す. メモリは0で埋められている。
+pub fn mmap(len: usize, prot: u64) -> OsResult<usize> {
+    let mut addr: u64;
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("mov x0, $3
+              mov x1, $4
+              svc $2
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(addr), "=r"(ecode)
+             : "i"(NR_MMAP), "r"(len), "r"(prot)
+             : "x0", "x1", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, addr as usize)
+}
+
+/// `mmap` で割り当てたメモリのうち `[addr, addr + len)` の割り当てを解除する.
+pub fn munmap(addr: usize, len: usize) -> OsResult<()> {
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("mov x0, $2
+              mov x1, $3
+              svc $1
+              mov $0, x7"
+             : "=r"(ecode)
+             : "i"(NR_MUNMAP), "r"(addr), "r"(len)
+             : "x0", "x1", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, ())
+}
+
+/// `[addr, addr + len)` のメモリの保護フラグを `prot` (`PROT_READ` など) に
+/// 変更する. `addr` はページ境界になければならない。
+pub fn mprotect(addr: usize, len: usize, prot: u64) -> OsResult<()> {
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("mov x0, $2
+              mov x1, $3
+              mov x2, $4
+              svc $1
+              mov $0, x7"
+             : "=r"(ecode)
+             : "i"(NR_MPROTECT), "r"(addr), "r"(len), "r"(prot)
+             : "x0", "x1", "x2", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, ())
+}
+
+pub fn open(path: &str, flags: u64) -> OsResult<FileDescriptor> {
+    let ptr = path.as_ptr() as u64;
+    let len = path.len();
+    let mut fd: u64;
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("mov x0, $3
+              mov x1, $4
+              mov x2, $5
+              svc $2
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(fd), "=r"(ecode)
+             : "i"(NR_OPEN), "r"(ptr), "r"(len), "r"(flags)
+             : "x0", "x1", "x2", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, FileDescriptor(fd))
+}
+
+pub fn read(fd: FileDescriptor, buf: &mut [u8]) -> OsResult<usize> {
+    let buf_addr = buf.as_mut_ptr() as u64;
+    let buf_len = buf.len();
+    let mut size: u64;
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("mov x0, $3
+              mov x1, $4
+              mov x2, $5
+              svc $2
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(size), "=r"(ecode)
+             : "i"(NR_READ), "r"(fd.raw()), "r"(buf_addr), "r"(buf_len)
+             : "x0", "x1", "x2", "x7", "memory"
+             : "volatile");
+    }
+    err_or!(ecode, size as usize)
+}
+
+pub fn write(fd: FileDescriptor, buf: &[u8]) -> OsResult<usize> {
+    let buf_addr = buf.as_ptr() as u64;
+    let buf_len = buf.len();
+    let mut size: u64;
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("mov x0, $3
+              mov x1, $4
+              mov x2, $5
+              svc $2
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(size), "=r"(ecode)
+             : "i"(NR_WRITE), "r"(fd.raw()), "r"(buf_addr), "r"(buf_len)
+             : "x0", "x1", "x2", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, size as usize)
+}
+
+pub fn close(fd: FileDescriptor) -> OsResult<()> {
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("mov x0, $2
+              svc $1
+              mov $0, x7"
+             : "=r"(ecode)
+             : "i"(NR_CLOSE), "r"(fd.raw())
+             : "x0", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, ())
+}
+
+pub fn seek(fd: FileDescriptor, pos: io::SeekFrom) -> OsResult<u64> {
+    let (whence, offset) = match pos {
+        io::SeekFrom::Start(offset) => (SEEK_SET, offset),
+        io::SeekFrom::Current(offset) => (SEEK_CUR, offset as u64),
+        io::SeekFrom::End(offset) => (SEEK_END, offset as u64),
+    };
+    let mut position: u64;
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("mov x0, $3
+              mov x1, $4
+              mov x2, $5
+              svc $2
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(position), "=r"(ecode)
+             : "i"(NR_SEEK), "r"(fd.raw()), "r"(whence), "r"(offset)
+             : "x0", "x1", "x2", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, position)
+}
+
+pub fn stat(path: &str) -> OsResult<Stat> {
+    let ptr = path.as_ptr() as u64;
+    let len = path.len();
+    let mut stat = Stat::default();
+    let stat_addr = &mut stat as *mut Stat as u64;
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("mov x0, $2
+              mov x1, $3
+              mov x2, $4
+              svc $1
+              mov $0, x7"
+             : "=r"(ecode)
+             : "i"(NR_STAT), "r"(ptr), "r"(len), "r"(stat_addr)
+             : "x0", "x1", "x2", "x7", "memory"
+             : "volatile");
+    }
+    err_or!(ecode, stat)
+}
+
+pub fn fstat(fd: FileDescriptor) -> OsResult<Stat> {
+    let mut stat = Stat::default();
+    let stat_addr = &mut stat as *mut Stat as u64;
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("mov x0, $2
+              mov x1, $3
+              svc $1
+              mov $0, x7"
+             : "=r"(ecode)
+             : "i"(NR_FSTAT), "r"(fd.raw()), "r"(stat_addr)
+             : "x0", "x1", "x7", "memory"
+             : "volatile");
+    }
+    err_or!(ecode, stat)
+}
+
+/// ディレクトリ `fd` の次のエントリを `entry` に読み込む. 最後のエントリを
+/// 読み終えている場合は `false` を返す。
+pub fn readdir(fd: FileDescriptor, entry: &mut DirEnt) -> OsResult<bool> {
+    let entry_addr = entry as *mut DirEnt as u64;
+    let mut found: u64;
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("mov x0, $3
+              mov x1, $4
+              svc $2
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(found), "=r"(ecode)
+             : "i"(NR_READDIR), "r"(fd.raw()), "r"(entry_addr)
+             : "x0", "x1", "x7", "memory"
+             : "volatile");
+    }
+    err_or!(ecode, found != 0)
+}
+
+/// カレントディレクトリのパスを `buf` に書き込んで返す.
+pub fn getcwd(buf: &mut [u8]) -> OsResult<&str> {
+    let buf_addr = buf.as_mut_ptr() as u64;
+    let buf_len = buf.len();
+    let mut len: u64;
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("mov x0, $3
+              mov x1, $4
+              svc $2
+              mov $0, x0
+              mov $1, x7"
+             : "=r"(len), "=r"(ecode)
+             : "i"(NR_GETCWD), "r"(buf_addr), "r"(buf_len)
+             : "x0", "x1", "x7", "memory"
+             : "volatile");
+    }
+    err_or!(ecode, ())?;
+    core::str::from_utf8(&buf[..len as usize]).map_err(|_| OsError::InvalidArgument)
+}
+
+pub fn chdir(path: &str) -> OsResult<()> {
+    let ptr = path.as_ptr() as u64;
+    let len = path.len();
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("mov x0, $2
+              mov x1, $3
+              svc $1
+              mov $0, x7"
+             : "=r"(ecode)
+             : "i"(NR_CHDIR), "r"(ptr), "r"(len)
+             : "x0", "x1", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, ())
+}
+
+pub fn mkdir(path: &str) -> OsResult<()> {
+    let ptr = path.as_ptr() as u64;
+    let len = path.len();
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("mov x0, $2
+              mov x1, $3
+              svc $1
+              mov $0, x7"
+             : "=r"(ecode)
+             : "i"(NR_MKDIR), "r"(ptr), "r"(len)
+             : "x0", "x1", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, ())
+}
+
+pub fn rmdir(path: &str) -> OsResult<()> {
+    let ptr = path.as_ptr() as u64;
+    let len = path.len();
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("mov x0, $2
+              mov x1, $3
+              svc $1
+              mov $0, x7"
+             : "=r"(ecode)
+             : "i"(NR_RMDIR), "r"(ptr), "r"(len)
+             : "x0", "x1", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, ())
+}
+
+pub fn unlink(path: &str) -> OsResult<()> {
+    let ptr = path.as_ptr() as u64;
+    let len = path.len();
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("mov x0, $2
+              mov x1, $3
+              svc $1
+              mov $0, x7"
+             : "=r"(ecode)
+             : "i"(NR_UNLINK), "r"(ptr), "r"(len)
+             : "x0", "x1", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, ())
+}
+
+pub fn rename(from: &str, to: &str) -> OsResult<()> {
+    let from_ptr = from.as_ptr() as u64;
+    let from_len = from.len();
+    let to_ptr = to.as_ptr() as u64;
+    let to_len = to.len();
+    let mut ecode: u64;
+
+    unsafe {
+        asm!("mov x0, $2
+              mov x1, $3
+              mov x2, $4
+              mov x3, $5
+              svc $1
+              mov $0, x7"
+             : "=r"(ecode)
+             : "i"(NR_RENAME), "r"(from_ptr), "r"(from_len), "r"(to_ptr), "r"(to_len)
+             : "x0", "x1", "x2", "x3", "x7"
+             : "volatile");
+    }
+    err_or!(ecode, ())
+}
+
+pub fn sock_create() -> OsResult<SocketDescriptor> {
     // Lab 5 2.D
     let mut descriptor;
     let mut ecode: u64;
@@ -129,7 +573,7 @@ pub fn sock_create() -> SocketDescriptor {
              : "x0", "x7"
              : "volatile");
     }
-    SocketDescriptor(descriptor)
+    err_or!(ecode, FileDescriptor(descriptor))
 }
 
 pub fn sock_status(descriptor: SocketDescriptor) -> OsResult<SocketStatus> {
@@ -149,7 +593,7 @@ pub fn sock_status(descriptor: SocketDescriptor) -> OsResult<SocketStatus> {
               mov $3, x3
               mov $4, x7"
              : "=r"(is_active), "=r"(is_listening), "=r"(can_send), "=r"(can_recv), "=r"(ecode)
-             : "i"(NR_SOCK_CREATE), "r"(descriptor.raw())
+             : "i"(NR_SOCK_STATUS), "r"(descriptor.raw())
              : "x0", "x1", "x2", "x3", "x7"
              : "volatile");
     }
@@ -0,0 +1,111 @@
+use std::fs::OpenOptions;
+use std::path::PathBuf;
+use std::process;
+
+use structopt::StructOpt;
+
+use fat32::mkfs::{self, FormatOptions};
+
+const SECTOR_SIZE: u64 = 512;
+
+fn parse_size(s: &str) -> Result<u64, String> {
+    let (digits, unit) = match s.char_indices().find(|&(_, c)| !c.is_ascii_digit()) {
+        Some((i, _)) => s.split_at(i),
+        None => (s, ""),
+    };
+    let value: u64 = digits.parse().map_err(|e| format!("{}", e))?;
+    let unit = match unit {
+        "" => 1,
+        "K" | "k" => 1 << 10,
+        "M" | "m" => 1 << 20,
+        "G" | "g" => 1 << 30,
+        _ => return Err(format!("unknown size suffix: {}", unit)),
+    };
+    Ok(value * unit)
+}
+
+fn parse_label(s: &str) -> Result<[u8; 11], String> {
+    if s.len() > 11 || !s.is_ascii() {
+        return Err("label must be at most 11 ASCII characters".into());
+    }
+    let mut label = [b' '; 11];
+    label[..s.len()].copy_from_slice(s.to_ascii_uppercase().as_bytes());
+    Ok(label)
+}
+
+fn parse_hex(s: &str) -> Result<u32, String> {
+    u32::from_str_radix(s.trim_start_matches("0x"), 16).map_err(|e| format!("{}", e))
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "Create a FAT32 file system on a disk image.")]
+struct Opt {
+    #[structopt(short = "s", long = "size", parse(try_from_str = "parse_size"),
+                help = "Size of the image (e.g. 64M); defaults to the current size")]
+    size: Option<u64>,
+
+    #[structopt(short = "c", long = "sectors-per-cluster",
+                help = "Sectors per cluster; chosen from the volume size by default")]
+    sectors_per_cluster: Option<u8>,
+
+    #[structopt(short = "f", long = "fats", default_value = "2", help = "Number of FATs")]
+    fat_count: u8,
+
+    #[structopt(short = "L", long = "label", parse(try_from_str = "parse_label"),
+                help = "Volume label")]
+    label: Option<[u8; 11]>,
+
+    #[structopt(short = "i", long = "volume-id", parse(try_from_str = "parse_hex"),
+                help = "Volume serial number in hex")]
+    volume_id: Option<u32>,
+
+    #[structopt(help = "Path to disk image", parse(from_os_str))]
+    image: PathBuf,
+}
+
+fn main() {
+    let opt = Opt::from_args();
+
+    let file = match OpenOptions::new().read(true).write(true).create(true).open(&opt.image) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("{}: {}", opt.image.display(), e);
+            process::exit(1);
+        }
+    };
+
+    let size = match opt.size {
+        Some(size) => file.set_len(size).map(|_| size),
+        None => file.metadata().map(|m| m.len()),
+    };
+    let size = match size {
+        Ok(size) => size,
+        Err(e) => {
+            eprintln!("{}: {}", opt.image.display(), e);
+            process::exit(1);
+        }
+    };
+
+    let mut options = FormatOptions::default();
+    options.sectors_per_cluster = opt.sectors_per_cluster;
+    options.fat_count = opt.fat_count;
+    if let Some(label) = opt.label {
+        options.volume_label = label;
+    }
+    if let Some(volume_id) = opt.volume_id {
+        options.volume_id = volume_id;
+    }
+
+    match mkfs::format(file, size / SECTOR_SIZE, &options) {
+        Ok(geometry) => {
+            println!("{}: {} clusters of {} bytes, {} sectors per FAT, partition at sector {}",
+                opt.image.display(), geometry.cluster_count,
+                geometry.sectors_per_cluster as u64 * SECTOR_SIZE,
+                geometry.sectors_per_fat, geometry.partition_start);
+        }
+        Err(e) => {
+            eprintln!("{}: format failed: {}", opt.image.display(), e);
+            process::exit(1);
+        }
+    }
+}
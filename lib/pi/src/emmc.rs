@@ -0,0 +1,509 @@
+use core::fmt;
+use core::time::Duration;
+
+use shim::io;
+use shim::ioerr;
+
+use volatile::prelude::*;
+use volatile::{ReadVolatile, Reserved, Volatile};
+
+use crate::common::IO_BASE;
+use crate::gpio::{Function, Gpio, Pull};
+use crate::timer;
+
+/// `EMMC` レジスタの基底アドレス.
+const EMMC_REG_BASE: usize = IO_BASE + 0x300000;
+
+/// SDカードのブロックサイズ.
+pub const BLOCK_SIZE: usize = 512;
+
+/// 1回のコマンドで転送できる最大のブロック数 (`BLKSIZECNT` のビット31:16).
+pub const MAX_BLOCKS: usize = 0xFFFF;
+
+/// コマンドおよびデータ転送のタイムアウト.
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+/// カードの初期化 (ACMD41) を待つ時間の上限.
+const INIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// EMMCコントローラのベースクロック (Hz).
+const BASE_CLOCK: u32 = 41_666_666;
+
+/// カード識別時のクロック (Hz).
+const CLOCK_ID: u32 = 400_000;
+/// デフォルトスピードモードのクロック (Hz).
+const CLOCK_NORMAL: u32 = 25_000_000;
+/// ハイスピードモードのクロック (Hz).
+const CLOCK_HIGH: u32 = 50_000_000;
+
+/// カード検出に使用するGPIOピン.
+const GPIO_CD: u8 = 47;
+/// CLK, CMD, DAT0-3 に使用するGPIOピン.
+const GPIO_DATA: [u8; 6] = [48, 49, 50, 51, 52, 53];
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct Registers {
+    ARG2: Volatile<u32>,
+    BLKSIZECNT: Volatile<u32>,
+    ARG1: Volatile<u32>,
+    CMDTM: Volatile<u32>,
+    RESP: [ReadVolatile<u32>; 4],
+    DATA: Volatile<u32>,
+    STATUS: ReadVolatile<u32>,
+    CONTROL0: Volatile<u32>,
+    CONTROL1: Volatile<u32>,
+    INTERRUPT: Volatile<u32>,
+    IRPT_MASK: Volatile<u32>,
+    IRPT_EN: Volatile<u32>,
+    CONTROL2: Volatile<u32>,
+    __r0: [Reserved<u32>; 47],
+    SLOTISR_VER: ReadVolatile<u32>,
+}
+
+/// `STATUS` レジスタのビットフィールド.
+#[repr(u32)]
+enum Status {
+    CmdInhibit = 1 << 0,
+    DatInhibit = 1 << 1,
+}
+
+/// `CONTROL0` レジスタのビットフィールド.
+#[repr(u32)]
+enum Control0 {
+    /// 4ビットデータバス.
+    Bus4Bit = 1 << 1,
+    /// ハイスピードモード.
+    HighSpeed = 1 << 2,
+}
+
+/// `CONTROL1` レジスタのビットフィールド.
+#[repr(u32)]
+enum Control1 {
+    ClockInternal = 1 << 0,
+    ClockStable = 1 << 1,
+    ClockEnable = 1 << 2,
+    /// データタイムアウトの最大値 (TMCLK * 2^27).
+    TimeoutMax = 0xE << 16,
+    ResetHost = 1 << 24,
+    ResetCmd = 1 << 25,
+    ResetData = 1 << 26,
+}
+
+/// `INTERRUPT` レジスタのビットフィールド.
+#[repr(u32)]
+enum Interrupt {
+    CmdDone = 1 << 0,
+    DataDone = 1 << 1,
+    WriteReady = 1 << 4,
+    ReadReady = 1 << 5,
+    CmdTimeout = 1 << 16,
+    DataTimeout = 1 << 20,
+    ErrorMask = 0x017F_8000,
+}
+
+/// `CMDTM` レジスタに書き込むコマンド.
+///
+/// ビット29:24がコマンド番号、ビット17:16がレスポンスの種類、ビット21が
+/// データ転送の有無、ビット4が転送方向（1: カードからホスト）を表す。
+/// ビット5はマルチブロック転送、ビット1はブロックカウントの有効化、
+/// ビット3:2はCMD12の自動発行を指定する。
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum Command {
+    GoIdle = 0x0000_0000,
+    AllSendCid = 0x0201_0000,
+    SendRelativeAddr = 0x0302_0000,
+    SwitchFunc = 0x0622_0010,
+    CardSelect = 0x0703_0000,
+    SendIfCond = 0x0802_0000,
+    SendStatus = 0x0D02_0000,
+    ReadSingle = 0x1122_0010,
+    ReadMulti = 0x1222_0036,
+    WriteSingle = 0x1822_0000,
+    WriteMulti = 0x1922_0026,
+    AppCmd = 0x3702_0000,
+}
+
+/// `AppCmd` (CMD55) に続けて送信するアプリケーション固有コマンド.
+#[repr(u32)]
+#[derive(Copy, Clone)]
+enum AppCommand {
+    SetBusWidth = 0x0602_0000,
+    SendOpCond = 0x2902_0000,
+    SendScr = 0x3322_0010,
+}
+
+/// EMMCコントローラへのハンドル.
+pub struct Emmc {
+    registers: &'static mut Registers,
+    /// SDHCIの仕様バージョン (`SLOTISR_VER` のビット23:16).
+    version: u32,
+    /// カードの相対アドレス (RCA). ビット31:16に格納される.
+    rca: u32,
+    /// SDHC/SDXCカードはブロック番号で、SDSCカードはバイト単位で
+    /// アドレスを指定する.
+    block_addressing: bool,
+    /// 4ビットバスで動作しているか否か.
+    bus_4bit: bool,
+    /// ハイスピードモードで動作しているか否か.
+    high_speed: bool,
+}
+
+impl Emmc {
+    /// EMMCコントローラとSDカードを初期化してそのハンドルを返す.
+    ///
+    /// GPIOピンをSDカード用に設定してコントローラをリセットした後、
+    /// カードの識別 (CMD0, CMD8, ACMD41, CMD2, CMD3) を行ってカードを
+    /// 選択する。カードが対応していれば4ビットバスとハイスピードモードに
+    /// 切り替える。
+    ///
+    /// # エラー
+    ///
+    /// カードが応答しない場合はエラー種別 `TimedOut` の、
+    /// コントローラがエラーを報告した場合は `BrokenPipe` のI/Oエラーが
+    /// 返される。カードが対応していない電圧範囲を報告した場合は
+    /// `Other` のエラーが返される。
+    ///
+    /// # 安全性
+    ///
+    /// callerはこのメソッドがカーネルの初期化中に一度だけ呼び出される
+    /// ことを保証しなければならない。
+    pub unsafe fn new() -> io::Result<Emmc> {
+        let mut cd = Gpio::new(GPIO_CD);
+        cd.set_pull(Pull::Up);
+        cd.into_input();
+        for &pin in GPIO_DATA.iter() {
+            let mut gpio = Gpio::new(pin);
+            gpio.set_pull(Pull::Up);
+            gpio.into_alt(Function::Alt3);
+        }
+
+        let registers = &mut *(EMMC_REG_BASE as *mut Registers);
+        let version = (registers.SLOTISR_VER.read() >> 16) & 0xFF;
+        let mut emmc = Emmc {
+            registers,
+            version,
+            rca: 0,
+            block_addressing: false,
+            bus_4bit: false,
+            high_speed: false,
+        };
+
+        emmc.reset()?;
+        emmc.identify()?;
+        emmc.set_clock(CLOCK_NORMAL)?;
+        emmc.send_command(Command::CardSelect, emmc.rca)?;
+        emmc.configure_bus()?;
+
+        Ok(emmc)
+    }
+
+    /// コントローラをリセットして識別用のクロックを供給し、割り込みを
+    /// 有効にする.
+    fn reset(&mut self) -> io::Result<()> {
+        self.registers.CONTROL0.write(0);
+        self.registers.CONTROL1.or_mask(Control1::ResetHost as u32);
+        self.wait_control1(Control1::ResetHost as u32)?;
+
+        self.registers.CONTROL1.or_mask(Control1::ClockInternal as u32 | Control1::TimeoutMax as u32);
+        timer::spin_sleep(Duration::from_millis(10));
+        self.set_clock(CLOCK_ID)?;
+
+        self.registers.IRPT_EN.write(0xFFFF_FFFF);
+        self.registers.IRPT_MASK.write(0xFFFF_FFFF);
+        Ok(())
+    }
+
+    /// カードを識別してRCAとアドレス指定方式を決定する.
+    fn identify(&mut self) -> io::Result<()> {
+        self.send_command(Command::GoIdle, 0)?;
+
+        // 電圧範囲 2.7-3.6V とチェックパターン 0xAA. SD 1.x のカードは
+        // CMD8 に応答しないので、その場合は HCS を立てずに続行する
+        let v2 = match self.send_command(Command::SendIfCond, 0x1AA) {
+            Ok(resp) if resp & 0xFFF == 0x1AA => true,
+            Ok(_) => return ioerr!(Other, "sd card voltage not supported"),
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut => false,
+            Err(e) => return Err(e),
+        };
+
+        // HCS (ビット30), XPC (ビット28), 3.2-3.4V
+        let arg = if v2 { 0x5030_0000 } else { 0x0030_0000 };
+        let end = timer::current_time() + INIT_TIMEOUT;
+        let ocr = loop {
+            let ocr = self.send_app_command(AppCommand::SendOpCond, arg)?;
+            // ビット31: 電源投入シーケンスの完了
+            if ocr & (1 << 31) != 0 {
+                break ocr;
+            }
+            if timer::current_time() >= end {
+                return ioerr!(TimedOut, "sd card initialization timed out");
+            }
+            timer::spin_sleep(Duration::from_millis(10));
+        };
+        // ビット30: CCS (Card Capacity Status)
+        self.block_addressing = ocr & (1 << 30) != 0;
+
+        self.send_command(Command::AllSendCid, 0)?;
+        self.rca = self.send_command(Command::SendRelativeAddr, 0)? & 0xFFFF_0000;
+        Ok(())
+    }
+
+    /// SCRを読み込み、カードが対応していれば4ビットバスと
+    /// ハイスピードモードに切り替える.
+    fn configure_bus(&mut self) -> io::Result<()> {
+        let mut scr = [0u8; 8];
+        self.registers.BLKSIZECNT.write((1 << 16) | scr.len() as u32);
+        self.send_app_command(AppCommand::SendScr, 0)?;
+        self.read_data(&mut scr)?;
+
+        // SCRはビッグエンディアン. SD_SPEC はビット59:56、
+        // SD_BUS_WIDTHS はビット51:48 (ビット50が4ビット対応)
+        let sd_spec = scr[0] & 0xF;
+        if scr[1] & (1 << 2) != 0 {
+            self.send_app_command(AppCommand::SetBusWidth, 2)?;
+            self.registers.CONTROL0.or_mask(Control0::Bus4Bit as u32);
+            self.bus_4bit = true;
+        }
+
+        // CMD6 は SD 1.10 以降で対応している
+        if sd_spec >= 1 {
+            let mut status = [0u8; 64];
+            self.registers.BLKSIZECNT.write((1 << 16) | status.len() as u32);
+            // モード1 (切り替え), グループ1 を機能1 (ハイスピード) に
+            self.send_command(Command::SwitchFunc, 0x80FF_FFF1)?;
+            self.read_data(&mut status)?;
+
+            // ステータスのビット379:376が切り替え後のグループ1の機能
+            if status[16] & 0xF == 1 {
+                self.registers.CONTROL0.or_mask(Control0::HighSpeed as u32);
+                self.set_clock(CLOCK_HIGH)?;
+                self.high_speed = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// SDクロックを `freq` 以下の最大の周波数に設定する.
+    fn set_clock(&mut self, freq: u32) -> io::Result<()> {
+        self.wait_status(Status::CmdInhibit as u32 | Status::DatInhibit as u32)?;
+
+        self.registers.CONTROL1.and_mask(!(Control1::ClockEnable as u32));
+        timer::spin_sleep(Duration::from_millis(10));
+
+        // SDクロック = ベースクロック / (2 * div)
+        let mut div = (BASE_CLOCK + 2 * freq - 1) / (2 * freq);
+        if self.version < 2 {
+            // SDHCI 2.0 以前は2の冪の8ビット分周のみ
+            div = div.next_power_of_two().min(0x80);
+        } else {
+            div = div.min(0x3FF);
+        }
+
+        let bits = ((div & 0xFF) << 8) | (((div >> 8) & 0x3) << 6);
+        self.registers.CONTROL1.and_mask(!0xFFE0);
+        self.registers.CONTROL1.or_mask(bits);
+        timer::spin_sleep(Duration::from_millis(10));
+
+        self.registers.CONTROL1.or_mask(Control1::ClockEnable as u32);
+        let end = timer::current_time() + TIMEOUT;
+        while self.registers.CONTROL1.read() & Control1::ClockStable as u32 == 0 {
+            if timer::current_time() >= end {
+                return ioerr!(TimedOut, "emmc clock not stable");
+            }
+        }
+        Ok(())
+    }
+
+    /// `CONTROL1` レジスタの `mask` がクリアされるまで待つ.
+    fn wait_control1(&self, mask: u32) -> io::Result<()> {
+        let end = timer::current_time() + TIMEOUT;
+        while self.registers.CONTROL1.read() & mask != 0 {
+            if timer::current_time() >= end {
+                return ioerr!(TimedOut, "emmc reset timed out");
+            }
+        }
+        Ok(())
+    }
+
+    /// エラー発生後にコマンド回路とデータ回路をリセットする.
+    fn reset_lines(&mut self) {
+        self.registers.CONTROL1.or_mask(Control1::ResetCmd as u32 | Control1::ResetData as u32);
+        let _ = self.wait_control1(Control1::ResetCmd as u32 | Control1::ResetData as u32);
+    }
+
+    /// `mask` のいずれかの割り込みが発生するまで待ち、発生した割り込みを
+    /// クリアする.
+    fn wait_interrupt(&mut self, mask: u32) -> io::Result<()> {
+        let end = timer::current_time() + TIMEOUT;
+        let mask = mask | Interrupt::ErrorMask as u32;
+
+        let mut flags = self.registers.INTERRUPT.read();
+        while flags & mask == 0 {
+            if timer::current_time() >= end {
+                self.reset_lines();
+                return ioerr!(TimedOut, "emmc interrupt timed out");
+            }
+            flags = self.registers.INTERRUPT.read();
+        }
+        self.registers.INTERRUPT.write(flags & mask);
+
+        if flags & (Interrupt::CmdTimeout as u32 | Interrupt::DataTimeout as u32) != 0 {
+            self.reset_lines();
+            return ioerr!(TimedOut, "emmc command timed out");
+        }
+        if flags & Interrupt::ErrorMask as u32 != 0 {
+            self.reset_lines();
+            return ioerr!(BrokenPipe, "emmc command failed");
+        }
+        Ok(())
+    }
+
+    /// `STATUS` レジスタの `mask` がクリアされるまで待つ.
+    fn wait_status(&self, mask: u32) -> io::Result<()> {
+        let end = timer::current_time() + TIMEOUT;
+        while self.registers.STATUS.read() & mask != 0 {
+            if timer::current_time() >= end {
+                return ioerr!(TimedOut, "emmc busy");
+            }
+        }
+        Ok(())
+    }
+
+    /// コマンド `cmd` を引数 `arg` で送信し、レスポンスの最初のワードを
+    /// 返す.
+    fn send_command(&mut self, cmd: Command, arg: u32) -> io::Result<u32> {
+        self.send_raw(cmd as u32, arg)
+    }
+
+    /// `AppCmd` に続けてアプリケーション固有コマンド `cmd` を送信する.
+    fn send_app_command(&mut self, cmd: AppCommand, arg: u32) -> io::Result<u32> {
+        self.send_command(Command::AppCmd, self.rca)?;
+        self.send_raw(cmd as u32, arg)
+    }
+
+    fn send_raw(&mut self, cmdtm: u32, arg: u32) -> io::Result<u32> {
+        self.wait_status(Status::CmdInhibit as u32)?;
+
+        self.registers.INTERRUPT.write(self.registers.INTERRUPT.read());
+        self.registers.ARG1.write(arg);
+        self.registers.CMDTM.write(cmdtm);
+        self.wait_interrupt(Interrupt::CmdDone as u32)?;
+
+        Ok(self.registers.RESP[0].read())
+    }
+
+    /// 1ブロック分のデータを `DATA` レジスタから `buf` に読み込み、
+    /// 転送の完了を待つ. `buf.len()` は4の倍数でなければならない.
+    fn read_data(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.wait_interrupt(Interrupt::ReadReady as u32)?;
+        for word in buf.chunks_mut(4) {
+            word.copy_from_slice(&self.registers.DATA.read().to_le_bytes());
+        }
+        self.wait_interrupt(Interrupt::DataDone as u32)
+    }
+
+    /// ブロック `n` から始まる `count` ブロックを転送するための
+    /// カード上のアドレスを返す.
+    fn address(&self, n: u64, count: usize) -> io::Result<u32> {
+        if count == 0 || count > MAX_BLOCKS {
+            return ioerr!(InvalidInput, "invalid buf len");
+        }
+
+        let last = n + count as u64 - 1;
+        let (addr, last) = if self.block_addressing {
+            (n, last)
+        } else {
+            (n * BLOCK_SIZE as u64, last * BLOCK_SIZE as u64)
+        };
+        if last > u32::max_value() as u64 {
+            return ioerr!(InvalidInput, "invalid sector number");
+        }
+        Ok(addr as u32)
+    }
+
+    /// ブロック `n` から `buf.len() / BLOCK_SIZE` ブロックを `buf` に
+    /// 読み込む. 複数のブロックは1回のコマンド (CMD18) でまとめて
+    /// 転送される。読み込んだバイト数が返される。
+    ///
+    /// # エラー
+    ///
+    /// `buf.len() < BLOCK_SIZE` の場合、転送するブロック数が
+    /// `MAX_BLOCKS` を超える場合、またはブロック番号がカードの
+    /// アドレス範囲外の場合はエラー種別 `InvalidInput` のI/Oエラーが
+    /// 返される。
+    ///
+    /// コマンドの送信中またはデータ転送中にタイムアウトが発生した場合は
+    /// `TimedOut` のエラーが、コントローラがエラーを報告した場合は
+    /// `BrokenPipe` のエラーが返される。
+    pub fn read_blocks(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let count = buf.len() / BLOCK_SIZE;
+        let addr = self.address(n, count)?;
+
+        self.wait_status(Status::DatInhibit as u32)?;
+        self.registers.BLKSIZECNT.write(((count as u32) << 16) | BLOCK_SIZE as u32);
+        let cmd = if count == 1 { Command::ReadSingle } else { Command::ReadMulti };
+        self.send_command(cmd, addr)?;
+
+        for block in buf[..count * BLOCK_SIZE].chunks_mut(BLOCK_SIZE) {
+            self.wait_interrupt(Interrupt::ReadReady as u32)?;
+            for word in block.chunks_mut(4) {
+                word.copy_from_slice(&self.registers.DATA.read().to_le_bytes());
+            }
+        }
+        self.wait_interrupt(Interrupt::DataDone as u32)?;
+
+        Ok(count * BLOCK_SIZE)
+    }
+
+    /// `buf` の内容でブロック `n` から `buf.len() / BLOCK_SIZE` ブロックを
+    /// 上書きする. 複数のブロックは1回のコマンド (CMD25) でまとめて
+    /// 転送される。書き込んだバイト数が返される。
+    ///
+    /// # エラー
+    ///
+    /// `read_blocks()` と同様.
+    pub fn write_blocks(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        let count = buf.len() / BLOCK_SIZE;
+        let addr = self.address(n, count)?;
+
+        self.wait_status(Status::DatInhibit as u32)?;
+        self.registers.BLKSIZECNT.write(((count as u32) << 16) | BLOCK_SIZE as u32);
+        let cmd = if count == 1 { Command::WriteSingle } else { Command::WriteMulti };
+        self.send_command(cmd, addr)?;
+
+        for block in buf[..count * BLOCK_SIZE].chunks(BLOCK_SIZE) {
+            self.wait_interrupt(Interrupt::WriteReady as u32)?;
+            for word in block.chunks(4) {
+                self.registers.DATA.write(u32::from_le_bytes([word[0], word[1], word[2], word[3]]));
+            }
+        }
+        self.wait_interrupt(Interrupt::DataDone as u32)?;
+
+        // プログラミングが完了してカードが転送状態に戻るまで待つ
+        let end = timer::current_time() + TIMEOUT;
+        loop {
+            let status = self.send_command(Command::SendStatus, self.rca)?;
+            // ビット12:9 が CURRENT_STATE、4 (tran) で書き込み完了
+            if (status >> 9) & 0xF == 4 {
+                return Ok(count * BLOCK_SIZE);
+            }
+            if timer::current_time() >= end {
+                return ioerr!(TimedOut, "sd write programming timed out");
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Emmc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Emmc")
+            .field("version", &self.version)
+            .field("rca", &self.rca)
+            .field("block_addressing", &self.block_addressing)
+            .field("bus_4bit", &self.bus_4bit)
+            .field("high_speed", &self.high_speed)
+            .finish()
+    }
+}
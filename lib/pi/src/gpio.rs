@@ -1,6 +1,8 @@
 use core::marker::PhantomData;
+use core::time::Duration;
 
 use crate::common::{states, GPIO_BASE};
+use crate::timer;
 use volatile::prelude::*;
 use volatile::{ReadVolatile, Reserved, Volatile, WriteVolatile};
 
@@ -17,6 +19,14 @@ pub enum Function {
     Alt5 = 0b010,
 }
 
+/// GPIOのプルアップ/プルダウン制御.
+#[repr(u8)]
+pub enum Pull {
+    Off = 0b00,
+    Down = 0b01,
+    Up = 0b10,
+}
+
 #[repr(C)]
 #[allow(non_snake_case)]
 struct Registers {
@@ -77,6 +87,22 @@ impl<T> Gpio<T> {
             _state: PhantomData,
         }
     }
+
+    /// このピンのプルアップ/プルダウンを `pull` に設定する.
+    ///
+    /// `PUD` に制御値を書き込んだ後 `PUDCLK` で対象のピンにクロックを
+    /// 与える。各ステップの間には150サイクル以上待つ必要がある。
+    pub fn set_pull(&mut self, pull: Pull) {
+        let g = self.pin as usize / 32;
+        let bit = 1u32 << (self.pin as usize % 32);
+
+        self.registers.PUD.write(pull as u32);
+        timer::spin_sleep(Duration::from_micros(1));
+        self.registers.PUDCLK[g].write(bit);
+        timer::spin_sleep(Duration::from_micros(1));
+        self.registers.PUD.write(0);
+        self.registers.PUDCLK[g].write(0);
+    }
 }
 
 impl Gpio<Uninitialized> {
@@ -8,8 +8,10 @@
 
 pub mod atags;
 pub mod common;
+pub mod emmc;
 pub mod gpio;
 pub mod interrupt;
 pub mod local_interrupt;
+pub mod rng;
 pub mod timer;
 pub mod uart;
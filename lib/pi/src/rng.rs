@@ -0,0 +1,61 @@
+use volatile::prelude::*;
+use volatile::{ReadVolatile, Reserved, Volatile};
+
+use crate::common::IO_BASE;
+
+/// ハードウェア乱数生成器レジスタの基底アドレス.
+const RNG_REG_BASE: usize = IO_BASE + 0x104000;
+
+/// 生成器を有効にしてから出力を使用するまでに捨てる乱数の数.
+const WARMUP_COUNT: u32 = 0x40000;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct Registers {
+    CTRL: Volatile<u32>,
+    STATUS: Volatile<u32>,
+    DATA: ReadVolatile<u32>,
+    __r0: Reserved<u32>,
+    INT_MASK: Volatile<u32>,
+}
+
+/// `CTRL` レジスタのビットフィールド.
+#[repr(u32)]
+enum Ctrl {
+    Enable = 1,
+}
+
+/// Raspberry Piのハードウェア乱数生成器.
+pub struct Rng {
+    registers: &'static mut Registers,
+}
+
+impl Rng {
+    /// 乱数生成器を初期化する. 割り込みをマスクし、ウォームアップの
+    /// 回数を設定して生成器を有効にする。すでに有効な場合は何もしない。
+    pub fn new() -> Rng {
+        let registers = unsafe { &mut *(RNG_REG_BASE as *mut Registers) };
+        if registers.CTRL.read() & Ctrl::Enable as u32 == 0 {
+            registers.INT_MASK.or_mask(1);
+            registers.STATUS.write(WARMUP_COUNT);
+            registers.CTRL.or_mask(Ctrl::Enable as u32);
+        }
+
+        Rng { registers }
+    }
+
+    /// 32ビットの乱数を返す. 乱数が生成されるまでブロックする。
+    pub fn next_u32(&mut self) -> u32 {
+        // STATUS のビット31:24は読み出せる乱数の数
+        while self.registers.STATUS.read() >> 24 == 0 {}
+        self.registers.DATA.read()
+    }
+
+    /// `buf` を乱数で埋める.
+    pub fn fill_bytes(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(4) {
+            let bytes = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
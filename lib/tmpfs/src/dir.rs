@@ -0,0 +1,36 @@
+use alloc::string::String;
+use alloc::vec::{self, Vec};
+
+use shim::io;
+
+use fat32::traits;
+
+use crate::tmpfs::{entry, Ino};
+use crate::{Entry, Metadata, TmpFs, TmpFsHandle};
+
+/// tmpfsのディレクトリ.
+#[derive(Debug)]
+pub struct Dir<HANDLE: TmpFsHandle> {
+    pub tmpfs: TmpFs<HANDLE>,
+    pub(crate) ino: Ino,
+    pub name: String,
+    pub metadata: Metadata,
+}
+
+impl<HANDLE: TmpFsHandle> traits::Dir for Dir<HANDLE> {
+    type Entry = Entry<HANDLE>;
+    type Iter = vec::IntoIter<Entry<HANDLE>>;
+
+    /// このディレクトリのエントリを作成順に走査するイテレータを返す.
+    /// イテレータは呼び出し時点のエントリの一覧を返す。
+    fn entries(&self) -> io::Result<Self::Iter> {
+        let tmpfs = &self.tmpfs;
+        let entries = tmpfs.lock(|fs| {
+            fs.entries(self.ino)?
+                .iter()
+                .map(|(name, ino)| entry(fs, tmpfs, *ino, name))
+                .collect::<io::Result<Vec<_>>>()
+        })?;
+        Ok(entries.into_iter())
+    }
+}
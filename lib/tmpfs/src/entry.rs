@@ -0,0 +1,58 @@
+use fat32::traits;
+
+use crate::{Dir, File, Metadata, TmpFsHandle};
+
+/// tmpfsのディレクトリエントリ.
+#[derive(Debug)]
+pub enum Entry<HANDLE: TmpFsHandle> {
+    File(File<HANDLE>),
+    Dir(Dir<HANDLE>),
+}
+
+impl<HANDLE: TmpFsHandle> traits::Entry for Entry<HANDLE> {
+    type File = File<HANDLE>;
+    type Dir = Dir<HANDLE>;
+    type Metadata = Metadata;
+
+    fn name(&self) -> &str {
+        match self {
+            Entry::File(f) => f.name.as_str(),
+            Entry::Dir(d) => d.name.as_str(),
+        }
+    }
+
+    fn metadata(&self) -> &Self::Metadata {
+        match self {
+            Entry::File(f) => &f.metadata,
+            Entry::Dir(d) => &d.metadata,
+        }
+    }
+
+    fn as_file(&self) -> Option<&File<HANDLE>> {
+        match self {
+            Entry::File(f) => Some(f),
+            Entry::Dir(_) => None,
+        }
+    }
+
+    fn as_dir(&self) -> Option<&Dir<HANDLE>> {
+        match self {
+            Entry::File(_) => None,
+            Entry::Dir(d) => Some(d),
+        }
+    }
+
+    fn into_file(self) -> Option<File<HANDLE>> {
+        match self {
+            Entry::File(f) => Some(f),
+            Entry::Dir(_) => None,
+        }
+    }
+
+    fn into_dir(self) -> Option<Dir<HANDLE>> {
+        match self {
+            Entry::File(_) => None,
+            Entry::Dir(d) => Some(d),
+        }
+    }
+}
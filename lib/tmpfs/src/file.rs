@@ -0,0 +1,104 @@
+use alloc::string::String;
+
+use shim::io::{self, SeekFrom};
+use shim::ioerr;
+
+use fat32::traits;
+
+use crate::tmpfs::Ino;
+use crate::{Metadata, TmpFs, TmpFsHandle};
+
+/// tmpfsのファイル. 読み書きの位置はファイルごとに保持される。
+#[derive(Debug)]
+pub struct File<HANDLE: TmpFsHandle> {
+    pub tmpfs: TmpFs<HANDLE>,
+    pub(crate) ino: Ino,
+    pub name: String,
+    pub metadata: Metadata,
+    offset: u64,
+}
+
+impl<HANDLE: TmpFsHandle> File<HANDLE> {
+    pub(crate) fn new(tmpfs: TmpFs<HANDLE>, ino: Ino, name: String,
+        metadata: Metadata) -> File<HANDLE> {
+        File { tmpfs, ino, name, metadata, offset: 0 }
+    }
+
+    /// ファイルのサイズを `size` バイトに変更する. 伸ばした部分は
+    /// ゼロで埋められる。現在位置は変更されない。
+    ///
+    /// # エラー
+    ///
+    /// 容量の上限を超える場合は `Other` を、ファイルが削除されている
+    /// 場合は `NotFound` を返す。
+    pub fn set_len(&mut self, size: u64) -> io::Result<()> {
+        let ino = self.ino;
+        self.tmpfs.lock(|fs| fs.set_len(ino, size))
+    }
+}
+
+impl<HANDLE: TmpFsHandle> traits::File for File<HANDLE> {
+    /// 内容はメモリ上にしかないため何もしない.
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// ファイルのバイト単位のサイズを返す. ファイルが削除されている
+    /// 場合は0を返す。
+    fn size(&self) -> u64 {
+        self.tmpfs.lock(|fs| fs.len(self.ino)).unwrap_or(0)
+    }
+}
+
+impl<HANDLE: TmpFsHandle> io::Read for File<HANDLE> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (ino, offset) = (self.ino, self.offset);
+        let read = self.tmpfs.lock(|fs| fs.read(ino, offset, buf))?;
+        self.offset += read as u64;
+        Ok(read)
+    }
+}
+
+impl<HANDLE: TmpFsHandle> io::Write for File<HANDLE> {
+    /// ファイルの現在位置に `buf` を書き込む. ファイル終端を超えて
+    /// 書き込んだ場合はファイルが伸びる。
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let (ino, offset) = (self.ino, self.offset);
+        let wrote = self.tmpfs.lock(|fs| fs.write(ino, offset, buf))?;
+        self.offset += wrote as u64;
+        Ok(wrote)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<HANDLE: TmpFsHandle> io::Seek for File<HANDLE> {
+    /// ファイル内のオフセット `pos` にシークする.
+    ///
+    /// # エラー
+    ///
+    /// ファイルの先頭より前、または終端より後ろにシークしようとした場合は
+    /// `InvalidInput` を返す。ファイルを伸ばすには `set_len()` を使う。
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let size = traits::File::size(self);
+        let (base, delta) = match pos {
+            SeekFrom::Start(offset) => (offset, 0),
+            SeekFrom::End(delta) => (size, delta),
+            SeekFrom::Current(delta) => (self.offset, delta),
+        };
+        let offset = if delta >= 0 {
+            base.checked_add(delta as u64)
+        } else {
+            base.checked_sub(delta.wrapping_neg() as u64)
+        };
+        match offset {
+            Some(offset) if offset <= size => {
+                self.offset = offset;
+                Ok(offset)
+            }
+            _ => ioerr!(InvalidInput, "seek outside of file"),
+        }
+    }
+}
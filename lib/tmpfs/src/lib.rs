@@ -0,0 +1,21 @@
+#![cfg_attr(feature = "no_std", no_std)]
+
+#[cfg(not(feature = "no_std"))]
+extern crate core;
+
+extern crate alloc;
+
+#[cfg(test)]
+mod tests;
+
+mod dir;
+mod entry;
+mod file;
+mod metadata;
+mod tmpfs;
+
+pub use crate::dir::Dir;
+pub use crate::entry::Entry;
+pub use crate::file::File;
+pub use crate::metadata::{Metadata, Timestamp};
+pub use crate::tmpfs::{TmpFs, TmpFsHandle, TmpFsState};
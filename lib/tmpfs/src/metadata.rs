@@ -0,0 +1,99 @@
+use fat32::traits;
+
+/// tmpfsのタイムスタンプ.
+///
+/// tmpfsは時計を持たないため、すべてのタイムスタンプはUNIXエポック
+/// (1970-01-01 00:00:00) である。
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Timestamp {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl Default for Timestamp {
+    fn default() -> Timestamp {
+        Timestamp {
+            year: 1970,
+            month: 1,
+            day: 1,
+            hour: 0,
+            minute: 0,
+            second: 0,
+        }
+    }
+}
+
+impl traits::Timestamp for Timestamp {
+    fn year(&self) -> usize {
+        self.year as usize
+    }
+
+    fn month(&self) -> u8 {
+        self.month
+    }
+
+    fn day(&self) -> u8 {
+        self.day
+    }
+
+    fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    fn second(&self) -> u8 {
+        self.second
+    }
+}
+
+/// tmpfsのエントリのメタデータ.
+#[derive(Default, Debug, Clone)]
+pub struct Metadata {
+    pub read_only: bool,
+    pub hidden: bool,
+    pub created: Timestamp,
+    pub accessed: Timestamp,
+    pub modified: Timestamp,
+}
+
+impl Metadata {
+    /// 名前 `name` のエントリのメタデータを返す. UNIXの慣習に従い、
+    /// `.` で始まる名前のエントリは隠しエントリとする。
+    pub(crate) fn for_name(name: &str) -> Metadata {
+        Metadata {
+            hidden: name.starts_with('.'),
+            ..Metadata::default()
+        }
+    }
+}
+
+impl traits::Metadata for Metadata {
+    type Timestamp = Timestamp;
+
+    fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn hidden(&self) -> bool {
+        self.hidden
+    }
+
+    fn created(&self) -> Self::Timestamp {
+        self.created
+    }
+
+    fn accessed(&self) -> Self::Timestamp {
+        self.accessed
+    }
+
+    fn modified(&self) -> Self::Timestamp {
+        self.modified
+    }
+}
@@ -0,0 +1,183 @@
+use std::fmt::{self, Debug};
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::sync::{Arc, Mutex};
+
+use fat32::traits::*;
+
+use crate::{TmpFs, TmpFsHandle, TmpFsState};
+
+#[derive(Clone)]
+struct StdTmpFsHandle(Arc<Mutex<TmpFsState>>);
+
+impl Debug for StdTmpFsHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "StdTmpFsHandle")
+    }
+}
+
+impl TmpFsHandle for StdTmpFsHandle {
+    fn new(val: TmpFsState) -> Self {
+        StdTmpFsHandle(Arc::new(Mutex::new(val)))
+    }
+
+    fn lock<R>(&self, f: impl FnOnce(&mut TmpFsState) -> R) -> R {
+        f(&mut self.0.lock().expect("all okay"))
+    }
+}
+
+fn entry_names(fs: &TmpFs<StdTmpFsHandle>, path: &str) -> Vec<String> {
+    fs.open_dir(path)
+        .expect("directory exists")
+        .entries()
+        .expect("entries iterator")
+        .map(|e| e.name().to_string())
+        .collect()
+}
+
+fn read_all(fs: &TmpFs<StdTmpFsHandle>, path: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    fs.open_file(path)
+        .expect("file exists")
+        .read_to_end(&mut data)
+        .expect("read file");
+    data
+}
+
+#[test]
+fn test_create_write_read() {
+    let fs: TmpFs<StdTmpFsHandle> = TmpFs::new(None);
+    assert!(entry_names(&fs, "/").is_empty());
+
+    let mut file = fs.create_file("/hello.txt").expect("create file");
+    file.write_all(b"hello, ").unwrap();
+    file.write_all(b"world").unwrap();
+    assert_eq!(file.size(), 12);
+    assert_eq!(read_all(&fs, "/hello.txt"), b"hello, world");
+
+    // Each handle keeps its own position.
+    let mut other = fs.open_file("/hello.txt").unwrap();
+    other.seek(SeekFrom::Start(7)).unwrap();
+    other.write_all(b"tmpfs").unwrap();
+    assert_eq!(read_all(&fs, "/hello.txt"), b"hello, tmpfs");
+
+    // Seeking outside the file is rejected; extending it zero-fills the gap.
+    assert!(file.seek(SeekFrom::End(2)).is_err());
+    file.set_len(14).unwrap();
+    file.seek(SeekFrom::End(0)).unwrap();
+    file.write_all(b"!").unwrap();
+    assert_eq!(read_all(&fs, "/hello.txt"), b"hello, tmpfs\0\0!");
+    assert!(file.seek(SeekFrom::Current(-100)).is_err());
+    let outside = [SeekFrom::Current(i64::MAX), SeekFrom::Start(1 << 40), SeekFrom::End(i64::MIN)];
+    for &pos in outside.iter() {
+        assert_eq!(file.seek(pos).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+    assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 15);
+
+    assert_eq!(fs.create_file("/hello.txt").unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+    assert_eq!(fs.open("/missing").unwrap_err().kind(), io::ErrorKind::NotFound);
+    assert_eq!(fs.open("/hello.txt/x").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(fs.open("relative").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_truncate() {
+    let fs: TmpFs<StdTmpFsHandle> = TmpFs::new(None);
+    let mut file = fs.create_file("/data").unwrap();
+    file.write_all(&[0xAB; 1000]).unwrap();
+    assert_eq!(fs.used_bytes(), 1000);
+
+    file.set_len(10).unwrap();
+    assert_eq!(read_all(&fs, "/data"), vec![0xAB; 10]);
+    file.set_len(12).unwrap();
+    assert_eq!(read_all(&fs, "/data"), [&[0xAB; 10][..], &[0, 0]].concat());
+    assert_eq!(fs.used_bytes(), 12);
+
+    // Resizing leaves the position alone.
+    assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 1000);
+    let mut buf = [0u8; 4];
+    assert_eq!(file.read(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn test_directories_and_remove() {
+    let fs: TmpFs<StdTmpFsHandle> = TmpFs::new(None);
+    fs.create_dir("/a").unwrap();
+    fs.create_dir("/a/b").unwrap();
+    fs.create_file("/a/b/c").unwrap().write_all(b"abc").unwrap();
+    fs.create_file("/a/.hidden").unwrap();
+    assert_eq!(entry_names(&fs, "/a"), vec!["b", ".hidden"]);
+    assert!(fs.open("/a/.hidden").unwrap().metadata().hidden());
+    assert!(fs.open("/a/b").unwrap().is_dir());
+
+    // Names are case-sensitive.
+    assert_eq!(fs.open("/A").unwrap_err().kind(), io::ErrorKind::NotFound);
+
+    assert_eq!(fs.remove("/a/b").unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    assert_eq!(fs.remove("/").unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    let mut file = fs.open_file("/a/b/c").unwrap();
+    fs.remove("/a/b/c").unwrap();
+    fs.remove("/a/b").unwrap();
+    assert_eq!(entry_names(&fs, "/a"), vec![".hidden"]);
+    assert_eq!(fs.used_bytes(), 0);
+
+    // Operations on a removed file fail.
+    let mut buf = [0u8; 3];
+    assert_eq!(file.read(&mut buf).unwrap_err().kind(), io::ErrorKind::NotFound);
+    assert_eq!(file.write(b"x").unwrap_err().kind(), io::ErrorKind::NotFound);
+
+    // A stale handle never refers to a recreated file of the same name.
+    fs.create_dir("/a/b").unwrap();
+    fs.create_file("/a/b/c").unwrap();
+    assert_eq!(file.read(&mut buf).unwrap_err().kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn test_rename() {
+    let fs: TmpFs<StdTmpFsHandle> = TmpFs::new(None);
+    fs.create_dir("/dir").unwrap();
+    fs.create_dir("/dir/sub").unwrap();
+    fs.create_file("/file").unwrap().write_all(b"contents").unwrap();
+    fs.create_file("/other").unwrap();
+
+    let mut file = fs.open_file("/file").unwrap();
+    fs.rename("/file", "/dir/sub/moved").unwrap();
+    assert_eq!(read_all(&fs, "/dir/sub/moved"), b"contents");
+    assert_eq!(fs.open("/file").unwrap_err().kind(), io::ErrorKind::NotFound);
+
+    // A handle opened before the move still refers to the same file.
+    let mut data = String::new();
+    file.read_to_string(&mut data).unwrap();
+    assert_eq!(data, "contents");
+
+    assert_eq!(fs.rename("/other", "/dir/sub/moved").unwrap_err().kind(),
+        io::ErrorKind::AlreadyExists);
+    assert_eq!(fs.rename("/dir", "/dir/sub/dir").unwrap_err().kind(),
+        io::ErrorKind::InvalidInput);
+    assert_eq!(fs.rename("/missing", "/x").unwrap_err().kind(), io::ErrorKind::NotFound);
+
+    fs.rename("/dir/sub", "/sub").unwrap();
+    assert_eq!(read_all(&fs, "/sub/moved"), b"contents");
+    assert!(entry_names(&fs, "/dir").is_empty());
+    fs.rename("/other", "/other").unwrap();
+}
+
+#[test]
+fn test_limit() {
+    let fs: TmpFs<StdTmpFsHandle> = TmpFs::new(Some(100));
+    let mut a = fs.create_file("/a").unwrap();
+    let mut b = fs.create_file("/b").unwrap();
+    a.write_all(&[1; 60]).unwrap();
+    assert!(b.write_all(&[2; 41]).is_err());
+    assert_eq!(b.size(), 0);
+    b.write_all(&[2; 40]).unwrap();
+    assert!(b.set_len(41).is_err());
+
+    // Shrinking always succeeds and frees space for reuse.
+    a.set_len(0).unwrap();
+    b.set_len(100).unwrap();
+    assert_eq!((fs.used_bytes(), fs.limit()), (100, Some(100)));
+    fs.remove("/b").unwrap();
+    assert_eq!(fs.used_bytes(), 0);
+}
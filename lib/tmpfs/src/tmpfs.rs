@@ -0,0 +1,412 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use shim::io;
+use shim::ioerr;
+use shim::path::{Component, Path};
+
+use fat32::traits::FileSystem;
+
+use crate::{Dir, Entry, File, Metadata};
+
+/// 共有される `TmpFsState` へのハンドル.
+///
+/// `fat32::vfat::VFatHandle` と同様に、ロックの実装は利用者が与える。
+pub trait TmpFsHandle: Clone + Debug + Send + Sync {
+    fn new(val: TmpFsState) -> Self;
+    fn lock<R>(&self, f: impl FnOnce(&mut TmpFsState) -> R) -> R;
+}
+
+/// ノード番号. 番号は再利用されないため、削除されたノードを指す
+/// ハンドルが別のノードを指すことはない。
+pub(crate) type Ino = u64;
+
+/// ルートディレクトリのノード番号.
+pub(crate) const ROOT_INO: Ino = 0;
+
+/// ファイルまたはディレクトリの内容.
+#[derive(Debug)]
+pub(crate) enum Node {
+    File(Vec<u8>),
+    /// 作成順に並んだエントリの名前とノード番号.
+    Dir(Vec<(String, Ino)>),
+}
+
+/// ヒープ上にファイルの内容を保持するファイルシステム.
+///
+/// 名前の大文字小文字は区別される。オープン中のファイルが削除された
+/// 場合、そのファイルへの以降の操作は `NotFound` エラーを返す。
+#[derive(Clone, Debug)]
+pub struct TmpFs<HANDLE: TmpFsHandle> {
+    handle: HANDLE,
+}
+
+/// `TmpFs` のノードの表. `TmpFsHandle` のロックを介してアクセスする.
+#[derive(Debug)]
+pub struct TmpFsState {
+    nodes: BTreeMap<Ino, Node>,
+    next_ino: Ino,
+    /// ファイルの内容の合計バイト数.
+    used_bytes: u64,
+    /// ファイルの内容の合計バイト数の上限.
+    limit: Option<u64>,
+}
+
+impl<HANDLE: TmpFsHandle> TmpFs<HANDLE> {
+    /// 空のファイルシステムを作成する. `limit` が `Some` の場合、
+    /// ファイルの内容の合計はそのバイト数までに制限される。
+    pub fn new(limit: Option<u64>) -> TmpFs<HANDLE> {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(ROOT_INO, Node::Dir(Vec::new()));
+
+        TmpFs {
+            handle: HANDLE::new(TmpFsState {
+                nodes,
+                next_ino: ROOT_INO + 1,
+                used_bytes: 0,
+                limit,
+            }),
+        }
+    }
+
+    /// このファイルシステムのハンドルを返す.
+    pub fn handle(&self) -> &HANDLE {
+        &self.handle
+    }
+
+    /// ロックを取得して `f` を呼び出す.
+    pub(crate) fn lock<R>(&self, f: impl FnOnce(&mut TmpFsState) -> R) -> R {
+        self.handle.lock(f)
+    }
+
+    /// ファイルの内容の合計バイト数を返す.
+    pub fn used_bytes(&self) -> u64 {
+        self.lock(|fs| fs.used_bytes)
+    }
+
+    /// ファイルの内容の合計バイト数の上限を返す.
+    pub fn limit(&self) -> Option<u64> {
+        self.lock(|fs| fs.limit)
+    }
+}
+
+impl TmpFsState {
+    fn node(&self, ino: Ino) -> io::Result<&Node> {
+        match self.nodes.get(&ino) {
+            Some(node) => Ok(node),
+            None => ioerr!(NotFound, "file was removed"),
+        }
+    }
+
+    fn children(&self, ino: Ino) -> io::Result<&Vec<(String, Ino)>> {
+        match self.node(ino)? {
+            Node::Dir(children) => Ok(children),
+            Node::File(_) => ioerr!(Other, "not a directory"),
+        }
+    }
+
+    fn children_mut(&mut self, ino: Ino) -> io::Result<&mut Vec<(String, Ino)>> {
+        match self.nodes.get_mut(&ino) {
+            Some(Node::Dir(children)) => Ok(children),
+            Some(Node::File(_)) => ioerr!(Other, "not a directory"),
+            None => ioerr!(NotFound, "directory was removed"),
+        }
+    }
+
+    fn data(&self, ino: Ino) -> io::Result<&Vec<u8>> {
+        match self.node(ino)? {
+            Node::File(data) => Ok(data),
+            Node::Dir(_) => ioerr!(Other, "not a regular file"),
+        }
+    }
+
+    /// ノード `ino` がディレクトリか否かを返す.
+    pub(crate) fn is_dir(&self, ino: Ino) -> io::Result<bool> {
+        Ok(match self.node(ino)? {
+            Node::Dir(_) => true,
+            Node::File(_) => false,
+        })
+    }
+
+    /// ディレクトリ `dir` から名前 `name` のエントリのノード番号を返す.
+    ///
+    /// # エラー
+    ///
+    /// `name` が存在しない場合は `NotFound` を返す。
+    pub(crate) fn lookup(&self, dir: Ino, name: &str) -> io::Result<Ino> {
+        match self.children(dir)?.iter().find(|(n, _)| n == name) {
+            Some(&(_, ino)) => Ok(ino),
+            None => ioerr!(NotFound, "entry not found"),
+        }
+    }
+
+    /// 絶対パス `path` を辿ってノード番号を返す.
+    ///
+    /// # エラー
+    ///
+    /// `path` が絶対パスでない、または途中のコンポーネントがディレクトリ
+    /// でない場合は `InvalidInput` を、エントリが存在しない場合は
+    /// `NotFound` を返す。
+    pub(crate) fn resolve(&self, path: &Path) -> io::Result<Ino> {
+        if !path.is_absolute() {
+            return ioerr!(InvalidInput, "path is not absolute");
+        }
+
+        let mut ino = ROOT_INO;
+        for component in path.components() {
+            match component {
+                Component::RootDir => ino = ROOT_INO,
+                Component::Normal(name) => {
+                    if !self.is_dir(ino)? {
+                        return ioerr!(InvalidInput, "found file in path traversal");
+                    }
+                    let name = name.to_str().ok_or(io::ErrorKind::InvalidInput)?;
+                    ino = self.lookup(ino, name)?;
+                }
+                _ => return ioerr!(InvalidInput, "unexpected path item"),
+            }
+        }
+        Ok(ino)
+    }
+
+    /// ディレクトリ `dir` のエントリの名前とノード番号の一覧を返す.
+    pub(crate) fn entries(&self, dir: Ino) -> io::Result<Vec<(String, Ino)>> {
+        Ok(self.children(dir)?.clone())
+    }
+
+    /// ディレクトリ `dir` に名前 `name` の空のノードを作成してノード
+    /// 番号を返す.
+    ///
+    /// # エラー
+    ///
+    /// `name` がすでに存在する場合は `AlreadyExists` を返す。
+    pub(crate) fn create(&mut self, dir: Ino, name: &str, is_dir: bool) -> io::Result<Ino> {
+        if self.children(dir)?.iter().any(|(n, _)| n == name) {
+            return ioerr!(AlreadyExists, "entry already exists");
+        }
+
+        let ino = self.next_ino;
+        self.next_ino += 1;
+        let node = if is_dir { Node::Dir(Vec::new()) } else { Node::File(Vec::new()) };
+        self.nodes.insert(ino, node);
+        self.children_mut(dir)?.push((String::from(name), ino));
+        Ok(ino)
+    }
+
+    /// ディレクトリ `dir` からファイルまたは空のディレクトリ `name` を
+    /// 削除する.
+    ///
+    /// # エラー
+    ///
+    /// `name` が存在しない場合は `NotFound` を、空でないディレクトリの
+    /// 場合は `PermissionDenied` を返す。
+    pub(crate) fn remove(&mut self, dir: Ino, name: &str) -> io::Result<()> {
+        let ino = self.lookup(dir, name)?;
+        if let Node::Dir(children) = self.node(ino)? {
+            if !children.is_empty() {
+                return ioerr!(PermissionDenied, "directory not empty");
+            }
+        }
+
+        self.children_mut(dir)?.retain(|(_, i)| *i != ino);
+        if let Some(Node::File(data)) = self.nodes.remove(&ino) {
+            self.used_bytes -= data.len() as u64;
+        }
+        Ok(())
+    }
+
+    /// ディレクトリ `src` のエントリ `from` をディレクトリ `dst` の
+    /// エントリ `to` に移動する. 移動先がディレクトリ自身の配下でない
+    /// ことは呼び出し側が確認する。
+    ///
+    /// # エラー
+    ///
+    /// `from` が存在しない場合は `NotFound` を、`to` がすでに存在する
+    /// 場合は `AlreadyExists` を返す。
+    pub(crate) fn rename(&mut self, src: Ino, from: &str, dst: Ino, to: &str) -> io::Result<()> {
+        let ino = self.lookup(src, from)?;
+        if src == dst && from == to {
+            return Ok(());
+        }
+        if self.children(dst)?.iter().any(|(n, _)| n == to) {
+            return ioerr!(AlreadyExists, "entry already exists");
+        }
+
+        self.children_mut(src)?.retain(|(_, i)| *i != ino);
+        self.children_mut(dst)?.push((String::from(to), ino));
+        Ok(())
+    }
+
+    /// ファイル `ino` のバイト単位のサイズを返す.
+    pub(crate) fn len(&self, ino: Ino) -> io::Result<u64> {
+        Ok(self.data(ino)?.len() as u64)
+    }
+
+    /// ファイルの内容の合計が `old` バイトから `new` バイトに変わる
+    /// ことを記録する.
+    ///
+    /// # エラー
+    ///
+    /// 上限を超える場合は `Other` を返す。
+    fn account(&mut self, old: u64, new: u64) -> io::Result<()> {
+        let used = self.used_bytes - old + new;
+        if new > old && self.limit.map_or(false, |limit| used > limit) {
+            return ioerr!(Other, "no space left on device");
+        }
+        self.used_bytes = used;
+        Ok(())
+    }
+
+    /// ファイル `ino` の `offset` バイト目から `buf` に読み込み、読み込んだ
+    /// バイト数を返す.
+    pub(crate) fn read(&self, ino: Ino, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.data(ino)?;
+        if offset >= data.len() as u64 {
+            return Ok(0);
+        }
+
+        let start = offset as usize;
+        let n = core::cmp::min(buf.len(), data.len() - start);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+
+    /// ファイル `ino` の `offset` バイト目に `buf` を書き込む. ファイル
+    /// 終端を超える位置に書き込む場合、間はゼロで埋められる。
+    ///
+    /// # エラー
+    ///
+    /// 容量の上限を超える場合は `Other` を、書き込み後のサイズが表せない
+    /// 場合は `InvalidInput` を返す。
+    pub(crate) fn write(&mut self, ino: Ino, offset: u64, buf: &[u8]) -> io::Result<usize> {
+        let end = match offset.checked_add(buf.len() as u64) {
+            Some(end) => end,
+            None => return ioerr!(InvalidInput, "file too large"),
+        };
+        let len = self.len(ino)?;
+        if end > len {
+            self.set_len(ino, end)?;
+        }
+
+        let start = offset as usize;
+        if let Some(Node::File(data)) = self.nodes.get_mut(&ino) {
+            data[start..start + buf.len()].copy_from_slice(buf);
+        }
+        Ok(buf.len())
+    }
+
+    /// ファイル `ino` のサイズを `size` バイトにする. 伸ばした部分は
+    /// ゼロで埋められる。
+    ///
+    /// # エラー
+    ///
+    /// 容量の上限を超える場合は `Other` を、`size` がメモリ上で表せない
+    /// 場合は `InvalidInput` を返す。
+    pub(crate) fn set_len(&mut self, ino: Ino, size: u64) -> io::Result<()> {
+        if size > usize::max_value() as u64 {
+            return ioerr!(InvalidInput, "file too large");
+        }
+        let len = self.len(ino)?;
+        self.account(len, size)?;
+        if let Some(Node::File(data)) = self.nodes.get_mut(&ino) {
+            data.resize(size as usize, 0);
+            if size < len {
+                data.shrink_to_fit();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 絶対パス `path` を親ディレクトリとエントリ名に分割する.
+///
+/// # エラー
+///
+/// `path` が絶対パスでない場合は `InvalidInput` を、エントリ名を
+/// 持たない場合（ルートディレクトリ）は `PermissionDenied` を返す。
+fn split_path(path: &Path) -> io::Result<(&Path, &str)> {
+    if !path.is_absolute() {
+        return ioerr!(InvalidInput, "path is not absolute");
+    }
+
+    match (path.parent(), path.file_name().and_then(|name| name.to_str())) {
+        (Some(parent), Some(name)) => Ok((parent, name)),
+        _ => ioerr!(PermissionDenied, "path has no entry name"),
+    }
+}
+
+/// ノード `ino` のエントリを作成する.
+pub(crate) fn entry<HANDLE: TmpFsHandle>(fs: &TmpFsState, tmpfs: &TmpFs<HANDLE>, ino: Ino,
+    name: &str) -> io::Result<Entry<HANDLE>> {
+    let name = String::from(name);
+    let metadata = Metadata::for_name(&name);
+    Ok(if fs.is_dir(ino)? {
+        Entry::Dir(Dir { tmpfs: tmpfs.clone(), ino, name, metadata })
+    } else {
+        Entry::File(File::new(tmpfs.clone(), ino, name, metadata))
+    })
+}
+
+impl<'a, HANDLE: TmpFsHandle> FileSystem for &'a TmpFs<HANDLE> {
+    type File = File<HANDLE>;
+    type Dir = Dir<HANDLE>;
+    type Entry = Entry<HANDLE>;
+
+    fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry> {
+        let path = path.as_ref();
+        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+        self.lock(|fs| {
+            let ino = fs.resolve(path)?;
+            entry(fs, self, ino, name)
+        })
+    }
+
+    fn create_file<P: AsRef<Path>>(self, path: P) -> io::Result<Self::File> {
+        let (parent, name) = split_path(path.as_ref())?;
+        let ino = self.lock(|fs| {
+            let dir = fs.resolve(parent)?;
+            fs.create(dir, name, false)
+        })?;
+        Ok(File::new(self.clone(), ino, String::from(name), Metadata::for_name(name)))
+    }
+
+    fn create_dir<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Dir> {
+        let (parent, name) = split_path(path.as_ref())?;
+        let ino = self.lock(|fs| {
+            let dir = fs.resolve(parent)?;
+            fs.create(dir, name, true)
+        })?;
+        Ok(Dir {
+            tmpfs: self.clone(),
+            ino,
+            name: String::from(name),
+            metadata: Metadata::for_name(name),
+        })
+    }
+
+    fn remove<P: AsRef<Path>>(self, path: P) -> io::Result<()> {
+        let (parent, name) = split_path(path.as_ref())?;
+        self.lock(|fs| {
+            let dir = fs.resolve(parent)?;
+            fs.remove(dir, name)
+        })
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(self, from: P, to: Q) -> io::Result<()> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        let (src, from_name) = split_path(from)?;
+        let (dst, to_name) = split_path(to)?;
+
+        self.lock(|fs| {
+            let src = fs.resolve(src)?;
+            let ino = fs.lookup(src, from_name)?;
+            if fs.is_dir(ino)? && to != from && to.starts_with(from) {
+                return ioerr!(InvalidInput, "cannot move a directory into itself");
+            }
+            let dst = fs.resolve(dst)?;
+            fs.rename(src, from_name, dst, to_name)
+        })
+    }
+}
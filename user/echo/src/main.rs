@@ -1,4 +1,5 @@
 #![feature(asm)]
+#![feature(alloc_error_handler)]
 #![feature(never_type)]
 #![no_std]
 #![no_main]
@@ -22,7 +23,7 @@ fn main_inner() -> OsResult<!> {
     let mut buf = [0_u8; 512];
 
     println!("[ECHO] sock_create");
-    let descriptor = sock_create();
+    let descriptor = sock_create()?;
     println!("[ECHO] socket {} created", descriptor.raw());
 
     println!("[ECHO] sock_listen");
@@ -1,9 +1,11 @@
 #![feature(asm)]
+#![feature(alloc_error_handler)]
 #![no_std]
 #![no_main]
 
 mod cr0;
 
+use kernel_api::env;
 use kernel_api::println;
 use kernel_api::syscall::{getpid, time};
 
@@ -16,13 +18,15 @@ fn fib(n: u64) -> u64 {
 }
 
 fn main() {
+    // 第1引数で項数を指定できる
+    let n = env::args().nth(1).and_then(|arg| arg.parse().ok()).unwrap_or(20);
     let pid = getpid();
     let beg = time();
     //println!("PID [{}] fib started: {:?}", pid, beg);
 
-    let rtn = fib(20);
+    let rtn = fib(n);
 
     let end = time();
-    println!("PID [{}] fib(20) = {} ({:?})", pid, rtn, end - beg);
+    println!("PID [{}] fib({}) = {} ({:?})", pid, n, rtn, end - beg);
     //println!("PID [{}] fib ended: {:?}", pid, end);
 }
@@ -1,3 +1,4 @@
+use core::alloc::Layout;
 use core::mem::zeroed;
 use core::panic::PanicInfo;
 use core::ptr::write_volatile;
@@ -7,6 +8,14 @@ fn panic(_info: &PanicInfo) -> ! {
     loop {}
 }
 
+#[global_allocator]
+static ALLOCATOR: kernel_api::allocator::Allocator = kernel_api::allocator::Allocator::new();
+
+#[alloc_error_handler]
+fn alloc_error(_layout: Layout) -> ! {
+    kernel_api::syscall::exit(-1);
+}
+
 unsafe fn zeros_bss() {
     extern "C" {
         static mut __bss_beg: u64;
@@ -23,8 +32,10 @@ unsafe fn zeros_bss() {
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn _start() -> ! {
+pub unsafe extern "C" fn _start(argc: usize, argv: *const *const u8,
+    envp: *const *const u8, auxv: *const u64) -> ! {
     zeros_bss();
+    kernel_api::env::init(argc, argv, envp, auxv);
     crate::main();
-    kernel_api::syscall::exit();
+    kernel_api::syscall::exit(0);
 }